@@ -0,0 +1,102 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bongo::grammar::{
+  build,
+  passes::{follows::Follows, PassContext},
+  Elem, Grammar, NonTerminal, ProdElement, Terminal,
+};
+use bongo::utils::Name;
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const NUM_NONTERMS: usize = 500;
+const NUM_TERMS: usize = 100;
+
+/// Builds a deterministic synthetic grammar with `NUM_NONTERMS` nonterminals.
+///
+/// Every fifth nonterminal has an empty production, and every other
+/// production contains a terminal, so the grammar is never ambiguously
+/// nullable.
+fn make_synthetic() -> Grammar<Terminal, NonTerminal, Name, ()> {
+  let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+  let mut next = move |bound: usize| {
+    state = state
+      .wrapping_mul(6_364_136_223_846_793_005)
+      .wrapping_add(1_442_695_040_888_963_407);
+    (state >> 33) as usize % bound
+  };
+
+  let nts = (0..NUM_NONTERMS)
+    .map(|i| NonTerminal::new(&format!("n{}", i)))
+    .collect::<Vec<_>>();
+  let ts = (0..NUM_TERMS)
+    .map(|i| Terminal::new(&format!("T{}", i)))
+    .collect::<Vec<_>>();
+
+  let mut rules = Vec::new();
+  for i in 0..NUM_NONTERMS {
+    let mut prods = Vec::new();
+    // Chain each nonterminal to the next one, so all are reachable.
+    let chained = nts[(i + 1) % NUM_NONTERMS].clone();
+    prods.push(vec![
+      Elem::NonTerm(nts[next(NUM_NONTERMS)].clone()),
+      Elem::Term(ts[next(NUM_TERMS)].clone()),
+      Elem::NonTerm(chained),
+    ]);
+    prods.push(vec![
+      Elem::NonTerm(nts[next(NUM_NONTERMS)].clone()),
+      Elem::NonTerm(nts[next(NUM_NONTERMS)].clone()),
+      Elem::Term(ts[next(NUM_TERMS)].clone()),
+    ]);
+    prods.push(vec![
+      Elem::Term(ts[next(NUM_TERMS)].clone()),
+      Elem::NonTerm(nts[next(NUM_NONTERMS)].clone()),
+    ]);
+    if i % 5 == 0 {
+      prods.push(Vec::new());
+    }
+    rules.push(prods);
+  }
+
+  build(&nts[0], |gb| {
+    for (nt, prods) in nts.iter().zip(rules) {
+      gb.add_rule(nt, |rb| {
+        for (i, elems) in prods.into_iter().enumerate() {
+          rb.add_prod_with_elems(
+            Name::new(&format!("p{}", i)),
+            (),
+            elems
+              .into_iter()
+              .map(ProdElement::new_empty)
+              .collect::<Vec<_>>(),
+          );
+        }
+      });
+    }
+  })
+  .unwrap()
+}
+
+fn bench_first_follow(c: &mut Criterion) {
+  let g = make_synthetic();
+  c.bench_function("first_follow_500", |b| {
+    b.iter(|| {
+      let passes = PassContext::new(&g);
+      passes.get_pass::<Follows<_, _>>().unwrap();
+    })
+  });
+}
+
+criterion_group!(benches, bench_first_follow);
+criterion_main!(benches);
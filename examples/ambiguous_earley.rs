@@ -10,9 +10,9 @@ fn main() {
   let a_nt = NonTerminal::new("A");
   let g: Grammar<Terminal, NonTerminal, Name, ()> = build(&a_nt, |b| {
     b.add_rule(&a_nt, |br| {
-      br.add_prod_with_elems(&Name::new("a_empty"), (), vec![])
+      br.add_prod_with_elems(Name::new("a_empty"), (), vec![])
         .add_prod_with_elems(
-          &Name::new("a_recurse"),
+          Name::new("a_recurse"),
           (),
           vec![ProdElement::new_with_name(
             Name::new("recurse"),
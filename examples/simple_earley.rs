@@ -14,7 +14,7 @@ fn main() {
   let g: Grammar<Terminal, NonTerminal, Name, ()> = build(&a_nt, |b| {
     b.add_rule(&a_nt, |br| {
       br.add_prod_with_elems(
-        &Name::new("value"),
+        Name::new("value"),
         (),
         vec![ProdElement::new_with_name(
           Name::new("val"),
@@ -22,7 +22,7 @@ fn main() {
         )],
       )
       .add_prod_with_elems(
-        &Name::new("parens"),
+        Name::new("parens"),
         (),
         vec![
           ProdElement::new_empty(Elem::Term(lp_t.clone())),
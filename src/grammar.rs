@@ -14,13 +14,41 @@
 
 //! Creation and manipulation of grammars.
 
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
 mod base;
+pub mod diff;
+mod ebnf;
 pub mod examples;
+mod factoring;
+pub mod gen;
+mod graph;
+pub mod ir;
+mod lint;
+mod ll1;
+mod llk;
+mod normal_form;
+mod overlap;
 pub mod passes;
+#[cfg(feature = "railroad")]
+mod railroad;
+mod summary;
+pub mod template;
 pub mod transform;
 
+pub use factoring::FactoringSuggestion;
+pub use graph::NonTermGraph;
+pub use lint::{Lint, Severity};
+pub use ll1::Ll1Blocker;
+pub use overlap::TerminalOverlap;
+pub use summary::TerminalContext;
+
 pub use base::{
-  builder::{build, GrammarBuilder, ProductionBuilder, RuleBuilder},
-  Elem, Grammar, GrammarErrors, NonTerminal, Prod, ProdElement, ProdKey, Rule,
-  Terminal,
+  builder::{
+    build, build_lenient, build_strict, AlternationBuilder, BuildError,
+    GrammarBuilder, ProductionBuilder, RuleBuilder,
+  },
+  BaseElementTypes, DisplayOptions, Elem, ElementTypes, Grammar, GrammarErrors,
+  GrammarFor, GrammarWarnings, NonTerminal, Prod, ProdElement, ProdKey, Prods,
+  Rule, RuleOrder, Rules, SymbolPool, Terminal,
 };
@@ -0,0 +1,147 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An `arbitrary::Arbitrary` implementation for grammars, for use with fuzzers.
+//!
+//! Generated grammars are well-formed by construction: every nonterminal has
+//! a rule with at least one production, and every nonterminal is reachable
+//! from the start nonterminal. This lets fuzz targets exercise the analyses
+//! without having to discard most of their inputs as invalid grammars.
+
+use {
+  crate::{
+    grammar::{build, Elem, Grammar, NonTerminal, ProdElement, Terminal},
+    utils::Name,
+  },
+  arbitrary::{Arbitrary, Unstructured},
+};
+
+const MAX_TERMINALS: usize = 8;
+const MAX_NONTERMINALS: usize = 8;
+const MAX_PRODS_PER_RULE: usize = 4;
+const MAX_PROD_LEN: usize = 5;
+
+type ArbitraryBody = Vec<ProdElement<Terminal, NonTerminal>>;
+
+fn arbitrary_body(
+  u: &mut Unstructured<'_>,
+  terms: &[Terminal],
+  nonterms: &[NonTerminal],
+) -> arbitrary::Result<ArbitraryBody> {
+  let len = u.int_in_range(0..=MAX_PROD_LEN)?;
+  let mut body = Vec::with_capacity(len);
+  for i in 0..len {
+    let elem = if u.arbitrary()? {
+      Elem::Term(u.choose(terms)?.clone())
+    } else {
+      Elem::NonTerm(u.choose(nonterms)?.clone())
+    };
+
+    let id = if u.arbitrary()? {
+      Some(Name::new(&format!("f{}", i)))
+    } else {
+      None
+    };
+
+    body.push(ProdElement::new(id, elem));
+  }
+
+  Ok(body)
+}
+
+impl<'a> Arbitrary<'a> for Grammar<Terminal, NonTerminal, Name, ()> {
+  fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+    let num_terms = u.int_in_range(1..=MAX_TERMINALS)?;
+    let num_nonterms = u.int_in_range(1..=MAX_NONTERMINALS)?;
+
+    let terms = (0..num_terms)
+      .map(|i| Terminal::new(&format!("T{}", i)))
+      .collect::<Vec<_>>();
+    let nonterms = (0..num_nonterms)
+      .map(|i| NonTerminal::new(&format!("n{}", i)))
+      .collect::<Vec<_>>();
+
+    let mut rule_bodies = Vec::with_capacity(num_nonterms);
+    for _ in 0..num_nonterms {
+      let num_prods = u.int_in_range(1..=MAX_PRODS_PER_RULE)?;
+      let mut bodies = Vec::with_capacity(num_prods);
+      for _ in 0..num_prods {
+        bodies.push(arbitrary_body(u, &terms, &nonterms)?);
+      }
+      rule_bodies.push(bodies);
+    }
+
+    // Guarantee reachability by referencing each nonterminal from some
+    // production of a nonterminal defined before it. The start nonterminal is
+    // the first, so by induction all nonterminals are reachable.
+    for (i, nonterm) in nonterms.iter().enumerate().skip(1) {
+      let parent = u.int_in_range(0..=i - 1)?;
+      let bodies = &mut rule_bodies[parent];
+      let body_index = u.choose_index(bodies.len())?;
+      let body = &mut bodies[body_index];
+      let position = u.int_in_range(0..=body.len())?;
+      body.insert(
+        position,
+        ProdElement::new_empty(Elem::NonTerm(nonterm.clone())),
+      );
+    }
+
+    build(&nonterms[0], |gb| {
+      for (nonterm, bodies) in nonterms.iter().zip(rule_bodies) {
+        gb.add_rule(nonterm, |rb| {
+          for (i, body) in bodies.into_iter().enumerate() {
+            rb.add_prod_with_elems(Name::new(&format!("p{}", i)), (), body);
+          }
+        });
+      }
+    })
+    .map_err(|_| arbitrary::Error::IncorrectFormat)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::passes::{nullable::Nullable, PassContext};
+
+  /// A small xorshift generator, so the test is deterministic and doesn't
+  /// need a dependency on a random number crate.
+  fn pseudo_random_bytes(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..len)
+      .map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 24) as u8
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_arbitrary_grammars_are_valid() {
+    for seed in 0..1000 {
+      let bytes = pseudo_random_bytes(seed, 512);
+      let mut u = Unstructured::new(&bytes);
+      let g = Grammar::<Terminal, NonTerminal, Name, ()>::arbitrary(&mut u)
+        .expect("arbitrary grammars are valid by construction");
+      assert!(g.validate().is_ok());
+
+      // The grammar may be ambiguously nullable, so we only check that the
+      // analysis terminates without panicking.
+      let pass_map = PassContext::new(&g);
+      let _ = pass_map.get_pass::<Nullable<_, _>>();
+    }
+  }
+}
@@ -17,17 +17,25 @@ mod cmp_wrappers;
 mod element_types;
 
 use {
-  crate::utils::{breadth_first_search, Name, ToDoc},
-  std::collections::{BTreeMap, BTreeSet},
+  crate::utils::{
+    breadth_first_search, change_iter, change_loop, doc_string, fmt_doc,
+    FnvHasher, Name, ToDoc, WasChanged,
+  },
+  std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    hash::{Hash, Hasher},
+  },
 };
 
 use std::fmt::Debug;
 
-pub use cmp_wrappers::{NoCompare, ParentRef, RefCompare};
-pub use element_types::{NonTerminal, Terminal};
+pub use cmp_wrappers::{ParentRef, RefCompare};
+pub use element_types::{
+  BaseElementTypes, ElementTypes, GrammarFor, NonTerminal, SymbolPool, Terminal,
+};
 
 /// A single element (terminal or non-terminal).
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub enum Elem<T, NT> {
   Term(T),
   NonTerm(NT),
@@ -59,6 +67,16 @@ impl<T, NT> Elem<T, NT> {
   }
 }
 
+impl<T, NT> std::fmt::Display for Elem<T, NT>
+where
+  T: ToDoc,
+  NT: ToDoc,
+{
+  fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fmt_doc(self, fmt)
+  }
+}
+
 impl<T, NT> ToDoc for Elem<T, NT>
 where
   T: ToDoc,
@@ -94,7 +112,7 @@ where
 }
 
 /// An element within a production. Includes an optional identifier.
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ProdElement<T, NT> {
   identifier: Option<Name>,
   element: Elem<T, NT>,
@@ -215,6 +233,10 @@ impl<T, NT, AK, AV> ProdInner<T, NT, AK, AV> {
   pub fn action_key(&self) -> &AK {
     &self.action_key
   }
+
+  pub fn is_empty(&self) -> bool {
+    self.elements.is_empty()
+  }
 }
 impl<T, NT, AK, AV> ToDoc for ProdInner<T, NT, AK, AV>
 where
@@ -228,7 +250,7 @@ where
   where
     DA::Doc: Clone,
   {
-    if self.elements.is_empty() {
+    if self.is_empty() {
       da.text("ε")
     } else {
       da.intersperse(self.elements.iter().map(|e| e.to_doc(da)), da.softline())
@@ -242,6 +264,11 @@ where
 /// lifetime key value that allows us to track which production is used. It's
 /// possible we may be able to transform that later, but for now this is simple
 /// enough.
+///
+/// Prod keys are ordered by head, then by action key. This only depends on
+/// the keys themselves, not on the order rules and productions were added
+/// in, so maps keyed by `ProdKey` iterate in the same order for any two
+/// constructions of the same grammar.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct ProdKey<NT, AK> {
   head: NT,
@@ -249,6 +276,10 @@ pub struct ProdKey<NT, AK> {
 }
 
 impl<NT, AK> ProdKey<NT, AK> {
+  pub fn new(head: NT, action_key: AK) -> Self {
+    ProdKey { head, action_key }
+  }
+
   /// Gets the head of this prod key.
   pub fn head(&self) -> &NT {
     &self.head
@@ -265,11 +296,29 @@ impl<NT, AK> ProdKey<NT, AK> {
 struct RuleInner<T, NT, AK, AV> {
   head: NT,
   prods: Vec<ProdInner<T, NT, AK, AV>>,
+  doc: Option<String>,
+  /// Whether the builder generated this rule for an inline alternation.
+  generated: bool,
 }
 
 impl<T, NT, AK, AV> RuleInner<T, NT, AK, AV> {
   pub fn new(head: NT, prods: Vec<ProdInner<T, NT, AK, AV>>) -> Self {
-    RuleInner { head, prods }
+    RuleInner {
+      head,
+      prods,
+      doc: None,
+      generated: false,
+    }
+  }
+
+  fn with_doc(mut self, doc: Option<String>) -> Self {
+    self.doc = doc;
+    self
+  }
+
+  fn into_generated(mut self) -> Self {
+    self.generated = true;
+    self
   }
 
   pub fn head(&self) -> &NT {
@@ -281,10 +330,53 @@ impl<T, NT, AK, AV> RuleInner<T, NT, AK, AV> {
   }
 }
 
+/// Options for the layout of `Grammar::to_pretty_with_options`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DisplayOptions {
+  /// Text placed before the head of the start rule, followed by a space.
+  /// There is no marker by default.
+  pub start_marker: Option<String>,
+  /// Whether each production is followed by `=> action_key`. Defaults to
+  /// true.
+  pub show_action_keys: bool,
+  /// The number of spaces that rules and productions are indented by.
+  /// Defaults to 2.
+  pub indent: usize,
+  /// The order the rules are listed in. Defaults to alphabetical, which
+  /// matches `to_pretty`.
+  pub rule_order: RuleOrder,
+}
+
+impl Default for DisplayOptions {
+  fn default() -> Self {
+    DisplayOptions {
+      start_marker: None,
+      show_action_keys: true,
+      indent: 2,
+      rule_order: RuleOrder::Alphabetical,
+    }
+  }
+}
+
+/// An order for listing the rules of a grammar.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RuleOrder {
+  /// Ordered by head.
+  Alphabetical,
+  /// The start rule, followed by the others ordered by head. This is the
+  /// usual order in grammar files, and the order of exported grammars.
+  StartFirst,
+  /// The start rule, followed by the rules in the order they are first
+  /// used in a breadth-first walk from it, then any unreachable rules,
+  /// ordered by head.
+  BreadthFirst,
+}
+
 impl<T, NT, AK, AV> ToDoc for RuleInner<T, NT, AK, AV>
 where
   T: ToDoc,
   NT: ToDoc,
+  AK: ToDoc,
 {
   fn to_doc<'a, DA: pretty::DocAllocator<'a>>(
     &self,
@@ -293,15 +385,64 @@ where
   where
     DA::Doc: Clone,
   {
-    self
-      .head
-      .to_doc(da)
-      .append(da.text(" =>"))
-      .append(da.softline())
-      .append(da.intersperse(
-        self.prods.iter().map(|prod| prod.to_doc(da)),
-        da.text(" |").append(da.softline()),
-      ))
+    self.to_doc_with_options(da, &DisplayOptions::default(), false)
+  }
+}
+
+impl<T, NT, AK, AV> RuleInner<T, NT, AK, AV>
+where
+  T: ToDoc,
+  NT: ToDoc,
+  AK: ToDoc,
+{
+  /// Lays out the rule with one production per line, each prefixed with `|`
+  /// and followed by its action key. The action keys are aligned into a
+  /// column. The rule's doc string, if any, precedes it as `//` comments.
+  fn to_doc_with_options<'a, DA: pretty::DocAllocator<'a>>(
+    &self,
+    da: &'a DA,
+    options: &DisplayOptions,
+    is_start: bool,
+  ) -> pretty::DocBuilder<'a, DA>
+  where
+    DA::Doc: Clone,
+  {
+    let bodies = self.prods.iter().map(doc_string).collect::<Vec<_>>();
+    let width = bodies
+      .iter()
+      .map(|body| body.chars().count())
+      .max()
+      .unwrap_or(0);
+
+    let comments = self.doc.iter().flat_map(|doc| doc.lines()).map(|line| {
+      da.text(format!("// {}", line).trim_end().to_string())
+        .append(da.hardline())
+    });
+
+    let marker = match &options.start_marker {
+      Some(marker) if is_start => da.text(format!("{} ", marker)),
+      _ => da.nil(),
+    };
+
+    da.concat(comments)
+      .append(marker)
+      .append(self.head.to_doc(da))
+      .append(da.text(" ::="))
+      .append(
+        da.concat(self.prods.iter().zip(bodies).map(|(prod, body)| {
+          let line = da.hardline().append(da.text("| "));
+          if !options.show_action_keys {
+            return line.append(da.text(body));
+          }
+          let padding = " ".repeat(width - body.chars().count());
+          line
+            .append(da.text(body))
+            .append(da.text(padding))
+            .append(da.text(" => "))
+            .append(prod.action_key.to_doc(da))
+        }))
+        .nest(options.indent as isize),
+      )
   }
 }
 
@@ -341,6 +482,83 @@ where
   }
 }
 
+/// Grammars are equal if they have the same start nonterminal and the same
+/// productions, with the same action values, regardless of the order in which
+/// the productions were added.
+impl<T, NT, AK, AV> PartialEq for Grammar<T, NT, AK, AV>
+where
+  T: Ord,
+  NT: Ord,
+  AK: Ord,
+  AV: PartialEq,
+{
+  fn eq(&self, other: &Self) -> bool {
+    self.start_symbol == other.start_symbol
+      && self.rule_set.keys().eq(other.rule_set.keys())
+      && self.canonical_prods() == other.canonical_prods()
+  }
+}
+
+impl<T, NT, AK, AV> Eq for Grammar<T, NT, AK, AV>
+where
+  T: Ord,
+  NT: Ord,
+  AK: Ord,
+  AV: Eq,
+{
+}
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: Ord,
+  NT: Ord,
+  AK: Ord,
+{
+  /// Returns a hash of the start nonterminal, the rules and the productions
+  /// of this grammar, including their action keys and values.
+  ///
+  /// Like equality, the hash does not depend on the order in which
+  /// productions were added, so grammars that are equal have the same hash.
+  /// The hash is deterministic within a build of this crate, but `Hash`
+  /// implementations and the hashing scheme may change between versions, so
+  /// it should not be persisted as a cache key across builds.
+  pub fn content_hash(&self) -> u64
+  where
+    T: Hash,
+    NT: Hash,
+    AK: Hash,
+    AV: Hash,
+  {
+    let mut hasher = FnvHasher::default();
+    self.start_symbol.hash(&mut hasher);
+    self.rule_set.keys().collect::<Vec<_>>().hash(&mut hasher);
+    self.canonical_prods().hash(&mut hasher);
+    hasher.finish()
+  }
+
+  /// Returns every production as a tuple of its head, action key, elements
+  /// and action value, sorted by all but the action value.
+  #[allow(clippy::type_complexity)]
+  fn canonical_prods(&self) -> Vec<(&NT, &AK, &Vec<ProdElement<T, NT>>, &AV)> {
+    let mut prods = self
+      .rule_set
+      .values()
+      .flat_map(|rule| {
+        rule.prods().iter().map(move |prod| {
+          (
+            rule.head(),
+            &prod.action_key,
+            &prod.elements,
+            &prod.action_value,
+          )
+        })
+      })
+      .collect::<Vec<_>>();
+    prods.sort_by(|a, b| (a.0, a.1, a.2).cmp(&(b.0, b.1, b.2)));
+    prods
+  }
+}
+
 impl<T, NT, AK, AV> Grammar<T, NT, AK, AV> {
   /// Returns the start nonterminal for this grammar.
   pub fn start_nt(&self) -> &NT {
@@ -364,15 +582,15 @@ impl<T, NT, AK, AV> Grammar<T, NT, AK, AV> {
   }
 
   /// Returns an iterator over all of the rules for this grammar.
-  pub fn rules(&self) -> impl Iterator<Item = Rule<T, NT, AK, AV>> {
-    self.rule_set.iter().map(move |(_, rule)| Rule {
-      grammar: ParentRef::new(self),
-      rule: RefCompare::new(rule),
-    })
+  pub fn rules(&self) -> Rules<'_, T, NT, AK, AV> {
+    Rules {
+      grammar: self,
+      rules: self.rule_set.values(),
+    }
   }
 
   /// Gets an iterator over all productions in the grammar.
-  pub fn prods(&self) -> impl Iterator<Item = Prod<T, NT, AK, AV>> {
+  pub fn prods(&self) -> impl Iterator<Item = Prod<'_, T, NT, AK, AV>> {
     self.rules().flat_map(move |rule| rule.prods())
   }
 }
@@ -382,10 +600,14 @@ where
   NT: Ord + Clone,
   AK: Ord + Clone,
 {
+  /// Creates a grammar from its rules, and checks that it is well-formed.
+  /// If `max_prod_len` is set, productions with more elements are also
+  /// errors.
   fn new(
     start: NT,
     rule_set: impl IntoIterator<Item = RuleInner<T, NT, AK, AV>>,
-  ) -> Result<Self, GrammarErrors<NT>> {
+    max_prod_len: Option<usize>,
+  ) -> Result<Self, GrammarErrors<NT, AK>> {
     let g = Grammar {
       start_symbol: start,
       rule_set: rule_set
@@ -394,11 +616,29 @@ where
         .collect(),
     };
 
-    g.check_grammar().map(|_| g)
+    g.check_grammar(max_prod_len).map(|_| g)
+  }
+
+  /// Like `new`, but returns the grammar even if it is not well-formed,
+  /// along with its errors.
+  fn new_lenient(
+    start: NT,
+    rule_set: impl IntoIterator<Item = RuleInner<T, NT, AK, AV>>,
+    max_prod_len: Option<usize>,
+  ) -> (Self, GrammarErrors<NT, AK>) {
+    let g = Grammar {
+      start_symbol: start,
+      rule_set: rule_set
+        .into_iter()
+        .map(|r| (r.head().clone(), r))
+        .collect(),
+    };
+    let errors = g.collect_errors(max_prod_len);
+    (g, errors)
   }
 
   /// Returns a map over rules of the grammar, keyed by the rule's head nonterminal.
-  pub fn rule_set(&self) -> BTreeMap<&NT, Rule<T, NT, AK, AV>> {
+  pub fn rule_set(&self) -> BTreeMap<&NT, Rule<'_, T, NT, AK, AV>> {
     self
       .rule_set
       .iter()
@@ -406,6 +646,25 @@ where
       .collect()
   }
 
+  /// Returns true if `nt` is the head of a rule of this grammar.
+  pub fn defines_nonterminal(&self, nt: &NT) -> bool {
+    self.rule_set.contains_key(nt)
+  }
+
+  /// Returns true if some production of this grammar contains `t`.
+  pub fn uses_terminal(&self, t: &T) -> bool
+  where
+    T: PartialEq,
+  {
+    self.get_terminals().any(|term| term == t)
+  }
+
+  /// Returns true if some production of this grammar contains `nt`. This
+  /// does not include the start nonterminal, unless it is used recursively.
+  pub fn uses_nonterminal(&self, nt: &NT) -> bool {
+    self.get_nonterminals().any(|nonterm| nonterm == nt)
+  }
+
   /// Gets the rule that has the given nonterminal as a head.
   pub fn try_get_rule<'a>(
     &'a self,
@@ -421,6 +680,37 @@ where
       .expect("An NT rule exists in the grammar.")
   }
 
+  /// Returns the rules of this grammar in the given order.
+  pub fn rules_in_order(
+    &self,
+    order: RuleOrder,
+  ) -> Vec<Rule<'_, T, NT, AK, AV>> {
+    let mut ordered = Vec::new();
+    let mut seen = BTreeSet::new();
+    if order != RuleOrder::Alphabetical {
+      if let Some(rule) = self.try_get_rule(&self.start_symbol) {
+        seen.insert(rule.head());
+        ordered.push(rule);
+      }
+    }
+    if order == RuleOrder::BreadthFirst {
+      let mut queue = ordered.iter().copied().collect::<VecDeque<_>>();
+      while let Some(rule) = queue.pop_front() {
+        let used = rule.prods().flat_map(|prod| prod.elements());
+        for nt in used.filter_map(|elem| elem.as_nonterm()) {
+          if let Some(next) = self.try_get_rule(nt) {
+            if seen.insert(next.head()) {
+              ordered.push(next);
+              queue.push_back(next);
+            }
+          }
+        }
+      }
+    }
+    ordered.extend(self.rules().filter(|rule| !seen.contains(rule.head())));
+    ordered
+  }
+
   fn nonterminals_without_rules(&self) -> BTreeSet<&NT> {
     self
       .get_nonterminals()
@@ -430,29 +720,123 @@ where
 
   fn rules_without_prods(&self) -> BTreeSet<&NT> {
     let rules = self.rules();
-    let prodless_rules = rules
-      .into_iter()
-      .filter(|r| matches!(r.prods().next(), None));
+    let prodless_rules =
+      rules.into_iter().filter(|r| r.prods().next().is_none());
     let head_iter = prodless_rules.map(|r| r.head());
     head_iter.collect()
   }
 
+  fn over_long_prods(&self, max_len: usize) -> BTreeSet<ProdKey<NT, AK>>
+  where
+    AK: Ord + Clone,
+  {
+    self
+      .prods()
+      .filter(|prod| prod.prod_elements().len() > max_len)
+      .map(|prod| prod.prod_key())
+      .collect()
+  }
+
+  fn duplicate_action_keys(&self) -> BTreeSet<ProdKey<NT, AK>> {
+    let mut seen = BTreeSet::new();
+    self
+      .prods()
+      .map(|prod| prod.prod_key())
+      .filter(|key| !seen.insert(key.clone()))
+      .collect()
+  }
+
   fn reachable_nonterms(&self) -> BTreeSet<&NT> {
-    breadth_first_search(std::iter::once(&self.start_symbol), |nt| {
+    self.reachable_from(&self.start_symbol)
+  }
+
+  /// Returns the nonterminals that appear in productions reachable from
+  /// `root`. This only includes `root` itself if it is used recursively.
+  pub(crate) fn reachable_from<'a>(&'a self, root: &'a NT) -> BTreeSet<&'a NT> {
+    self.reachable_through(std::iter::once(root), |_| true)
+  }
+
+  /// Like `reachable_from`, but starts from each of `roots`, and only
+  /// follows the productions that `follow` accepts.
+  pub(crate) fn reachable_through<'a>(
+    &'a self,
+    roots: impl IntoIterator<Item = &'a NT>,
+    follow: impl Fn(&Prod<'a, T, NT, AK, AV>) -> bool,
+  ) -> BTreeSet<&'a NT> {
+    breadth_first_search(roots, |nt| {
       self
-        .get_rule(nt)
-        .prods()
+        .try_get_rule(nt)
+        .into_iter()
+        .flat_map(|rule| rule.prods())
+        .filter(|p| follow(p))
         .flat_map(|p| p.elements())
         .filter_map(|e| e.as_nonterm())
         .collect::<BTreeSet<_>>()
     })
   }
 
+  /// Returns the sub-grammar rooted at `root`, containing exactly the rules
+  /// reachable from it. Returns `None` if `root` has no rule in this grammar.
+  pub fn sub_grammar(&self, root: &NT) -> Option<Self>
+  where
+    T: Clone,
+    AV: Clone,
+  {
+    self.try_get_rule(root)?;
+    let mut nonterms = self.reachable_from(root);
+    nonterms.insert(root);
+
+    // Every rule reachable from a valid rule is itself valid, so this can't
+    // fail.
+    Grammar::new(
+      root.clone(),
+      nonterms.into_iter().map(|nt| self.rule_set[nt].clone()),
+      None,
+    )
+    .ok()
+  }
+
+  /// Counts the productions that refer to each terminal and nonterminal.
+  ///
+  /// A production that refers to a symbol more than once is only counted
+  /// once. Every rule head is included, even if no production refers to it.
+  pub fn symbol_frequencies(&self) -> (BTreeMap<T, usize>, BTreeMap<NT, usize>)
+  where
+    T: Ord + Clone,
+  {
+    let mut terms = BTreeMap::new();
+    let mut nonterms = self
+      .rule_set
+      .keys()
+      .map(|nt| (nt.clone(), 0))
+      .collect::<BTreeMap<_, _>>();
+    for prod in self.prods() {
+      let elems = prod.elements().collect::<BTreeSet<_>>();
+      for elem in elems {
+        match elem {
+          Elem::Term(t) => *terms.entry(t.clone()).or_insert(0) += 1,
+          Elem::NonTerm(nt) => *nonterms.entry(nt.clone()).or_insert(0) += 1,
+        }
+      }
+    }
+    (terms, nonterms)
+  }
+
+  /// Returns the action keys of every production in the grammar.
+  pub fn action_keys(&self) -> BTreeSet<&AK> {
+    self.prods().map(|prod| prod.action_key()).collect()
+  }
+
+  /// Returns the heads of the rules that cannot be reached from the start
+  /// nonterminal.
   fn unreachable_nonterms(&self) -> BTreeSet<&NT> {
     let reachable_nonterms = self.reachable_nonterms();
     self
-      .get_nonterminals()
-      .filter(|nt| !reachable_nonterms.contains(nt))
+      .rules()
+      .map(|rule| rule.head())
+      .filter(|nt| {
+        *nt != &self.start_symbol && !reachable_nonterms.contains(nt)
+      })
       .collect()
   }
 }
@@ -461,26 +845,121 @@ impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
 where
   T: ToDoc,
   NT: ToDoc,
+  AK: ToDoc,
 {
   pub fn to_pretty(&self) -> String {
     let arena = pretty::Arena::new();
     format!("{}", self.to_doc(&arena).into_doc().pretty(80))
   }
+
+  /// Like `to_pretty`, but with the given layout options.
+  pub fn to_pretty_with_options(&self, options: &DisplayOptions) -> String
+  where
+    NT: Ord + Clone,
+    AK: Ord + Clone,
+  {
+    let arena = pretty::Arena::new();
+    let rules = self.rules_in_order(options.rule_order);
+    let doc = self.to_doc_with_options(
+      &arena,
+      options,
+      rules.iter().map(|rule| *rule.rule),
+      |head| *head == self.start_symbol,
+    );
+    format!("{}", doc.into_doc().pretty(80))
+  }
+
+  fn to_doc_with_options<'a, 'g, DA: pretty::DocAllocator<'a>>(
+    &self,
+    da: &'a DA,
+    options: &DisplayOptions,
+    rules: impl Iterator<Item = &'g RuleInner<T, NT, AK, AV>>,
+    is_start: impl Fn(&NT) -> bool,
+  ) -> pretty::DocBuilder<'a, DA>
+  where
+    T: 'g,
+    NT: 'g,
+    AK: 'g,
+    AV: 'g,
+    DA::Doc: Clone,
+  {
+    let start_entry = da
+      .text("Start =")
+      .group()
+      .append(da.softline())
+      .append(self.start_nt().to_doc(da));
+    let rules_entry = da.text("Rules ").append(
+      da.softline()
+        .append(
+          da.concat(rules.map(|rule| {
+            rule
+              .to_doc_with_options(da, options, is_start(&rule.head))
+              .append(da.text(";"))
+              .append(da.softline())
+          }))
+          .nest(options.indent as isize),
+        )
+        .braces(),
+    );
+
+    da.concat(
+      vec![start_entry, rules_entry]
+        .into_iter()
+        .map(|doc| doc.append(da.text(",")).append(da.softline())),
+    )
+  }
 }
 
 #[derive(Clone, Debug)]
-pub struct GrammarErrors<NT> {
+pub struct GrammarErrors<NT, AK> {
   unreachable_nonterms: BTreeSet<NT>,
   nonterms_without_rules: BTreeSet<NT>,
   rules_without_prods: BTreeSet<NT>,
+  over_long_prods: BTreeSet<ProdKey<NT, AK>>,
+  duplicate_action_keys: BTreeSet<ProdKey<NT, AK>>,
 }
 
-impl<NT> GrammarErrors<NT> {
-  fn into_result(self) -> Result<(), Self> {
-    if self.unreachable_nonterms.is_empty()
+impl<NT, AK> GrammarErrors<NT, AK> {
+  /// Nonterminals with rules that cannot be reached from the start
+  /// nonterminal.
+  pub fn unreachable_nonterms(&self) -> &BTreeSet<NT> {
+    &self.unreachable_nonterms
+  }
+
+  /// Nonterminals that are used in a production, but have no rule.
+  pub fn nonterms_without_rules(&self) -> &BTreeSet<NT> {
+    &self.nonterms_without_rules
+  }
+
+  /// Nonterminals whose rule has no productions.
+  pub fn rules_without_prods(&self) -> &BTreeSet<NT> {
+    &self.rules_without_prods
+  }
+
+  /// Productions with more elements than the maximum production length.
+  /// This is only checked if a maximum was set with
+  /// `GrammarBuilder::set_max_prod_len`.
+  pub fn over_long_prods(&self) -> &BTreeSet<ProdKey<NT, AK>> {
+    &self.over_long_prods
+  }
+
+  /// Action keys that are used by more than one production of the same
+  /// rule, which can happen when `GrammarBuilder::add_rule` is called more
+  /// than once for a nonterminal.
+  pub fn duplicate_action_keys(&self) -> &BTreeSet<ProdKey<NT, AK>> {
+    &self.duplicate_action_keys
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.unreachable_nonterms.is_empty()
       && self.nonterms_without_rules.is_empty()
       && self.rules_without_prods.is_empty()
-    {
+      && self.over_long_prods.is_empty()
+      && self.duplicate_action_keys.is_empty()
+  }
+
+  fn into_result(self) -> Result<(), Self> {
+    if self.is_empty() {
       Ok(())
     } else {
       Err(self)
@@ -488,12 +967,120 @@ impl<NT> GrammarErrors<NT> {
   }
 }
 
+/// Problems with a grammar that do not prevent it from being built.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GrammarWarnings<NT> {
+  empty_language_nonterms: BTreeSet<NT>,
+}
+
+impl<NT> GrammarWarnings<NT> {
+  /// Nonterminals that do not derive any sentence.
+  pub fn empty_language_nonterms(&self) -> &BTreeSet<NT> {
+    &self.empty_language_nonterms
+  }
+
+  /// Returns true if there are no warnings.
+  pub fn is_empty(&self) -> bool {
+    self.empty_language_nonterms.is_empty()
+  }
+}
+
 impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
 where
   NT: Ord + Clone,
   AK: Ord + Clone,
 {
-  fn check_grammar(&self) -> Result<(), GrammarErrors<NT>> {
+  /// Returns the problems with this grammar that are not errors.
+  ///
+  /// These are kept out of `validate` and `build`, whose errors mean the
+  /// grammar is malformed and can't be used. A grammar with warnings is
+  /// still well-formed and every analysis works on it, so making them part
+  /// of validation would reject grammars that are only suspicious. Callers
+  /// that want to treat them as errors can check this after building.
+  pub fn warnings(&self) -> GrammarWarnings<NT> {
+    GrammarWarnings {
+      empty_language_nonterms: self.empty_language_nonterms(),
+    }
+  }
+
+  /// Returns the nonterminals that do not derive any sentence, because every
+  /// one of their productions contains such a nonterminal.
+  pub fn empty_language_nonterms(&self) -> BTreeSet<NT> {
+    let mut productive = BTreeSet::new();
+    change_loop(|| {
+      change_iter(self.prods(), |prod| {
+        let is_productive = prod
+          .elements()
+          .filter_map(Elem::as_nonterm)
+          .all(|nt| productive.contains(nt));
+        WasChanged::from_changed(
+          is_productive && productive.insert(prod.head()),
+        )
+      })
+    });
+
+    self
+      .rules()
+      .map(|rule| rule.head())
+      .filter(|nt| !productive.contains(nt))
+      .cloned()
+      .collect()
+  }
+
+  /// Returns each pair of productions of the same rule where the elements of
+  /// the first are a proper prefix of the elements of the second, along
+  /// with the head of the rule. Empty productions are not reported, since
+  /// they are a prefix of every other production.
+  ///
+  /// This is a purely structural check, which does not look through
+  /// nonterminals.
+  #[allow(clippy::type_complexity)]
+  pub fn prefix_conflicts(&self) -> Vec<(NT, ProdKey<NT, AK>, ProdKey<NT, AK>)>
+  where
+    T: PartialEq,
+  {
+    let mut conflicts = Vec::new();
+    for rule in self.rules() {
+      for prefix in rule.prods().filter(|prod| !prod.is_empty()) {
+        for prod in rule.prods() {
+          let (short, long) = (prefix.prod_elements(), prod.prod_elements());
+          let is_prefix = short.len() < long.len()
+            && short.iter().zip(long).all(|(a, b)| a.elem() == b.elem());
+          if is_prefix {
+            conflicts.push((
+              rule.head().clone(),
+              prefix.prod_key(),
+              prod.prod_key(),
+            ));
+          }
+        }
+      }
+    }
+    conflicts
+  }
+
+  /// Checks that this grammar is well-formed: every nonterminal is
+  /// reachable from the start nonterminal, and has a rule with at least one
+  /// production.
+  ///
+  /// Grammars are validated when they are built, so this should always
+  /// succeed for a constructed grammar. Problems that don't make the grammar
+  /// malformed are reported by `warnings` instead.
+  pub fn validate(&self) -> Result<(), GrammarErrors<NT, AK>> {
+    self.check_grammar(None)
+  }
+
+  fn check_grammar(
+    &self,
+    max_prod_len: Option<usize>,
+  ) -> Result<(), GrammarErrors<NT, AK>> {
+    self.collect_errors(max_prod_len).into_result()
+  }
+
+  fn collect_errors(
+    &self,
+    max_prod_len: Option<usize>,
+  ) -> GrammarErrors<NT, AK> {
     GrammarErrors {
       unreachable_nonterms: self
         .unreachable_nonterms()
@@ -510,8 +1097,22 @@ where
         .into_iter()
         .cloned()
         .collect(),
+      over_long_prods: max_prod_len
+        .map(|max_len| self.over_long_prods(max_len))
+        .unwrap_or_default(),
+      duplicate_action_keys: self.duplicate_action_keys(),
     }
-    .into_result()
+  }
+}
+
+impl<T, NT, AK, AV> std::fmt::Display for Grammar<T, NT, AK, AV>
+where
+  T: ToDoc,
+  NT: ToDoc,
+  AK: ToDoc,
+{
+  fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fmt_doc(self, fmt)
   }
 }
 
@@ -519,6 +1120,7 @@ impl<T, NT, AK, AV> ToDoc for Grammar<T, NT, AK, AV>
 where
   T: ToDoc,
   NT: ToDoc,
+  AK: ToDoc,
 {
   fn to_doc<'a, DA: pretty::DocAllocator<'a>>(
     &self,
@@ -527,26 +1129,11 @@ where
   where
     DA::Doc: Clone,
   {
-    let start_entry = da
-      .text("Start =")
-      .group()
-      .append(da.softline())
-      .append(self.start_nt().to_doc(da));
-    let rules_entry = da.text("Rules ").append(
-      da.softline()
-        .append(
-          da.concat(self.rule_set.iter().map(|rule| {
-            rule.1.to_doc(da).append(da.text(";")).append(da.softline())
-          }))
-          .nest(2),
-        )
-        .braces(),
-    );
-
-    da.concat(
-      vec![start_entry, rules_entry]
-        .into_iter()
-        .map(|doc| doc.append(da.text(",")).append(da.softline())),
+    self.to_doc_with_options(
+      da,
+      &DisplayOptions::default(),
+      self.rule_set.values(),
+      |_| false,
     )
   }
 }
@@ -581,14 +1168,38 @@ impl<'a, T, NT, AK, AV> Rule<'a, T, NT, AK, AV> {
     &self.rule.head
   }
 
+  /// Returns the doc string of this rule, if one was set with
+  /// `RuleBuilder::set_doc`.
+  pub fn doc(&self) -> Option<&'a str> {
+    let rule = *self.rule;
+    rule.doc.as_deref()
+  }
+
+  /// Returns true if this rule was generated by `GrammarBuilder` for an
+  /// inline alternation, rather than added by the user.
+  pub fn is_generated(&self) -> bool {
+    self.rule.generated
+  }
+
   /// Returns an iterator over the productions of this rule.
-  pub fn prods(&self) -> impl Iterator<Item = Prod<'a, T, NT, AK, AV>> {
-    let prods = &self.rule.prods;
-    prods.iter().map({
-      let grammar = *self.grammar;
-      let head = &self.rule.head;
-      move |prod| Prod::new(grammar, head, prod)
-    })
+  pub fn prods(&self) -> Prods<'a, T, NT, AK, AV> {
+    let rule = *self.rule;
+    Prods {
+      grammar: *self.grammar,
+      head: &rule.head,
+      prods: rule.prods.iter(),
+    }
+  }
+
+  /// Returns an iterator over the productions of this rule. This is the
+  /// same as `prods`.
+  pub fn iter(&self) -> Prods<'a, T, NT, AK, AV> {
+    self.prods()
+  }
+
+  /// Returns the first empty production of this rule, if there is one.
+  pub fn empty_production(&self) -> Option<Prod<'a, T, NT, AK, AV>> {
+    self.prods().find(|prod| prod.is_empty())
   }
 }
 
@@ -608,6 +1219,80 @@ where
   }
 }
 
+impl<'a, T, NT, AK, AV> IntoIterator for Rule<'a, T, NT, AK, AV> {
+  type Item = Prod<'a, T, NT, AK, AV>;
+  type IntoIter = Prods<'a, T, NT, AK, AV>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.prods()
+  }
+}
+
+impl<'a, T, NT, AK, AV> IntoIterator for &Rule<'a, T, NT, AK, AV> {
+  type Item = Prod<'a, T, NT, AK, AV>;
+  type IntoIter = Prods<'a, T, NT, AK, AV>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.prods()
+  }
+}
+
+/// An iterator over the rules of a grammar, in order of their heads.
+pub struct Rules<'a, T, NT, AK, AV> {
+  grammar: &'a Grammar<T, NT, AK, AV>,
+  rules: std::collections::btree_map::Values<'a, NT, RuleInner<T, NT, AK, AV>>,
+}
+
+impl<'a, T, NT, AK, AV> Iterator for Rules<'a, T, NT, AK, AV> {
+  type Item = Rule<'a, T, NT, AK, AV>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let rule = self.rules.next()?;
+    Some(Rule {
+      grammar: ParentRef::new(self.grammar),
+      rule: RefCompare::new(rule),
+    })
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.rules.size_hint()
+  }
+}
+
+impl<'a, T, NT, AK, AV> ExactSizeIterator for Rules<'a, T, NT, AK, AV> {}
+
+impl<'a, T, NT, AK, AV> IntoIterator for &'a Grammar<T, NT, AK, AV> {
+  type Item = Rule<'a, T, NT, AK, AV>;
+  type IntoIter = Rules<'a, T, NT, AK, AV>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.rules()
+  }
+}
+
+/// An iterator over the productions of a rule, in the order they were
+/// added.
+pub struct Prods<'a, T, NT, AK, AV> {
+  grammar: &'a Grammar<T, NT, AK, AV>,
+  head: &'a NT,
+  prods: std::slice::Iter<'a, ProdInner<T, NT, AK, AV>>,
+}
+
+impl<'a, T, NT, AK, AV> Iterator for Prods<'a, T, NT, AK, AV> {
+  type Item = Prod<'a, T, NT, AK, AV>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let prod = self.prods.next()?;
+    Some(Prod::new(self.grammar, self.head, prod))
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.prods.size_hint()
+  }
+}
+
+impl<'a, T, NT, AK, AV> ExactSizeIterator for Prods<'a, T, NT, AK, AV> {}
+
 impl<'a, T, NT, AK, AV> std::fmt::Debug for Rule<'a, T, NT, AK, AV>
 where
   T: Debug,
@@ -621,6 +1306,18 @@ where
   }
 }
 
+/// Displays the rule in the same layout as `Grammar::to_pretty`.
+impl<'a, T, NT, AK, AV> std::fmt::Display for Rule<'a, T, NT, AK, AV>
+where
+  T: ToDoc,
+  NT: ToDoc,
+  AK: ToDoc,
+{
+  fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fmt_doc(*self.rule, fmt)
+  }
+}
+
 // ------------
 
 /// A single production in a grammar.
@@ -632,21 +1329,26 @@ where
 pub struct Prod<'a, T, NT, AK, AV> {
   grammar: ParentRef<'a, Grammar<T, NT, AK, AV>>,
   head: &'a NT,
-  prod: RefCompare<'a, ProdInner<T, NT, AK, AV>>,
+  prod: &'a ProdInner<T, NT, AK, AV>,
 }
 
+/// Productions are ordered by their head, then their action key. These
+/// identify a production within a grammar, so a production compares equal
+/// to the same production in a clone of its grammar.
 impl<'a, T, NT, AK, AV> Ord for Prod<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-    self.prod.cmp(&other.prod)
+    (self.head, self.action_key()).cmp(&(other.head, other.action_key()))
   }
 }
 
 impl<'a, T, NT, AK, AV> PartialOrd for Prod<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
     Some(self.cmp(other))
@@ -656,13 +1358,19 @@ where
 impl<'a, T, NT, AK, AV> PartialEq for Prod<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn eq(&self, other: &Self) -> bool {
-    self.prod == other.prod
+    self.cmp(other) == std::cmp::Ordering::Equal
   }
 }
 
-impl<'a, T, NT, AK, AV> Eq for Prod<'a, T, NT, AK, AV> where NT: Ord {}
+impl<'a, T, NT, AK, AV> Eq for Prod<'a, T, NT, AK, AV>
+where
+  NT: Ord,
+  AK: Ord,
+{
+}
 
 impl<'a, T, NT, AK, AV> Copy for Prod<'a, T, NT, AK, AV> {}
 
@@ -675,7 +1383,7 @@ impl<'a, T, NT, AK, AV> Prod<'a, T, NT, AK, AV> {
     Prod {
       grammar: ParentRef::new(grammar),
       head,
-      prod: RefCompare::new(prod),
+      prod,
     }
   }
 
@@ -698,10 +1406,52 @@ impl<'a, T, NT, AK, AV> Prod<'a, T, NT, AK, AV> {
     self.prod.elements.len()
   }
 
+  /// Returns true if this production has no elements.
+  pub fn is_empty(&self) -> bool {
+    self.prod.is_empty()
+  }
+
   /// Returns the action key of this production.
   pub fn action_key(&self) -> &'a AK {
     self.prod.action_key()
   }
+
+  /// Returns a key that orders productions by head, action key, and then
+  /// elements. Unlike the order of `Prod` itself, this distinguishes
+  /// productions of a lenient grammar that share an action key.
+  pub fn sort_key(&self) -> (&'a NT, &'a AK, Vec<&'a Elem<T, NT>>) {
+    (self.head, self.action_key(), self.elements().collect())
+  }
+
+  /// Returns the first element of this production named `id`, if any.
+  pub fn element_by_id(&self, id: &Name) -> Option<&'a Elem<T, NT>> {
+    self.elements_by_id(id).next()
+  }
+
+  /// Returns the elements of this production named `id`, in order.
+  pub fn elements_by_id<'i>(
+    &self,
+    id: &'i Name,
+  ) -> impl Iterator<Item = &'a Elem<T, NT>> + 'i
+  where
+    'a: 'i,
+  {
+    self
+      .prod
+      .elements
+      .iter()
+      .filter(move |prod_elem| prod_elem.id() == Some(id))
+      .map(|prod_elem| prod_elem.elem())
+  }
+
+  /// Returns the `Debug` representation of the action value of this
+  /// production, so tools can display it without knowing its type.
+  pub fn action_value_debug(&self) -> String
+  where
+    AV: Debug,
+  {
+    format!("{:?}", self.prod.action_value)
+  }
 }
 
 impl<'a, T, NT, AK, AV> Prod<'a, T, NT, AK, AV>
@@ -722,6 +1472,15 @@ where
     self.prod.element_at(index)
   }
 
+  /// Returns a copy of the elements of this production, without any
+  /// identifiers.
+  pub fn symbols(&self) -> Vec<Elem<T, NT>>
+  where
+    T: Clone,
+  {
+    self.elements().cloned().collect()
+  }
+
   /// Returns the action value of this production.
   pub fn action_value(&self) -> &'a AV {
     &self.prod.action_value
@@ -744,11 +1503,7 @@ where
 
 impl<'a, T, NT, AK, AV> Clone for Prod<'a, T, NT, AK, AV> {
   fn clone(&self) -> Self {
-    Prod {
-      grammar: self.grammar,
-      head: self.head,
-      prod: self.prod,
-    }
+    *self
   }
 }
 
@@ -764,3 +1519,567 @@ where
     dbg_struct.finish()
   }
 }
+
+/// Displays the production as `head ::= body => action_key`.
+impl<'a, T, NT, AK, AV> std::fmt::Display for Prod<'a, T, NT, AK, AV>
+where
+  T: ToDoc,
+  NT: ToDoc,
+  AK: ToDoc,
+{
+  fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(fmt, "{} ::= ", DocDisplay(self.head))?;
+    fmt_doc(self.prod, fmt)?;
+    write!(fmt, " => {}", DocDisplay(self.action_key()))
+  }
+}
+
+/// Adapts a `ToDoc` value to `Display`.
+struct DocDisplay<'a, D>(&'a D);
+
+impl<'a, D: ToDoc> std::fmt::Display for DocDisplay<'a, D> {
+  fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fmt_doc(self.0, fmt)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    grammar::{
+      build, build_lenient, examples, gen::bounded_sentences, DisplayOptions,
+      Elem, Grammar, NonTerminal, ProdKey, RuleOrder, Terminal,
+    },
+    utils::Name,
+  };
+
+  #[test]
+  fn test_sub_grammar() {
+    let g = examples::make_paren();
+    let expr = NonTerminal::new("expr");
+    let expr_list = NonTerminal::new("expr_list");
+
+    let expr_g = g.sub_grammar(&expr).unwrap();
+    assert_eq!(expr_g.start_nt(), &expr);
+    assert_eq!(
+      expr_g.rule_set().into_keys().cloned().collect::<Vec<_>>(),
+      vec![expr.clone(), expr_list.clone()]
+    );
+    assert_eq!(expr_g.bounded_equivalent(&g, 6), Ok(()));
+
+    // Lists of expressions are only accepted by the slice at `expr_list`.
+    let list_g = g.sub_grammar(&expr_list).unwrap();
+    let pair = ["LPAREN", "RPAREN", "LPAREN", "RPAREN"]
+      .iter()
+      .map(|name| Terminal::new(name))
+      .collect::<Vec<_>>();
+    assert!(bounded_sentences(&list_g, 4).contains(&pair));
+    assert!(!bounded_sentences(&g, 4).contains(&pair));
+
+    assert!(g.sub_grammar(&NonTerminal::new("missing")).is_none());
+  }
+
+  #[test]
+  fn test_sub_grammar_rejects_outer_input() {
+    use crate::{
+      parsers::{earley, tree::TreeOwner, Token},
+      start_grammar::wrap_grammar_with_start,
+    };
+
+    // A statement is an expression followed by a semicolon.
+    let g = examples::from_rules(&[
+      ("stmt", &[&["expr", "SEMI"]]),
+      ("expr", &[&["LPAREN", "expr_list", "RPAREN"]]),
+      ("expr_list", &[&[], &["expr", "expr_list"]]),
+    ]);
+    let expr_g = g.sub_grammar(&NonTerminal::new("expr")).unwrap();
+
+    let g = wrap_grammar_with_start(g).unwrap();
+    let expr_g = wrap_grammar_with_start(expr_g).unwrap();
+    let tree: TreeOwner<_, _, ()> = TreeOwner::new();
+    let tokens = |names: &[&str]| {
+      names
+        .iter()
+        .map(|name| Token::new(Terminal::new(name), ()))
+        .collect::<Vec<_>>()
+    };
+    let expr = ["LPAREN", "LPAREN", "RPAREN", "RPAREN"];
+    let stmt = ["LPAREN", "LPAREN", "RPAREN", "RPAREN", "SEMI"];
+
+    assert!(earley::parse(&expr_g, &tree.handle(), tokens(&expr)).is_some());
+    assert!(earley::parse(&expr_g, &tree.handle(), tokens(&stmt)).is_none());
+    assert!(earley::parse(&g, &tree.handle(), tokens(&stmt)).is_some());
+    assert!(earley::parse(&g, &tree.handle(), tokens(&expr)).is_none());
+  }
+
+  #[test]
+  fn test_prods_carry_action_values() {
+    // Action values are stored alongside each production rather than in a
+    // separate map, so iterating productions never needs a lookup that could
+    // fail.
+    let s = NonTerminal::new("s");
+    let g: Grammar<Terminal, NonTerminal, Name, usize> = build(&s, |gb| {
+      gb.add_rule(&s, |rb| {
+        rb.add_prod("a", 1, |pb| {
+          pb.add_term(Terminal::new("A"));
+        })
+        .add_prod("b", 2, |_| {});
+      });
+    })
+    .unwrap();
+
+    let values = g
+      .get_rule(&s)
+      .prods()
+      .map(|prod| (prod.action_key().str().to_string(), *prod.action_value()))
+      .collect::<Vec<_>>();
+    assert_eq!(values, vec![("a".to_string(), 1), ("b".to_string(), 2)]);
+  }
+
+  #[test]
+  fn test_empty_language_nonterms() {
+    let g = examples::from_rules(&[
+      ("s", &[&["a"], &["b"]]),
+      ("a", &[&["a", "A"]]),
+      ("b", &[&["B", "b"], &["B"]]),
+    ]);
+    let expected = std::iter::once(NonTerminal::new("a")).collect();
+    assert_eq!(g.empty_language_nonterms(), expected);
+    assert_eq!(g.warnings().empty_language_nonterms(), &expected);
+
+    assert!(examples::make_paren().warnings().is_empty());
+  }
+
+  #[test]
+  fn test_aligned_rule_layout() {
+    let g = examples::from_rules(&[(
+      "s",
+      &[&["A", "s"], &["LONG_TERMINAL", "B"], &[]],
+    )]);
+    let expected = [
+      "Start = s, Rules { s ::=",
+      "    | A <s>           => p0",
+      "    | LONG_TERMINAL B => p1",
+      "    | ε               => p2; }, ",
+    ];
+    assert_eq!(g.to_pretty(), expected.join("\n"));
+  }
+
+  #[test]
+  fn test_display() {
+    let g = examples::from_rules(&[("s", &[&["A", "s"], &[]])]);
+    assert_eq!(format!("{}", Terminal::new("A")), "A");
+    assert_eq!(format!("{}", NonTerminal::new("s")), "s");
+    assert_eq!(
+      format!(
+        "{}",
+        super::Elem::<Terminal, _>::NonTerm(NonTerminal::new("s"))
+      ),
+      "<s>"
+    );
+
+    let rule = g.get_rule(&NonTerminal::new("s"));
+    let prods = rule.prods().map(|p| p.to_string()).collect::<Vec<_>>();
+    assert_eq!(prods, vec!["s ::= A <s> => p0", "s ::= ε => p1"]);
+    assert_eq!(rule.to_string(), "s ::=\n  | A <s> => p0\n  | ε     => p1");
+    assert_eq!(g.to_string(), g.to_pretty());
+  }
+
+  #[test]
+  fn test_symbol_frequencies() {
+    let (terms, nonterms) = examples::make_paren().symbol_frequencies();
+    assert_eq!(
+      terms,
+      vec![(Terminal::new("LPAREN"), 1), (Terminal::new("RPAREN"), 1)]
+        .into_iter()
+        .collect()
+    );
+    assert_eq!(
+      nonterms,
+      vec![
+        (NonTerminal::new("expr"), 2),
+        (NonTerminal::new("expr_list"), 2),
+        (NonTerminal::new("start"), 0),
+      ]
+      .into_iter()
+      .collect()
+    );
+
+    // Repeated references within one production count once.
+    let g = examples::from_rules(&[("s", &[&["A", "s", "A", "s"], &["A"]])]);
+    let (terms, nonterms) = g.symbol_frequencies();
+    assert_eq!(terms[&Terminal::new("A")], 2);
+    assert_eq!(nonterms[&NonTerminal::new("s")], 1);
+  }
+
+  #[test]
+  fn test_action_keys() {
+    let g = examples::make_paren();
+    let keys = ["start", "paren_expr", "empty", "elem"]
+      .iter()
+      .map(Name::new)
+      .collect::<std::collections::BTreeSet<_>>();
+    assert_eq!(g.action_keys(), keys.iter().collect());
+  }
+
+  #[test]
+  fn test_prod_order_across_clones() {
+    let g = examples::make_paren();
+    let cloned = g.clone();
+    let prods = g.prods().collect::<Vec<_>>();
+    let cloned_prods = cloned.prods().collect::<Vec<_>>();
+    assert_eq!(prods, cloned_prods);
+
+    // Productions sort by head, then by action key.
+    let mut sorted = prods.clone();
+    sorted.sort();
+    let mut keys = prods.iter().map(|p| p.prod_key()).collect::<Vec<_>>();
+    keys.sort();
+    assert_eq!(
+      sorted.iter().map(|p| p.prod_key()).collect::<Vec<_>>(),
+      keys
+    );
+    assert!(prods[0] < cloned_prods[1]);
+  }
+
+  #[test]
+  fn test_symbol_membership() {
+    let g = examples::make_paren();
+    let start = NonTerminal::new("start");
+    let expr = NonTerminal::new("expr");
+    let missing = NonTerminal::new("missing");
+
+    assert!(g.defines_nonterminal(&start));
+    assert!(g.defines_nonterminal(&expr));
+    assert!(!g.defines_nonterminal(&missing));
+
+    assert!(g.uses_terminal(&Terminal::new("LPAREN")));
+    assert!(!g.uses_terminal(&Terminal::new("ATOM")));
+
+    assert!(g.uses_nonterminal(&expr));
+    assert!(g.uses_nonterminal(&NonTerminal::new("expr_list")));
+    assert!(!g.uses_nonterminal(&start));
+    assert!(!g.uses_nonterminal(&missing));
+  }
+
+  #[test]
+  fn test_content_hash() {
+    let g = examples::from_rules(&[
+      ("expr", &[&["expr", "PLUS", "term"], &["term"]]),
+      ("term", &[&["NUM"]]),
+    ]);
+    let build_expr = |first: &str, second: &str| {
+      build(NonTerminal::new("expr"), |gb| {
+        gb.add_rule(NonTerminal::new("expr"), |rb| {
+          for key in &[first, second] {
+            rb.add_prod(*key, (), |pb| match *key {
+              "p0" => {
+                pb.add_nonterm(NonTerminal::new("expr"))
+                  .add_term(Terminal::new("PLUS"))
+                  .add_nonterm(NonTerminal::new("term"));
+              }
+              _ => {
+                pb.add_nonterm(NonTerminal::new("term"));
+              }
+            });
+          }
+        })
+        .add_rule(NonTerminal::new("term"), |rb| {
+          rb.add_prod("p0", (), |pb| {
+            pb.add_term(Terminal::new("NUM"));
+          });
+        });
+      })
+      .unwrap()
+    };
+
+    let reordered: Grammar<Terminal, NonTerminal, Name, ()> =
+      build_expr("p1", "p0");
+    assert_eq!(g, reordered);
+    assert_eq!(g.content_hash(), reordered.content_hash());
+    assert_eq!(g.content_hash(), build_expr("p0", "p1").content_hash());
+
+    let changed = examples::from_rules(&[
+      ("expr", &[&["expr", "TIMES", "term"], &["term"]]),
+      ("term", &[&["NUM"]]),
+    ]);
+    assert_ne!(g, changed);
+    assert_ne!(g.content_hash(), changed.content_hash());
+  }
+
+  #[test]
+  fn test_prefix_conflicts() {
+    let g = examples::from_rules(&[
+      ("s", &[&["a", "B"], &["a", "B", "C"], &["a", "C"], &[]]),
+      ("a", &[&["A"], &["B", "A"]]),
+    ]);
+    let key =
+      |head, action| ProdKey::new(NonTerminal::new(head), Name::new(action));
+    assert_eq!(
+      g.prefix_conflicts(),
+      vec![(NonTerminal::new("s"), key("s", "p0"), key("s", "p1"))]
+    );
+    assert!(examples::make_arithmetic().prefix_conflicts().is_empty());
+  }
+
+  #[test]
+  fn test_rule_doc() {
+    let g: Grammar<Terminal, NonTerminal, Name, ()> =
+      build(NonTerminal::new("s"), |gb| {
+        gb.add_rule(NonTerminal::new("s"), |rb| {
+          rb.set_doc("A list of `A`s.\n\nMay be empty.")
+            .add_prod("more", (), |pb| {
+              pb.add_term(Terminal::new("A"))
+                .add_nonterm(NonTerminal::new("s"));
+            })
+            .add_prod("done", (), |_| {});
+        });
+      })
+      .unwrap();
+
+    let rule = g.get_rule(&NonTerminal::new("s"));
+    assert_eq!(rule.doc(), Some("A list of `A`s.\n\nMay be empty."));
+    assert_eq!(
+      rule.to_string(),
+      "// A list of `A`s.\n//\n// May be empty.\ns ::=\n  | A <s> => more\n  | ε     => done"
+    );
+    assert!(g.to_pretty().contains("// A list of `A`s."));
+    assert_eq!(
+      examples::make_paren()
+        .get_rule(&NonTerminal::new("expr"))
+        .doc(),
+      None
+    );
+  }
+
+  #[test]
+  fn test_into_iterator() {
+    let g = examples::make_paren();
+    let rule = g.get_rule(&NonTerminal::new("expr_list"));
+
+    let mut count = 0;
+    for prod in &rule {
+      assert_eq!(prod.head(), &NonTerminal::new("expr_list"));
+      count += 1;
+    }
+    assert_eq!(count, 2);
+    assert_eq!(rule.iter().count(), rule.prods().count());
+    assert_eq!(
+      rule.into_iter().collect::<Vec<_>>(),
+      rule.prods().collect::<Vec<_>>()
+    );
+
+    let heads = (&g).into_iter().map(|rule| rule.head()).collect::<Vec<_>>();
+    assert_eq!(heads, g.rules().map(|rule| rule.head()).collect::<Vec<_>>());
+    let mut prods = 0;
+    for rule in &g {
+      prods += rule.iter().len();
+    }
+    assert_eq!(prods, g.prods().count());
+  }
+
+  #[test]
+  fn test_action_value_debug() {
+    let value = "say \"hi\"".to_string();
+    let g: Grammar<Terminal, NonTerminal, Name, String> =
+      build(NonTerminal::new("s"), |gb| {
+        gb.add_rule(NonTerminal::new("s"), |rb| {
+          rb.add_prod("quoted", value.clone(), |pb| {
+            pb.add_term(Terminal::new("A"));
+          });
+        });
+      })
+      .unwrap();
+
+    let prod = g.prods().next().unwrap();
+    assert_eq!(prod.action_value_debug(), format!("{:?}", value));
+    assert_eq!(prod.action_value_debug(), r#""say \"hi\"""#);
+  }
+
+  #[test]
+  fn test_symbols() {
+    let g = examples::make_paren();
+    let prod = g
+      .get_rule(&NonTerminal::new("expr"))
+      .prods()
+      .next()
+      .unwrap();
+    assert_eq!(
+      prod.symbols(),
+      vec![
+        Elem::Term(Terminal::new("LPAREN")),
+        Elem::NonTerm(NonTerminal::new("expr_list")),
+        Elem::Term(Terminal::new("RPAREN")),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_display_options() {
+    let g = examples::make_paren();
+    assert_eq!(
+      g.to_pretty_with_options(&DisplayOptions::default()),
+      g.to_pretty()
+    );
+
+    let options = DisplayOptions {
+      start_marker: Some("→".to_string()),
+      show_action_keys: false,
+      indent: 4,
+      ..DisplayOptions::default()
+    };
+    let pretty = g.to_pretty_with_options(&options);
+    assert!(pretty.contains("; → start ::=\n        | expr:<expr>;"));
+    assert!(pretty.contains("expr_list ::=\n        | ε\n"));
+    assert!(!pretty.contains("=>"));
+    assert_eq!(pretty.matches('→').count(), 1);
+  }
+
+  #[test]
+  fn test_element_by_id() {
+    let g: Grammar<Terminal, NonTerminal, Name, ()> =
+      build(NonTerminal::new("pair"), |gb| {
+        gb.add_rule(NonTerminal::new("pair"), |rb| {
+          rb.add_prod("pair", (), |pb| {
+            pb.add_named_term("open", Terminal::new("LPAREN"))
+              .add_named_nonterm("value", NonTerminal::new("item"))
+              .add_term(Terminal::new("COMMA"))
+              .add_named_nonterm("value", NonTerminal::new("pair"));
+          })
+          .add_prod("item", (), |pb| {
+            pb.add_named_nonterm("value", NonTerminal::new("item"));
+          });
+        })
+        .add_rule(NonTerminal::new("item"), |rb| {
+          rb.add_prod("item", (), |pb| {
+            pb.add_term(Terminal::new("ITEM"));
+          });
+        });
+      })
+      .unwrap();
+
+    let prod = g
+      .get_rule(&NonTerminal::new("pair"))
+      .prods()
+      .next()
+      .unwrap();
+    let value = Name::new("value");
+    assert_eq!(
+      prod.element_by_id(&value),
+      Some(&Elem::NonTerm(NonTerminal::new("item")))
+    );
+    assert_eq!(
+      prod.element_by_id(&Name::new("open")),
+      Some(&Elem::Term(Terminal::new("LPAREN")))
+    );
+    assert_eq!(prod.element_by_id(&Name::new("missing")), None);
+    assert_eq!(
+      prod.elements_by_id(&value).collect::<Vec<_>>(),
+      vec![
+        &Elem::NonTerm(NonTerminal::new("item")),
+        &Elem::NonTerm(NonTerminal::new("pair")),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_deterministic_prod_order() {
+    // The same grammar, with rules and productions added in different
+    // orders, and two productions of `x` sharing an action key.
+    let build = |reversed: bool| {
+      let (g, errors) = build_lenient(NonTerminal::new("s"), |gb| {
+        let mut rules: Vec<(&str, Vec<(&str, &str)>)> = vec![
+          ("s", vec![("s", "x")]),
+          ("x", vec![("dup", "A"), ("dup", "B"), ("other", "C")]),
+        ];
+        if reversed {
+          rules.reverse();
+          rules.iter_mut().for_each(|(_, prods)| prods.reverse());
+        }
+        for (head, prods) in rules {
+          gb.add_rule(NonTerminal::new(head), |rb| {
+            for (key, sym) in prods {
+              rb.add_prod(Name::new(key), (), |pb| {
+                if sym.starts_with(|c: char| c.is_ascii_uppercase()) {
+                  pb.add_term(Terminal::new(sym));
+                } else {
+                  pb.add_nonterm(NonTerminal::new(sym));
+                }
+              });
+            }
+          });
+        }
+      });
+      assert_eq!(errors.duplicate_action_keys().len(), 1);
+      g
+    };
+    let (g1, g2) = (build(false), build(true));
+
+    let keys = |g: &Grammar<Terminal, NonTerminal, Name, ()>| {
+      let map = g
+        .prods()
+        .map(|prod| (prod.prod_key(), ()))
+        .collect::<std::collections::BTreeMap<_, _>>();
+      map.into_keys().collect::<Vec<_>>()
+    };
+    assert_eq!(keys(&g1), keys(&g2));
+
+    type SortKey = (NonTerminal, Name, Vec<Elem<Terminal, NonTerminal>>);
+    let sort_keys = |g: &Grammar<Terminal, NonTerminal, Name, ()>| {
+      let mut keys = g
+        .prods()
+        .map(|prod| {
+          let (head, key, elems) = prod.sort_key();
+          (
+            head.clone(),
+            key.clone(),
+            elems.into_iter().cloned().collect(),
+          )
+        })
+        .collect::<Vec<SortKey>>();
+      keys.sort();
+      keys
+    };
+    let sorted = sort_keys(&g1);
+    assert_eq!(sorted, sort_keys(&g2));
+    assert_eq!(
+      sorted
+        .iter()
+        .map(|(head, key, elems)| {
+          let elems = elems.iter().map(|elem| match elem {
+            Elem::Term(t) => t.to_string(),
+            Elem::NonTerm(nt) => nt.to_string(),
+          });
+          format!("{} {} {}", head, key, elems.collect::<Vec<_>>().join(" "))
+        })
+        .collect::<Vec<_>>(),
+      vec!["s s x", "x dup A", "x dup B", "x other C",]
+    );
+  }
+
+  #[test]
+  fn test_rule_order() {
+    let g = examples::from_rules(&[
+      ("s", &[&["b", "a"]]),
+      ("a", &[&["A"]]),
+      ("b", &[&["c"]]),
+      ("c", &[&["C"]]),
+    ]);
+    let heads = |order: RuleOrder| {
+      g.rules_in_order(order)
+        .iter()
+        .map(|rule| rule.head().name().str().to_string())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(heads(RuleOrder::Alphabetical), vec!["a", "b", "c", "s"]);
+    assert_eq!(heads(RuleOrder::StartFirst), vec!["s", "a", "b", "c"]);
+    assert_eq!(heads(RuleOrder::BreadthFirst), vec!["s", "b", "a", "c"]);
+
+    let options = DisplayOptions {
+      rule_order: RuleOrder::StartFirst,
+      ..DisplayOptions::default()
+    };
+    let pretty = g.to_pretty_with_options(&options);
+    assert!(pretty.starts_with("Start = s, Rules { s ::="));
+  }
+}
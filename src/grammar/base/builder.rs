@@ -12,8 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{
-  Elem, Grammar, GrammarErrors, Name, ProdElement, ProdInner, RuleInner,
+use {
+  super::{
+    Elem, Grammar, GrammarErrors, Name, NonTerminal, ProdElement, ProdInner,
+    ProdKey, RuleInner, Terminal,
+  },
+  crate::{
+    grammar::transform::names::{
+      fresh_nonterm, AuxKind, DefaultNameGen, NameGen,
+    },
+    utils::{doc_string, ToDoc},
+  },
+  std::{
+    collections::{BTreeSet, VecDeque},
+    fmt::Debug,
+  },
 };
 
 /// A helper trait to allow builder methods to either take a type `T`, or a
@@ -29,7 +42,7 @@ impl<T> BuilderInto<T> for T {
   }
 }
 
-impl<'a, T> BuilderInto<T> for &'a T
+impl<T> BuilderInto<T> for &T
 where
   T: Clone,
 {
@@ -48,6 +61,7 @@ pub struct ProductionBuilder<T, NT, AK, AV> {
   action_key: AK,
   action_value: AV,
   elems: Vec<ProdElement<T, NT>>,
+  alternations: Vec<Alternation<T, NT, AK, AV>>,
 }
 
 impl<T, NT, AK, AV> ProductionBuilder<T, NT, AK, AV> {
@@ -56,16 +70,27 @@ impl<T, NT, AK, AV> ProductionBuilder<T, NT, AK, AV> {
       action_key,
       action_value,
       elems: Vec::new(),
+      alternations: Vec::new(),
     }
   }
 
-  fn build(self) -> ProdInner<T, NT, AK, AV> {
+  /// Builds the production, without the elements that refer to its inline
+  /// alternations. Those are inserted by `GrammarBuilder`, once the
+  /// auxiliary nonterminals are named.
+  #[allow(clippy::type_complexity)]
+  fn build(
+    self,
+  ) -> (ProdInner<T, NT, AK, AV>, Vec<Alternation<T, NT, AK, AV>>) {
     let ProductionBuilder {
       action_key,
       action_value,
       elems,
+      alternations,
     } = self;
-    ProdInner::new(action_key, action_value, elems)
+    (
+      ProdInner::new(action_key, action_value, elems),
+      alternations,
+    )
   }
 
   pub fn add_term(&mut self, term: impl BuilderInto<T>) -> &mut Self {
@@ -107,11 +132,102 @@ impl<T, NT, AK, AV> ProductionBuilder<T, NT, AK, AV> {
   }
 }
 
+impl<T, AV> ProductionBuilder<T, NonTerminal, Name, AV>
+where
+  AV: Default,
+{
+  /// Adds a reference to a new auxiliary nonterminal, which has one
+  /// production for each alternative added by `build_fn`.
+  ///
+  /// This allows writing `a (b | c) d` inline. The auxiliary nonterminal is
+  /// named by `DefaultNameGen` for `AuxKind::Alt` of the head of the
+  /// production's rule, as in `expr__alt__1`, with the first number that is
+  /// not otherwise used in the grammar. Its productions have the action keys
+  /// `alt0`, `alt1`, ..., and default action values.
+  pub fn add_alternation(
+    &mut self,
+    build_fn: impl FnOnce(&mut AlternationBuilder<T, NonTerminal, Name, AV>),
+  ) -> &mut Self {
+    let mut builder = AlternationBuilder { alts: Vec::new() };
+    build_fn(&mut builder);
+    self.alternations.push(Alternation {
+      index: self.elems.len() + self.alternations.len(),
+      alts: builder.alts,
+      names: |kind, attempt| DefaultNameGen.name(kind, attempt),
+    });
+    self
+  }
+}
+
+/// An inline alternation within a production.
+struct Alternation<T, NT, AK, AV> {
+  /// The index of the element that refers to the auxiliary nonterminal.
+  index: usize,
+  alts: Vec<ProductionBuilder<T, NT, AK, AV>>,
+  /// Names the auxiliary nonterminal. It is only asked for `AuxKind::Alt`
+  /// names, so the terminal type doesn't matter.
+  names: fn(AuxKind<'_, Terminal, NT>, usize) -> NT,
+}
+
+impl<T, NT, AK, AV> Alternation<T, NT, AK, AV>
+where
+  NT: Ord + Clone,
+{
+  /// Adds every nonterminal referred to by the alternatives to `used`.
+  fn collect_nonterms(&self, used: &mut BTreeSet<NT>) {
+    for alt in &self.alts {
+      used.extend(
+        alt
+          .elems
+          .iter()
+          .filter_map(|e| e.elem().as_nonterm())
+          .cloned(),
+      );
+      for alternation in &alt.alternations {
+        alternation.collect_nonterms(used);
+      }
+    }
+  }
+}
+
+pub struct AlternationBuilder<T, NT, AK, AV> {
+  alts: Vec<ProductionBuilder<T, NT, AK, AV>>,
+}
+
+impl<T, AV> AlternationBuilder<T, NonTerminal, Name, AV>
+where
+  AV: Default,
+{
+  /// Adds an alternative, with the elements added by `build_fn`.
+  pub fn alt(
+    &mut self,
+    build_fn: impl FnOnce(&mut ProductionBuilder<T, NonTerminal, Name, AV>),
+  ) -> &mut Self {
+    let action_key = Name::new(&format!("alt{}", self.alts.len()));
+    let mut builder = ProductionBuilder::new(action_key, AV::default());
+    build_fn(&mut builder);
+    self.alts.push(builder);
+    self
+  }
+}
+
+/// The inline alternations of a production, identified by the indexes of its
+/// rule and of the production within the rule.
+type PendingAlternations<T, NT, AK, AV> =
+  (usize, usize, Vec<Alternation<T, NT, AK, AV>>);
+
+/// The inline alternations of a production, identified by the index of the
+/// production within its rule.
+type ProdAlternations<T, NT, AK, AV> = (usize, Vec<Alternation<T, NT, AK, AV>>);
+
 // ----------------
 
 pub struct RuleBuilder<T, NT, AK, AV> {
   head: NT,
   prods: Vec<ProdInner<T, NT, AK, AV>>,
+  /// The inline alternations of each production, by production index.
+  alternations: Vec<ProdAlternations<T, NT, AK, AV>>,
+  doc: Option<String>,
 }
 
 impl<T, NT, AK, AV> RuleBuilder<T, NT, AK, AV>
@@ -122,12 +238,32 @@ where
     RuleBuilder {
       head,
       prods: Vec::new(),
+      alternations: Vec::new(),
+      doc: None,
     }
   }
 
-  fn build(self) -> RuleInner<T, NT, AK, AV> {
-    let RuleBuilder { head, prods, .. } = self;
-    RuleInner::new(head, prods)
+  #[allow(clippy::type_complexity)]
+  fn build(
+    self,
+  ) -> (
+    RuleInner<T, NT, AK, AV>,
+    Vec<ProdAlternations<T, NT, AK, AV>>,
+  ) {
+    let RuleBuilder {
+      head,
+      prods,
+      alternations,
+      doc,
+    } = self;
+    (RuleInner::new(head, prods).with_doc(doc), alternations)
+  }
+
+  /// Attaches a free-text doc string to the rule, which is shown as a comment
+  /// when the grammar is displayed.
+  pub fn set_doc(&mut self, doc: impl Into<String>) -> &mut Self {
+    self.doc = Some(doc.into());
+    self
   }
 
   pub fn add_prod(
@@ -140,7 +276,11 @@ where
     let action_value = action_value.builder_into();
     let mut builder = ProductionBuilder::new(action_key, action_value);
     build_fn(&mut builder);
-    self.prods.push(builder.build());
+    let (prod, alternations) = builder.build();
+    if !alternations.is_empty() {
+      self.alternations.push((self.prods.len(), alternations));
+    }
+    self.prods.push(prod);
     self
   }
 
@@ -159,6 +299,36 @@ where
     });
     self
   }
+
+  /// Adds a production whose elements are all unnamed.
+  pub fn add_prod_from_elems(
+    &mut self,
+    action_key: impl BuilderInto<AK>,
+    action_value: impl BuilderInto<AV>,
+    elems: impl IntoIterator<Item = Elem<T, NT>>,
+  ) -> &mut Self {
+    let elems = elems.into_iter().map(ProdElement::new_empty);
+    self.add_prod_with_elems(
+      action_key,
+      action_value,
+      elems.collect::<Vec<_>>(),
+    )
+  }
+
+  /// Adds a production from pairs of optional element names and elements.
+  pub fn add_prod_from_named_elems(
+    &mut self,
+    action_key: impl BuilderInto<AK>,
+    action_value: impl BuilderInto<AV>,
+    elems: impl IntoIterator<Item = (Option<Name>, Elem<T, NT>)>,
+  ) -> &mut Self {
+    let elems = elems.into_iter().map(|(name, e)| ProdElement::new(name, e));
+    self.add_prod_with_elems(
+      action_key,
+      action_value,
+      elems.collect::<Vec<_>>(),
+    )
+  }
 }
 
 // ----------------
@@ -166,6 +336,8 @@ where
 pub struct GrammarBuilder<T, NT, AK, AV> {
   start: NT,
   rules: Vec<RuleInner<T, NT, AK, AV>>,
+  alternations: Vec<PendingAlternations<T, NT, AK, AV>>,
+  max_prod_len: Option<usize>,
 }
 
 impl<T, NT, AK, AV> GrammarBuilder<T, NT, AK, AV>
@@ -177,14 +349,113 @@ where
     GrammarBuilder {
       start,
       rules: Vec::new(),
+      alternations: Vec::new(),
+      max_prod_len: None,
     }
   }
 
-  fn build(self) -> Result<Grammar<T, NT, AK, AV>, GrammarErrors<NT>> {
-    let GrammarBuilder { start, rules } = self;
-    Grammar::new(start, rules)
+  fn build(mut self) -> Result<Grammar<T, NT, AK, AV>, GrammarErrors<NT, AK>> {
+    self.expand_alternations();
+    let GrammarBuilder {
+      start,
+      rules,
+      max_prod_len,
+      ..
+    } = self;
+    Grammar::new(start, rules, max_prod_len)
+  }
+
+  fn build_lenient(
+    mut self,
+  ) -> (Grammar<T, NT, AK, AV>, GrammarErrors<NT, AK>) {
+    self.expand_alternations();
+    let GrammarBuilder {
+      start,
+      rules,
+      max_prod_len,
+      ..
+    } = self;
+    Grammar::new_lenient(start, rules, max_prod_len)
   }
 
+  /// Replaces each inline alternation with a reference to a new auxiliary
+  /// nonterminal, and adds the auxiliary rules.
+  ///
+  /// Alternations are expanded in the order they were added, with the
+  /// alternations nested within them expanded afterwards, so the names of
+  /// the auxiliary nonterminals only depend on the order of the builder
+  /// calls.
+  fn expand_alternations(&mut self) {
+    if self.alternations.is_empty() {
+      return;
+    }
+
+    let mut used = BTreeSet::new();
+    for rule in &self.rules {
+      used.insert(rule.head.clone());
+      for prod in &rule.prods {
+        used.extend(
+          prod
+            .elements
+            .iter()
+            .filter_map(|e| e.elem().as_nonterm())
+            .cloned(),
+        );
+      }
+    }
+    for (_, _, alternations) in &self.alternations {
+      for alternation in alternations {
+        alternation.collect_nonterms(&mut used);
+      }
+    }
+
+    let mut queue = std::mem::take(&mut self.alternations)
+      .into_iter()
+      .collect::<VecDeque<_>>();
+    while let Some((rule_index, prod_index, alternations)) = queue.pop_front() {
+      for alternation in alternations {
+        let head = &self.rules[rule_index].head;
+        let mut names = alternation.names;
+        let nt = fresh_nonterm(&mut names, &mut used, AuxKind::Alt(head));
+        self.rules[rule_index].prods[prod_index].elements.insert(
+          alternation.index,
+          ProdElement::new_empty(Elem::NonTerm(nt.clone())),
+        );
+
+        let aux_index = self.rules.len();
+        let mut prods = Vec::new();
+        for alt in alternation.alts {
+          let (prod, nested) = alt.build();
+          if !nested.is_empty() {
+            queue.push_back((aux_index, prods.len(), nested));
+          }
+          prods.push(prod);
+        }
+        self.rules.push(RuleInner::new(nt, prods).into_generated());
+      }
+    }
+  }
+
+  /// Replaces the start nonterminal that was passed to `build`. If this is
+  /// called more than once, the last call wins.
+  pub fn set_start(&mut self, start: impl BuilderInto<NT>) -> &mut Self {
+    self.start = start.builder_into();
+    self
+  }
+
+  /// Makes productions with more than `max_len` elements an error. This is
+  /// not checked by default.
+  pub fn set_max_prod_len(&mut self, max_len: usize) -> &mut Self {
+    self.max_prod_len = Some(max_len);
+    self
+  }
+
+  /// Adds a rule with the productions added by `build_fn`.
+  ///
+  /// If this is called more than once for the same head, the productions of
+  /// the later calls are appended to the rule, and a doc string from a later
+  /// call replaces the earlier one. Building fails if two of the rule's
+  /// productions then have the same action key.
   pub fn add_rule<F>(
     &mut self,
     head: impl BuilderInto<NT>,
@@ -195,7 +466,29 @@ where
   {
     let mut rule_builder = RuleBuilder::new(head.builder_into());
     build_fn(&mut rule_builder);
-    self.rules.push(rule_builder.build());
+    let (rule, alternations) = rule_builder.build();
+    let existing = self.rules.iter().position(|r| r.head == rule.head);
+    let (rule_index, prod_offset) = match existing {
+      Some(index) => (index, self.rules[index].prods.len()),
+      None => (self.rules.len(), 0),
+    };
+    for (prod_index, alternations) in alternations {
+      self.alternations.push((
+        rule_index,
+        prod_offset + prod_index,
+        alternations,
+      ));
+    }
+    match existing {
+      Some(index) => {
+        let existing = &mut self.rules[index];
+        existing.prods.extend(rule.prods);
+        if rule.doc.is_some() {
+          existing.doc = rule.doc;
+        }
+      }
+      None => self.rules.push(rule),
+    }
     self
   }
 }
@@ -225,7 +518,7 @@ where
 pub fn build<T, NT, AK, AV>(
   start: impl BuilderInto<NT>,
   build_fn: impl FnOnce(&mut GrammarBuilder<T, NT, AK, AV>),
-) -> Result<Grammar<T, NT, AK, AV>, GrammarErrors<NT>>
+) -> Result<Grammar<T, NT, AK, AV>, GrammarErrors<NT, AK>>
 where
   NT: Ord + Clone,
   AK: Ord + Clone,
@@ -234,3 +527,547 @@ where
   build_fn(&mut builder);
   builder.build()
 }
+
+/// Like `build`, but always returns the grammar, along with the errors that
+/// would have made `build` fail.
+///
+/// This is useful for holding a grammar that is still being edited. The
+/// analyses on the grammar treat nonterminals without rules as deriving
+/// nothing, so they may be used on it. Transforms that build a new grammar
+/// with `build` return the same errors for it; the ones that accept lenient
+/// grammars say so.
+pub fn build_lenient<T, NT, AK, AV>(
+  start: impl BuilderInto<NT>,
+  build_fn: impl FnOnce(&mut GrammarBuilder<T, NT, AK, AV>),
+) -> (Grammar<T, NT, AK, AV>, GrammarErrors<NT, AK>)
+where
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  let mut builder = GrammarBuilder::new(start.builder_into());
+  build_fn(&mut builder);
+  builder.build_lenient()
+}
+
+/// An error from `build_strict`.
+#[derive(Clone, Debug)]
+pub enum BuildError<NT, AK> {
+  /// An element of a production refers to a nonterminal that has no rule.
+  UndefinedNonTerm {
+    prod: ProdKey<NT, AK>,
+    /// The index of the element within the production.
+    index: usize,
+    nonterm: NT,
+    /// The defined nonterminal with the most similar name, if any is close.
+    suggestion: Option<NT>,
+  },
+  /// The grammar failed validation for another reason.
+  Grammar(GrammarErrors<NT, AK>),
+}
+
+impl<NT, AK> std::fmt::Display for BuildError<NT, AK>
+where
+  NT: ToDoc + Debug,
+  AK: ToDoc + Debug,
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      BuildError::UndefinedNonTerm {
+        prod,
+        index,
+        nonterm,
+        suggestion,
+      } => {
+        write!(
+          f,
+          "Element {} of production `{}` of `{}` refers to undefined \
+           nonterminal `{}`",
+          index,
+          doc_string(prod.action_key()),
+          doc_string(prod.head()),
+          doc_string(nonterm),
+        )?;
+        if let Some(suggestion) = suggestion {
+          write!(f, "; did you mean `{}`?", doc_string(suggestion))?;
+        }
+        Ok(())
+      }
+      BuildError::Grammar(errors) => write!(f, "Invalid grammar: {:?}", errors),
+    }
+  }
+}
+
+impl<NT, AK> std::error::Error for BuildError<NT, AK>
+where
+  NT: ToDoc + Debug,
+  AK: ToDoc + Debug,
+{
+}
+
+/// Builds a grammar like `build`, but first checks that every nonterminal
+/// used in a production has a rule.
+///
+/// The error points at the first element referring to an undefined
+/// nonterminal, and suggests a defined nonterminal if one has a similar name.
+pub fn build_strict<T, NT, AK, AV>(
+  start: impl BuilderInto<NT>,
+  build_fn: impl FnOnce(&mut GrammarBuilder<T, NT, AK, AV>),
+) -> Result<Grammar<T, NT, AK, AV>, BuildError<NT, AK>>
+where
+  NT: Ord + Clone + ToDoc,
+  AK: Ord + Clone,
+{
+  let mut builder = GrammarBuilder::new(start.builder_into());
+  build_fn(&mut builder);
+  builder.expand_alternations();
+
+  let heads = builder
+    .rules
+    .iter()
+    .map(|rule| &rule.head)
+    .collect::<BTreeSet<_>>();
+  for rule in &builder.rules {
+    for prod in &rule.prods {
+      for (index, elem) in prod.elements.iter().enumerate() {
+        if let Elem::NonTerm(nt) = elem.elem() {
+          if !heads.contains(nt) {
+            return Err(BuildError::UndefinedNonTerm {
+              prod: ProdKey::new(rule.head.clone(), prod.action_key.clone()),
+              index,
+              nonterm: nt.clone(),
+              suggestion: closest_name(nt, heads.iter().copied()).cloned(),
+            });
+          }
+        }
+      }
+    }
+  }
+
+  builder.build().map_err(BuildError::Grammar)
+}
+
+/// Returns the candidate whose name has the smallest edit distance from
+/// `name`, if that distance is at most a third of the length of `name`.
+fn closest_name<'a, NT>(
+  name: &NT,
+  candidates: impl Iterator<Item = &'a NT>,
+) -> Option<&'a NT>
+where
+  NT: ToDoc + 'a,
+{
+  let name = doc_string(name).chars().collect::<Vec<_>>();
+  candidates
+    .map(|candidate| {
+      let distance = edit_distance(
+        &name,
+        &doc_string(candidate).chars().collect::<Vec<_>>(),
+      );
+      (distance, candidate)
+    })
+    .filter(|(distance, _)| *distance <= (name.len() / 3).max(1))
+    .min_by_key(|(distance, _)| *distance)
+    .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+  let mut row = (0..=b.len()).collect::<Vec<_>>();
+  for (i, ca) in a.iter().enumerate() {
+    let mut prev = row[0];
+    row[0] = i + 1;
+    for (j, cb) in b.iter().enumerate() {
+      let next = (prev + usize::from(ca != cb))
+        .min(row[j] + 1)
+        .min(row[j + 1] + 1);
+      prev = row[j + 1];
+      row[j + 1] = next;
+    }
+  }
+  row[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{NonTerminal, Terminal};
+
+  #[test]
+  fn test_strict_undefined_nonterm() {
+    let result: Result<Grammar<Terminal, NonTerminal, Name, ()>, _> =
+      build_strict(NonTerminal::new("expr"), |gb| {
+        gb.add_rule(NonTerminal::new("expr"), |rb| {
+          rb.add_prod("paren", (), |pb| {
+            pb.add_term(Terminal::new("LPAREN"))
+              .add_nonterm(NonTerminal::new("exp"))
+              .add_term(Terminal::new("RPAREN"));
+          })
+          .add_prod("empty", (), |_| {});
+        });
+      });
+
+    let err = result.unwrap_err();
+    assert!(matches!(
+      &err,
+      BuildError::UndefinedNonTerm { index: 1, suggestion: Some(nt), .. }
+        if nt == &NonTerminal::new("expr")
+    ));
+    assert_eq!(
+      err.to_string(),
+      "Element 1 of production `paren` of `expr` refers to undefined \
+       nonterminal `exp`; did you mean `expr`?"
+    );
+  }
+
+  #[test]
+  fn test_strict_valid() {
+    let g: Grammar<Terminal, NonTerminal, Name, ()> =
+      build_strict(NonTerminal::new("s"), |gb| {
+        gb.add_rule(NonTerminal::new("s"), |rb| {
+          rb.add_prod("a", (), |pb| {
+            pb.add_term(Terminal::new("A"));
+          });
+        });
+      })
+      .unwrap();
+    assert_eq!(g.start_nt(), &NonTerminal::new("s"));
+  }
+
+  #[test]
+  fn test_lenient() {
+    let (g, errors): (Grammar<Terminal, NonTerminal, Name, ()>, _) =
+      build_lenient(NonTerminal::new("s"), |gb| {
+        gb.add_rule(NonTerminal::new("s"), |rb| {
+          rb.add_prod("a", (), |pb| {
+            pb.add_term(Terminal::new("A"));
+          })
+          .add_prod("missing", (), |pb| {
+            pb.add_nonterm(NonTerminal::new("undefined"));
+          });
+        })
+        .add_rule(NonTerminal::new("unused"), |rb| {
+          rb.add_prod("b", (), |pb| {
+            pb.add_term(Terminal::new("B"))
+              .add_nonterm(NonTerminal::new("helper"));
+          });
+        })
+        .add_rule(NonTerminal::new("helper"), |rb| {
+          rb.add_prod("c", (), |pb| {
+            pb.add_term(Terminal::new("C"));
+          });
+        });
+      });
+
+    assert!(!errors.is_empty());
+    assert_eq!(
+      errors.unreachable_nonterms(),
+      &vec![NonTerminal::new("helper"), NonTerminal::new("unused")]
+        .into_iter()
+        .collect()
+    );
+    assert_eq!(
+      errors.nonterms_without_rules(),
+      &std::iter::once(NonTerminal::new("undefined")).collect()
+    );
+    assert!(errors.rules_without_prods().is_empty());
+    assert!(g.validate().is_err());
+
+    // Analyses still work, and treat the undefined nonterminal as deriving
+    // nothing.
+    let a = std::iter::once(Terminal::new("A")).collect();
+    assert_eq!(g.start_firsts(), a);
+    assert_eq!(g.accepting_lasts(), a);
+    assert!(!g.accepts_empty());
+    assert_eq!(g.lookahead_requirement(2), Some(1));
+    assert_eq!(
+      crate::grammar::gen::bounded_sentences(&g, 3),
+      std::iter::once(vec![Terminal::new("A")]).collect()
+    );
+    assert!(!g.to_pretty().is_empty());
+  }
+
+  #[test]
+  fn test_set_start() {
+    let add_rules =
+      |gb: &mut GrammarBuilder<Terminal, NonTerminal, Name, ()>| {
+        gb.add_rule(NonTerminal::new("s"), |rb| {
+          rb.add_prod("a", (), |pb| {
+            pb.add_nonterm(NonTerminal::new("t"));
+          });
+        })
+        .add_rule(NonTerminal::new("t"), |rb| {
+          rb.add_prod("b", (), |pb| {
+            pb.add_term(Terminal::new("B"));
+          });
+        });
+      };
+
+    let expected = build(NonTerminal::new("s"), add_rules).unwrap();
+    let g = build(NonTerminal::new("t"), |gb| {
+      gb.set_start(NonTerminal::new("s"));
+      add_rules(gb);
+    })
+    .unwrap();
+    assert_eq!(g.start_nt(), &NonTerminal::new("s"));
+    assert_eq!(g, expected);
+
+    // Without `set_start`, `s` would be unreachable.
+    assert!(build(NonTerminal::new("t"), add_rules).is_err());
+  }
+
+  #[test]
+  fn test_max_prod_len() {
+    let build_with = |max_len: Option<usize>| {
+      build::<Terminal, NonTerminal, Name, ()>(NonTerminal::new("s"), |gb| {
+        if let Some(max_len) = max_len {
+          gb.set_max_prod_len(max_len);
+        }
+        gb.add_rule(NonTerminal::new("s"), |rb| {
+          rb.add_prod_from_elems(
+            "long",
+            (),
+            (0..10).map(|i| Elem::Term(Terminal::new(&format!("T{}", i)))),
+          )
+          .add_prod_from_elems(
+            "short",
+            (),
+            vec![Elem::Term(Terminal::new("A"))],
+          );
+        });
+      })
+    };
+
+    assert!(build_with(None).is_ok());
+    assert!(build_with(Some(10)).is_ok());
+
+    let errors = build_with(Some(5)).unwrap_err();
+    assert_eq!(
+      errors.over_long_prods(),
+      &std::iter::once(ProdKey::new(NonTerminal::new("s"), Name::new("long")))
+        .collect()
+    );
+    assert!(errors.unreachable_nonterms().is_empty());
+  }
+
+  #[test]
+  fn test_repeated_add_rule() {
+    let (a, b) = (Terminal::new("A"), Terminal::new("B"));
+    let x = NonTerminal::new("x");
+    let g: Grammar<Terminal, NonTerminal, Name, ()> = build(&x, |gb| {
+      gb.add_rule(&x, |rb| {
+        rb.add_prod("a", (), |pb| {
+          pb.add_term(&a);
+        });
+      })
+      .add_rule(&x, |rb| {
+        rb.set_doc("Both productions.").add_prod("b", (), |pb| {
+          pb.add_term(&b);
+        });
+      });
+    })
+    .unwrap();
+
+    let expected = build(&x, |gb| {
+      gb.add_rule(&x, |rb| {
+        rb.set_doc("Both productions.")
+          .add_prod("a", (), |pb| {
+            pb.add_term(&a);
+          })
+          .add_prod("b", (), |pb| {
+            pb.add_term(&b);
+          });
+      });
+    })
+    .unwrap();
+    assert_grammar_eq!(g, expected);
+
+    let errors = build::<Terminal, NonTerminal, Name, ()>(&x, |gb| {
+      gb.add_rule(&x, |rb| {
+        rb.add_prod("a", (), |pb| {
+          pb.add_term(&a);
+        });
+      })
+      .add_rule(&x, |rb| {
+        rb.add_prod("a", (), |pb| {
+          pb.add_term(&b);
+        });
+      });
+    })
+    .unwrap_err();
+    assert_eq!(
+      errors.duplicate_action_keys(),
+      &std::iter::once(ProdKey::new(x.clone(), Name::new("a"))).collect()
+    );
+  }
+
+  #[test]
+  fn test_duplicate_action_keys() {
+    let (a, b) = (Terminal::new("A"), Terminal::new("B"));
+    let x = NonTerminal::new("x");
+    let errors = build::<Terminal, NonTerminal, Name, ()>(&x, |gb| {
+      gb.add_rule(&x, |rb| {
+        rb.add_prod("a", (), |pb| {
+          pb.add_term(&a);
+        })
+        .add_prod("a", (), |pb| {
+          pb.add_term(&b);
+        });
+      });
+    })
+    .unwrap_err();
+    assert_eq!(
+      errors.duplicate_action_keys(),
+      &std::iter::once(ProdKey::new(x.clone(), Name::new("a"))).collect()
+    );
+  }
+
+  #[test]
+  fn test_prod_from_elems() {
+    let (a, x) = (Terminal::new("A"), NonTerminal::new("x"));
+    let g: Grammar<Terminal, NonTerminal, Name, ()> = build(&x, |gb| {
+      gb.add_rule(&x, |rb| {
+        rb.add_prod("closure", (), |pb| {
+          pb.add_term(&a).add_named_nonterm("inner", &x);
+        })
+        .add_prod_from_elems(
+          "unnamed",
+          (),
+          vec![Elem::Term(a.clone()), Elem::NonTerm(x.clone())],
+        )
+        .add_prod_from_named_elems(
+          "named",
+          (),
+          vec![
+            (None, Elem::Term(a.clone())),
+            (Some(Name::new("inner")), Elem::NonTerm(x.clone())),
+          ],
+        )
+        .add_prod("empty", (), |_| {});
+      });
+    })
+    .unwrap();
+
+    let prods = g.get_rule(&x).prods().collect::<Vec<_>>();
+    assert_eq!(prods[0].prod_elements(), prods[2].prod_elements());
+    assert_ne!(prods[0].prod_elements(), prods[1].prod_elements());
+    assert_eq!(
+      prods[1].elements().collect::<Vec<_>>(),
+      prods[2].elements().collect::<Vec<_>>()
+    );
+    assert!(prods[1].prod_elements().iter().all(|e| e.id().is_none()));
+  }
+
+  #[test]
+  fn test_inline_alternation() {
+    use crate::{
+      parsers::{earley, tree::TreeOwner, Token},
+      start_grammar::wrap_grammar_with_start,
+    };
+
+    // s ::= A (B | C (D | )) D, with a clashing name for the first
+    // auxiliary nonterminal.
+    let inline: Grammar<Terminal, NonTerminal, Name, ()> =
+      build(NonTerminal::new("s"), |gb| {
+        gb.add_rule(NonTerminal::new("s"), |rb| {
+          rb.add_prod("s", (), |pb| {
+            pb.add_term(Terminal::new("A"))
+              .add_alternation(|ab| {
+                ab.alt(|pb| {
+                  pb.add_term(Terminal::new("B"));
+                })
+                .alt(|pb| {
+                  pb.add_term(Terminal::new("C")).add_alternation(|ab| {
+                    ab.alt(|pb| {
+                      pb.add_term(Terminal::new("D"));
+                    })
+                    .alt(|_| {});
+                  });
+                });
+              })
+              .add_term(Terminal::new("D"));
+          })
+          .add_prod("clash", (), |pb| {
+            pb.add_nonterm(NonTerminal::new("s__alt__1"));
+          });
+        })
+        .add_rule(NonTerminal::new("s__alt__1"), |rb| {
+          rb.add_prod("e", (), |pb| {
+            pb.add_term(Terminal::new("E"));
+          });
+        });
+      })
+      .unwrap();
+
+    let expanded: Grammar<Terminal, NonTerminal, Name, ()> =
+      build(NonTerminal::new("s"), |gb| {
+        gb.add_rule(NonTerminal::new("s"), |rb| {
+          rb.add_prod("s", (), |pb| {
+            pb.add_term(Terminal::new("A"))
+              .add_nonterm(NonTerminal::new("bc"))
+              .add_term(Terminal::new("D"));
+          })
+          .add_prod("clash", (), |pb| {
+            pb.add_nonterm(NonTerminal::new("e"));
+          });
+        })
+        .add_rule(NonTerminal::new("bc"), |rb| {
+          rb.add_prod("b", (), |pb| {
+            pb.add_term(Terminal::new("B"));
+          })
+          .add_prod("c", (), |pb| {
+            pb.add_term(Terminal::new("C"))
+              .add_nonterm(NonTerminal::new("d"));
+          });
+        })
+        .add_rule(NonTerminal::new("d"), |rb| {
+          rb.add_prod("d", (), |pb| {
+            pb.add_term(Terminal::new("D"));
+          })
+          .add_prod("empty", (), |_| {});
+        })
+        .add_rule(NonTerminal::new("e"), |rb| {
+          rb.add_prod("e", (), |pb| {
+            pb.add_term(Terminal::new("E"));
+          });
+        });
+      })
+      .unwrap();
+
+    let s_prod = inline.get_rule(&NonTerminal::new("s")).prods().next();
+    assert_eq!(
+      s_prod.unwrap().elements().nth(1),
+      Some(&Elem::NonTerm(NonTerminal::new("s__alt__2")))
+    );
+    let nested = inline.get_rule(&NonTerminal::new("s__alt__2__alt__1"));
+    assert_eq!(nested.prods().count(), 2);
+
+    let inline = wrap_grammar_with_start(inline).unwrap();
+    let expanded = wrap_grammar_with_start(expanded).unwrap();
+    let tree: TreeOwner<_, _, ()> = TreeOwner::new();
+    let mut inputs = vec![vec![]];
+    for _ in 0..4 {
+      inputs = inputs
+        .iter()
+        .flat_map(|input: &Vec<&str>| {
+          ["A", "B", "C", "D", "E"].iter().map(move |t| {
+            let mut input = input.clone();
+            input.push(*t);
+            input
+          })
+        })
+        .collect();
+      for input in &inputs {
+        let tokens = || {
+          input
+            .iter()
+            .map(|t| Token::new(Terminal::new(t), ()))
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+          earley::parse(&inline, &tree.handle(), tokens()).is_some(),
+          earley::parse(&expanded, &tree.handle(), tokens()).is_some(),
+          "{:?}",
+          input
+        );
+      }
+    }
+  }
+}
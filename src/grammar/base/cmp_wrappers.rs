@@ -52,7 +52,7 @@ impl<'a, T> ops::Deref for ParentRef<'a, T> {
 
 impl<T> Clone for ParentRef<'_, T> {
   fn clone(&self) -> Self {
-    ParentRef(self.0)
+    *self
   }
 }
 
@@ -78,8 +78,8 @@ impl<T> cmp::PartialEq for NoCompare<T> {
 impl<T> cmp::Eq for NoCompare<T> {}
 
 impl<T> cmp::PartialOrd for NoCompare<T> {
-  fn partial_cmp(&self, _: &Self) -> Option<cmp::Ordering> {
-    Some(cmp::Ordering::Equal)
+  fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+    Some(self.cmp(other))
   }
 }
 
@@ -136,7 +136,7 @@ impl<'a, T> ops::Deref for RefCompare<'a, T> {
 
 impl<T> Clone for RefCompare<'_, T> {
   fn clone(&self) -> Self {
-    RefCompare(self.0)
+    *self
   }
 }
 
@@ -1,4 +1,123 @@
-use crate::utils::{Name, ToDoc};
+use {
+  crate::{
+    grammar::Grammar,
+    utils::{Name, ToDoc},
+  },
+  std::{collections::BTreeSet, fmt::Debug},
+};
+
+/// A family of the four types a grammar is generic over.
+///
+/// This allows a grammar's types to be named once, and then referred to with
+/// `GrammarFor`. Families are usually declared with `element_types!`.
+///
+/// Symbols and action keys must be `'static`, since the grammar analyses are
+/// cached by type. Action values are only stored, so they may borrow data
+/// that outlives the grammar, with a family that is generic over a lifetime.
+pub trait ElementTypes {
+  type Term: Ord + Clone + Debug + 'static;
+  type NonTerm: Ord + Clone + Debug + 'static;
+  type ActionKey: Ord + Clone + Debug + 'static;
+  type ActionValue: Clone + Debug;
+}
+
+/// The grammar type of an element type family.
+pub type GrammarFor<E> = Grammar<
+  <E as ElementTypes>::Term,
+  <E as ElementTypes>::NonTerm,
+  <E as ElementTypes>::ActionKey,
+  <E as ElementTypes>::ActionValue,
+>;
+
+/// Declares a zero-sized struct implementing `ElementTypes`.
+///
+/// The struct may take a single lifetime parameter, which the action value
+/// type can borrow with:
+///
+/// ```
+/// bongo::element_types! {
+///   pub struct Spans<'a> {
+///     term: char,
+///     nonterm: &'static str,
+///     action_key: &'static str,
+///     action_value: &'a str,
+///   }
+/// }
+/// ```
+///
+/// ```
+/// bongo::element_types! {
+///   pub struct Tokens {
+///     term: char,
+///     nonterm: &'static str,
+///     action_key: &'static str,
+///     action_value: (),
+///   }
+/// }
+///
+/// let g: bongo::grammar::GrammarFor<Tokens> = bongo::grammar::build("s", |gb| {
+///   gb.add_rule("s", |rb| {
+///     rb.add_prod("a", (), |pb| {
+///       pb.add_term('a');
+///     });
+///   });
+/// })
+/// .unwrap();
+/// ```
+#[macro_export]
+macro_rules! element_types {
+  (
+    $(#[$meta:meta])*
+    $vis:vis struct $name:ident {
+      term: $term:ty,
+      nonterm: $nonterm:ty,
+      action_key: $action_key:ty,
+      action_value: $action_value:ty $(,)?
+    }
+  ) => {
+    $(#[$meta])*
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+    $vis struct $name;
+
+    impl $crate::grammar::ElementTypes for $name {
+      type Term = $term;
+      type NonTerm = $nonterm;
+      type ActionKey = $action_key;
+      type ActionValue = $action_value;
+    }
+  };
+  (
+    $(#[$meta:meta])*
+    $vis:vis struct $name:ident<$lt:lifetime> {
+      term: $term:ty,
+      nonterm: $nonterm:ty,
+      action_key: $action_key:ty,
+      action_value: $action_value:ty $(,)?
+    }
+  ) => {
+    $(#[$meta])*
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+    $vis struct $name<$lt>(::std::marker::PhantomData<&$lt ()>);
+
+    impl<$lt> $crate::grammar::ElementTypes for $name<$lt> {
+      type Term = $term;
+      type NonTerm = $nonterm;
+      type ActionKey = $action_key;
+      type ActionValue = $action_value;
+    }
+  };
+}
+
+/// The element types used by the grammars in `examples`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct BaseElementTypes;
+
+impl ElementTypes for BaseElementTypes {
+  type Term = Terminal;
+  type NonTerm = NonTerminal;
+  type ActionKey = Name;
+  type ActionValue = ();
+}
 
 /// A terminal element.
 ///
@@ -10,6 +129,17 @@ impl Terminal {
   pub fn new(s: &str) -> Self {
     Terminal(Name::new(s))
   }
+
+  /// Returns the name of this terminal.
+  pub fn name(&self) -> &Name {
+    &self.0
+  }
+}
+
+impl std::fmt::Display for Terminal {
+  fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fmt.write_str(self.0.str())
+  }
 }
 
 impl ToDoc for Terminal {
@@ -28,6 +158,17 @@ impl NonTerminal {
   pub fn new(s: &str) -> Self {
     NonTerminal(Name::new(s))
   }
+
+  /// Returns the name of this nonterminal.
+  pub fn name(&self) -> &Name {
+    &self.0
+  }
+}
+
+impl std::fmt::Display for NonTerminal {
+  fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fmt.write_str(self.0.str())
+  }
 }
 
 impl ToDoc for NonTerminal {
@@ -38,3 +179,151 @@ impl ToDoc for NonTerminal {
     da.text(self.0.str().to_string())
   }
 }
+
+/// A pool of interned symbol names.
+///
+/// Every terminal and nonterminal handed out by a pool for the same string
+/// shares a single `Name`, so building many grammars over the same symbols
+/// only allocates each name once, and comparing symbols from the same pool
+/// does not compare their strings.
+#[derive(Clone, Default, Debug)]
+pub struct SymbolPool {
+  names: BTreeSet<Name>,
+}
+
+impl SymbolPool {
+  pub fn new() -> Self {
+    SymbolPool::default()
+  }
+
+  /// Returns the interned name for `s`, adding it to the pool if needed.
+  pub fn name(&mut self, s: &str) -> Name {
+    if let Some(name) = self.names.get(s) {
+      return name.clone();
+    }
+    let name = Name::new(s);
+    self.names.insert(name.clone());
+    name
+  }
+
+  pub fn terminal(&mut self, s: &str) -> Terminal {
+    Terminal(self.name(s))
+  }
+
+  pub fn nonterminal(&mut self, s: &str) -> NonTerminal {
+    NonTerminal(self.name(s))
+  }
+
+  /// Returns the number of distinct names in the pool.
+  pub fn len(&self) -> usize {
+    self.names.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.names.is_empty()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::grammar::{
+    build,
+    passes::{nullable::NullableSet, PassContext},
+    GrammarFor, SymbolPool, Terminal,
+  };
+
+  crate::element_types! {
+    /// Element types with plain strings.
+    struct StrTypes {
+      term: &'static str,
+      nonterm: &'static str,
+      action_key: &'static str,
+      action_value: usize,
+    }
+  }
+
+  #[test]
+  fn test_symbol_pool() {
+    let mut pool = SymbolPool::new();
+    let first = pool.terminal("A");
+    let second = pool.terminal("A");
+    assert_eq!(first, second);
+    assert!(first.name().ptr_eq(second.name()));
+
+    // Terminals and nonterminals with the same string share a name.
+    let nonterm = pool.nonterminal("A");
+    assert!(nonterm.name().ptr_eq(first.name()));
+    assert!(!pool.terminal("B").name().ptr_eq(first.name()));
+    assert_eq!(pool.len(), 2);
+
+    // Names outside the pool are equal, but not shared.
+    let unpooled = Terminal::new("A");
+    assert_eq!(unpooled, first);
+    assert!(!unpooled.name().ptr_eq(first.name()));
+  }
+
+  #[test]
+  fn test_element_types_macro() {
+    let g: GrammarFor<StrTypes> = build("s", |gb| {
+      gb.add_rule("s", |rb| {
+        rb.add_prod("pair", 0, |pb| {
+          pb.add_nonterm("opt").add_term("A");
+        });
+      })
+      .add_rule("opt", |rb| {
+        rb.add_prod("empty", 1, |_| {}).add_prod("b", 2, |pb| {
+          pb.add_term("B");
+        });
+      });
+    })
+    .unwrap();
+
+    let passes = PassContext::new(&g);
+    let nullables = passes
+      .get_pass::<NullableSet<&str>>()
+      .expect("nullable sets are defined for every grammar");
+    assert!(nullables.is_nullable(&"opt"));
+    assert!(!nullables.is_nullable(&"s"));
+  }
+
+  crate::element_types! {
+    /// Element types whose action values borrow a source string.
+    struct SpanTypes<'a> {
+      term: &'static str,
+      nonterm: &'static str,
+      action_key: &'static str,
+      action_value: &'a str,
+    }
+  }
+
+  fn build_with_spans(source: &str) -> GrammarFor<SpanTypes<'_>> {
+    build("s", |gb| {
+      gb.add_rule("s", |rb| {
+        rb.add_prod("head", &source[..4], |pb| {
+          pb.add_term("A").add_nonterm("opt");
+        });
+      })
+      .add_rule("opt", |rb| {
+        rb.add_prod("empty", &source[5..], |_| {});
+      });
+    })
+    .unwrap()
+  }
+
+  #[test]
+  fn test_borrowed_action_values() {
+    let source = String::from("head tail");
+    let g = build_with_spans(&source);
+    let values = g
+      .prods()
+      .map(|prod| *prod.action_value())
+      .collect::<Vec<_>>();
+    assert_eq!(values, vec!["tail", "head"]);
+
+    let passes = PassContext::new(&g);
+    let nullables = passes
+      .get_pass::<NullableSet<&str>>()
+      .expect("nullable sets are defined for every grammar");
+    assert!(nullables.is_nullable(&"opt"));
+  }
+}
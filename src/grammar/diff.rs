@@ -0,0 +1,93 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Comparison of the languages of two versions of a grammar.
+
+use {
+  crate::grammar::{gen::bounded_sentences, Grammar},
+  std::collections::BTreeSet,
+};
+
+/// The bounded-length sentences whose acceptance changed between two
+/// versions of a grammar.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BehaviorDiff<T> {
+  /// Sentences accepted by the new grammar, but not the old one.
+  pub newly_accepted: BTreeSet<Vec<T>>,
+  /// Sentences accepted by the old grammar, but not the new one. These are
+  /// inputs that previously worked, and will now fail.
+  pub newly_rejected: BTreeSet<Vec<T>>,
+}
+
+impl<T> BehaviorDiff<T> {
+  /// Returns true if both grammars accept the same bounded sentences.
+  pub fn is_empty(&self) -> bool {
+    self.newly_accepted.is_empty() && self.newly_rejected.is_empty()
+  }
+}
+
+/// Returns the sentences of at most `max_len` terminals that are accepted by
+/// exactly one of `old` and `new`.
+pub fn behavioral_diff<T, NT, AK, AV, NT2, AK2, AV2>(
+  old: &Grammar<T, NT, AK, AV>,
+  new: &Grammar<T, NT2, AK2, AV2>,
+  max_len: usize,
+) -> BehaviorDiff<T>
+where
+  T: Ord + Clone,
+  NT: Ord,
+  NT2: Ord,
+{
+  let old_sentences = bounded_sentences(old, max_len);
+  let new_sentences = bounded_sentences(new, max_len);
+  BehaviorDiff {
+    newly_accepted: new_sentences.difference(&old_sentences).cloned().collect(),
+    newly_rejected: old_sentences.difference(&new_sentences).cloned().collect(),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{examples, Terminal};
+
+  fn terms(names: &[&str]) -> Vec<Terminal> {
+    names.iter().map(|name| Terminal::new(name)).collect()
+  }
+
+  #[test]
+  fn test_added_production() {
+    let old = examples::make_paren();
+    let new = examples::from_rules(&[
+      ("start", &[&["expr"]]),
+      ("expr", &[&["LPAREN", "expr_list", "RPAREN"], &["ATOM"]]),
+      ("expr_list", &[&[], &["expr", "expr_list"]]),
+    ]);
+
+    let diff = behavioral_diff(&old, &new, 3);
+    assert_eq!(
+      diff.newly_accepted,
+      vec![terms(&["ATOM"]), terms(&["LPAREN", "ATOM", "RPAREN"])]
+        .into_iter()
+        .collect()
+    );
+    assert!(diff.newly_rejected.is_empty());
+
+    let diff = behavioral_diff(&new, &old, 3);
+    assert!(diff.newly_accepted.is_empty());
+    assert_eq!(diff.newly_rejected.len(), 2);
+
+    assert!(behavioral_diff(&old, &old, 6).is_empty());
+  }
+}
@@ -0,0 +1,373 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Lossy rendering of grammars as EBNF.
+//!
+//! Rules that describe a list or an optional sequence, such as
+//! `x ::= ε | x a b`, are written with the `*`, `+` and `?` operators. The
+//! rules that `GrammarBuilder` generates for inline alternations are inlined
+//! at their use as parenthesized groups, so that a grammar built from these
+//! shapes reads much like the EBNF it was written from. The rules the user
+//! wrote are always kept. The action keys, action values and element
+//! identifiers are dropped.
+
+use {
+  super::Rule,
+  crate::{
+    grammar::{Elem, Grammar, RuleOrder},
+    utils::{doc_string, ToDoc},
+  },
+  std::collections::{BTreeMap, BTreeSet},
+};
+
+/// A rule that repeats, or optionally matches, a sequence of elements.
+pub(super) enum Repetition<'a, T, NT> {
+  /// `x ::= ε | x body`, or `x ::= ε | body x`.
+  Star(Vec<&'a Elem<T, NT>>),
+  /// `x ::= body | x body`, or `x ::= body | body x`.
+  Plus(Vec<&'a Elem<T, NT>>),
+  /// `x ::= ε | body`.
+  Optional(Vec<&'a Elem<T, NT>>),
+}
+
+/// Returns how `rule` repeats some sequence, if it has one of the shapes of
+/// `Repetition`.
+pub(super) fn repetition<'a, T, NT, AK, AV>(
+  rule: Rule<'a, T, NT, AK, AV>,
+) -> Option<Repetition<'a, T, NT>>
+where
+  T: PartialEq,
+  NT: PartialEq,
+{
+  let prods = rule
+    .prods()
+    .map(|prod| prod.elements().collect::<Vec<_>>())
+    .collect::<Vec<_>>();
+  if prods.len() != 2 {
+    return None;
+  }
+  let head = Elem::NonTerm(rule.head());
+  let is_head = |elem: &&Elem<T, NT>| elem.as_ref() == head;
+  let pairs = [(&prods[0], &prods[1]), (&prods[1], &prods[0])];
+
+  for &(base, other) in &pairs {
+    if base.iter().any(is_head) || other.is_empty() {
+      continue;
+    }
+    let body = if other.len() < 2 {
+      None
+    } else if other.first().is_some_and(is_head) {
+      Some(&other[1..])
+    } else if other.last().is_some_and(is_head) {
+      Some(&other[..other.len() - 1])
+    } else {
+      None
+    };
+    match body {
+      Some(body) if !body.iter().any(is_head) => {
+        if base.is_empty() {
+          return Some(Repetition::Star(body.to_vec()));
+        } else if base == body {
+          return Some(Repetition::Plus(body.to_vec()));
+        }
+      }
+      _ => {}
+    }
+  }
+
+  for &(base, other) in &pairs {
+    if base.is_empty() && !other.is_empty() && !other.iter().any(is_head) {
+      return Some(Repetition::Optional(other.clone()));
+    }
+  }
+  None
+}
+
+/// An EBNF expression.
+enum Expr {
+  Symbol(String),
+  Sequence(Vec<Expr>),
+  Choice(Vec<Expr>),
+  /// A sequence followed by one of `*`, `+` or `?`. It is grouped when it is
+  /// itself the body of a postfix expression, as in `( B+ )*`.
+  Postfix(Box<Expr>, char),
+}
+
+/// How tightly the context of an expression binds, from loosest to
+/// tightest.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Context {
+  Rule,
+  Sequence,
+  Postfix,
+}
+
+impl Expr {
+  fn write(&self, context: Context, out: &mut String) {
+    match self {
+      Expr::Symbol(name) => out.push_str(name),
+      Expr::Sequence(items) if items.is_empty() => out.push('ε'),
+      Expr::Sequence(items) if items.len() == 1 => items[0].write(context, out),
+      Expr::Sequence(items) => {
+        Self::write_grouped(context >= Context::Postfix, out, |out| {
+          for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+              out.push(' ');
+            }
+            item.write(Context::Sequence, out);
+          }
+        })
+      }
+      Expr::Choice(branches) => {
+        Self::write_grouped(context >= Context::Sequence, out, |out| {
+          for (i, branch) in branches.iter().enumerate() {
+            if i > 0 {
+              out.push_str(" | ");
+            }
+            branch.write(Context::Rule, out);
+          }
+        })
+      }
+      Expr::Postfix(body, op) => {
+        Self::write_grouped(context >= Context::Postfix, out, |out| {
+          body.write(Context::Postfix, out);
+          out.push(*op);
+        })
+      }
+    }
+  }
+
+  fn write_grouped(
+    grouped: bool,
+    out: &mut String,
+    write: impl FnOnce(&mut String),
+  ) {
+    if grouped {
+      out.push_str("( ");
+    }
+    write(out);
+    if grouped {
+      out.push_str(" )");
+    }
+  }
+}
+
+/// Converts rules to expressions, inlining the rules in `inlined`.
+struct Writer<'a, T, NT, AK, AV> {
+  grammar: &'a Grammar<T, NT, AK, AV>,
+  inlined: BTreeSet<&'a NT>,
+  /// The inlined rules that have been written at their use.
+  expanded: BTreeSet<&'a NT>,
+  /// The inlined rules currently being expanded, to guard against cycles
+  /// of rules that are only used by each other.
+  expanding: Vec<&'a NT>,
+}
+
+impl<'a, T, NT, AK, AV> Writer<'a, T, NT, AK, AV>
+where
+  T: ToDoc + PartialEq,
+  NT: ToDoc + Ord + Clone,
+  AK: Ord + Clone,
+{
+  fn rule_expr(&mut self, rule: Rule<'a, T, NT, AK, AV>) -> Expr {
+    let repeated = match repetition(rule) {
+      Some(Repetition::Star(body)) => Some((body, '*')),
+      Some(Repetition::Plus(body)) => Some((body, '+')),
+      Some(Repetition::Optional(body)) => Some((body, '?')),
+      None => None,
+    };
+    if let Some((body, op)) = repeated {
+      let body = self.sequence_expr(body);
+      return Expr::Postfix(Box::new(body), op);
+    }
+
+    let mut branches = rule
+      .prods()
+      .map(|prod| self.sequence_expr(prod.elements().collect()))
+      .collect::<Vec<_>>();
+    if branches.len() == 1 {
+      branches.pop().unwrap()
+    } else {
+      Expr::Choice(branches)
+    }
+  }
+
+  fn sequence_expr(&mut self, elems: Vec<&'a Elem<T, NT>>) -> Expr {
+    Expr::Sequence(elems.into_iter().map(|elem| self.elem_expr(elem)).collect())
+  }
+
+  fn elem_expr(&mut self, elem: &'a Elem<T, NT>) -> Expr {
+    match elem {
+      Elem::Term(t) => Expr::Symbol(doc_string(t)),
+      Elem::NonTerm(nt) => {
+        if !self.inlined.contains(nt) || self.expanding.contains(&nt) {
+          return Expr::Symbol(doc_string(nt));
+        }
+        self.expanded.insert(nt);
+        self.expanding.push(nt);
+        let expr = self.rule_expr(self.grammar.get_rule(nt));
+        self.expanding.pop();
+        expr
+      }
+    }
+  }
+}
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: ToDoc + PartialEq,
+  NT: ToDoc + Ord + Clone,
+  AK: Ord + Clone,
+{
+  /// Renders the grammar as EBNF, with one `head = expression ;` line per
+  /// rule, starting with the start rule.
+  ///
+  /// Lists and optional sequences are written with `*`, `+` and `?`. A rule
+  /// generated for an inline alternation that is only used once, and is not
+  /// the start rule, is inlined at its use as a group instead of being
+  /// written on its own line. Terminals and nonterminals
+  /// are both written by name, and empty sequences as `ε`.
+  pub fn to_ebnf(&self) -> String {
+    let mut uses = BTreeMap::<&NT, usize>::new();
+    for rule in self.rules() {
+      let used = rule.prods().flat_map(|prod| prod.elements());
+      for nt in used.filter_map(|elem| elem.as_nonterm()) {
+        if nt != rule.head() {
+          *uses.entry(nt).or_insert(0) += 1;
+        }
+      }
+    }
+    let inlined = self
+      .rules()
+      .filter(|rule| {
+        rule.head() != self.start_nt()
+          && uses.get(rule.head()) == Some(&1)
+          && rule.is_generated()
+      })
+      .map(|rule| rule.head())
+      .collect();
+
+    let mut writer = Writer {
+      grammar: self,
+      inlined,
+      expanded: BTreeSet::new(),
+      expanding: Vec::new(),
+    };
+    let rules = self.rules_in_order(RuleOrder::StartFirst);
+    let mut lines = Vec::new();
+    for pass in 0..2 {
+      for &rule in &rules {
+        // Inlined rules are only written on their own if they are not
+        // reachable from any other rule, which takes a second pass to tell.
+        let own_line = if pass == 0 {
+          !writer.inlined.contains(rule.head())
+        } else {
+          writer.inlined.contains(rule.head())
+            && !writer.expanded.contains(rule.head())
+        };
+        if !own_line {
+          continue;
+        }
+        writer.expanding = vec![rule.head()];
+        let expr = writer.rule_expr(rule);
+        let mut line = format!("{} = ", doc_string(rule.head()));
+        expr.write(Context::Rule, &mut line);
+        line.push_str(" ;");
+        lines.push(line);
+      }
+    }
+    lines.join("\n")
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::grammar::{build, examples, Grammar, NonTerminal, Terminal};
+  use crate::utils::Name;
+
+  #[test]
+  fn test_repetitions() {
+    // `expr_list` is a rule of the grammar, so it is kept rather than
+    // inlined.
+    assert_eq!(
+      examples::make_paren().to_ebnf(),
+      "start = expr ;\nexpr = LPAREN expr_list RPAREN ;\nexpr_list = expr* ;"
+    );
+
+    let g = examples::from_rules(&[
+      ("s", &[&["items", "opt", "A"], &["items", "B"]]),
+      ("items", &[&["C", "D"], &["items", "C", "D"]]),
+      ("opt", &[&[], &["A", "B"]]),
+    ]);
+    assert_eq!(
+      g.to_ebnf(),
+      "s = items opt A | items B ;\nitems = ( C D )+ ;\nopt = ( A B )? ;"
+    );
+  }
+
+  #[test]
+  fn test_nested_repetitions() {
+    // The alternation is an optional sequence, inlined as the body of the
+    // list.
+    let g: Grammar<Terminal, NonTerminal, Name, ()> =
+      build(NonTerminal::new("s"), |gb| {
+        gb.add_rule(NonTerminal::new("s"), |rb| {
+          rb.add_prod("s", (), |pb| {
+            pb.add_term(Terminal::new("A"))
+              .add_nonterm(NonTerminal::new("outer"));
+          });
+        })
+        .add_rule(NonTerminal::new("outer"), |rb| {
+          rb.add_prod("empty", (), |_| {}).add_prod("more", (), |pb| {
+            pb.add_nonterm(NonTerminal::new("outer"))
+              .add_alternation(|ab| {
+                ab.alt(|_| {}).alt(|pb| {
+                  pb.add_term(Terminal::new("B")).add_term(Terminal::new("C"));
+                });
+              });
+          });
+        });
+      })
+      .unwrap();
+    assert_eq!(g.to_ebnf(), "s = A outer ;\nouter = ( ( B C )? )* ;");
+  }
+
+  #[test]
+  fn test_alternations() {
+    let g: Grammar<Terminal, NonTerminal, Name, ()> =
+      build(NonTerminal::new("s"), |gb| {
+        gb.add_rule(NonTerminal::new("s"), |rb| {
+          rb.add_prod("s", (), |pb| {
+            pb.add_term(Terminal::new("A")).add_alternation(|ab| {
+              ab.alt(|pb| {
+                pb.add_term(Terminal::new("B"));
+              })
+              .alt(|pb| {
+                pb.add_term(Terminal::new("C")).add_term(Terminal::new("D"));
+              });
+            });
+          });
+        });
+      })
+      .unwrap();
+    assert_eq!(g.to_ebnf(), "s = A ( B | C D ) ;");
+
+    // A rule the user wrote is kept, even if its name looks generated.
+    let g = examples::from_rules(&[
+      ("s", &[&["A", "s__alt__1"]]),
+      ("s__alt__1", &[&["B"], &["C", "D"]]),
+    ]);
+    assert_eq!(g.to_ebnf(), "s = A s__alt__1 ;\ns__alt__1 = B | C D ;");
+  }
+}
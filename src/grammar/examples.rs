@@ -13,10 +13,55 @@
 // limitations under the License.
 
 use crate::{
-  grammar::{build, Grammar, NonTerminal, Terminal},
+  grammar::{
+    build, build_lenient, Grammar, GrammarBuilder, NonTerminal, Terminal,
+  },
   utils::Name,
 };
 
+/// Builds a grammar from a compact description, for use in tests.
+///
+/// Each entry is a rule head followed by the bodies of its productions.
+/// Symbols starting with an uppercase letter are terminals, and all others
+/// are nonterminals. The first rule's head is the start nonterminal, and
+/// productions are named `p0`, `p1`, etc. within each rule.
+pub fn from_rules(
+  rules: &[(&str, &[&[&str]])],
+) -> Grammar<Terminal, NonTerminal, Name, ()> {
+  build(NonTerminal::new(rules[0].0), |gb| add_rules(gb, rules)).unwrap()
+}
+
+/// Like `from_rules`, but builds the grammar with `build_lenient`, so the
+/// rules may refer to nonterminals that have no rule.
+pub fn from_rules_lenient(
+  rules: &[(&str, &[&[&str]])],
+) -> Grammar<Terminal, NonTerminal, Name, ()> {
+  let (g, _) =
+    build_lenient(NonTerminal::new(rules[0].0), |gb| add_rules(gb, rules));
+  g
+}
+
+fn add_rules(
+  gb: &mut GrammarBuilder<Terminal, NonTerminal, Name, ()>,
+  rules: &[(&str, &[&[&str]])],
+) {
+  for (head, prods) in rules {
+    gb.add_rule(NonTerminal::new(head), |rb| {
+      for (i, body) in prods.iter().enumerate() {
+        rb.add_prod(Name::new(&format!("p{}", i)), (), |pb| {
+          for sym in body.iter() {
+            if sym.starts_with(|c: char| c.is_ascii_uppercase()) {
+              pb.add_term(Terminal::new(sym));
+            } else {
+              pb.add_nonterm(NonTerminal::new(sym));
+            }
+          }
+        });
+      }
+    });
+  }
+}
+
 pub fn make_simple() -> Grammar<Terminal, NonTerminal, Name, ()> {
   let start = NonTerminal::new("start");
   let a_nt = NonTerminal::new("a");
@@ -147,3 +192,248 @@ pub fn make_ambiguous_nullable() -> Grammar<Terminal, NonTerminal, Name, ()> {
   })
   .unwrap()
 }
+
+/// Sums of numbers and parenthesized sums, such as `NUM PLUS NUM`, with a
+/// left-recursive `expr` rule.
+pub fn make_left_recursive() -> Grammar<Terminal, NonTerminal, Name, ()> {
+  let expr = NonTerminal::new("expr");
+  let atom = NonTerminal::new("atom");
+
+  let plus = Terminal::new("PLUS");
+  let num = Terminal::new("NUM");
+  let lparen = Terminal::new("LPAREN");
+  let rparen = Terminal::new("RPAREN");
+
+  build(&expr, |b| {
+    b.add_rule(&expr, |b| {
+      b.add_prod("plus", (), |b| {
+        b.add_named_nonterm("left", &expr)
+          .add_term(&plus)
+          .add_named_nonterm("right", &atom);
+      })
+      .add_prod("atom", (), |b| {
+        b.add_named_nonterm("value", &atom);
+      });
+    })
+    .add_rule(&atom, |b| {
+      b.add_prod("num", (), |b| {
+        b.add_term(&num);
+      })
+      .add_prod("paren", (), |b| {
+        b.add_term(&lparen)
+          .add_named_nonterm("contents", &expr)
+          .add_term(&rparen);
+      });
+    });
+  })
+  .unwrap()
+}
+
+/// Arithmetic expressions over `ID`, with `PLUS`, `TIMES` and parentheses,
+/// such as `ID PLUS ID TIMES LPAREN ID RPAREN`.
+///
+/// The usual `expr`/`term`/`factor` layering makes `TIMES` bind tighter than
+/// `PLUS`, and both are left associative, so the grammar is unambiguous and
+/// LALR(1), but left recursive.
+pub fn make_arithmetic() -> Grammar<Terminal, NonTerminal, Name, ()> {
+  let expr = NonTerminal::new("expr");
+  let term = NonTerminal::new("term");
+  let factor = NonTerminal::new("factor");
+
+  let plus = Terminal::new("PLUS");
+  let times = Terminal::new("TIMES");
+  let lparen = Terminal::new("LPAREN");
+  let rparen = Terminal::new("RPAREN");
+  let id = Terminal::new("ID");
+
+  build(&expr, |b| {
+    b.add_rule(&expr, |b| {
+      b.add_prod("plus", (), |b| {
+        b.add_named_nonterm("left", &expr)
+          .add_term(&plus)
+          .add_named_nonterm("right", &term);
+      })
+      .add_prod("term", (), |b| {
+        b.add_named_nonterm("value", &term);
+      });
+    })
+    .add_rule(&term, |b| {
+      b.add_prod("times", (), |b| {
+        b.add_named_nonterm("left", &term)
+          .add_term(&times)
+          .add_named_nonterm("right", &factor);
+      })
+      .add_prod("factor", (), |b| {
+        b.add_named_nonterm("value", &factor);
+      });
+    })
+    .add_rule(&factor, |b| {
+      b.add_prod("id", (), |b| {
+        b.add_term(&id);
+      })
+      .add_prod("paren", (), |b| {
+        b.add_term(&lparen)
+          .add_named_nonterm("contents", &expr)
+          .add_term(&rparen);
+      });
+    });
+  })
+  .unwrap()
+}
+
+/// JSON values, over one terminal per token kind: `LBRACE`, `RBRACE`,
+/// `LBRACKET`, `RBRACKET`, `COMMA`, `COLON`, `STRING`, `NUMBER`, `TRUE`,
+/// `FALSE` and `NULL`. For example, `LBRACE STRING COLON NUMBER RBRACE`.
+///
+/// Objects and arrays may be empty, and their members and elements are
+/// separated by commas, without a trailing comma.
+pub fn make_json() -> Grammar<Terminal, NonTerminal, Name, ()> {
+  let value = NonTerminal::new("value");
+  let object = NonTerminal::new("object");
+  let members = NonTerminal::new("members");
+  let pair = NonTerminal::new("pair");
+  let array = NonTerminal::new("array");
+  let elements = NonTerminal::new("elements");
+
+  let lbrace = Terminal::new("LBRACE");
+  let rbrace = Terminal::new("RBRACE");
+  let lbracket = Terminal::new("LBRACKET");
+  let rbracket = Terminal::new("RBRACKET");
+  let comma = Terminal::new("COMMA");
+  let colon = Terminal::new("COLON");
+  let string = Terminal::new("STRING");
+
+  build(&value, |b| {
+    b.add_rule(&value, |b| {
+      b.add_prod("object", (), |b| {
+        b.add_named_nonterm("value", &object);
+      })
+      .add_prod("array", (), |b| {
+        b.add_named_nonterm("value", &array);
+      });
+      for literal in &["STRING", "NUMBER", "TRUE", "FALSE", "NULL"] {
+        b.add_prod(Name::new(&literal.to_lowercase()), (), |b| {
+          b.add_term(Terminal::new(literal));
+        });
+      }
+    })
+    .add_rule(&object, |b| {
+      b.add_prod("empty", (), |b| {
+        b.add_term(&lbrace).add_term(&rbrace);
+      })
+      .add_prod("members", (), |b| {
+        b.add_term(&lbrace)
+          .add_named_nonterm("members", &members)
+          .add_term(&rbrace);
+      });
+    })
+    .add_rule(&members, |b| {
+      b.add_prod("last", (), |b| {
+        b.add_named_nonterm("pair", &pair);
+      })
+      .add_prod("more", (), |b| {
+        b.add_named_nonterm("pair", &pair)
+          .add_term(&comma)
+          .add_named_nonterm("rest", &members);
+      });
+    })
+    .add_rule(&pair, |b| {
+      b.add_prod("pair", (), |b| {
+        b.add_named_term("key", &string)
+          .add_term(&colon)
+          .add_named_nonterm("value", &value);
+      });
+    })
+    .add_rule(&array, |b| {
+      b.add_prod("empty", (), |b| {
+        b.add_term(&lbracket).add_term(&rbracket);
+      })
+      .add_prod("elements", (), |b| {
+        b.add_term(&lbracket)
+          .add_named_nonterm("elements", &elements)
+          .add_term(&rbracket);
+      });
+    })
+    .add_rule(&elements, |b| {
+      b.add_prod("last", (), |b| {
+        b.add_named_nonterm("value", &value);
+      })
+      .add_prod("more", (), |b| {
+        b.add_named_nonterm("value", &value)
+          .add_term(&comma)
+          .add_named_nonterm("rest", &elements);
+      });
+    });
+  })
+  .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::Ll1Blocker,
+    parsers::{earley, tree::TreeOwner, Token},
+    start_grammar::wrap_grammar_with_start,
+  };
+
+  fn tokens(kinds: &[&str]) -> Vec<Token<Terminal, ()>> {
+    kinds
+      .iter()
+      .map(|kind| Token::new(Terminal::new(kind), ()))
+      .collect()
+  }
+
+  #[test]
+  fn test_arithmetic() {
+    let g = wrap_grammar_with_start(make_arithmetic()).unwrap();
+    let tree: TreeOwner<_, _, ()> = TreeOwner::new();
+    let parse = |input: &str| {
+      let kinds = input
+        .chars()
+        .map(|c| match c {
+          '+' => "PLUS",
+          '*' => "TIMES",
+          '(' => "LPAREN",
+          ')' => "RPAREN",
+          _ => "ID",
+        })
+        .collect::<Vec<_>>();
+      earley::parse(&g, &tree.handle(), tokens(&kinds)).is_some()
+    };
+    assert!(parse("a+a*a"));
+    assert!(parse("(a+a)*a"));
+    assert!(!parse("a+*a"));
+    assert!(!parse("(a"));
+  }
+
+  #[test]
+  fn test_json() {
+    let g = wrap_grammar_with_start(make_json()).unwrap();
+    let tree: TreeOwner<_, _, ()> = TreeOwner::new();
+    let parse = |kinds: &[&str]| {
+      earley::parse(&g, &tree.handle(), tokens(kinds)).is_some()
+    };
+    assert!(parse(&["NULL"]));
+    assert!(parse(&["LBRACE", "RBRACE"]));
+    assert!(parse(&[
+      "LBRACE", "STRING", "COLON", "LBRACKET", "NUMBER", "COMMA", "TRUE",
+      "RBRACKET", "RBRACE",
+    ]));
+    assert!(!parse(&["LBRACKET", "NUMBER", "COMMA", "RBRACKET"]));
+    assert!(!parse(&["LBRACE", "STRING", "RBRACE"]));
+  }
+
+  #[test]
+  fn test_left_recursive() {
+    let is_left_recursive = |g: &Grammar<Terminal, NonTerminal, Name, ()>| {
+      g.ll1_blockers()
+        .iter()
+        .any(|blocker| matches!(blocker, Ll1Blocker::LeftRecursion { .. }))
+    };
+    assert!(is_left_recursive(&make_left_recursive()));
+    assert!(is_left_recursive(&make_arithmetic()));
+    assert!(!is_left_recursive(&make_json()));
+    assert!(!is_left_recursive(&make_paren()));
+  }
+}
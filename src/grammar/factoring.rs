@@ -0,0 +1,167 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An advisory report of repeated sequences of elements that could be
+//! factored out into their own nonterminals.
+
+use {
+  crate::grammar::{Elem, Grammar, ProdKey},
+  std::collections::BTreeMap,
+};
+
+/// A sequence of at least two elements that appears in more than one place
+/// in a grammar.
+///
+/// Replacing each occurrence with a new nonterminal that derives `elements`
+/// would not change the language of the grammar.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FactoringSuggestion<T, NT, AK> {
+  pub elements: Vec<Elem<T, NT>>,
+  /// Each production that contains the sequence, with the index at which
+  /// the sequence begins. Occurrences within a production do not overlap.
+  pub occurrences: Vec<(ProdKey<NT, AK>, usize)>,
+}
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  /// Returns the repeated sequences of elements in this grammar, most
+  /// frequent first, with longer sequences first among those that occur
+  /// equally often.
+  ///
+  /// A sequence is left out if it only ever occurs within a longer
+  /// suggested sequence, since factoring out the longer one covers it.
+  pub fn suggest_factoring(&self) -> Vec<FactoringSuggestion<T, NT, AK>> {
+    let mut occurrences = BTreeMap::<_, Vec<_>>::new();
+    for prod in self.prods() {
+      let elems = prod.elements().collect::<Vec<_>>();
+      for len in 2..=elems.len() {
+        let mut next_free = BTreeMap::new();
+        for start in 0..=elems.len() - len {
+          let seq = &elems[start..start + len];
+          if next_free.get(seq).is_some_and(|free| start < *free) {
+            continue;
+          }
+          next_free.insert(seq, start + len);
+          occurrences
+            .entry(seq.to_vec())
+            .or_default()
+            .push((prod.prod_key(), start));
+        }
+      }
+    }
+
+    let repeated = occurrences
+      .into_iter()
+      .filter(|(_, occurrences)| occurrences.len() > 1)
+      .collect::<Vec<_>>();
+    let mut suggestions = repeated
+      .iter()
+      .filter(|(seq, occurrences)| {
+        !repeated.iter().any(|(longer, longer_occurrences)| {
+          longer.len() > seq.len()
+            && longer_occurrences.len() == occurrences.len()
+            && longer.windows(seq.len()).any(|window| window == &seq[..])
+        })
+      })
+      .map(|(seq, occurrences)| FactoringSuggestion {
+        elements: seq.iter().map(|elem| (*elem).clone()).collect(),
+        occurrences: occurrences.clone(),
+      })
+      .collect::<Vec<_>>();
+    suggestions.sort_by(|a, b| {
+      (b.occurrences.len(), b.elements.len())
+        .cmp(&(a.occurrences.len(), a.elements.len()))
+    });
+    suggestions
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{examples, NonTerminal, Terminal};
+  use crate::utils::Name;
+
+  fn key(head: &str, action: &str) -> ProdKey<NonTerminal, Name> {
+    ProdKey::new(NonTerminal::new(head), Name::new(action))
+  }
+
+  #[test]
+  fn test_parenthesized_expr() {
+    let g = examples::from_rules(&[
+      (
+        "stmt",
+        &[
+          &["IF", "LPAREN", "expr", "RPAREN", "stmt"],
+          &["WHILE", "LPAREN", "expr", "RPAREN", "stmt"],
+          &["expr", "SEMI"],
+        ],
+      ),
+      ("expr", &[&["LPAREN", "expr", "RPAREN"], &["ID"]]),
+    ]);
+
+    let paren = vec![
+      Elem::Term(Terminal::new("LPAREN")),
+      Elem::NonTerm(NonTerminal::new("expr")),
+      Elem::Term(Terminal::new("RPAREN")),
+    ];
+    let mut paren_stmt = paren.clone();
+    paren_stmt.push(Elem::NonTerm(NonTerminal::new("stmt")));
+    assert_eq!(
+      g.suggest_factoring(),
+      vec![
+        FactoringSuggestion {
+          elements: paren,
+          occurrences: vec![
+            (key("expr", "p0"), 0),
+            (key("stmt", "p0"), 1),
+            (key("stmt", "p1"), 1),
+          ],
+        },
+        FactoringSuggestion {
+          elements: paren_stmt,
+          occurrences: vec![(key("stmt", "p0"), 1), (key("stmt", "p1"), 1)],
+        },
+      ]
+    );
+
+    assert!(examples::make_paren().suggest_factoring().is_empty());
+  }
+
+  #[test]
+  fn test_overlapping_occurrences() {
+    // `A A` occurs twice in `A A A A`, but only once in `A A A`.
+    let g = examples::from_rules(&[(
+      "s",
+      &[&["A", "A", "A"], &["A", "A", "A", "A"]],
+    )]);
+    let suggestions = g.suggest_factoring();
+    let pair = suggestions
+      .iter()
+      .find(|suggestion| suggestion.elements.len() == 2)
+      .unwrap();
+    assert_eq!(
+      pair.occurrences,
+      vec![
+        (key("s", "p0"), 0),
+        (key("s", "p1"), 0),
+        (key("s", "p1"), 2)
+      ]
+    );
+  }
+}
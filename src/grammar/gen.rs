@@ -0,0 +1,557 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Enumeration and sampling of the sentences of a grammar.
+//!
+//! Language equivalence of context-free grammars is undecidable in general,
+//! but comparing every sentence up to a fixed length is a practical way to
+//! check that a transform preserves the language of a grammar.
+//!
+//! Random sentences, for example for fuzzing a parser, can be drawn with
+//! `sample_weighted`, using weights carried in the productions' action
+//! values.
+
+use {
+  crate::{
+    grammar::{Elem, Grammar, Prod},
+    utils::{change_iter, change_loop, WasChanged},
+  },
+  rand::Rng,
+  std::collections::{BTreeMap, BTreeSet},
+};
+
+/// Returns the set of all sentences of at most `max_len` terminals that are
+/// accepted by the grammar.
+///
+/// This computes, for each nonterminal, the set of bounded-length terminal
+/// sequences it derives, iterating until a fixed point is reached. The result
+/// is finite, so this terminates even for left-recursive or cyclic grammars.
+pub fn bounded_sentences<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+  max_len: usize,
+) -> BTreeSet<Vec<T>>
+where
+  T: Ord + Clone,
+  NT: Ord,
+{
+  let mut sentences: BTreeMap<&NT, BTreeSet<Vec<&T>>> = BTreeMap::new();
+
+  change_loop(|| {
+    change_iter(g.prods(), |prod| {
+      let mut partials = BTreeSet::new();
+      partials.insert(Vec::new());
+
+      for elem in prod.elements() {
+        let mut next_partials = BTreeSet::new();
+        match elem {
+          Elem::Term(t) => {
+            for mut partial in partials {
+              if partial.len() < max_len {
+                partial.push(t);
+                next_partials.insert(partial);
+              }
+            }
+          }
+          Elem::NonTerm(nt) => {
+            let suffixes = match sentences.get(nt) {
+              Some(suffixes) => suffixes,
+              None => return WasChanged::Unchanged,
+            };
+            for partial in &partials {
+              for suffix in suffixes {
+                if partial.len() + suffix.len() <= max_len {
+                  let mut next = partial.clone();
+                  next.extend(suffix.iter().copied());
+                  next_partials.insert(next);
+                }
+              }
+            }
+          }
+        }
+        partials = next_partials;
+      }
+
+      let head_sentences = sentences.entry(prod.head()).or_default();
+      change_iter(partials, |partial| {
+        WasChanged::from_changed(head_sentences.insert(partial))
+      })
+    })
+  });
+
+  sentences
+    .remove(g.start_nt())
+    .unwrap_or_default()
+    .into_iter()
+    .map(|sentence| sentence.into_iter().cloned().collect())
+    .collect()
+}
+
+/// Returns, for each nonterminal, the set of sentences of exactly `len`
+/// terminals that it derives.
+///
+/// `shorter[n]` must hold the result of this function for length `n`, for
+/// every `n` less than `len`.
+fn exact_length_sentences<'a, T, NT, AK, AV>(
+  g: &'a Grammar<T, NT, AK, AV>,
+  shorter: &[BTreeMap<&'a NT, BTreeSet<Vec<&'a T>>>],
+  len: usize,
+) -> BTreeMap<&'a NT, BTreeSet<Vec<&'a T>>>
+where
+  T: Ord,
+  NT: Ord,
+{
+  let mut current: BTreeMap<&NT, BTreeSet<Vec<&T>>> = BTreeMap::new();
+
+  change_loop(|| {
+    change_iter(g.prods(), |prod| {
+      let mut partials = BTreeSet::new();
+      partials.insert(Vec::new());
+
+      for elem in prod.elements() {
+        let mut next_partials = BTreeSet::new();
+        match elem {
+          Elem::Term(t) => {
+            for mut partial in partials {
+              if partial.len() < len {
+                partial.push(t);
+                next_partials.insert(partial);
+              }
+            }
+          }
+          Elem::NonTerm(nt) => {
+            for partial in &partials {
+              // Only a nonterminal that covers the whole sentence can need
+              // sentences of the length still being computed.
+              let remaining = len - partial.len();
+              let (complete, in_progress) = if remaining == len {
+                (shorter, Some(&current))
+              } else {
+                (&shorter[..=remaining], None)
+              };
+              for layer in complete.iter().chain(in_progress) {
+                for suffix in layer.get(nt).into_iter().flatten() {
+                  let mut next = partial.clone();
+                  next.extend(suffix.iter().copied());
+                  next_partials.insert(next);
+                }
+              }
+            }
+          }
+        }
+        partials = next_partials;
+      }
+
+      let head_sentences = current.entry(prod.head()).or_default();
+      change_iter(
+        partials.into_iter().filter(|partial| partial.len() == len),
+        |partial| WasChanged::from_changed(head_sentences.insert(partial)),
+      )
+    })
+  });
+
+  current
+}
+
+/// Which of the two compared grammars accepted a counterexample sentence.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AcceptedBy {
+  /// The grammar `bounded_equivalent` was called on.
+  This,
+  /// The grammar passed as an argument to `bounded_equivalent`.
+  Other,
+}
+
+/// A sentence that is accepted by exactly one of two grammars.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Counterexample<T> {
+  pub sentence: Vec<T>,
+  pub accepted_by: AcceptedBy,
+}
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: Ord + Clone,
+  NT: Ord,
+{
+  /// Checks that this grammar and `other` accept exactly the same sentences
+  /// of at most `max_len` terminals.
+  ///
+  /// If they do not, the shortest (and then smallest) sentence accepted by
+  /// only one of them is returned.
+  pub fn bounded_equivalent<NT2, AK2, AV2>(
+    &self,
+    other: &Grammar<T, NT2, AK2, AV2>,
+    max_len: usize,
+  ) -> Result<(), Counterexample<T>>
+  where
+    NT2: Ord,
+  {
+    let this_sentences = bounded_sentences(self, max_len);
+    let other_sentences = bounded_sentences(other, max_len);
+
+    let this_only = this_sentences
+      .difference(&other_sentences)
+      .map(|s| (s, AcceptedBy::This));
+    let other_only = other_sentences
+      .difference(&this_sentences)
+      .map(|s| (s, AcceptedBy::Other));
+
+    match this_only
+      .chain(other_only)
+      .min_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+    {
+      None => Ok(()),
+      Some((sentence, accepted_by)) => Err(Counterexample {
+        sentence: sentence.clone(),
+        accepted_by,
+      }),
+    }
+  }
+
+  /// Returns the shortest sentence of at most `max_len` terminals that
+  /// satisfies `pred`, preferring the lexicographically smallest among
+  /// sentences of the same length.
+  ///
+  /// Derivations are deepened one sentence length at a time. The sentences
+  /// of each length are built from those of all shorter lengths, which are
+  /// kept between steps, so a short match is found without enumerating
+  /// every sentence up to `max_len`.
+  pub fn find_shortest_matching(
+    &self,
+    pred: impl Fn(&[T]) -> bool,
+    max_len: usize,
+  ) -> Option<Vec<T>> {
+    let mut layers = Vec::new();
+    for len in 0..=max_len {
+      let layer = exact_length_sentences(self, &layers, len);
+      let found = layer.get(self.start_nt()).and_then(|sentences| {
+        sentences
+          .iter()
+          .map(|sentence| sentence.iter().copied().cloned().collect::<Vec<_>>())
+          .find(|sentence| pred(sentence))
+      });
+      if found.is_some() {
+        return found;
+      }
+      layers.push(layer);
+    }
+    None
+  }
+}
+
+/// An action value that may carry the weight of its production, for
+/// `sample_weighted`.
+pub trait Weight {
+  /// The relative weight of the production, or `None` for the default
+  /// weight of 1.
+  fn weight(&self) -> Option<u32>;
+}
+
+impl Weight for () {
+  fn weight(&self) -> Option<u32> {
+    None
+  }
+}
+
+impl Weight for u32 {
+  fn weight(&self) -> Option<u32> {
+    Some(*self)
+  }
+}
+
+impl Weight for Option<u32> {
+  fn weight(&self) -> Option<u32> {
+    *self
+  }
+}
+
+/// Returns a random sentence of the grammar, or `None` if the grammar
+/// accepts no sentences.
+///
+/// Each nonterminal is expanded by a production chosen with probability
+/// proportional to its weight. Productions that cannot derive any sentence
+/// are never chosen. Once the derivation is `max_depth` nonterminals deep,
+/// only the productions with the shortest derivations are chosen, so that
+/// sampling terminates for recursive grammars.
+pub fn sample_weighted<T, NT, AK, AV, R>(
+  g: &Grammar<T, NT, AK, AV>,
+  rng: &mut R,
+  max_depth: usize,
+) -> Option<Vec<T>>
+where
+  T: Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  AV: Weight,
+  R: Rng + ?Sized,
+{
+  let heights = derivation_heights(g);
+  if !heights.contains_key(g.start_nt()) {
+    return None;
+  }
+
+  let mut sentence = Vec::new();
+  let mut stack = vec![(Elem::<&T, &NT>::NonTerm(g.start_nt()), 0)];
+  while let Some((elem, depth)) = stack.pop() {
+    let nt = match elem {
+      Elem::Term(t) => {
+        sentence.push(t.clone());
+        continue;
+      }
+      Elem::NonTerm(nt) => nt,
+    };
+
+    let mut candidates = g
+      .get_rule(nt)
+      .prods()
+      .filter_map(|prod| prod_height(&heights, &prod).map(|h| (prod, h)))
+      .collect::<Vec<_>>();
+    if depth >= max_depth {
+      let min_height = heights[&nt];
+      candidates.retain(|(_, height)| *height == min_height);
+    }
+
+    let weights = candidates
+      .iter()
+      .map(|(prod, _)| prod.action_value().weight().unwrap_or(1) as u64)
+      .collect::<Vec<_>>();
+    let total = weights.iter().sum::<u64>();
+    let index = if total == 0 {
+      rng.gen_range(0..candidates.len())
+    } else {
+      let mut point = rng.gen_range(0..total);
+      weights
+        .iter()
+        .position(|weight| {
+          if point < *weight {
+            true
+          } else {
+            point -= weight;
+            false
+          }
+        })
+        .expect("the point is less than the total weight")
+    };
+
+    let (prod, _) = &candidates[index];
+    stack.extend(
+      prod
+        .prod_elements()
+        .iter()
+        .rev()
+        .map(|elem| (elem.elem().as_ref(), depth + 1)),
+    );
+  }
+
+  Some(sentence)
+}
+
+/// Returns the height of the shortest derivation tree of each nonterminal
+/// that derives at least one sentence.
+fn derivation_heights<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> BTreeMap<&NT, usize>
+where
+  NT: Ord,
+{
+  let mut heights = BTreeMap::new();
+  change_loop(|| {
+    change_iter(g.prods(), |prod| match prod_height(&heights, &prod) {
+      Some(height) if heights.get(prod.head()).is_none_or(|h| height < *h) => {
+        heights.insert(prod.head(), height);
+        WasChanged::Changed
+      }
+      _ => WasChanged::Unchanged,
+    })
+  });
+  heights
+}
+
+fn prod_height<T, NT, AK, AV>(
+  heights: &BTreeMap<&NT, usize>,
+  prod: &Prod<T, NT, AK, AV>,
+) -> Option<usize>
+where
+  NT: Ord,
+{
+  prod
+    .elements()
+    .filter_map(Elem::as_nonterm)
+    .map(|nt| heights.get(nt).copied())
+    .try_fold(0, |max, height| height.map(|h| max.max(h)))
+    .map(|max| max + 1)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{
+    build, examples,
+    transform::{gnf::to_gnf, nonnull::transform_to_nonnull},
+    NonTerminal, Terminal,
+  };
+  use crate::utils::Name;
+  use rand::{rngs::StdRng, SeedableRng};
+
+  fn terms(names: &[&str]) -> Vec<Terminal> {
+    names.iter().map(|name| Terminal::new(name)).collect()
+  }
+
+  #[test]
+  fn test_paren_sentences() {
+    let g = examples::make_paren();
+    let sentences = bounded_sentences(&g, 4);
+    let expected = vec![
+      terms(&["LPAREN", "LPAREN", "RPAREN", "RPAREN"]),
+      terms(&["LPAREN", "RPAREN"]),
+    ];
+    assert_eq!(sentences.into_iter().collect::<Vec<_>>(), expected);
+  }
+
+  #[test]
+  fn test_nullable_epsilon_removal_is_equivalent() {
+    // The language of the nullable example is just the empty sentence. The
+    // GNF transform removes every empty production but keeps an empty
+    // production for the start nonterminal, so the language is unchanged.
+    let g = examples::make_simple_nullable();
+    let removed = to_gnf(&g).unwrap();
+    assert!(removed
+      .prods()
+      .all(|prod| !prod.is_empty() || prod.head() == removed.start_nt()));
+    assert_eq!(g.bounded_equivalent(&removed, 5), Ok(()));
+    assert_eq!(removed.bounded_equivalent(&g, 5), Ok(()));
+
+    // `transform_to_nonnull` drops the empty sentence itself, which leaves
+    // no productions at all for this grammar.
+    assert!(transform_to_nonnull(&g).is_err());
+  }
+
+  #[test]
+  fn test_nonnull_transform_is_equivalent() {
+    let g = examples::make_paren();
+    let nonnull = transform_to_nonnull(&g).unwrap();
+    assert_eq!(g.bounded_equivalent(&nonnull, 5), Ok(()));
+    assert_eq!(nonnull.bounded_equivalent(&g, 5), Ok(()));
+  }
+
+  #[test]
+  fn test_counterexample() {
+    let paren = examples::make_paren();
+    let simple = examples::make_simple();
+    assert_eq!(
+      paren.bounded_equivalent(&simple, 3),
+      Err(Counterexample {
+        sentence: terms(&["A"]),
+        accepted_by: AcceptedBy::Other,
+      })
+    );
+
+    // Too short to see any sentence of either grammar.
+    assert_eq!(paren.bounded_equivalent(&simple, 0), Ok(()));
+  }
+
+  #[test]
+  fn test_sample_weighted() {
+    let s = NonTerminal::new("s");
+    let g: Grammar<Terminal, NonTerminal, Name, u32> = build(&s, |gb| {
+      gb.add_rule(&s, |rb| {
+        rb.add_prod("heavy", 99, |pb| {
+          pb.add_term(Terminal::new("A"));
+        })
+        .add_prod("light", 1, |pb| {
+          pb.add_term(Terminal::new("B"));
+        });
+      });
+    })
+    .unwrap();
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let samples = 10000;
+    let heavy = (0..samples)
+      .filter(|_| sample_weighted(&g, &mut rng, 10).unwrap() == terms(&["A"]))
+      .count();
+    let ratio = heavy as f64 / samples as f64;
+    assert!((0.98..=1.0).contains(&ratio), "ratio was {}", ratio);
+    assert!(heavy < samples);
+
+    // Without weights, productions are chosen uniformly.
+    let g = examples::from_rules(&[("s", &[&["A"], &["B"]])]);
+    let a_count = (0..samples)
+      .filter(|_| sample_weighted(&g, &mut rng, 10).unwrap() == terms(&["A"]))
+      .count();
+    let ratio = a_count as f64 / samples as f64;
+    assert!((0.47..=0.53).contains(&ratio), "ratio was {}", ratio);
+  }
+
+  #[test]
+  fn test_sample_recursive() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let g = examples::make_left_recursive();
+    let sentences = bounded_sentences(&g, 7);
+    for _ in 0..100 {
+      let sentence = sample_weighted(&g, &mut rng, 3).unwrap();
+      if sentence.len() <= 7 {
+        assert!(sentences.contains(&sentence));
+      }
+    }
+
+    // No sentence can be derived without a base case.
+    let g = examples::from_rules(&[("s", &[&["s", "A"]])]);
+    assert_eq!(sample_weighted(&g, &mut rng, 3), None);
+  }
+
+  #[test]
+  fn test_find_shortest_matching() {
+    let g = examples::make_paren();
+    let lparen = Terminal::new("LPAREN");
+    let depth = |sentence: &[Terminal]| {
+      let mut curr = 0usize;
+      let mut max = 0;
+      for t in sentence {
+        if *t == lparen {
+          curr += 1;
+          max = max.max(curr);
+        } else {
+          curr -= 1;
+        }
+      }
+      max
+    };
+
+    assert_eq!(
+      g.find_shortest_matching(|s| depth(s) >= 2, 10),
+      Some(terms(&["LPAREN", "LPAREN", "RPAREN", "RPAREN"]))
+    );
+    assert_eq!(
+      g.find_shortest_matching(|s| s.len() > 2, 10),
+      Some(terms(&["LPAREN", "LPAREN", "RPAREN", "RPAREN"]))
+    );
+    assert_eq!(g.find_shortest_matching(|s| depth(s) >= 2, 3), None);
+
+    // The incremental search agrees with a full enumeration, including
+    // through nullable and left-recursive rules.
+    for g in &[
+      examples::make_simple_nullable(),
+      examples::make_left_recursive(),
+    ] {
+      let sentences = bounded_sentences(g, 4);
+      for len in 0..=4 {
+        assert_eq!(
+          g.find_shortest_matching(|s| s.len() == len, 4),
+          sentences.iter().find(|s| s.len() == len).cloned()
+        );
+      }
+    }
+  }
+}
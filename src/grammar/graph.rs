@@ -0,0 +1,157 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The dependency graph between the nonterminals of a grammar.
+
+use {
+  crate::grammar::{passes::nullable::NullableSet, Elem, Grammar, Prod},
+  std::collections::{BTreeMap, BTreeSet},
+};
+
+/// The left corners of the productions of each nonterminal, as returned by
+/// `Grammar::left_corners`: each nonterminal that a production may begin
+/// with, the production, and the index of the nonterminal in it.
+pub(crate) type LeftCorners<'a, T, NT, AK, AV> =
+  BTreeMap<&'a NT, Vec<(&'a NT, Prod<'a, T, NT, AK, AV>, usize)>>;
+
+/// A directed graph with a node for each nonterminal of a grammar, and an
+/// edge from the head of each rule to every nonterminal used in its
+/// productions.
+///
+/// Nodes are numbered from zero, in the order of their nonterminals, so the
+/// graph can be handed to external graph libraries. For example,
+/// `petgraph::Graph::from_edges(graph.edges())` builds the same graph, with
+/// the same node indices.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NonTermGraph<'a, NT> {
+  nodes: Vec<&'a NT>,
+  indices: BTreeMap<&'a NT, usize>,
+  edges: Vec<(usize, usize)>,
+  successors: Vec<Vec<usize>>,
+}
+
+impl<'a, NT> NonTermGraph<'a, NT>
+where
+  NT: Ord,
+{
+  /// Returns the nonterminal of each node, by node index.
+  pub fn nodes(&self) -> &[&'a NT] {
+    &self.nodes
+  }
+
+  /// Returns the index of the node of `nt`, if it is in the grammar.
+  pub fn index_of(&self, nt: &NT) -> Option<usize> {
+    self.indices.get(nt).copied()
+  }
+
+  /// Returns every edge as a pair of node indices, in order. A rule that
+  /// uses a nonterminal more than once has a single edge to it.
+  pub fn edges(&self) -> &[(usize, usize)] {
+    &self.edges
+  }
+
+  /// Returns the nodes that `node` has an edge to, in order.
+  pub fn successors(&self, node: usize) -> &[usize] {
+    &self.successors[node]
+  }
+}
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  NT: Ord,
+{
+  /// Returns the dependency graph between the nonterminals of the grammar.
+  ///
+  /// This includes nonterminals that are used, but have no rule.
+  pub fn nonterm_graph(&self) -> NonTermGraph<'_, NT> {
+    let mut deps: BTreeMap<&NT, BTreeSet<&NT>> = BTreeMap::new();
+    for rule in self.rules() {
+      let used = rule
+        .prods()
+        .flat_map(|prod| prod.elements())
+        .filter_map(Elem::as_nonterm);
+      deps.entry(rule.head()).or_default().extend(used);
+    }
+    let nonterms = deps.values().flatten().copied().collect::<Vec<_>>();
+    for nt in nonterms {
+      deps.entry(nt).or_default();
+    }
+
+    let nodes = deps.keys().copied().collect::<Vec<_>>();
+    let indices = nodes
+      .iter()
+      .enumerate()
+      .map(|(i, nt)| (*nt, i))
+      .collect::<BTreeMap<_, _>>();
+    let successors = deps
+      .values()
+      .map(|used| used.iter().map(|nt| indices[nt]).collect::<Vec<_>>())
+      .collect::<Vec<_>>();
+    let edges = successors
+      .iter()
+      .enumerate()
+      .flat_map(|(from, to)| to.iter().map(move |to| (from, *to)))
+      .collect();
+    NonTermGraph {
+      nodes,
+      indices,
+      edges,
+      successors,
+    }
+  }
+
+  /// Returns an edge from the head of each production to every nonterminal
+  /// that may begin it, which is each nonterminal that follows a nullable
+  /// prefix of the production. Left recursion is a cycle in this graph.
+  pub(crate) fn left_corners(
+    &self,
+    nullables: &NullableSet<NT>,
+  ) -> LeftCorners<'_, T, NT, AK, AV> {
+    let mut edges: LeftCorners<T, NT, AK, AV> = BTreeMap::new();
+    for prod in self.prods() {
+      for (i, elem) in prod.elements().enumerate() {
+        let nt = match elem {
+          Elem::NonTerm(nt) => nt,
+          Elem::Term(_) => break,
+        };
+        edges.entry(prod.head()).or_default().push((nt, prod, i));
+        if !nullables.is_nullable(nt) {
+          break;
+        }
+      }
+    }
+    edges
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::grammar::{examples, NonTerminal};
+
+  #[test]
+  fn test_paren_graph() {
+    let g = examples::make_paren();
+    let graph = g.nonterm_graph();
+    let nodes = ["expr", "expr_list", "start"]
+      .iter()
+      .map(|name| NonTerminal::new(name))
+      .collect::<Vec<_>>();
+    assert_eq!(graph.nodes(), nodes.iter().collect::<Vec<_>>().as_slice());
+    assert_eq!(graph.index_of(&NonTerminal::new("start")), Some(2));
+    assert_eq!(graph.index_of(&NonTerminal::new("missing")), None);
+    // start -> expr, expr -> expr_list, expr_list -> expr, expr_list.
+    assert_eq!(graph.edges(), &[(0, 1), (1, 0), (1, 1), (2, 0)]);
+    assert_eq!(graph.successors(1), &[0, 1]);
+  }
+}
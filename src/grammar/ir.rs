@@ -0,0 +1,719 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A stable, versioned intermediate representation of grammars.
+//!
+//! The IR is meant to be consumed by external tools (editors, documentation
+//! generators, etc.), so it does not mirror the in-memory layout of
+//! `Grammar`. Instead, all symbols and action keys are listed once in their
+//! own tables, and rules refer to them by integer index:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "terminals": ["A"],
+//!   "nonterminals": ["x"],
+//!   "actions": ["Empty", "Recursive"],
+//!   "start": 0,
+//!   "rules": [
+//!     {
+//!       "head": 0,
+//!       "prods": [
+//!         { "action": 1, "elements": [{ "term": 0 }, { "nonterm": 0 }] },
+//!         { "action": 0, "elements": [] }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Action values are not part of the IR.
+//!
+//! The results of the nullable, FIRST and FOLLOW analyses can be exported
+//! alongside the grammar as an `AnalysisIr`, which names symbols directly
+//! rather than by index.
+
+use {
+  crate::{
+    grammar::{
+      build,
+      passes::{
+        firsts::Firsts,
+        follows::Follows,
+        nullable::{GrammarNullableInfo, Nullable, NullableSet},
+        symbols::TermSet,
+        PassContext,
+      },
+      Elem, Grammar, GrammarErrors, NonTerminal, ProdElement, ProdKey,
+      RuleOrder, Terminal,
+    },
+    utils::{Name, TreeNode, TreeValue, Void},
+  },
+  serde::{Deserialize, Serialize},
+  std::collections::{BTreeMap, BTreeSet},
+};
+
+/// The current version of the IR format. This is incremented whenever the
+/// format changes in an incompatible way.
+pub const IR_VERSION: u32 = 1;
+
+/// A symbol within a production element.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolIr {
+  /// An index into `GrammarIr::terminals`.
+  Term(usize),
+  /// An index into `GrammarIr::nonterminals`.
+  NonTerm(usize),
+}
+
+/// A single element of a production.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ElemIr {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  #[serde(flatten)]
+  pub symbol: SymbolIr,
+}
+
+/// A production of a rule.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ProdIr {
+  /// An index into `GrammarIr::actions`.
+  pub action: usize,
+  pub elements: Vec<ElemIr>,
+}
+
+/// A rule, consisting of a head nonterminal and its productions.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct RuleIr {
+  /// An index into `GrammarIr::nonterminals`.
+  pub head: usize,
+  pub prods: Vec<ProdIr>,
+}
+
+/// The intermediate representation of a whole grammar.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct GrammarIr {
+  pub version: u32,
+  pub terminals: Vec<String>,
+  pub nonterminals: Vec<String>,
+  pub actions: Vec<String>,
+  /// An index into `nonterminals`.
+  pub start: usize,
+  pub rules: Vec<RuleIr>,
+}
+
+impl GrammarIr {
+  /// Renders this IR as a JSON string.
+  pub fn to_json(&self) -> String {
+    serde_json::to_string_pretty(self).expect("IR is always serializable")
+  }
+
+  /// Parses an IR value from a JSON string.
+  pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+    serde_json::from_str(json)
+  }
+}
+
+/// A type that can be named in the IR.
+pub trait IrName: Sized {
+  /// Returns the name of this value, as used in the IR.
+  fn ir_name(&self) -> String;
+
+  /// Creates a value from its IR name.
+  fn from_ir_name(name: &str) -> Self;
+}
+
+impl IrName for Name {
+  fn ir_name(&self) -> String {
+    self.str().to_string()
+  }
+
+  fn from_ir_name(name: &str) -> Self {
+    Name::new(name)
+  }
+}
+
+impl IrName for Terminal {
+  fn ir_name(&self) -> String {
+    self.name().ir_name()
+  }
+
+  fn from_ir_name(name: &str) -> Self {
+    Terminal::new(name)
+  }
+}
+
+impl IrName for NonTerminal {
+  fn ir_name(&self) -> String {
+    self.name().ir_name()
+  }
+
+  fn from_ir_name(name: &str) -> Self {
+    NonTerminal::new(name)
+  }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum IrError<NT: std::fmt::Debug, AK: std::fmt::Debug> {
+  #[error("unsupported IR version {0} (expected {})", IR_VERSION)]
+  UnsupportedVersion(u32),
+  #[error("{table} index {index} is out of range")]
+  IndexOutOfRange { table: &'static str, index: usize },
+  #[error("IR does not describe a valid grammar: {0:?}")]
+  InvalidGrammar(GrammarErrors<NT, AK>),
+}
+
+fn index_table<'a, V: Ord + 'a>(
+  values: impl IntoIterator<Item = &'a V>,
+) -> BTreeMap<&'a V, usize> {
+  values
+    .into_iter()
+    .collect::<BTreeSet<_>>()
+    .into_iter()
+    .enumerate()
+    .map(|(i, v)| (v, i))
+    .collect()
+}
+
+fn names<V: IrName>(table: &BTreeMap<&V, usize>) -> Vec<String> {
+  // BTreeMap iteration order matches the assigned indexes.
+  table.keys().map(|v| v.ir_name()).collect()
+}
+
+fn lookup<'a, NT: std::fmt::Debug, AK: std::fmt::Debug>(
+  table: &'static str,
+  values: &'a [String],
+  index: usize,
+) -> Result<&'a str, IrError<NT, AK>> {
+  values
+    .get(index)
+    .map(String::as_str)
+    .ok_or(IrError::IndexOutOfRange { table, index })
+}
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: Ord + IrName,
+  NT: Ord + Clone + IrName,
+  AK: Ord + Clone + IrName,
+{
+  /// Converts this grammar into its intermediate representation.
+  pub fn to_ir(&self) -> GrammarIr {
+    let terminals = index_table(
+      self
+        .prods()
+        .flat_map(|p| p.elements())
+        .filter_map(|e| e.as_term()),
+    );
+    let nonterminals = index_table(
+      self
+        .rules()
+        .map(|r| r.head())
+        .chain(std::iter::once(self.start_nt())),
+    );
+    let actions = index_table(self.prods().map(|p| p.action_key()));
+
+    let rules = self
+      .rules_in_order(RuleOrder::StartFirst)
+      .into_iter()
+      .map(|rule| RuleIr {
+        head: nonterminals[rule.head()],
+        prods: rule
+          .prods()
+          .map(|prod| ProdIr {
+            action: actions[prod.action_key()],
+            elements: prod
+              .prod_elements()
+              .iter()
+              .map(|prod_elem| ElemIr {
+                id: prod_elem.id().map(|id| id.str().to_string()),
+                symbol: match prod_elem.elem() {
+                  Elem::Term(t) => SymbolIr::Term(terminals[t]),
+                  Elem::NonTerm(nt) => SymbolIr::NonTerm(nonterminals[nt]),
+                },
+              })
+              .collect(),
+          })
+          .collect(),
+      })
+      .collect();
+
+    GrammarIr {
+      version: IR_VERSION,
+      terminals: names(&terminals),
+      nonterminals: names(&nonterminals),
+      actions: names(&actions),
+      start: nonterminals[self.start_nt()],
+      rules,
+    }
+  }
+}
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: IrName,
+  NT: Ord + Clone + IrName + std::fmt::Debug,
+  AK: Ord + Clone + IrName + std::fmt::Debug,
+  AV: Default,
+{
+  /// Creates a grammar from its intermediate representation. All action
+  /// values are set to their default value.
+  pub fn from_ir(ir: &GrammarIr) -> Result<Self, IrError<NT, AK>> {
+    if ir.version != IR_VERSION {
+      return Err(IrError::UnsupportedVersion(ir.version));
+    }
+
+    let nonterm = |index| {
+      lookup("nonterminal", &ir.nonterminals, index).map(NT::from_ir_name)
+    };
+
+    let mut rules = Vec::new();
+    for rule in &ir.rules {
+      let mut prods = Vec::new();
+      for prod in &rule.prods {
+        let action =
+          AK::from_ir_name(lookup("action", &ir.actions, prod.action)?);
+        let mut elems = Vec::new();
+        for elem in &prod.elements {
+          let symbol = match elem.symbol {
+            SymbolIr::Term(index) => Elem::Term(T::from_ir_name(lookup(
+              "terminal",
+              &ir.terminals,
+              index,
+            )?)),
+            SymbolIr::NonTerm(index) => Elem::NonTerm(nonterm(index)?),
+          };
+          elems.push(ProdElement::new(elem.id.as_ref().map(Name::new), symbol));
+        }
+        prods.push((action, elems));
+      }
+      rules.push((nonterm(rule.head)?, prods));
+    }
+
+    build(nonterm(ir.start)?, |gb| {
+      for (head, prods) in rules {
+        gb.add_rule(head, |rb| {
+          for (action, elems) in prods {
+            rb.add_prod_with_elems(action, AV::default(), elems);
+          }
+        });
+      }
+    })
+    .map_err(IrError::InvalidGrammar)
+  }
+}
+
+/// How serious a diagnostic is.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeverityIr {
+  Error,
+  Warning,
+}
+
+/// A machine-readable diagnostic about a grammar, for editors and other
+/// tools. Symbols are listed by their IR names.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct DiagnosticIr {
+  pub severity: SeverityIr,
+  /// A stable identifier for the kind of problem.
+  pub code: String,
+  pub message: String,
+  pub symbols: Vec<String>,
+}
+
+/// Renders `diagnostics` as a JSON array.
+pub fn diagnostics_to_json(diagnostics: &[DiagnosticIr]) -> String {
+  serde_json::to_string_pretty(diagnostics)
+    .expect("diagnostics are always serializable")
+}
+
+impl<NT: IrName, AK: IrName> GrammarErrors<NT, AK> {
+  /// Returns one error diagnostic for each kind of problem in these errors,
+  /// listing the nonterminals with that problem. Over-long productions and
+  /// duplicate action keys are listed as `head:action`.
+  pub fn to_diagnostics(&self) -> Vec<DiagnosticIr> {
+    let mut diagnostics = [
+      (
+        "unreachable_nonterms",
+        "nonterminals are unreachable from the start nonterminal",
+        self.unreachable_nonterms(),
+      ),
+      (
+        "nonterms_without_rules",
+        "nonterminals are used, but have no rule",
+        self.nonterms_without_rules(),
+      ),
+      (
+        "rules_without_prods",
+        "rules have no productions",
+        self.rules_without_prods(),
+      ),
+    ]
+    .iter()
+    .filter(|(_, _, nts)| !nts.is_empty())
+    .map(|&(code, message, nts)| {
+      let symbols = nts.iter().map(IrName::ir_name).collect::<Vec<_>>();
+      DiagnosticIr {
+        severity: SeverityIr::Error,
+        code: code.to_string(),
+        message: format!("{}: {}", message, symbols.join(", ")),
+        symbols,
+      }
+    })
+    .collect::<Vec<_>>();
+
+    let prod_problems = [
+      (
+        "over_long_prods",
+        "productions are longer than the maximum length",
+        self.over_long_prods(),
+      ),
+      (
+        "duplicate_action_keys",
+        "action keys are used by more than one production of a rule",
+        self.duplicate_action_keys(),
+      ),
+    ];
+    for &(code, message, prods) in &prod_problems {
+      if prods.is_empty() {
+        continue;
+      }
+      let symbols = prods
+        .iter()
+        .map(|prod| {
+          format!("{}:{}", prod.head().ir_name(), prod.action_key().ir_name())
+        })
+        .collect::<Vec<_>>();
+      diagnostics.push(DiagnosticIr {
+        severity: SeverityIr::Error,
+        code: code.to_string(),
+        message: format!("{}: {}", message, symbols.join(", ")),
+        symbols,
+      });
+    }
+    diagnostics
+  }
+
+  /// Renders `to_diagnostics` as a JSON array.
+  pub fn to_json(&self) -> String {
+    diagnostics_to_json(&self.to_diagnostics())
+  }
+}
+
+/// The empty derivation of a nullable nonterminal: the production it
+/// starts with, and the empty derivations of the named nonterminals in that
+/// production.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct NullableIr {
+  pub head: String,
+  pub action: String,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub params: BTreeMap<String, NullableIr>,
+}
+
+impl NullableIr {
+  fn from_tree<NT: IrName, AK: IrName>(
+    tree: &TreeNode<ProdKey<NT, AK>, Void>,
+  ) -> Self {
+    NullableIr {
+      head: tree.action().head().ir_name(),
+      action: tree.action().action_key().ir_name(),
+      params: tree
+        .params()
+        .iter()
+        .map(|(name, value)| {
+          let value = match value {
+            TreeValue::Node(node) => NullableIr::from_tree(&**node),
+            TreeValue::Leaf(void) => match *void {},
+          };
+          (name.ir_name(), value)
+        })
+        .collect(),
+    }
+  }
+}
+
+impl<NT, AK> GrammarNullableInfo<NT, AK>
+where
+  NT: Ord + Clone + IrName,
+  AK: IrName,
+{
+  /// Returns the empty derivation of each nullable nonterminal, keyed by
+  /// the nonterminal's IR name.
+  pub fn to_ir(&self) -> BTreeMap<String, NullableIr> {
+    self
+      .get_nullable_set()
+      .iter()
+      .map(|nt| {
+        let tree = self
+          .get_nullable_action(nt)
+          .expect("every nullable nonterminal has an empty derivation");
+        (nt.ir_name(), NullableIr::from_tree(tree))
+      })
+      .collect()
+  }
+}
+
+/// The nullable, FIRST and FOLLOW sets of a grammar, for tools that display
+/// them without running the analyses. Every set is keyed by the IR name of
+/// its nonterminal, and lists terminals by their IR names.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct AnalysisIr {
+  pub version: u32,
+  pub nullables: BTreeSet<String>,
+  /// The empty derivation of each nullable nonterminal. This is `None` if
+  /// some nonterminal is ambiguously nullable, since it then has no single
+  /// empty derivation.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub nullable_derivations: Option<BTreeMap<String, NullableIr>>,
+  pub firsts: BTreeMap<String, BTreeSet<String>>,
+  pub follows: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl AnalysisIr {
+  /// Renders this IR as a JSON string.
+  pub fn to_json(&self) -> String {
+    serde_json::to_string_pretty(self).expect("IR is always serializable")
+  }
+
+  /// Parses an IR value from a JSON string.
+  pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+    serde_json::from_str(json)
+  }
+
+  /// Returns the FIRST sets, with the symbols converted from their IR
+  /// names.
+  pub fn first_sets<T, NT>(&self) -> BTreeMap<NT, BTreeSet<T>>
+  where
+    T: Ord + IrName,
+    NT: Ord + IrName,
+  {
+    from_ir_sets(&self.firsts)
+  }
+
+  /// Returns the FOLLOW sets, with the symbols converted from their IR
+  /// names.
+  pub fn follow_sets<T, NT>(&self) -> BTreeMap<NT, BTreeSet<T>>
+  where
+    T: Ord + IrName,
+    NT: Ord + IrName,
+  {
+    from_ir_sets(&self.follows)
+  }
+}
+
+fn from_ir_sets<T, NT>(
+  sets: &BTreeMap<String, BTreeSet<String>>,
+) -> BTreeMap<NT, BTreeSet<T>>
+where
+  T: Ord + IrName,
+  NT: Ord + IrName,
+{
+  sets
+    .iter()
+    .map(|(nt, set)| {
+      let set = set.iter().map(|t| T::from_ir_name(t)).collect();
+      (NT::from_ir_name(nt), set)
+    })
+    .collect()
+}
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: Ord + Clone + IrName + 'static,
+  NT: Ord + Clone + IrName + 'static,
+  AK: Ord + Clone + IrName + 'static,
+{
+  /// Computes the nullable, FIRST and FOLLOW sets of this grammar, keyed by
+  /// the IR names of its symbols, along with the empty derivations of the
+  /// nullable nonterminals if none of them is ambiguously nullable.
+  pub fn to_analysis_ir(&self) -> AnalysisIr {
+    let passes = PassContext::new(self);
+    let nullables = passes
+      .get_pass::<NullableSet<NT>>()
+      .expect("nullable sets are defined for every grammar");
+    let derivations = passes.get_pass::<Nullable<NT, AK>>().ok();
+    let firsts = passes
+      .get_pass::<Firsts<T, NT>>()
+      .expect("FIRST sets are defined for every grammar");
+    let follows = passes
+      .get_pass::<Follows<T, NT>>()
+      .expect("FOLLOW sets are defined for every grammar");
+
+    let names = |set: TermSet<'_, T, NT>| -> BTreeSet<String> {
+      set.iter().map(IrName::ir_name).collect()
+    };
+    let sets = |get: &dyn Fn(&NT) -> Option<BTreeSet<String>>| {
+      self
+        .rules()
+        .filter_map(|rule| Some((rule.head().ir_name(), get(rule.head())?)))
+        .collect()
+    };
+    AnalysisIr {
+      version: IR_VERSION,
+      nullables: nullables
+        .get_nullable_set()
+        .iter()
+        .map(IrName::ir_name)
+        .collect(),
+      nullable_derivations: derivations
+        .map(|derivations| derivations.get_nullable_info().to_ir()),
+      firsts: sets(&|nt| firsts.get(nt).map(names)),
+      follows: sets(&|nt| follows.get(nt).map(names)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::examples;
+
+  type TestGrammar = Grammar<Terminal, NonTerminal, Name, ()>;
+
+  #[test]
+  fn test_paren_round_trip() {
+    let g = examples::make_paren();
+    let ir = g.to_ir();
+    assert_eq!(ir.version, IR_VERSION);
+    assert_eq!(ir.terminals, vec!["LPAREN", "RPAREN"]);
+    assert_eq!(ir.nonterminals, vec!["expr", "expr_list", "start"]);
+    assert_eq!(ir.start, 2);
+    // The start rule is exported first.
+    assert_eq!(ir.rules[0].head, ir.start);
+
+    let json = ir.to_json();
+    let parsed_ir = GrammarIr::from_json(&json).unwrap();
+    assert_eq!(parsed_ir, ir);
+
+    let round_trip = TestGrammar::from_ir(&parsed_ir).unwrap();
+    assert_eq!(round_trip.to_pretty(), g.to_pretty());
+    assert_eq!(round_trip.to_ir(), ir);
+  }
+
+  #[test]
+  fn test_analysis_round_trip() {
+    let g = examples::make_paren();
+    let ir = g.to_analysis_ir();
+    let parsed_ir = AnalysisIr::from_json(&ir.to_json()).unwrap();
+    assert_eq!(parsed_ir, ir);
+
+    let passes = PassContext::new(&g);
+    let firsts = passes.get_pass::<Firsts<Terminal, NonTerminal>>().unwrap();
+    let first_sets = parsed_ir.first_sets::<Terminal, NonTerminal>();
+    assert_eq!(first_sets.len(), 3);
+    for (nt, set) in &first_sets {
+      assert_eq!(Some(set.clone()), firsts.get(nt).map(|set| set.to_set()));
+    }
+    assert_eq!(
+      parsed_ir.follow_sets::<Terminal, NonTerminal>()
+        [&NonTerminal::new("expr_list")],
+      std::iter::once(Terminal::new("RPAREN")).collect()
+    );
+
+    // `expr_list` is the only nullable nonterminal, through its empty
+    // production.
+    assert_eq!(
+      parsed_ir.nullables,
+      std::iter::once("expr_list".to_string()).collect()
+    );
+    assert_eq!(
+      serde_json::to_value(&parsed_ir.nullable_derivations).unwrap(),
+      serde_json::json!({
+        "expr_list": { "head": "expr_list", "action": "empty" }
+      })
+    );
+  }
+
+  #[test]
+  fn test_analysis_ambiguous_nullable() {
+    // `c` has two empty derivations, so no derivations are exported, but
+    // the sets still are.
+    let ir = examples::make_ambiguous_nullable().to_analysis_ir();
+    assert_eq!(ir.nullables.len(), 4);
+    assert_eq!(ir.nullable_derivations, None);
+    assert_eq!(ir.firsts.len(), 4);
+    assert!(!ir.to_json().contains("nullable_derivations"));
+    assert_eq!(AnalysisIr::from_json(&ir.to_json()).unwrap(), ir);
+  }
+
+  #[test]
+  fn test_element_json_shape() {
+    let elem = ElemIr {
+      id: Some("value".to_string()),
+      symbol: SymbolIr::NonTerm(3),
+    };
+    assert_eq!(
+      serde_json::to_string(&elem).unwrap(),
+      r#"{"id":"value","nonterm":3}"#
+    );
+  }
+
+  #[test]
+  fn test_rejects_bad_ir() {
+    let mut ir = examples::make_paren().to_ir();
+    ir.version = IR_VERSION + 1;
+    assert!(matches!(
+      TestGrammar::from_ir(&ir),
+      Err(IrError::UnsupportedVersion(_))
+    ));
+
+    let mut ir = examples::make_paren().to_ir();
+    ir.rules[0].prods[0].action = 100;
+    assert!(matches!(
+      TestGrammar::from_ir(&ir),
+      Err(IrError::IndexOutOfRange {
+        table: "action",
+        index: 100
+      })
+    ));
+  }
+
+  #[test]
+  fn test_error_diagnostics() {
+    let (_, errors): (TestGrammar, _) =
+      crate::grammar::build_lenient(NonTerminal::new("s"), |gb| {
+        gb.add_rule(NonTerminal::new("s"), |rb| {
+          rb.add_prod("missing", (), |pb| {
+            pb.add_nonterm(NonTerminal::new("undefined"))
+              .add_nonterm(NonTerminal::new("empty"));
+          });
+        })
+        .add_rule(NonTerminal::new("empty"), |_| {});
+      });
+
+    let json: serde_json::Value =
+      serde_json::from_str(&errors.to_json()).unwrap();
+    assert_eq!(
+      json,
+      serde_json::json!([
+        {
+          "severity": "error",
+          "code": "nonterms_without_rules",
+          "message": "nonterminals are used, but have no rule: undefined",
+          "symbols": ["undefined"],
+        },
+        {
+          "severity": "error",
+          "code": "rules_without_prods",
+          "message": "rules have no productions: empty",
+          "symbols": ["empty"],
+        },
+      ])
+    );
+  }
+}
@@ -0,0 +1,339 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A single report of the advisory checks on a grammar.
+
+use {
+  crate::{
+    grammar::{
+      passes::{
+        nullable::{calculate_nullables_verbose, NullableSet},
+        PassContext,
+      },
+      Elem, Grammar, Prod, ProdKey,
+    },
+    utils::{doc_string, ToDoc},
+  },
+  std::collections::BTreeSet,
+};
+
+/// How likely a lint is to point at a mistake.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+  /// A shape that some parsing algorithms can't handle, but which is often
+  /// intended.
+  Info,
+  /// A shape that is almost always a mistake.
+  Warning,
+}
+
+impl std::fmt::Display for Severity {
+  fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fmt.write_str(match self {
+      Severity::Info => "info",
+      Severity::Warning => "warning",
+    })
+  }
+}
+
+/// A problem found by `Grammar::lint`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Lint<T, NT, AK> {
+  /// A terminal that only appears in productions that can't be part of any
+  /// derivation from the start nonterminal.
+  UnusedTerminal(T),
+  /// A nonterminal that does not derive any sentence.
+  EmptyLanguage(NT),
+  /// Two productions of the same rule with the same elements.
+  DuplicateProds {
+    first: ProdKey<NT, AK>,
+    second: ProdKey<NT, AK>,
+  },
+  /// A nonterminal with more than one way to derive the empty sentence.
+  AmbiguousNullable {
+    nonterm: NT,
+    prods: Vec<ProdKey<NT, AK>>,
+  },
+  /// A nonterminal that is left recursive only through a nullable prefix,
+  /// as in `a ::= b a c` where `b` is nullable.
+  HiddenLeftRecursion(NT),
+  /// The elements of `prefix` are a proper prefix of the elements of `prod`,
+  /// as found by `Grammar::prefix_conflicts`.
+  PrefixConflict {
+    prefix: ProdKey<NT, AK>,
+    prod: ProdKey<NT, AK>,
+  },
+}
+
+impl<T, NT, AK> Lint<T, NT, AK> {
+  pub fn severity(&self) -> Severity {
+    match self {
+      Lint::UnusedTerminal(_)
+      | Lint::EmptyLanguage(_)
+      | Lint::DuplicateProds { .. }
+      | Lint::AmbiguousNullable { .. } => Severity::Warning,
+      Lint::HiddenLeftRecursion(_) | Lint::PrefixConflict { .. } => {
+        Severity::Info
+      }
+    }
+  }
+}
+
+fn prod_key_string<NT: ToDoc, AK: ToDoc>(key: &ProdKey<NT, AK>) -> String {
+  format!(
+    "`{}` of `{}`",
+    doc_string(key.action_key()),
+    doc_string(key.head())
+  )
+}
+
+impl<T, NT, AK> std::fmt::Display for Lint<T, NT, AK>
+where
+  T: ToDoc,
+  NT: ToDoc,
+  AK: ToDoc,
+{
+  fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(fmt, "{}: ", self.severity())?;
+    match self {
+      Lint::UnusedTerminal(t) => {
+        write!(fmt, "terminal `{}` is never used", doc_string(t))
+      }
+      Lint::EmptyLanguage(nt) => write!(
+        fmt,
+        "nonterminal `{}` does not derive any sentence",
+        doc_string(nt)
+      ),
+      Lint::DuplicateProds { first, second } => write!(
+        fmt,
+        "productions {} and {} are the same",
+        prod_key_string(first),
+        prod_key_string(second)
+      ),
+      Lint::AmbiguousNullable { nonterm, prods } => write!(
+        fmt,
+        "nonterminal `{}` is ambiguously nullable through {}",
+        doc_string(nonterm),
+        prods
+          .iter()
+          .map(prod_key_string)
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+      Lint::HiddenLeftRecursion(nt) => write!(
+        fmt,
+        "nonterminal `{}` is left recursive through a nullable prefix",
+        doc_string(nt)
+      ),
+      Lint::PrefixConflict { prefix, prod } => write!(
+        fmt,
+        "production {} is a prefix of {}",
+        prod_key_string(prefix),
+        prod_key_string(prod)
+      ),
+    }
+  }
+}
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: Ord + Clone + 'static,
+  NT: Ord + Clone + 'static,
+  AK: Ord + Clone + 'static,
+{
+  /// Runs every advisory check on this grammar, and returns what they found,
+  /// warnings first.
+  pub fn lint(&self) -> Vec<Lint<T, NT, AK>> {
+    let empty = self.empty_language_nonterms();
+    let mut lints = Vec::new();
+    lints.extend(
+      self
+        .unused_terminals(&empty)
+        .into_iter()
+        .map(Lint::UnusedTerminal),
+    );
+    lints.extend(empty.into_iter().map(Lint::EmptyLanguage));
+    for rule in self.rules() {
+      let prods = rule.prods().collect::<Vec<_>>();
+      for (i, first) in prods.iter().enumerate() {
+        for second in &prods[i + 1..] {
+          if first.elements().eq(second.elements()) {
+            lints.push(Lint::DuplicateProds {
+              first: first.prod_key(),
+              second: second.prod_key(),
+            });
+          }
+        }
+      }
+    }
+    for (nonterm, prods) in calculate_nullables_verbose(self) {
+      lints.push(Lint::AmbiguousNullable {
+        nonterm,
+        prods: prods.iter().map(|prod| prod.prod_key()).collect(),
+      });
+    }
+    lints.extend(
+      self
+        .hidden_left_recursion()
+        .into_iter()
+        .map(Lint::HiddenLeftRecursion),
+    );
+    for (_, prefix, prod) in self.prefix_conflicts() {
+      lints.push(Lint::PrefixConflict { prefix, prod });
+    }
+
+    // The sort is stable, so lints of the same severity stay in the order
+    // of the checks.
+    lints.sort_by_key(|lint| std::cmp::Reverse(lint.severity()));
+    lints
+  }
+
+  /// Returns the result of `lint` with one lint per line.
+  pub fn lint_report(&self) -> String
+  where
+    T: ToDoc,
+    NT: ToDoc,
+    AK: ToDoc,
+  {
+    let lints = self.lint();
+    let lines = lints.iter().map(ToString::to_string);
+    lines.collect::<Vec<_>>().join("\n")
+  }
+
+  /// Returns the terminals that only appear in productions that are
+  /// unreachable from the start nonterminal, or that contain a nonterminal
+  /// in `empty`, which does not derive any sentence.
+  fn unused_terminals(&self, empty: &BTreeSet<NT>) -> BTreeSet<T> {
+    let productive = |prod: &Prod<T, NT, AK, AV>| {
+      prod
+        .elements()
+        .filter_map(Elem::as_nonterm)
+        .all(|nt| !empty.contains(nt))
+    };
+    let mut reached =
+      self.reachable_through(std::iter::once(self.start_nt()), productive);
+    reached.insert(self.start_nt());
+    let used = reached
+      .into_iter()
+      .filter_map(|nt| self.try_get_rule(nt))
+      .flat_map(|rule| rule.prods())
+      .filter(productive)
+      .flat_map(|prod| prod.elements())
+      .filter_map(Elem::as_term)
+      .collect::<BTreeSet<_>>();
+
+    let all = self.prods().flat_map(|prod| prod.elements());
+    all
+      .filter_map(|elem| match elem {
+        Elem::Term(t) if !used.contains(t) => Some(t.clone()),
+        _ => None,
+      })
+      .collect()
+  }
+
+  /// Returns the nonterminals that are left recursive through at least one
+  /// production whose recursive nonterminal follows a nullable prefix.
+  fn hidden_left_recursion(&self) -> BTreeSet<NT> {
+    let passes = PassContext::new(self);
+    let nullables = passes
+      .get_pass::<NullableSet<NT>>()
+      .expect("nullable sets are defined for every grammar");
+    let edges = self.left_corners(&nullables);
+
+    let mut found = BTreeSet::new();
+    for &start in edges.keys() {
+      // Search for a path back to `start` that takes a hidden edge.
+      let mut seen = BTreeSet::new();
+      let mut stack = vec![(start, false)];
+      while let Some((nt, hidden)) = stack.pop() {
+        for &(next, _, index) in edges.get(nt).into_iter().flatten() {
+          // The edge is hidden if it follows a nullable prefix.
+          let hidden = hidden || index > 0;
+          if next == start && hidden {
+            found.insert(start.clone());
+          }
+          if seen.insert((next, hidden)) {
+            stack.push((next, hidden));
+          }
+        }
+      }
+    }
+    found
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{examples, NonTerminal, Terminal};
+  use crate::utils::Name;
+
+  fn key(head: &str, action_key: &str) -> ProdKey<NonTerminal, Name> {
+    ProdKey::new(NonTerminal::new(head), Name::new(action_key))
+  }
+
+  #[test]
+  fn test_unused_terminal_and_prefix_conflict() {
+    // `t` never finishes deriving, so the `C` in it is never used.
+    let g = examples::from_rules(&[
+      ("s", &[&["A", "B"], &["A"], &["t"]]),
+      ("t", &[&["C", "t"]]),
+    ]);
+    assert_eq!(
+      g.lint(),
+      vec![
+        Lint::UnusedTerminal(Terminal::new("C")),
+        Lint::EmptyLanguage(NonTerminal::new("t")),
+        Lint::PrefixConflict {
+          prefix: key("s", "p1"),
+          prod: key("s", "p0"),
+        },
+      ]
+    );
+    assert_eq!(
+      g.lint_report(),
+      [
+        "warning: terminal `C` is never used",
+        "warning: nonterminal `t` does not derive any sentence",
+        "info: production `p1` of `s` is a prefix of `p0` of `s`",
+      ]
+      .join("\n")
+    );
+  }
+
+  #[test]
+  fn test_duplicates_and_hidden_left_recursion() {
+    let g = examples::from_rules(&[
+      ("s", &[&["n", "s", "A"], &["B"]]),
+      ("n", &[&[], &["C"], &["C"]]),
+    ]);
+    assert_eq!(
+      g.lint(),
+      vec![
+        Lint::DuplicateProds {
+          first: key("n", "p1"),
+          second: key("n", "p2"),
+        },
+        Lint::HiddenLeftRecursion(NonTerminal::new("s")),
+      ]
+    );
+    assert_eq!(g.lint()[1].severity(), Severity::Info);
+
+    let nullable = examples::make_ambiguous_nullable().lint();
+    assert!(nullable
+      .iter()
+      .any(|lint| matches!(lint, Lint::AmbiguousNullable { .. })));
+    assert!(examples::make_paren().lint().is_empty());
+  }
+}
@@ -0,0 +1,373 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Diagnostics explaining why a grammar is not LL(1).
+//!
+//! A grammar is LL(1) if, for every pair of productions `A -> x | y` of the
+//! same rule:
+//!
+//! - `x` and `y` cannot begin with the same terminal,
+//! - at most one of `x` and `y` can derive the empty sentence, and
+//! - if `y` can derive the empty sentence, `x` cannot begin with a terminal
+//!   that can follow `A`.
+//!
+//! Left recursion always prevents a grammar from being LL(1), and is reported
+//! separately, since it is usually fixed differently from the other cases.
+
+use {
+  crate::grammar::{
+    passes::{
+      firsts::Firsts, follows::Follows, nullable::NullableSet, PassContext,
+    },
+    Elem, Grammar, Prod, ProdKey,
+  },
+  std::collections::{BTreeMap, BTreeSet, VecDeque},
+};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Ll1Blocker<T, NT, AK> {
+  /// A cycle of productions, where each production may begin with the head
+  /// of the next one, and the last may begin with the head of the first.
+  LeftRecursion { prods: Vec<ProdKey<NT, AK>> },
+  /// Two productions of the same rule may begin with the same terminals.
+  CommonFirst {
+    first: ProdKey<NT, AK>,
+    second: ProdKey<NT, AK>,
+    terminals: BTreeSet<T>,
+  },
+  /// Two productions of the same rule may both derive the empty sentence.
+  CommonEmpty {
+    first: ProdKey<NT, AK>,
+    second: ProdKey<NT, AK>,
+  },
+  /// A production may begin with terminals that can follow the head of its
+  /// rule, while another production of the rule may derive the empty
+  /// sentence.
+  FirstFollow {
+    prod: ProdKey<NT, AK>,
+    nullable_prod: ProdKey<NT, AK>,
+    terminals: BTreeSet<T>,
+  },
+}
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: Ord + Clone + 'static,
+  NT: Ord + Clone + 'static,
+  AK: Ord + Clone + 'static,
+{
+  /// Returns every reason this grammar is not LL(1). The result is empty if
+  /// and only if the grammar is LL(1).
+  pub fn ll1_blockers(&self) -> Vec<Ll1Blocker<T, NT, AK>> {
+    let passes = PassContext::new(self);
+    let nullables = passes
+      .get_pass::<NullableSet<NT>>()
+      .expect("nullable sets are defined for every grammar");
+    let firsts = passes
+      .get_pass::<Firsts<T, NT>>()
+      .expect("FIRST sets are defined for every grammar");
+    let follows = passes
+      .get_pass::<Follows<T, NT>>()
+      .expect("FOLLOW sets are defined for every grammar");
+
+    let prod_nullable =
+      |prod: &Prod<T, NT, AK, AV>| prod_is_nullable(&nullables, prod);
+
+    let mut blockers = self
+      .left_recursion_cycles(&nullables)
+      .into_iter()
+      .map(|prods| Ll1Blocker::LeftRecursion { prods })
+      .collect::<Vec<_>>();
+
+    for rule in self.rules() {
+      let head_follows = follows
+        .get(rule.head())
+        .map(|set| set.to_set())
+        .unwrap_or_default();
+      let prods = rule
+        .prods()
+        .map(|prod| {
+          (
+            prod.prod_key(),
+            prod.first_set(&firsts),
+            prod_nullable(&prod),
+          )
+        })
+        .collect::<Vec<_>>();
+
+      for (i, (first, first_firsts, first_nullable)) in prods.iter().enumerate()
+      {
+        for (second, second_firsts, second_nullable) in &prods[i + 1..] {
+          let terminals = first_firsts
+            .intersection(second_firsts)
+            .cloned()
+            .collect::<BTreeSet<_>>();
+          if !terminals.is_empty() {
+            blockers.push(Ll1Blocker::CommonFirst {
+              first: first.clone(),
+              second: second.clone(),
+              terminals,
+            });
+          }
+
+          if *first_nullable && *second_nullable {
+            blockers.push(Ll1Blocker::CommonEmpty {
+              first: first.clone(),
+              second: second.clone(),
+            });
+          }
+
+          for (prod, prod_firsts, nullable_prod, nullable) in [
+            (first, first_firsts, second, second_nullable),
+            (second, second_firsts, first, first_nullable),
+          ] {
+            if !nullable {
+              continue;
+            }
+            let terminals = prod_firsts
+              .intersection(&head_follows)
+              .cloned()
+              .collect::<BTreeSet<_>>();
+            if !terminals.is_empty() {
+              blockers.push(Ll1Blocker::FirstFollow {
+                prod: prod.clone(),
+                nullable_prod: nullable_prod.clone(),
+                terminals,
+              });
+            }
+          }
+        }
+      }
+    }
+
+    blockers
+  }
+
+  /// Returns true if this grammar is LL(1), which is the case exactly when
+  /// `ll1_blockers` would return nothing. This stops at the first conflict
+  /// it finds, and only looks for left recursion if there are none.
+  pub fn is_ll1(&self) -> bool {
+    let passes = PassContext::new(self);
+    let nullables = passes
+      .get_pass::<NullableSet<NT>>()
+      .expect("nullable sets are defined for every grammar");
+    let firsts = passes
+      .get_pass::<Firsts<T, NT>>()
+      .expect("FIRST sets are defined for every grammar");
+    let follows = passes
+      .get_pass::<Follows<T, NT>>()
+      .expect("FOLLOW sets are defined for every grammar");
+
+    for rule in self.rules() {
+      let mut seen = BTreeSet::new();
+      let mut prod_firsts = Vec::new();
+      let mut nullable_prod = None;
+      for (i, prod) in rule.prods().enumerate() {
+        let terminals = prod.first_set(&firsts);
+        if !terminals.is_disjoint(&seen) {
+          return false;
+        }
+        if prod_is_nullable(&nullables, &prod) {
+          if nullable_prod.is_some() {
+            return false;
+          }
+          nullable_prod = Some(i);
+        }
+        seen.extend(terminals.iter().cloned());
+        prod_firsts.push(terminals);
+      }
+
+      if let Some(nullable_prod) = nullable_prod {
+        let head_follows = follows
+          .get(rule.head())
+          .map(|set| set.to_set())
+          .unwrap_or_default();
+        let conflicts = prod_firsts.iter().enumerate().any(|(i, terminals)| {
+          i != nullable_prod && !terminals.is_disjoint(&head_follows)
+        });
+        if conflicts {
+          return false;
+        }
+      }
+    }
+
+    self.left_recursion_cycles(&nullables).is_empty()
+  }
+
+  /// Returns the shortest left-recursive cycle through each nonterminal that
+  /// has one. Each cycle is rotated to start at its smallest production, so
+  /// each cycle is only reported once.
+  #[allow(clippy::type_complexity)]
+  fn left_recursion_cycles(
+    &self,
+    nullables: &NullableSet<NT>,
+  ) -> BTreeSet<Vec<ProdKey<NT, AK>>> {
+    let edges = self.left_corners(nullables);
+
+    let mut cycles = BTreeSet::new();
+    for root in edges.keys().copied() {
+      let mut parents: BTreeMap<&NT, (&NT, Prod<T, NT, AK, AV>)> =
+        BTreeMap::new();
+      let mut queue = VecDeque::new();
+      queue.push_back(root);
+
+      'search: while let Some(curr) = queue.pop_front() {
+        for (next, prod, _) in edges.get(curr).into_iter().flatten() {
+          if *next == root {
+            let mut cycle = vec![prod.prod_key()];
+            let mut node = curr;
+            while node != root {
+              let (parent, parent_prod) = &parents[node];
+              cycle.push(parent_prod.prod_key());
+              node = parent;
+            }
+            cycle.reverse();
+
+            let min_index = (0..cycle.len())
+              .min_by_key(|i| &cycle[*i])
+              .expect("cycles are non-empty");
+            cycle.rotate_left(min_index);
+            cycles.insert(cycle);
+            break 'search;
+          }
+
+          if !parents.contains_key(next) {
+            parents.insert(next, (curr, *prod));
+            queue.push_back(next);
+          }
+        }
+      }
+    }
+
+    cycles
+  }
+}
+
+/// Returns true if `prod` can derive the empty sentence.
+fn prod_is_nullable<T, NT, AK, AV>(
+  nullables: &NullableSet<NT>,
+  prod: &Prod<T, NT, AK, AV>,
+) -> bool
+where
+  NT: Ord,
+{
+  prod.elements().all(|elem| match elem {
+    Elem::Term(_) => false,
+    Elem::NonTerm(nt) => nullables.is_nullable(nt),
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::{examples, NonTerminal, Terminal},
+    utils::Name,
+  };
+
+  fn key(head: &str, action: &str) -> ProdKey<NonTerminal, Name> {
+    ProdKey::new(NonTerminal::new(head), Name::new(action))
+  }
+
+  #[test]
+  fn test_left_recursive() {
+    let g = examples::make_left_recursive();
+    let blockers = g.ll1_blockers();
+    assert!(blockers.contains(&Ll1Blocker::LeftRecursion {
+      prods: vec![key("expr", "plus")],
+    }));
+    assert!(blockers.contains(&Ll1Blocker::CommonFirst {
+      first: key("expr", "plus"),
+      second: key("expr", "atom"),
+      terminals: vec![Terminal::new("LPAREN"), Terminal::new("NUM")]
+        .into_iter()
+        .collect(),
+    }));
+  }
+
+  #[test]
+  fn test_left_factored() {
+    let g = examples::from_rules(&[
+      ("expr", &[&["atom", "expr_tail"]]),
+      ("expr_tail", &[&["PLUS", "atom", "expr_tail"], &[]]),
+      ("atom", &[&["NUM"], &["LPAREN", "expr", "RPAREN"]]),
+    ]);
+    assert_eq!(g.ll1_blockers(), vec![]);
+    assert_eq!(examples::make_paren().ll1_blockers(), vec![]);
+  }
+
+  #[test]
+  fn test_is_ll1() {
+    let factored = examples::from_rules(&[
+      ("expr", &[&["atom", "expr_tail"]]),
+      ("expr_tail", &[&["PLUS", "atom", "expr_tail"], &[]]),
+      ("atom", &[&["NUM"], &["LPAREN", "expr", "RPAREN"]]),
+    ]);
+    assert!(factored.is_ll1());
+    assert!(examples::make_paren().is_ll1());
+    assert!(!examples::make_left_recursive().is_ll1());
+
+    // A left-recursive rule with a single production has no conflicts.
+    let g =
+      examples::from_rules(&[("s", &[&["x", "A"]]), ("x", &[&["x", "B"]])]);
+    assert!(!g.is_ll1());
+
+    for g in [
+      examples::make_simple(),
+      examples::make_simple_nullable(),
+      examples::make_ambiguous_nullable(),
+      examples::make_arithmetic(),
+      examples::make_json(),
+    ] {
+      assert_eq!(g.is_ll1(), g.ll1_blockers().is_empty());
+    }
+  }
+
+  #[test]
+  fn test_indirect_left_recursion() {
+    let g = examples::from_rules(&[
+      ("a", &[&["b", "X"], &["Y"]]),
+      ("b", &[&["c", "a"]]),
+      ("c", &[&[], &["Z"]]),
+    ]);
+    let blockers = g.ll1_blockers();
+    assert!(blockers.contains(&Ll1Blocker::LeftRecursion {
+      prods: vec![key("a", "p0"), key("b", "p0")],
+    }));
+  }
+
+  #[test]
+  fn test_nullable_conflicts() {
+    let g = examples::make_ambiguous_nullable();
+    assert_eq!(
+      g.ll1_blockers(),
+      vec![Ll1Blocker::CommonEmpty {
+        first: key("c", "c_left"),
+        second: key("c", "c_right"),
+      }]
+    );
+
+    // `A` can both begin `opt` and follow it.
+    let g =
+      examples::from_rules(&[("s", &[&["opt", "A"]]), ("opt", &[&["A"], &[]])]);
+    assert_eq!(
+      g.ll1_blockers(),
+      vec![Ll1Blocker::FirstFollow {
+        prod: key("opt", "p0"),
+        nullable_prod: key("opt", "p1"),
+        terminals: std::iter::once(Terminal::new("A")).collect(),
+      }]
+    );
+  }
+}
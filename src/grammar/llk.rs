@@ -0,0 +1,189 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The amount of lookahead an LL parser needs for a grammar.
+//!
+//! A grammar is LL(k) if, for every nonterminal `A` and every set `L` of
+//! lookahead strings that can follow `A` in a leftmost derivation from the
+//! start symbol, the FIRST_k sets of `x L` and `y L` are disjoint for every
+//! pair of productions `A -> x | y`. The sets `L` are the "local follow"
+//! sets of `A`, which are finer than the FOLLOW_k set of `A` when `k > 1`.
+//!
+//! Lookahead strings are sequences of at most `k` terminals. A string shorter
+//! than `k` terminals is followed by the end of the input.
+
+use {
+  crate::{
+    grammar::{Elem, Grammar},
+    utils::{change_iter, change_loop, WasChanged},
+  },
+  std::collections::{BTreeMap, BTreeSet},
+};
+
+/// A set of lookahead strings of at most `k` terminals each.
+type Lookaheads<'a, T> = BTreeSet<Vec<&'a T>>;
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: Ord,
+  NT: Ord,
+{
+  /// Returns the smallest `k <= max_k` for which this grammar is LL(k), or
+  /// `None` if this grammar is not LL(`max_k`).
+  ///
+  /// Left-recursive and ambiguous grammars are not LL(k) for any `k`, so
+  /// this always returns `None` for them.
+  pub fn lookahead_requirement(&self, max_k: usize) -> Option<usize> {
+    (0..=max_k).find(|k| self.is_ll_k(*k))
+  }
+
+  fn is_ll_k(&self, k: usize) -> bool {
+    let firsts = self.firsts_k(k);
+    let prods_by_head = self.rules().fold(BTreeMap::new(), |mut map, rule| {
+      map.insert(rule.head(), rule.prods().collect::<Vec<_>>());
+      map
+    });
+
+    let start: Lookaheads<T> = std::iter::once(Vec::new()).collect();
+    let mut seen = BTreeSet::new();
+    let mut stack = vec![(self.start_nt(), start)];
+    while let Some((head, follow)) = stack.pop() {
+      if seen.contains(&(head, follow.clone())) {
+        continue;
+      }
+
+      let prods = prods_by_head.get(head).map(Vec::as_slice).unwrap_or(&[]);
+      let mut prod_lookaheads: Vec<Lookaheads<T>> = Vec::new();
+      for prod in prods {
+        let lookaheads =
+          concat_k(&seq_firsts_k(&firsts, prod.elements(), k), &follow, k);
+        if prod_lookaheads
+          .iter()
+          .any(|other| !other.is_disjoint(&lookaheads))
+        {
+          return false;
+        }
+        prod_lookaheads.push(lookaheads);
+
+        for (i, elem) in prod.elements().enumerate() {
+          if let Elem::NonTerm(nt) = elem {
+            let rest = prod.elements().skip(i + 1);
+            let nt_follow =
+              concat_k(&seq_firsts_k(&firsts, rest, k), &follow, k);
+            stack.push((nt, nt_follow));
+          }
+        }
+      }
+
+      seen.insert((head, follow));
+    }
+
+    true
+  }
+
+  /// Returns the FIRST_k set of every nonterminal: the prefixes of at most
+  /// `k` terminals of the sentences it derives.
+  fn firsts_k(&self, k: usize) -> BTreeMap<&NT, Lookaheads<'_, T>> {
+    let mut firsts: BTreeMap<&NT, Lookaheads<T>> = BTreeMap::new();
+    change_loop(|| {
+      change_iter(self.prods(), |prod| {
+        let lookaheads = seq_firsts_k(&firsts, prod.elements(), k);
+        let entry = firsts.entry(prod.head()).or_default();
+        change_iter(lookaheads, |lookahead| {
+          WasChanged::from_changed(entry.insert(lookahead))
+        })
+      })
+    });
+    firsts
+  }
+}
+
+/// Returns the FIRST_k set of a sequence of elements.
+fn seq_firsts_k<'a, T, NT>(
+  firsts: &BTreeMap<&'a NT, Lookaheads<'a, T>>,
+  elems: impl Iterator<Item = &'a Elem<T, NT>>,
+  k: usize,
+) -> Lookaheads<'a, T>
+where
+  T: Ord,
+  NT: Ord,
+{
+  let mut result: Lookaheads<T> = std::iter::once(Vec::new()).collect();
+  for elem in elems {
+    if result.iter().all(|lookahead| lookahead.len() >= k) {
+      break;
+    }
+    let elem_firsts = match elem {
+      Elem::Term(t) => std::iter::once(vec![t]).collect(),
+      Elem::NonTerm(nt) => firsts.get(nt).cloned().unwrap_or_default(),
+    };
+    result = concat_k(&result, &elem_firsts, k);
+  }
+  result
+}
+
+/// Returns every string in `prefixes` followed by every string in
+/// `suffixes`, truncated to `k` terminals.
+fn concat_k<'a, T: Ord>(
+  prefixes: &Lookaheads<'a, T>,
+  suffixes: &Lookaheads<'a, T>,
+  k: usize,
+) -> Lookaheads<'a, T> {
+  let mut result = BTreeSet::new();
+  for prefix in prefixes {
+    if prefix.len() >= k {
+      result.insert(prefix[..k].to_vec());
+      continue;
+    }
+    for suffix in suffixes {
+      let mut lookahead = prefix.clone();
+      lookahead.extend(suffix.iter().take(k - prefix.len()).copied());
+      result.insert(lookahead);
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod test {
+  use crate::grammar::examples;
+
+  #[test]
+  fn test_ll2() {
+    let g = examples::from_rules(&[
+      ("s", &[&["A", "B"], &["A", "C"], &["t", "D"]]),
+      ("t", &[&[], &["C", "t"]]),
+    ]);
+    assert_eq!(g.lookahead_requirement(1), None);
+    assert_eq!(g.lookahead_requirement(3), Some(2));
+  }
+
+  #[test]
+  fn test_local_follows() {
+    // This grammar is not strong LL(2), since `Y X` is in the FOLLOW_2 set
+    // of `a` and may also begin `a Y X`, but each of the two local follow
+    // sets of `a` resolves the choice.
+    let g = examples::from_rules(&[
+      ("s", &[&["X", "a", "X", "X"], &["Y", "a", "Y", "X"]]),
+      ("a", &[&["Y"], &[]]),
+    ]);
+    assert_eq!(g.lookahead_requirement(3), Some(2));
+
+    assert_eq!(examples::make_paren().lookahead_requirement(3), Some(1));
+    assert_eq!(
+      examples::make_left_recursive().lookahead_requirement(4),
+      None
+    );
+  }
+}
@@ -0,0 +1,146 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Predicates for checking whether a grammar is in a normal form.
+//!
+//! In all of these, an empty production is permitted for the start
+//! nonterminal, as long as the start nonterminal does not appear in the body
+//! of any production. This allows grammars whose language includes the empty
+//! sentence to be in normal form.
+
+use crate::grammar::{Elem, Grammar, Prod};
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  NT: Ord,
+{
+  /// Returns true if the grammar is in Chomsky Normal Form.
+  ///
+  /// Every production must either be a single terminal, or exactly two
+  /// nonterminals, neither of which is the start nonterminal.
+  pub fn is_cnf(&self) -> bool {
+    self.prods().all(|prod| {
+      let elems = prod.elements().collect::<Vec<_>>();
+      match elems.as_slice() {
+        [] => self.is_allowed_empty(&prod),
+        [Elem::Term(_)] => true,
+        [Elem::NonTerm(a), Elem::NonTerm(b)] => {
+          a != self.start_nt() && b != self.start_nt()
+        }
+        _ => false,
+      }
+    })
+  }
+
+  /// Returns true if the grammar is in Greibach Normal Form.
+  ///
+  /// Every production must be a terminal followed by zero or more
+  /// nonterminals.
+  pub fn is_gnf(&self) -> bool {
+    self.prods().all(|prod| {
+      let mut elems = prod.elements();
+      match elems.next() {
+        None => self.is_allowed_empty(&prod),
+        Some(Elem::Term(_)) => {
+          elems.all(|elem| matches!(elem, Elem::NonTerm(_)))
+        }
+        Some(Elem::NonTerm(_)) => false,
+      }
+    })
+  }
+
+  /// Returns true if no production of the grammar is empty, other than the
+  /// start production exception described above.
+  pub fn is_epsilon_free(&self) -> bool {
+    self
+      .prods()
+      .all(|prod| !prod.is_empty() || self.is_allowed_empty(&prod))
+  }
+
+  /// Returns true if any production of the grammar consists of a single
+  /// nonterminal.
+  pub fn has_unit_productions(&self) -> bool {
+    self.prods().any(|prod| {
+      let mut elems = prod.elements();
+      matches!((elems.next(), elems.next()), (Some(Elem::NonTerm(_)), None))
+    })
+  }
+
+  fn is_allowed_empty(&self, prod: &Prod<T, NT, AK, AV>) -> bool {
+    prod.head() == self.start_nt()
+      && !self
+        .prods()
+        .flat_map(|p| p.elements())
+        .any(|elem| elem.as_nonterm() == Some(self.start_nt()))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::grammar::examples::{self, from_rules};
+
+  #[test]
+  fn test_cnf() {
+    let g = from_rules(&[
+      ("s", &[&["a", "b"], &["A"], &[]]),
+      ("a", &[&["A"]]),
+      ("b", &[&["a", "a"], &["B"]]),
+    ]);
+    assert!(g.is_cnf());
+    assert!(!g.is_gnf());
+    assert!(g.is_epsilon_free());
+    assert!(!g.has_unit_productions());
+
+    // The start nonterminal may not appear in a body.
+    let g = from_rules(&[("s", &[&["s", "s"], &["A"]])]);
+    assert!(!g.is_cnf());
+
+    assert!(!examples::make_simple().is_cnf());
+    assert!(!examples::make_paren().is_cnf());
+  }
+
+  #[test]
+  fn test_gnf() {
+    let g = from_rules(&[
+      ("s", &[&["A", "b", "b"], &["B"]]),
+      ("b", &[&["B"], &["A", "b"]]),
+    ]);
+    assert!(g.is_gnf());
+    assert!(!g.is_cnf());
+
+    assert!(!examples::make_simple().is_gnf());
+    assert!(!examples::make_paren().is_gnf());
+  }
+
+  #[test]
+  fn test_epsilon_free() {
+    assert!(examples::make_simple().is_epsilon_free());
+    assert!(!examples::make_paren().is_epsilon_free());
+    assert!(!examples::make_simple_nullable().is_epsilon_free());
+
+    // An empty start production is only allowed if the start nonterminal
+    // is not used in any body.
+    let g = from_rules(&[("s", &[&["A", "s"], &[]])]);
+    assert!(!g.is_epsilon_free());
+  }
+
+  #[test]
+  fn test_unit_productions() {
+    assert!(examples::make_simple().has_unit_productions());
+    assert!(examples::make_paren().has_unit_productions());
+
+    let g = from_rules(&[("s", &[&["A", "a"]]), ("a", &[&["B"]])]);
+    assert!(!g.has_unit_productions());
+  }
+}
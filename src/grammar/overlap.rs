@@ -0,0 +1,153 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A lint for terminals whose lexemes overlap, such as a keyword that is also
+//! a valid identifier.
+
+use {
+  crate::grammar::{
+    passes::{
+      firsts::Firsts, follows::Follows, nullable::NullableSet, PassContext,
+    },
+    Elem, Grammar, ProdKey,
+  },
+  std::collections::BTreeSet,
+};
+
+/// Two productions of the same rule that may begin with different terminals
+/// which can match the same lexeme.
+///
+/// A parser choosing between the productions can't tell which one the
+/// lexeme starts, unless the lexer always picks the same terminal for it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TerminalOverlap<T, NT, AK> {
+  pub first: ProdKey<NT, AK>,
+  /// The terminal that `first` may begin with.
+  pub first_terminal: T,
+  pub second: ProdKey<NT, AK>,
+  /// The terminal that `second` may begin with.
+  pub second_terminal: T,
+}
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: Ord + Clone + 'static,
+  NT: Ord + Clone + 'static,
+  AK: Ord + Clone + 'static,
+{
+  /// Returns the places where the terminals of one of the `overlaps` pairs
+  /// each begin a different production of the same rule.
+  ///
+  /// A production "begins" with the terminals in its FIRST set, and, if it
+  /// can derive the empty sentence, the FOLLOW set of its head.
+  pub fn ambiguous_terminals(
+    &self,
+    overlaps: &[(T, T)],
+  ) -> Vec<TerminalOverlap<T, NT, AK>> {
+    let passes = PassContext::new(self);
+    let nullables = passes
+      .get_pass::<NullableSet<NT>>()
+      .expect("nullable sets are defined for every grammar");
+    let firsts = passes
+      .get_pass::<Firsts<T, NT>>()
+      .expect("FIRST sets are defined for every grammar");
+    let follows = passes
+      .get_pass::<Follows<T, NT>>()
+      .expect("FOLLOW sets are defined for every grammar");
+
+    let mut found = Vec::new();
+    for rule in self.rules() {
+      let prods = rule
+        .prods()
+        .map(|prod| {
+          let mut lookaheads = BTreeSet::new();
+          let mut nullable = true;
+          for elem in prod.elements() {
+            match elem {
+              Elem::Term(t) => {
+                lookaheads.insert(t.clone());
+                nullable = false;
+              }
+              Elem::NonTerm(nt) => {
+                lookaheads.extend(firsts.terms(nt).cloned());
+                nullable = nullables.is_nullable(nt);
+              }
+            }
+            if !nullable {
+              break;
+            }
+          }
+          if nullable {
+            lookaheads.extend(follows.terms(rule.head()).cloned());
+          }
+          (prod.prod_key(), lookaheads)
+        })
+        .collect::<Vec<_>>();
+
+      for (i, (first, first_lookaheads)) in prods.iter().enumerate() {
+        for (second, second_lookaheads) in &prods[i + 1..] {
+          for (a, b) in overlaps {
+            for (first_terminal, second_terminal) in [(a, b), (b, a)] {
+              if first_lookaheads.contains(first_terminal)
+                && second_lookaheads.contains(second_terminal)
+              {
+                found.push(TerminalOverlap {
+                  first: first.clone(),
+                  first_terminal: first_terminal.clone(),
+                  second: second.clone(),
+                  second_terminal: second_terminal.clone(),
+                });
+              }
+            }
+          }
+        }
+      }
+    }
+
+    found
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{examples, NonTerminal, Terminal};
+  use crate::utils::Name;
+
+  #[test]
+  fn test_keyword_identifier() {
+    // `if` may also be lexed as an identifier, which begins a call.
+    let g = examples::from_rules(&[
+      ("stmt", &[&["IF", "IDENT", "stmt"], &["call"]]),
+      ("call", &[&["IDENT", "LPAREN", "RPAREN"]]),
+    ]);
+    let overlaps = [(Terminal::new("IF"), Terminal::new("IDENT"))];
+
+    assert_eq!(
+      g.ambiguous_terminals(&overlaps),
+      vec![TerminalOverlap {
+        first: ProdKey::new(NonTerminal::new("stmt"), Name::new("p0")),
+        first_terminal: Terminal::new("IF"),
+        second: ProdKey::new(NonTerminal::new("stmt"), Name::new("p1")),
+        second_terminal: Terminal::new("IDENT"),
+      }]
+    );
+
+    // Where only one of the terminals can appear, the lexer's choice
+    // doesn't matter.
+    assert!(examples::make_paren()
+      .ambiguous_terminals(&[(Terminal::new("LPAREN"), Terminal::new("IF"))])
+      .is_empty());
+  }
+}
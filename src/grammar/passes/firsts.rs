@@ -1,25 +1,101 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeSet;
+use std::rc::Rc;
 
-use crate::utils::{change_iter, change_loop, WasChanged};
-use crate::{grammar::Elem, utils::CollectMap};
+use crate::grammar::{Elem, Prod};
+use crate::utils::{
+  bitset::{union_within, BitSet},
+  change_iter, change_loop, WasChanged,
+};
 
-use super::nullable::{self, Nullable};
-use super::Pass;
+use super::nullable::NullableSet;
+use super::symbols::{SymbolTable, TermSet};
+use super::{IterationLimitExceeded, Pass};
 
 #[derive(thiserror::Error, Debug)]
 pub enum FirstsError {
   #[error(transparent)]
-  NullableError(#[from] nullable::NullableError),
+  IterationLimit(#[from] IterationLimitExceeded),
 }
 
-pub struct Firsts<T, NT>(BTreeMap<NT, BTreeSet<T>>);
+/// The FIRST set of each nonterminal.
+///
+/// Sets are stored as bitsets indexed by the `SymbolTable` pass.
+pub struct Firsts<T, NT> {
+  symbols: Rc<SymbolTable<T, NT>>,
+  sets: Vec<BitSet>,
+  /// Whether each nonterminal is nullable, so sequences can be handled
+  /// without the `NullableSet` pass.
+  nullable: Vec<bool>,
+}
 
 impl<T, NT> Firsts<T, NT>
 where
+  T: Ord + Clone,
   NT: Ord,
 {
-  pub fn get(&self, nt: &NT) -> Option<&BTreeSet<T>> {
-    self.0.get(nt)
+  /// Returns the set of terminals that can begin a sentence derived from
+  /// `nt`.
+  ///
+  /// This is `None` only if `nt` is not in the grammar. A nonterminal of the
+  /// grammar whose FIRST set is empty, such as one that only derives the
+  /// empty sentence, gives `Some` of an empty set. The set borrows from
+  /// these sets; `TermSet::to_set` copies it.
+  pub fn get(&self, nt: &NT) -> Option<TermSet<'_, T, NT>> {
+    self.get_bits(nt).map(|set| self.symbols.term_view(set))
+  }
+
+  /// Returns the terminals in the FIRST set of `nt`, in order, without
+  /// building a set. This is empty if `nt` is not in the grammar.
+  pub fn terms<'a>(&'a self, nt: &NT) -> impl Iterator<Item = &'a T> + 'a {
+    self.get(nt).into_iter().flat_map(|set| set.iter())
+  }
+
+  /// Returns true if `term` is in the FIRST set of `nt`.
+  pub fn contains(&self, nt: &NT, term: &T) -> bool {
+    match (self.get_bits(nt), self.symbols.term_index(term)) {
+      (Some(set), Some(i)) => set.contains(i),
+      _ => false,
+    }
+  }
+
+  /// Returns the set of terminals that can begin a sentence derived from
+  /// `elems`. Nonterminals that are not in the grammar are treated as
+  /// deriving nothing.
+  pub fn of_elems<'e>(
+    &self,
+    elems: impl IntoIterator<Item = &'e Elem<T, NT>>,
+  ) -> BTreeSet<T>
+  where
+    T: 'e,
+    NT: 'e,
+  {
+    let mut result = BTreeSet::new();
+    for elem in elems {
+      match elem {
+        Elem::Term(t) => {
+          result.insert(t.clone());
+          break;
+        }
+        Elem::NonTerm(nt) => match self.symbols.nonterm_index(nt) {
+          Some(i) => {
+            result.extend(self.symbols.term_set(&self.sets[i]));
+            if !self.nullable[i] {
+              break;
+            }
+          }
+          None => break,
+        },
+      }
+    }
+    result
+  }
+
+  pub(crate) fn get_bits(&self, nt: &NT) -> Option<&BitSet> {
+    self.symbols.nonterm_index(nt).map(|i| &self.sets[i])
+  }
+
+  pub(crate) fn bits_at(&self, nt_index: usize) -> &BitSet {
+    &self.sets[nt_index]
   }
 }
 
@@ -36,38 +112,143 @@ where
   ) -> Result<Self, FirstsError> {
     let gram = pass_context.grammar();
 
-    let nullables = pass_context.get_pass::<Nullable<NT, AK>>()?;
+    let nullables = pass_context.get_pass::<NullableSet<NT>>()?;
+    let symbols = pass_context.get_infallible_pass::<SymbolTable<T, NT>>();
+
+    let nullable_indexes = (0..symbols.num_nonterms())
+      .map(|i| nullables.is_nullable(symbols.nonterm(i)))
+      .collect::<Vec<_>>();
+    let prods = gram
+      .prods()
+      .map(|prod| {
+        let head = symbols.nonterm_index(prod.head()).unwrap();
+        (head, symbols.index_elems(&prod))
+      })
+      .collect::<Vec<_>>();
 
-    let mut firsts = CollectMap::new();
+    let mut firsts = vec![symbols.new_term_set(); symbols.num_nonterms()];
 
+    let limit = pass_context.iteration_limit();
+    let mut iteration = 0;
     change_loop(|| {
-      change_iter(gram.prods(), |prod| {
+      iteration += 1;
+      if IterationLimitExceeded::check("FIRST", limit, iteration).is_err() {
+        return WasChanged::Unchanged;
+      }
+      // The nonterminals whose sets grew, by `SymbolTable` index.
+      let mut grown = BTreeSet::new();
+      let changed = change_iter(&prods, |(head, elems)| {
         let mut changed = WasChanged::Unchanged;
-        for elem in prod.elements() {
-          match elem {
+        for elem in elems {
+          match *elem {
             Elem::Term(t) => {
-              changed.merge(firsts.insert(prod.head(), t));
+              changed.merge(firsts[*head].insert(t));
               break;
             }
             Elem::NonTerm(nt) => {
-              changed.merge(firsts.insert_from_key_set(prod.head(), nt));
-              if !nullables.is_nullable(nt) {
+              changed.merge(union_within(&mut firsts, *head, nt));
+              if !nullable_indexes[nt] {
                 break;
               }
             }
           }
         }
 
+        if cfg!(feature = "trace-analyses") && changed == WasChanged::Changed {
+          grown.insert(*head);
+        }
         changed
-      })
+      });
+
+      trace_fixpoint!(iteration, grown = ?grown, "FIRST sets grew");
+      changed
     });
+    IterationLimitExceeded::check("FIRST", limit, iteration)?;
+
+    Ok(Firsts {
+      symbols,
+      sets: firsts,
+      nullable: nullable_indexes,
+    })
+  }
+}
+
+impl<'a, T, NT, AK, AV> Prod<'a, T, NT, AK, AV>
+where
+  T: Ord + Clone,
+  NT: Ord,
+{
+  /// Returns the set of terminals that can begin a sentence derived from
+  /// this production.
+  pub fn first_set(&self, firsts: &Firsts<T, NT>) -> BTreeSet<T> {
+    firsts.of_elems(self.elements())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::passes::PassContext;
+  use crate::grammar::{examples, NonTerminal, Terminal};
+
+  #[test]
+  fn test_prod_first_set() {
+    let g = examples::from_rules(&[
+      ("start", &[&["stmt"]]),
+      ("stmt", &[&["sign", "NUM"], &["LPAREN", "stmt", "RPAREN"]]),
+      ("sign", &[&[], &["MINUS"], &["PLUS"]]),
+    ]);
+    let passes = PassContext::new(&g);
+    let firsts = passes.get_pass::<Firsts<Terminal, NonTerminal>>().unwrap();
+    let terms = |names: &[&str]| {
+      names
+        .iter()
+        .map(|n| Terminal::new(n))
+        .collect::<BTreeSet<_>>()
+    };
+
+    let rule = g.get_rule(&NonTerminal::new("stmt"));
+    let sets = rule
+      .prods()
+      .map(|prod| prod.first_set(&firsts))
+      .collect::<Vec<_>>();
+    // The nullable `sign` lets `NUM` begin the first production.
+    assert_eq!(
+      sets,
+      vec![terms(&["MINUS", "NUM", "PLUS"]), terms(&["LPAREN"])]
+    );
+    assert!(sets[0].is_disjoint(&sets[1]));
+
+    let rule = g.get_rule(&NonTerminal::new("sign"));
+    let sets = rule
+      .prods()
+      .map(|prod| prod.first_set(&firsts))
+      .collect::<Vec<_>>();
+    assert_eq!(sets, vec![terms(&[]), terms(&["MINUS"]), terms(&["PLUS"])]);
+  }
+
+  #[test]
+  fn test_get_contract() {
+    let g = examples::make_simple_nullable();
+    let passes = PassContext::new(&g);
+    let firsts = passes.get_pass::<Firsts<Terminal, NonTerminal>>().unwrap();
+
+    // `a` only derives the empty sentence, so its FIRST set is empty, but it
+    // is still in the grammar.
+    let a = NonTerminal::new("a");
+    assert!(firsts.get(&a).unwrap().is_empty());
+    assert_eq!(firsts.terms(&a).count(), 0);
+    let missing = NonTerminal::new("missing");
+    assert!(firsts.get(&missing).is_none());
+    assert_eq!(firsts.terms(&missing).count(), 0);
 
-    Ok(Firsts(
-      firsts
-        .into_inner()
-        .into_iter()
-        .map(|(k, v)| (k.clone(), v.into_iter().cloned().collect()))
-        .collect(),
-    ))
+    let g = examples::make_paren();
+    let passes = PassContext::new(&g);
+    let firsts = passes.get_pass::<Firsts<Terminal, NonTerminal>>().unwrap();
+    let expr = NonTerminal::new("expr");
+    let lparen = Terminal::new("LPAREN");
+    assert_eq!(firsts.terms(&expr).collect::<Vec<_>>(), vec![&lparen]);
+    assert!(firsts.contains(&expr, &lparen));
+    assert!(!firsts.contains(&expr, &Terminal::new("RPAREN")));
   }
 }
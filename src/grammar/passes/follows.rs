@@ -1,18 +1,193 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::rc::Rc;
 
-use crate::utils::{change_iter, change_loop, WasChanged};
-use crate::{grammar::Elem, utils::CollectMap};
+use crate::grammar::{Elem, Grammar, ProdElement, ProdKey};
+use crate::utils::{
+  bitset::{union_within, BitSet},
+  change_iter, change_loop, WasChanged,
+};
 
 use super::firsts::{Firsts, FirstsError};
-use super::Pass;
+use super::nullable::NullableSet;
+use super::symbols::{SymbolTable, TermSet};
+use super::{IterationLimitExceeded, Pass};
 
 #[derive(thiserror::Error, Debug)]
 pub enum FollowsError {
   #[error(transparent)]
   First(#[from] FirstsError),
+  #[error(transparent)]
+  IterationLimit(#[from] IterationLimitExceeded),
+}
+
+/// The FOLLOW set of each nonterminal.
+///
+/// Sets are stored as bitsets indexed by the `SymbolTable` pass.
+pub struct Follows<T, NT> {
+  symbols: Rc<SymbolTable<T, NT>>,
+  firsts: Rc<Firsts<T, NT>>,
+  nullables: Rc<NullableSet<NT>>,
+  sets: Vec<BitSet>,
 }
 
-pub struct Follows<T, NT>(BTreeMap<NT, BTreeSet<T>>);
+/// One step in the explanation of why a terminal is in a FOLLOW set.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum FollowReason<NT, AK> {
+  /// The terminal can begin the part of `prod` after `nt`.
+  Suffix { prod: ProdKey<NT, AK>, nt: NT },
+  /// The part of `prod` after `nt` is nullable, so the FOLLOW set of the
+  /// production's head is included in the FOLLOW set of `nt`.
+  Inherited { prod: ProdKey<NT, AK>, nt: NT },
+}
+
+impl<T, NT> Follows<T, NT>
+where
+  T: Ord + Clone,
+  NT: Ord,
+{
+  /// Returns the set of terminals that can immediately follow `nt` in a
+  /// sentential form.
+  ///
+  /// As with `Firsts::get`, this is `None` only if `nt` is not in the
+  /// grammar, and `Some` of an empty set if nothing can follow it. The set
+  /// borrows from these sets; `TermSet::to_set` copies it.
+  pub fn get(&self, nt: &NT) -> Option<TermSet<'_, T, NT>> {
+    self.get_bits(nt).map(|set| self.symbols.term_view(set))
+  }
+
+  /// Returns the terminals in the FOLLOW set of `nt`, in order, without
+  /// building a set. This is empty if `nt` is not in the grammar.
+  pub fn terms<'a>(&'a self, nt: &NT) -> impl Iterator<Item = &'a T> + 'a {
+    self.get(nt).into_iter().flat_map(|set| set.iter())
+  }
+
+  pub(crate) fn get_bits(&self, nt: &NT) -> Option<&BitSet> {
+    self.symbols.nonterm_index(nt).map(|i| &self.sets[i])
+  }
+
+  /// Returns true if `term` is in the FOLLOW set of `nt`.
+  pub fn contains(&self, nt: &NT, term: &T) -> bool {
+    match (self.get_bits(nt), self.symbols.term_index(term)) {
+      (Some(set), Some(i)) => set.contains(i),
+      _ => false,
+    }
+  }
+
+  /// Explains why `term` is in FOLLOW(`nt`), where `g` is the grammar these
+  /// sets were computed from.
+  ///
+  /// The explanation starts at `nt` and follows the productions whose
+  /// nullable suffixes propagated `term` into each FOLLOW set, ending with
+  /// the production where `term` can directly follow a nonterminal. It is
+  /// the shortest such chain, and is empty if `term` is not in the set.
+  pub fn explain<AK, AV>(
+    &self,
+    g: &Grammar<T, NT, AK, AV>,
+    nt: &NT,
+    term: &T,
+  ) -> Vec<FollowReason<NT, AK>>
+  where
+    NT: Clone,
+    AK: Clone,
+  {
+    if !self.contains(nt, term) {
+      return Vec::new();
+    }
+
+    // Search outwards from `nt`, remembering the step that reached each
+    // nonterminal from the one before it.
+    let mut parents: BTreeMap<NT, (NT, FollowReason<NT, AK>)> = BTreeMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(nt.clone());
+    while let Some(curr) = queue.pop_front() {
+      for prod in g.prods() {
+        let elems = prod.prod_elements();
+        for (i, elem) in elems.iter().enumerate() {
+          match elem.elem() {
+            Elem::NonTerm(elem_nt) if *elem_nt == curr => {}
+            _ => continue,
+          }
+
+          let step = match self.suffix_step(&elems[i + 1..], term) {
+            SuffixStep::Starts => FollowReason::Suffix {
+              prod: prod.prod_key(),
+              nt: curr.clone(),
+            },
+            SuffixStep::Nullable
+              if prod.head() != nt
+                && !parents.contains_key(prod.head())
+                && self.contains(prod.head(), term) =>
+            {
+              parents.insert(
+                prod.head().clone(),
+                (
+                  curr.clone(),
+                  FollowReason::Inherited {
+                    prod: prod.prod_key(),
+                    nt: curr.clone(),
+                  },
+                ),
+              );
+              queue.push_back(prod.head().clone());
+              continue;
+            }
+            _ => continue,
+          };
+
+          let mut chain = vec![step];
+          let mut at = curr;
+          while let Some((prev, reason)) = parents.remove(&at) {
+            chain.push(reason);
+            at = prev;
+          }
+          chain.reverse();
+          return chain;
+        }
+      }
+    }
+
+    unreachable!("every terminal in a FOLLOW set has a derivation")
+  }
+
+  fn suffix_step(&self, suffix: &[ProdElement<T, NT>], term: &T) -> SuffixStep {
+    let index = self.symbols.term_index(term);
+    for elem in suffix {
+      match elem.elem() {
+        Elem::Term(t) => {
+          return if t == term {
+            SuffixStep::Starts
+          } else {
+            SuffixStep::Blocked
+          };
+        }
+        Elem::NonTerm(next) => {
+          let starts = match (self.firsts.get_bits(next), index) {
+            (Some(firsts), Some(i)) => firsts.contains(i),
+            _ => false,
+          };
+          if starts {
+            return SuffixStep::Starts;
+          }
+          if !self.nullables.is_nullable(next) {
+            return SuffixStep::Blocked;
+          }
+        }
+      }
+    }
+    SuffixStep::Nullable
+  }
+}
+
+/// How the remainder of a production after a nonterminal relates to a
+/// terminal in that nonterminal's FOLLOW set.
+enum SuffixStep {
+  /// The terminal can begin the remainder.
+  Starts,
+  /// The remainder is nullable, but the terminal can't begin it.
+  Nullable,
+  /// The remainder can't be empty, and can't begin with the terminal.
+  Blocked,
+}
 
 impl<T, NT, AK, AV> Pass<T, NT, AK, AV> for Follows<T, NT>
 where
@@ -28,39 +203,155 @@ where
     let gram = pass_map.grammar();
 
     let firsts = pass_map.get_pass::<Firsts<T, NT>>()?;
+    let nullables = pass_map.get_pass::<NullableSet<NT>>()?;
+    let symbols = pass_map.get_infallible_pass::<SymbolTable<T, NT>>();
+
+    let nullable_indexes = (0..symbols.num_nonterms())
+      .map(|i| nullables.is_nullable(symbols.nonterm(i)))
+      .collect::<Vec<_>>();
+    let prods = gram
+      .prods()
+      .map(|prod| {
+        let head = symbols.nonterm_index(prod.head()).unwrap();
+        (head, symbols.index_elems(&prod))
+      })
+      .collect::<Vec<_>>();
 
-    let mut follows = CollectMap::new();
+    let mut follows = vec![symbols.new_term_set(); symbols.num_nonterms()];
 
+    let limit = pass_map.iteration_limit();
+    let mut iteration = 0;
     change_loop(|| {
-      change_iter(gram.prods(), |prod| {
+      iteration += 1;
+      if IterationLimitExceeded::check("FOLLOW", limit, iteration).is_err() {
+        return WasChanged::Unchanged;
+      }
+      // The nonterminals whose sets grew, by `SymbolTable` index.
+      let mut grown = BTreeSet::new();
+      let changed = change_iter(&prods, |(head, elems)| {
         let mut changed = WasChanged::Unchanged;
-        let elems = prod.elements().collect::<Vec<_>>();
-        for elem_pair in elems.windows(2) {
-          if let Elem::NonTerm(first_nt) = elem_pair[0] {
-            match elem_pair[1] {
-              Elem::Term(t) => changed.merge(follows.insert(first_nt, t)),
-              Elem::NonTerm(second_nt) => changed.merge(
-                follows
-                  .insert_iter(first_nt, firsts.get(second_nt).unwrap().iter()),
-              ),
+        for (i, elem) in elems.iter().enumerate() {
+          let curr_nt = match *elem {
+            Elem::NonTerm(nt) => nt,
+            Elem::Term(_) => continue,
+          };
+
+          // Everything that can start the rest of the production can follow
+          // this nonterminal. We can only look past nullable nonterminals.
+          let mut suffix_nullable = true;
+          for next_elem in &elems[i + 1..] {
+            match *next_elem {
+              Elem::Term(t) => {
+                changed.merge(follows[curr_nt].insert(t));
+                suffix_nullable = false;
+              }
+              Elem::NonTerm(next_nt) => {
+                changed
+                  .merge(follows[curr_nt].union_with(firsts.bits_at(next_nt)));
+                suffix_nullable = nullable_indexes[next_nt];
+              }
+            }
+
+            if !suffix_nullable {
+              break;
             }
           }
-        }
 
-        if let Some(Elem::NonTerm(last_nt)) = elems.last() {
-          changed.merge(follows.insert_from_key_set(last_nt, prod.head()));
+          if suffix_nullable {
+            changed.merge(union_within(&mut follows, curr_nt, *head));
+          }
         }
 
+        if cfg!(feature = "trace-analyses") && changed == WasChanged::Changed {
+          grown.insert(*head);
+        }
         changed
-      })
+      });
+
+      trace_fixpoint!(iteration, grown = ?grown, "FOLLOW sets grew");
+      changed
     });
+    IterationLimitExceeded::check("FOLLOW", limit, iteration)?;
+
+    Ok(Follows {
+      symbols,
+      firsts,
+      nullables,
+      sets: follows,
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{examples, passes::PassContext, NonTerminal, Terminal};
+  use crate::utils::Name;
+
+  fn key(head: &str, action: &str) -> ProdKey<NonTerminal, Name> {
+    ProdKey::new(NonTerminal::new(head), Name::new(action))
+  }
 
-    Ok(Follows(
+  #[test]
+  fn test_iteration_limit() {
+    let g = examples::make_paren();
+    let passes = PassContext::with_iteration_limit(&g, 1);
+    assert!(matches!(
+      passes.get_pass::<Follows<_, _>>(),
+      Err(FollowsError::First(FirstsError::IterationLimit(_)))
+    ));
+
+    let passes = PassContext::with_iteration_limit(&g, 10);
+    let follows = passes.get_pass::<Follows<_, _>>().unwrap();
+    assert_eq!(
       follows
-        .into_inner()
-        .into_iter()
-        .map(|(k, v)| (k.clone(), v.into_iter().cloned().collect()))
-        .collect(),
-    ))
+        .get(&NonTerminal::new("expr_list"))
+        .map(|set| set.to_set()),
+      Some(std::iter::once(Terminal::new("RPAREN")).collect())
+    );
+  }
+
+  #[test]
+  fn test_failed_pass_requested_again() {
+    let g = examples::make_paren();
+    let passes = PassContext::with_iteration_limit(&g, 1);
+    assert!(passes.get_pass::<Firsts<_, _>>().is_err());
+    assert!(passes.get_pass::<Firsts<_, _>>().is_err());
+    assert!(passes.get_pass::<Follows<_, _>>().is_err());
+    assert!(passes.get_pass::<Follows<_, _>>().is_err());
+  }
+
+  #[test]
+  fn test_explain() {
+    let g = examples::make_paren();
+    let passes = PassContext::new(&g);
+    let follows = passes.get_pass::<Follows<_, _>>().unwrap();
+
+    // The production `expr_list ::= expr expr_list`, where `expr_list` is
+    // nullable, passes FOLLOW(expr_list) on to `expr`, and `expr_list` is
+    // followed by `RPAREN` in `expr ::= LPAREN expr_list RPAREN`.
+    assert_eq!(
+      follows.explain(&g, &NonTerminal::new("expr"), &Terminal::new("RPAREN")),
+      vec![
+        FollowReason::Inherited {
+          prod: key("expr_list", "elem"),
+          nt: NonTerminal::new("expr"),
+        },
+        FollowReason::Suffix {
+          prod: key("expr", "paren_expr"),
+          nt: NonTerminal::new("expr_list"),
+        },
+      ]
+    );
+    assert_eq!(
+      follows.explain(&g, &NonTerminal::new("expr"), &Terminal::new("LPAREN")),
+      vec![FollowReason::Suffix {
+        prod: key("expr_list", "elem"),
+        nt: NonTerminal::new("expr"),
+      }]
+    );
+    assert!(follows
+      .explain(&g, &NonTerminal::new("start"), &Terminal::new("RPAREN"))
+      .is_empty());
   }
 }
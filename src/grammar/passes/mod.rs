@@ -2,9 +2,22 @@
 //! This allows us to build each different type of pass in isolation, and then combine them
 //! with automatic dependency resolution.
 
+/// Emits a trace-level `tracing` event about an iteration of an analysis
+/// fixpoint, if the `trace-analyses` feature is enabled.
+///
+/// When the feature is disabled, the event is compiled out entirely.
+macro_rules! trace_fixpoint {
+  ($($arg:tt)+) => {
+    #[cfg(feature = "trace-analyses")]
+    tracing::trace!(target: "bongo::fixpoint", $($arg)+);
+  };
+}
+
 pub mod firsts;
 pub mod follows;
 pub mod nullable;
+pub mod shared;
+pub mod symbols;
 
 use std::{
   any::{Any, TypeId},
@@ -33,6 +46,31 @@ where
   }
 }
 
+/// An error from an analysis that did not reach its fixpoint within the
+/// iteration limit it was given.
+#[derive(thiserror::Error, Clone, PartialEq, Eq, Debug)]
+#[error("{analysis} did not converge within {limit} iterations")]
+pub struct IterationLimitExceeded {
+  pub analysis: &'static str,
+  pub limit: usize,
+}
+
+impl IterationLimitExceeded {
+  /// Returns an error if `iteration`, counting from 1, is over `limit`.
+  pub(crate) fn check(
+    analysis: &'static str,
+    limit: Option<usize>,
+    iteration: usize,
+  ) -> Result<(), Self> {
+    match limit {
+      Some(limit) if iteration > limit => {
+        Err(IterationLimitExceeded { analysis, limit })
+      }
+      _ => Ok(()),
+    }
+  }
+}
+
 struct BoxPassError(Box<dyn BasePassError>);
 
 enum PassError<T, NT, AK, AV, P>
@@ -62,6 +100,7 @@ pub trait Pass<T, NT, AK, AV>: Any + Sized + 'static {
 pub struct PassContext<'a, T, NT, AK, AV> {
   grammar: &'a Grammar<T, NT, AK, AV>,
   passes: RefCell<BTreeMap<TypeId, Rc<dyn Any + 'static>>>,
+  iteration_limit: Option<usize>,
 }
 
 impl<'a, T, NT, AK, AV> PassContext<'a, T, NT, AK, AV> {
@@ -70,9 +109,28 @@ impl<'a, T, NT, AK, AV> PassContext<'a, T, NT, AK, AV> {
     PassContext {
       grammar,
       passes: RefCell::new(BTreeMap::new()),
+      iteration_limit: None,
+    }
+  }
+
+  /// Like `new`, but the `Nullable`, `NullableSet`, `Firsts` and `Follows`
+  /// passes fail with `IterationLimitExceeded` if their fixpoints take more
+  /// than `limit` iterations, including the final one that finds no changes.
+  pub fn with_iteration_limit(
+    grammar: &'a Grammar<T, NT, AK, AV>,
+    limit: usize,
+  ) -> Self {
+    PassContext {
+      iteration_limit: Some(limit),
+      ..PassContext::new(grammar)
     }
   }
 
+  /// Returns the iteration limit of the fixpoint passes, if there is one.
+  pub fn iteration_limit(&self) -> Option<usize> {
+    self.iteration_limit
+  }
+
   /// Returns the underlying grammar.
   pub fn grammar(&self) -> &'a Grammar<T, NT, AK, AV> {
     self.grammar
@@ -109,7 +167,15 @@ impl<'a, T, NT, AK, AV> PassContext<'a, T, NT, AK, AV> {
         let mut guard = self.passes.borrow_mut();
         guard.insert(pass_type, Rc::new(NoCurrentValue));
       }
-      let value = P::run_pass(self)?;
+      let value = match P::run_pass(self) {
+        Ok(value) => value,
+        Err(e) => {
+          // Clear the in-process marker, so a later request for this pass
+          // fails the same way instead of reporting a recursive loop.
+          self.passes.borrow_mut().remove(&pass_type);
+          return Err(e);
+        }
+      };
       let mut guard = self.passes.borrow_mut();
       guard.insert(pass_type, Rc::new(value));
     };
@@ -124,4 +190,15 @@ impl<'a, T, NT, AK, AV> PassContext<'a, T, NT, AK, AV> {
 
     Ok(any_pass_ref.downcast::<P>().expect("type already verified"))
   }
+
+  /// Like `get_pass`, but for passes that cannot fail.
+  pub fn get_infallible_pass<P>(&self) -> Rc<P>
+  where
+    P: Pass<T, NT, AK, AV, Error = std::convert::Infallible> + 'static,
+  {
+    match self.get_pass::<P>() {
+      Ok(pass) => pass,
+      Err(never) => match never {},
+    }
+  }
 }
@@ -1,11 +1,30 @@
 mod nullables;
 
-use crate::grammar::Prod;
+use crate::{
+  grammar::{Grammar, Prod, ProdKey},
+  utils::{TreeNode, Void},
+};
 
-use super::Pass;
+use super::{IterationLimitExceeded, Pass};
 
 pub struct Nullable<NT, AK>(nullables::GrammarNullableInfo<NT, AK>);
 
+impl<NT, AK> Nullable<NT, AK>
+where
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  /// Calculates the nullable info of `g` outside of a `PassContext`.
+  ///
+  /// Fails if some nullable nonterminal has more than one empty derivation.
+  pub fn new<T, AV>(g: &Grammar<T, NT, AK, AV>) -> Result<Self, NullableError>
+  where
+    T: Ord,
+  {
+    nullables::calculate_nullables(g).map(Nullable)
+  }
+}
+
 impl<NT, AK> Nullable<NT, AK>
 where
   NT: Ord + Clone,
@@ -22,12 +41,62 @@ where
     self.0.is_prod_nullable(prod)
   }
 
+  /// Returns the tree of productions that `nt` uses to derive the empty
+  /// sequence, or `None` if `nt` is not nullable.
+  pub fn get_nullable_action(
+    &self,
+    nt: &NT,
+  ) -> Option<&TreeNode<ProdKey<NT, AK>, Void>> {
+    self.0.get_nullable_action(nt)
+  }
+
   pub fn get_nullable_info(&self) -> &nullables::GrammarNullableInfo<NT, AK> {
     &self.0
   }
 }
 
-pub use nullables::{GrammarNullableInfo, NullableError};
+pub use nullables::{
+  calculate_nullables_verbose, GrammarNullableInfo, NullableError,
+};
+
+/// The set of nullable nonterminals of a grammar.
+///
+/// Unlike `Nullable`, this does not require each nullable nonterminal to have
+/// a unique empty derivation, so it only fails if the `PassContext` has an
+/// iteration limit that it exceeds.
+pub struct NullableSet<NT>(std::collections::BTreeSet<NT>);
+
+impl<NT> NullableSet<NT>
+where
+  NT: Ord,
+{
+  pub fn is_nullable(&self, nt: &NT) -> bool {
+    self.0.contains(nt)
+  }
+
+  pub fn get_nullable_set(&self) -> &std::collections::BTreeSet<NT> {
+    &self.0
+  }
+}
+
+impl<T, NT, AK, AV> Pass<T, NT, AK, AV> for NullableSet<NT>
+where
+  T: Ord,
+  NT: Ord + Clone + 'static,
+  AK: Ord,
+{
+  type Error = IterationLimitExceeded;
+
+  fn run_pass(
+    pass_map: &super::PassContext<T, NT, AK, AV>,
+  ) -> Result<Self, Self::Error> {
+    nullables::nullable_nonterms_with_limit(
+      pass_map.grammar(),
+      pass_map.iteration_limit(),
+    )
+    .map(NullableSet)
+  }
+}
 
 impl<T, NT, AK, AV> Pass<T, NT, AK, AV> for Nullable<NT, AK>
 where
@@ -40,7 +109,11 @@ where
   fn run_pass(
     pass_map: &super::PassContext<T, NT, AK, AV>,
   ) -> Result<Self, Self::Error> {
-    nullables::calculate_nullables(pass_map.grammar()).map(Nullable)
+    nullables::calculate_nullables_with_limit(
+      pass_map.grammar(),
+      pass_map.iteration_limit(),
+    )
+    .map(Nullable)
   }
 }
 
@@ -70,6 +143,40 @@ mod test {
     assert!(nullables.is_nullable(&NonTerminal::new("c")));
   }
 
+  #[test]
+  fn test_ambiguous_nullable_set() {
+    let g = examples::make_ambiguous_nullable();
+    let pass_map = PassContext::new(&g);
+    assert!(pass_map.get_pass::<Nullable<_, _>>().is_err());
+    assert!(matches!(Nullable::new(&g), Err(NullableError::Ambiguity)));
+    let nullables = pass_map.get_pass::<NullableSet<_>>().unwrap();
+    assert_eq!(nullables.get_nullable_set().len(), 4);
+  }
+
+  #[test]
+  fn test_nullable_set_iteration_limit() {
+    // Each iteration only finds the nullable production of the next
+    // nonterminal up the chain, so this takes four iterations, and a fifth
+    // to find no more.
+    let g = examples::from_rules(&[
+      ("n0", &[&["n1"]]),
+      ("n1", &[&["n2"]]),
+      ("n2", &[&["n3"]]),
+      ("n3", &[&[]]),
+    ]);
+    let pass_map = PassContext::with_iteration_limit(&g, 4);
+    assert_eq!(
+      pass_map.get_pass::<NullableSet<_>>().err(),
+      Some(IterationLimitExceeded {
+        analysis: "nullable",
+        limit: 4,
+      })
+    );
+    let pass_map = PassContext::with_iteration_limit(&g, 5);
+    let nullables = pass_map.get_pass::<NullableSet<_>>().unwrap();
+    assert_eq!(nullables.get_nullable_set().len(), 4);
+  }
+
   #[test]
   fn test_paren_grammar() {
     let g = examples::make_paren();
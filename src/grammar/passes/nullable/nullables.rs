@@ -13,7 +13,7 @@
 // limitations under the License.
 use {
   crate::{
-    grammar::{Elem, Grammar, Prod, ProdKey},
+    grammar::{passes::IterationLimitExceeded, Elem, Grammar, Prod, ProdKey},
     utils::{TreeNode, TreeValue, Void},
   },
   std::collections::{BTreeMap, BTreeSet},
@@ -26,6 +26,7 @@ struct InternalNullableInfo<'a, T, NT, AK, AV> {
 impl<'a, T, NT, AK, AV> Ord for InternalNullableInfo<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn cmp(&self, other: &Self) -> std::cmp::Ordering {
     self.nullable_actions.cmp(&other.nullable_actions)
@@ -35,6 +36,7 @@ where
 impl<'a, T, NT, AK, AV> PartialOrd for InternalNullableInfo<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
     Some(self.cmp(other))
@@ -44,14 +46,17 @@ where
 impl<'a, T, NT, AK, AV> PartialEq for InternalNullableInfo<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn eq(&self, other: &Self) -> bool {
     self.nullable_actions == other.nullable_actions
   }
 }
 
-impl<'a, T, NT, AK, AV> Eq for InternalNullableInfo<'a, T, NT, AK, AV> where
-  NT: Ord
+impl<'a, T, NT, AK, AV> Eq for InternalNullableInfo<'a, T, NT, AK, AV>
+where
+  NT: Ord,
+  AK: Ord,
 {
 }
 
@@ -67,34 +72,51 @@ impl<T, NT, AK, AV> InternalNullableInfo<'_, T, NT, AK, AV> {
 ///
 /// The nullable set of a grammar is the set of non-terminals in that grammar
 /// that can parse the empty terminal sequence.
+///
+/// Fails if that takes more than `limit` iterations.
+#[allow(clippy::type_complexity)]
 fn inner_calculate_nullables<T, NT, AK, AV>(
   g: &Grammar<T, NT, AK, AV>,
-) -> BTreeMap<NT, InternalNullableInfo<T, NT, AK, AV>>
+  limit: Option<usize>,
+) -> Result<
+  BTreeMap<NT, InternalNullableInfo<'_, T, NT, AK, AV>>,
+  IterationLimitExceeded,
+>
 where
   T: Ord,
   NT: Ord + Clone,
+  AK: Ord,
 {
   let prods = g.prods().collect::<Vec<_>>();
 
   let mut nullable_nts: BTreeMap<NT, InternalNullableInfo<T, NT, AK, AV>> =
     BTreeMap::new();
 
+  let mut iteration = 0;
   loop {
-    let mut changed = false;
+    iteration += 1;
+    IterationLimitExceeded::check("nullable", limit, iteration)?;
+    // The productions that became nullable, by index in `prods`.
+    let mut newly_nullable = Vec::new();
 
-    for prod in &prods {
+    for (i, prod) in prods.iter().enumerate() {
       if is_prod_nullable(&nullable_nts, prod) {
         let nullable_info = nullable_nts
           .entry(prod.head().clone())
           .or_insert_with(InternalNullableInfo::new);
         if nullable_info.nullable_actions.insert(*prod) {
-          changed = true;
+          newly_nullable.push(i);
         }
       }
     }
 
-    if !changed {
-      return nullable_nts;
+    trace_fixpoint!(
+      iteration,
+      prods = ?newly_nullable,
+      "productions became nullable"
+    );
+    if newly_nullable.is_empty() {
+      return Ok(nullable_nts);
     }
   }
 }
@@ -147,6 +169,57 @@ impl<NT, AK> NonTermNullableInfo<NT, AK> {
 pub enum NullableError {
   #[error("found nullable ambiguities in grammar")]
   Ambiguity,
+  #[error(transparent)]
+  IterationLimit(#[from] IterationLimitExceeded),
+}
+
+/// Returns the set of nullable nonterminals, without checking for
+/// ambiguities.
+pub fn nullable_nonterms<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> BTreeSet<NT>
+where
+  T: Ord,
+  NT: Ord + Clone,
+  AK: Ord,
+{
+  nullable_nonterms_with_limit(g, None).expect("there is no iteration limit")
+}
+
+/// Like `nullable_nonterms`, but fails if finding the nullable nonterminals
+/// takes more than `limit` iterations.
+pub fn nullable_nonterms_with_limit<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+  limit: Option<usize>,
+) -> Result<BTreeSet<NT>, IterationLimitExceeded>
+where
+  T: Ord,
+  NT: Ord + Clone,
+  AK: Ord,
+{
+  Ok(inner_calculate_nullables(g, limit)?.into_keys().collect())
+}
+
+/// Returns each nonterminal that is ambiguously nullable, along with every
+/// production that on its own makes it nullable. `calculate_nullables`
+/// fails with `NullableError::Ambiguity` exactly when this is not empty.
+///
+/// Only the nonterminals where the ambiguity arises are listed, and not the
+/// nonterminals that are ambiguous because they derive one of them.
+pub fn calculate_nullables_verbose<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> BTreeMap<NT, BTreeSet<Prod<'_, T, NT, AK, AV>>>
+where
+  T: Ord,
+  NT: Ord + Clone,
+  AK: Ord,
+{
+  inner_calculate_nullables(g, None)
+    .expect("there is no iteration limit")
+    .into_iter()
+    .filter(|(_, info)| info.nullable_actions.len() > 1)
+    .map(|(nt, info)| (nt, info.nullable_actions))
+    .collect()
 }
 
 pub fn calculate_nullables<T, NT, AK, AV>(
@@ -157,7 +230,21 @@ where
   NT: Ord + Clone,
   AK: Ord + Clone,
 {
-  let inner_info = inner_calculate_nullables(g);
+  calculate_nullables_with_limit(g, None)
+}
+
+/// Like `calculate_nullables`, but fails if finding the nullable
+/// nonterminals takes more than `limit` iterations.
+pub fn calculate_nullables_with_limit<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+  limit: Option<usize>,
+) -> Result<GrammarNullableInfo<NT, AK>, NullableError>
+where
+  T: Ord,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  let inner_info = inner_calculate_nullables(g, limit)?;
 
   // Sanity check outputs.
   for info in inner_info.values() {
@@ -260,6 +347,7 @@ mod test {
   use crate::grammar::examples;
   use crate::grammar::NonTerminal;
   use crate::start_grammar::wrap_grammar_with_start;
+  use crate::utils::Name;
 
   #[test]
   fn test_simple_grammar() {
@@ -294,10 +382,125 @@ mod test {
       .is_none());
   }
 
+  #[cfg(feature = "trace-analyses")]
+  #[test]
+  fn test_trace_iterations() {
+    use {
+      std::sync::{Arc, Mutex},
+      tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+      },
+    };
+
+    /// Captures the fields of each fixpoint event, as `name=value` pairs.
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    struct Fields(Vec<String>);
+
+    impl Visit for Fields {
+      fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.push(format!("{}={:?}", field.name(), value));
+      }
+    }
+
+    impl Subscriber for Capture {
+      fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == "bongo::fixpoint"
+      }
+
+      fn new_span(&self, _: &span::Attributes) -> span::Id {
+        span::Id::from_u64(1)
+      }
+
+      fn record(&self, _: &span::Id, _: &span::Record) {}
+
+      fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+      fn event(&self, event: &Event) {
+        let mut fields = Fields(Vec::new());
+        event.record(&mut fields);
+        self.0.lock().unwrap().push(fields.0.join(" "));
+      }
+
+      fn enter(&self, _: &span::Id) {}
+
+      fn exit(&self, _: &span::Id) {}
+    }
+
+    // Each iteration only finds the nullable production of the next
+    // nonterminal up the chain, since productions are visited in order.
+    let g = examples::from_rules(&[
+      ("n0", &[&["n1"]]),
+      ("n1", &[&["n2"]]),
+      ("n2", &[&["n3"]]),
+      ("n3", &[&[]]),
+    ]);
+    let events = Arc::new(Mutex::new(Vec::new()));
+    tracing::subscriber::with_default(Capture(events.clone()), || {
+      assert_eq!(nullable_nonterms(&g).len(), 4);
+    });
+    assert_eq!(
+      *events.lock().unwrap(),
+      vec![
+        "message=productions became nullable iteration=1 prods=[3]",
+        "message=productions became nullable iteration=2 prods=[2]",
+        "message=productions became nullable iteration=3 prods=[1]",
+        "message=productions became nullable iteration=4 prods=[0]",
+        "message=productions became nullable iteration=5 prods=[]",
+      ]
+    );
+  }
+
+  #[test]
+  fn test_iteration_limit() {
+    let g = examples::from_rules(&[
+      ("n0", &[&["n1"]]),
+      ("n1", &[&["n2"]]),
+      ("n2", &[&["n3"]]),
+      ("n3", &[&[]]),
+    ]);
+    assert!(matches!(
+      calculate_nullables_with_limit(&g, Some(1)),
+      Err(NullableError::IterationLimit(IterationLimitExceeded {
+        analysis: "nullable",
+        limit: 1,
+      }))
+    ));
+    // Four iterations find the nullable nonterminals, and a fifth finds no
+    // more.
+    assert!(calculate_nullables_with_limit(&g, Some(4)).is_err());
+    assert!(calculate_nullables_with_limit(&g, Some(5)).is_ok());
+  }
+
   #[test]
   fn test_ambiguous_nullable_grammar() {
     let g = examples::make_ambiguous_nullable();
     let nullable_error = calculate_nullables(&g).unwrap_err();
     assert!(matches!(nullable_error, NullableError::Ambiguity));
   }
+
+  #[test]
+  fn test_verbose_ambiguities() {
+    let g = examples::make_ambiguous_nullable();
+    let ambiguities = calculate_nullables_verbose(&g)
+      .into_iter()
+      .map(|(nt, prods)| {
+        let keys = prods.iter().map(|prod| prod.prod_key());
+        (nt, keys.collect::<Vec<_>>())
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      ambiguities,
+      vec![(
+        NonTerminal::new("c"),
+        vec![
+          ProdKey::new(NonTerminal::new("c"), Name::new("c_left")),
+          ProdKey::new(NonTerminal::new("c"), Name::new("c_right")),
+        ]
+      )]
+    );
+
+    assert!(calculate_nullables_verbose(&examples::make_paren()).is_empty());
+  }
 }
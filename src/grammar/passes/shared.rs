@@ -0,0 +1,241 @@
+//! A thread-safe cache of the common grammar analyses.
+//!
+//! `PassContext` is single-threaded, and its results can hold `Rc`s. When the
+//! same grammar needs to be analyzed from multiple threads (for example, in a
+//! language server), `SharedAnalysis` can be wrapped in an `Arc` instead. Each
+//! analysis is computed lazily by the first thread that needs it, while other
+//! threads asking for the same analysis block until it is available.
+
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  sync::{Arc, OnceLock},
+};
+
+use crate::{
+  grammar::Grammar,
+  utils::{doc_string, ToDoc},
+};
+
+use super::{
+  firsts::Firsts, follows::Follows, nullable::NullableSet, PassContext,
+};
+
+/// Lazily computed and memoized nullable, FIRST, and FOLLOW sets.
+///
+/// This is `Send + Sync` whenever the grammar's `T`, `NT`, `AK` and `AV`
+/// types are, so it may be shared between threads through an `Arc`. This
+/// includes grammars over the default `Terminal`, `NonTerminal` and `Name`
+/// types.
+pub struct SharedAnalysis<T, NT, AK, AV> {
+  grammar: Arc<Grammar<T, NT, AK, AV>>,
+  nullables: OnceLock<BTreeSet<NT>>,
+  firsts: OnceLock<BTreeMap<NT, BTreeSet<T>>>,
+  follows: OnceLock<BTreeMap<NT, BTreeSet<T>>>,
+  /// The number of analyses that have been computed, so tests can check
+  /// that each is only computed once.
+  #[cfg(test)]
+  computations: AtomicUsize,
+}
+
+impl<T, NT, AK, AV> SharedAnalysis<T, NT, AK, AV>
+where
+  T: Ord + Clone + 'static,
+  NT: Ord + Clone + 'static,
+  AK: Ord + Clone + 'static,
+{
+  pub fn new(grammar: Arc<Grammar<T, NT, AK, AV>>) -> Self {
+    SharedAnalysis {
+      grammar,
+      nullables: OnceLock::new(),
+      firsts: OnceLock::new(),
+      follows: OnceLock::new(),
+      #[cfg(test)]
+      computations: AtomicUsize::new(0),
+    }
+  }
+
+  /// Returns the underlying grammar.
+  pub fn grammar(&self) -> &Arc<Grammar<T, NT, AK, AV>> {
+    &self.grammar
+  }
+
+  pub fn is_nullable(&self, nt: &NT) -> bool {
+    self
+      .nullables
+      .get_or_init(|| {
+        self.record_computation();
+        let passes = PassContext::new(&*self.grammar);
+        passes
+          .get_pass::<NullableSet<NT>>()
+          .expect("nullable sets are defined for every grammar")
+          .get_nullable_set()
+          .clone()
+      })
+      .contains(nt)
+  }
+
+  /// Returns the FIRST set of `nt`, or `None` if `nt` is not in the grammar.
+  pub fn first_of_nonterm(&self, nt: &NT) -> Option<&BTreeSet<T>> {
+    self
+      .firsts
+      .get_or_init(|| {
+        self.record_computation();
+        let passes = PassContext::new(&*self.grammar);
+        let firsts = passes
+          .get_pass::<Firsts<T, NT>>()
+          .expect("FIRST sets are defined for every grammar");
+        self.collect_sets(|nt| firsts.get(nt).map(|set| set.to_set()))
+      })
+      .get(nt)
+  }
+
+  /// Returns the FOLLOW set of `nt`, or `None` if `nt` is not in the
+  /// grammar.
+  pub fn follow_of_nonterm(&self, nt: &NT) -> Option<&BTreeSet<T>> {
+    self
+      .follows
+      .get_or_init(|| {
+        self.record_computation();
+        let passes = PassContext::new(&*self.grammar);
+        let follows = passes
+          .get_pass::<Follows<T, NT>>()
+          .expect("FOLLOW sets are defined for every grammar");
+        self.collect_sets(|nt| follows.get(nt).map(|set| set.to_set()))
+      })
+      .get(nt)
+  }
+
+  /// Returns a table of the nullability, FIRST set and FOLLOW set of every
+  /// nonterminal, with one row per nonterminal, in the style of a compilers
+  /// textbook. This is mostly useful for debugging conflicts in parse
+  /// tables.
+  pub fn dump(&self) -> String
+  where
+    T: ToDoc,
+    NT: ToDoc,
+  {
+    let set_string = |set: Option<&BTreeSet<T>>| {
+      let terms = set.into_iter().flatten().map(doc_string);
+      format!("{{{}}}", terms.collect::<Vec<_>>().join(", "))
+    };
+
+    let mut rows = vec![["NONTERMINAL", "NULLABLE", "FIRST", "FOLLOW"]
+      .map(|header| header.to_string())];
+    for rule in self.grammar.rules() {
+      let nt = rule.head();
+      let nullable = if self.is_nullable(nt) { "yes" } else { "no" };
+      rows.push([
+        doc_string(nt),
+        nullable.to_string(),
+        set_string(self.first_of_nonterm(nt)),
+        set_string(self.follow_of_nonterm(nt)),
+      ]);
+    }
+
+    let mut widths = [0; 4];
+    for row in &rows {
+      for (width, cell) in widths.iter_mut().zip(row) {
+        *width = (*width).max(cell.chars().count());
+      }
+    }
+    rows
+      .iter()
+      .map(|row| {
+        let cells = row
+          .iter()
+          .zip(widths)
+          .map(|(cell, width)| format!("{:width$}", cell, width = width));
+        cells.collect::<Vec<_>>().join("  ").trim_end().to_string()
+      })
+      .collect::<Vec<_>>()
+      .join("\n")
+  }
+
+  fn record_computation(&self) {
+    #[cfg(test)]
+    self.computations.fetch_add(1, Ordering::Relaxed);
+  }
+
+  fn collect_sets(
+    &self,
+    get: impl Fn(&NT) -> Option<BTreeSet<T>>,
+  ) -> BTreeMap<NT, BTreeSet<T>> {
+    self
+      .grammar
+      .rules()
+      .filter_map(|rule| get(rule.head()).map(|set| (rule.head().clone(), set)))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{examples, NonTerminal, Terminal};
+  use crate::utils::Name;
+
+  fn assert_send_sync<T: Send + Sync>() {}
+
+  #[test]
+  fn test_shared_between_threads() {
+    assert_send_sync::<SharedAnalysis<Terminal, NonTerminal, Name, ()>>();
+
+    let analysis =
+      Arc::new(SharedAnalysis::new(Arc::new(examples::make_paren())));
+    let expr = NonTerminal::new("expr");
+    let expr_list = NonTerminal::new("expr_list");
+    let threads = (0..8)
+      .map(|_| {
+        let analysis = analysis.clone();
+        let (expr, expr_list) = (expr.clone(), expr_list.clone());
+        std::thread::spawn(move || {
+          (0..100)
+            .map(|_| {
+              (
+                analysis.is_nullable(&expr_list),
+                analysis.first_of_nonterm(&expr_list).cloned(),
+                analysis.follow_of_nonterm(&expr).cloned(),
+              )
+            })
+            .collect::<Vec<_>>()
+        })
+      })
+      .collect::<Vec<_>>();
+
+    let expected = (
+      true,
+      Some(std::iter::once(Terminal::new("LPAREN")).collect::<BTreeSet<_>>()),
+      Some(
+        vec![Terminal::new("LPAREN"), Terminal::new("RPAREN")]
+          .into_iter()
+          .collect::<BTreeSet<_>>(),
+      ),
+    );
+    for thread in threads {
+      for result in thread.join().unwrap() {
+        assert_eq!(result, expected);
+      }
+    }
+
+    // Each of the three analyses was computed exactly once.
+    assert_eq!(analysis.computations.load(Ordering::Relaxed), 3);
+    assert_eq!(
+      analysis.first_of_nonterm(&NonTerminal::new("missing")),
+      None
+    );
+  }
+
+  #[test]
+  fn test_dump() {
+    let analysis = SharedAnalysis::new(Arc::new(examples::make_paren()));
+    let expected = [
+      "NONTERMINAL  NULLABLE  FIRST     FOLLOW",
+      "expr         no        {LPAREN}  {LPAREN, RPAREN}",
+      "expr_list    yes       {LPAREN}  {RPAREN}",
+      "start        no        {LPAREN}  {}",
+    ];
+    assert_eq!(analysis.dump(), expected.join("\n"));
+  }
+}
@@ -0,0 +1,212 @@
+//! Dense integer indexes for the symbols of a grammar.
+//!
+//! Analyses that compute sets of symbols can use these indexes to store the
+//! sets as bitsets, and only convert back to the symbols at the API boundary.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+  grammar::{Elem, Prod},
+  utils::bitset::BitSet,
+};
+
+use super::Pass;
+
+pub struct SymbolTable<T, NT> {
+  terms: Vec<T>,
+  term_indexes: BTreeMap<T, usize>,
+  nonterms: Vec<NT>,
+  nonterm_indexes: BTreeMap<NT, usize>,
+}
+
+impl<T, NT> SymbolTable<T, NT>
+where
+  T: Ord,
+  NT: Ord,
+{
+  pub fn num_terms(&self) -> usize {
+    self.terms.len()
+  }
+
+  pub fn num_nonterms(&self) -> usize {
+    self.nonterms.len()
+  }
+
+  pub fn term_index(&self, t: &T) -> Option<usize> {
+    self.term_indexes.get(t).copied()
+  }
+
+  pub fn nonterm_index(&self, nt: &NT) -> Option<usize> {
+    self.nonterm_indexes.get(nt).copied()
+  }
+
+  pub fn term(&self, index: usize) -> &T {
+    &self.terms[index]
+  }
+
+  pub fn nonterm(&self, index: usize) -> &NT {
+    &self.nonterms[index]
+  }
+
+  /// Returns an empty set wide enough to hold any terminal index.
+  pub fn new_term_set(&self) -> BitSet {
+    BitSet::new(self.num_terms())
+  }
+
+  /// Converts a set of terminal indexes back into a set of terminals.
+  pub fn term_set(&self, set: &BitSet) -> BTreeSet<T>
+  where
+    T: Clone,
+  {
+    set.iter().map(|i| self.term(i).clone()).collect()
+  }
+
+  /// Returns a view of a set of terminal indexes as terminals, without
+  /// copying them.
+  pub fn term_view<'a>(&'a self, set: &'a BitSet) -> TermSet<'a, T, NT> {
+    TermSet { symbols: self, set }
+  }
+
+  /// Returns the elements of a production, with each symbol replaced by its
+  /// index.
+  pub fn index_elems<AK, AV>(
+    &self,
+    prod: &Prod<T, NT, AK, AV>,
+  ) -> Vec<Elem<usize, usize>> {
+    prod
+      .elements()
+      .map(|elem| match elem {
+        Elem::Term(t) => Elem::Term(self.term_indexes[t]),
+        Elem::NonTerm(nt) => Elem::NonTerm(self.nonterm_indexes[nt]),
+      })
+      .collect()
+  }
+}
+
+/// A borrowed set of terminals, stored as a bitset over a `SymbolTable`.
+pub struct TermSet<'a, T, NT> {
+  symbols: &'a SymbolTable<T, NT>,
+  set: &'a BitSet,
+}
+
+impl<'a, T, NT> TermSet<'a, T, NT>
+where
+  T: Ord,
+  NT: Ord,
+{
+  /// Returns the terminals in the set, in order.
+  pub fn iter(&self) -> impl Iterator<Item = &'a T> + 'a {
+    let symbols = self.symbols;
+    self.set.iter().map(move |i| symbols.term(i))
+  }
+
+  pub fn contains(&self, term: &T) -> bool {
+    match self.symbols.term_index(term) {
+      Some(i) => self.set.contains(i),
+      None => false,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.set.iter().count()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.set.is_empty()
+  }
+
+  /// Copies the terminals into a new set.
+  pub fn to_set(&self) -> BTreeSet<T>
+  where
+    T: Clone,
+  {
+    self.symbols.term_set(self.set)
+  }
+}
+
+impl<'a, T, NT> Clone for TermSet<'a, T, NT> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<'a, T, NT> Copy for TermSet<'a, T, NT> {}
+
+impl<'a, T, NT> std::fmt::Debug for TermSet<'a, T, NT>
+where
+  T: Ord + std::fmt::Debug,
+  NT: Ord,
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_set().entries(self.iter()).finish()
+  }
+}
+
+impl<T, NT, AK, AV> Pass<T, NT, AK, AV> for SymbolTable<T, NT>
+where
+  T: Ord + Clone + 'static,
+  NT: Ord + Clone + 'static,
+{
+  type Error = std::convert::Infallible;
+
+  fn run_pass(
+    pass_map: &super::PassContext<T, NT, AK, AV>,
+  ) -> Result<Self, Self::Error> {
+    let gram = pass_map.grammar();
+
+    let mut terms = BTreeSet::new();
+    let mut nonterms = BTreeSet::new();
+    nonterms.insert(gram.start_nt());
+    for prod in gram.prods() {
+      nonterms.insert(prod.head());
+      for elem in prod.elements() {
+        match elem {
+          Elem::Term(t) => terms.insert(t),
+          Elem::NonTerm(nt) => nonterms.insert(nt),
+        };
+      }
+    }
+
+    let terms = terms.into_iter().cloned().collect::<Vec<_>>();
+    let nonterms = nonterms.into_iter().cloned().collect::<Vec<_>>();
+    Ok(SymbolTable {
+      term_indexes: index_map(&terms),
+      terms,
+      nonterm_indexes: index_map(&nonterms),
+      nonterms,
+    })
+  }
+}
+
+fn index_map<V: Ord + Clone>(values: &[V]) -> BTreeMap<V, usize> {
+  values
+    .iter()
+    .enumerate()
+    .map(|(i, v)| (v.clone(), i))
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{examples, passes::PassContext, NonTerminal, Terminal};
+
+  #[test]
+  fn test_paren_symbols() {
+    let g = examples::make_paren();
+    let pass_map = PassContext::new(&g);
+    let symbols = pass_map.get_pass::<SymbolTable<_, _>>().unwrap();
+    assert_eq!(symbols.num_terms(), 2);
+    assert_eq!(symbols.num_nonterms(), 3);
+
+    let lparen = Terminal::new("LPAREN");
+    let index = symbols.term_index(&lparen).unwrap();
+    assert_eq!(symbols.term(index), &lparen);
+    assert!(symbols
+      .nonterm_index(&NonTerminal::new("expr_list"))
+      .is_some());
+    assert!(symbols
+      .nonterm_index(&NonTerminal::new("missing"))
+      .is_none());
+  }
+}
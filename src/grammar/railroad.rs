@@ -0,0 +1,375 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rendering of grammars as railroad diagrams.
+//!
+//! Each rule is drawn as a diagram whose main line runs left to right
+//! through the elements of a production. The productions of a rule are
+//! stacked as alternatives. Rules that describe a list of some sequence,
+//! such as `x ::= ε | x a b`, are drawn as a loop around the sequence
+//! instead.
+
+use crate::{
+  grammar::{Elem, Grammar, Rule},
+  utils::{doc_string, ToDoc},
+};
+
+use super::ebnf::{repetition, Repetition};
+
+const CHAR_WIDTH: usize = 8;
+const BOX_HALF_HEIGHT: usize = 11;
+/// The horizontal space taken by each connector or rail.
+const GAP: usize = 10;
+/// The vertical space between stacked alternatives, and above loops.
+const SPACING: usize = 10;
+
+/// A railroad diagram of part of a rule.
+enum Diagram {
+  Terminal(String),
+  NonTerminal(String),
+  /// A line that skips over nothing, for empty productions.
+  Skip,
+  Sequence(Vec<Diagram>),
+  /// Alternatives, the first of which is on the main line. An empty choice
+  /// has no path through it.
+  Choice(Vec<Diagram>),
+  /// One or more repetitions of the body.
+  Loop(Box<Diagram>),
+}
+
+/// The size of a diagram. Its main line is `up` below its top.
+#[derive(Clone, Copy)]
+struct Size {
+  width: usize,
+  up: usize,
+  down: usize,
+}
+
+impl Diagram {
+  fn size(&self) -> Size {
+    match self {
+      Diagram::Terminal(text) | Diagram::NonTerminal(text) => Size {
+        width: text.chars().count() * CHAR_WIDTH + 2 * GAP,
+        up: BOX_HALF_HEIGHT,
+        down: BOX_HALF_HEIGHT,
+      },
+      Diagram::Skip => Size {
+        width: 0,
+        up: 0,
+        down: 0,
+      },
+      Diagram::Sequence(items) => {
+        let sizes = items.iter().map(Diagram::size).collect::<Vec<_>>();
+        Size {
+          width: sizes.iter().map(|size| size.width).sum::<usize>()
+            + GAP * sizes.len().saturating_sub(1),
+          up: sizes.iter().map(|size| size.up).max().unwrap_or(0),
+          down: sizes.iter().map(|size| size.down).max().unwrap_or(0),
+        }
+      }
+      Diagram::Choice(branches) => {
+        let sizes = branches.iter().map(Diagram::size).collect::<Vec<_>>();
+        Size {
+          width: sizes.iter().map(|size| size.width).max().unwrap_or(0)
+            + 4 * GAP,
+          up: sizes.first().map_or(0, |size| size.up),
+          down: sizes.first().map_or(0, |size| size.down)
+            + sizes
+              .iter()
+              .skip(1)
+              .map(|size| SPACING + size.up + size.down)
+              .sum::<usize>(),
+        }
+      }
+      Diagram::Loop(body) => {
+        let body = body.size();
+        Size {
+          width: body.width + 2 * GAP,
+          up: body.up,
+          down: body.down + SPACING,
+        }
+      }
+    }
+  }
+
+  /// Appends the SVG elements of this diagram, with its main line starting
+  /// at `(x, y)`.
+  fn render(&self, x: usize, y: usize, out: &mut Vec<String>) {
+    let size = self.size();
+    match self {
+      Diagram::Terminal(text) | Diagram::NonTerminal(text) => {
+        let class = match self {
+          Diagram::Terminal(_) => "terminal",
+          _ => "nonterminal",
+        };
+        out.push(format!(
+          r#"<rect class="{}" x="{}" y="{}" width="{}" height="{}"/>"#,
+          class,
+          x,
+          y - BOX_HALF_HEIGHT,
+          size.width,
+          2 * BOX_HALF_HEIGHT
+        ));
+        out.push(format!(
+          r#"<text x="{}" y="{}">{}</text>"#,
+          x + size.width / 2,
+          y + 4,
+          escape(text)
+        ));
+      }
+      Diagram::Skip => {}
+      Diagram::Sequence(items) => {
+        let mut curr = x;
+        for (i, item) in items.iter().enumerate() {
+          if i > 0 {
+            out.push(format!(r#"<path d="M{} {} h{}"/>"#, curr, y, GAP));
+            curr += GAP;
+          }
+          item.render(curr, y, out);
+          curr += item.size().width;
+        }
+      }
+      Diagram::Choice(branches) => {
+        let inner_width = size.width - 4 * GAP;
+        let mut branch_y = y;
+        for (i, branch) in branches.iter().enumerate() {
+          let branch_size = branch.size();
+          if i > 0 {
+            branch_y += SPACING + branch_size.up;
+          }
+          let start = x + 2 * GAP;
+          let end = start + branch_size.width;
+          out.push(format!(
+            r#"<path class="branch" d="M{} {} L{} {} H{} M{} {} H{} L{} {}"/>"#,
+            x,
+            y,
+            x + GAP,
+            branch_y,
+            start,
+            end,
+            branch_y,
+            start + inner_width + GAP,
+            x + size.width,
+            y
+          ));
+          branch.render(start, branch_y, out);
+          branch_y += branch_size.down;
+        }
+      }
+      Diagram::Loop(body) => {
+        let body_size = body.size();
+        let end = x + GAP + body_size.width;
+        let bottom = y + body_size.down + SPACING;
+        out.push(format!(
+          r#"<path d="M{} {} h{} M{} {} h{}"/>"#,
+          x, y, GAP, end, y, GAP
+        ));
+        out.push(format!(
+          r#"<path class="loop" d="M{} {} L{} {} H{} L{} {}"/>"#,
+          end,
+          y,
+          end + GAP / 2,
+          bottom,
+          x + GAP / 2,
+          x + GAP,
+          y
+        ));
+        body.render(x + GAP, y, out);
+      }
+    }
+  }
+}
+
+fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+fn elem_diagram<T, NT>(elem: &Elem<T, NT>) -> Diagram
+where
+  T: ToDoc,
+  NT: ToDoc,
+{
+  match elem {
+    Elem::Term(t) => Diagram::Terminal(doc_string(t)),
+    Elem::NonTerm(nt) => Diagram::NonTerminal(doc_string(nt)),
+  }
+}
+
+fn sequence_diagram<'a, T, NT>(
+  elems: impl IntoIterator<Item = &'a Elem<T, NT>>,
+) -> Diagram
+where
+  T: ToDoc + 'a,
+  NT: ToDoc + 'a,
+{
+  let items = elems.into_iter().map(elem_diagram).collect::<Vec<_>>();
+  if items.is_empty() {
+    Diagram::Skip
+  } else {
+    Diagram::Sequence(items)
+  }
+}
+
+/// Returns the diagram of a rule, drawing it as a loop if it is a list of
+/// some sequence: `x ::= ε | x a` or `x ::= a | x a`, or the same with `x`
+/// on the right.
+fn rule_diagram<T, NT, AK, AV>(rule: Rule<'_, T, NT, AK, AV>) -> Diagram
+where
+  T: ToDoc + PartialEq,
+  NT: ToDoc + PartialEq,
+{
+  match repetition(rule) {
+    Some(Repetition::Star(body)) => {
+      let repeated = Diagram::Loop(Box::new(sequence_diagram(body)));
+      return Diagram::Choice(vec![Diagram::Skip, repeated]);
+    }
+    Some(Repetition::Plus(body)) => {
+      return Diagram::Loop(Box::new(sequence_diagram(body)));
+    }
+    _ => {}
+  }
+
+  let prods = rule
+    .prods()
+    .map(|prod| prod.elements().collect::<Vec<_>>())
+    .collect::<Vec<_>>();
+  let mut branches = prods
+    .iter()
+    .map(|elems| sequence_diagram(elems.iter().copied()))
+    .collect::<Vec<_>>();
+  // A rule without productions derives nothing, so it is drawn as a line
+  // that skips over nothing.
+  if branches.len() <= 1 {
+    branches.pop().unwrap_or(Diagram::Skip)
+  } else {
+    Diagram::Choice(branches)
+  }
+}
+
+const STYLE: &str = "path { fill: none; stroke: black; stroke-width: 2; } \
+  rect { fill: #eef; stroke: black; stroke-width: 2; } \
+  rect.terminal { rx: 10; fill: #efe; } \
+  text { font: 14px monospace; text-anchor: middle; } \
+  text.rule { font-weight: bold; text-anchor: start; }";
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: ToDoc + PartialEq,
+  NT: ToDoc + PartialEq,
+{
+  /// Renders every rule of the grammar as a railroad diagram, stacked in a
+  /// single SVG image.
+  ///
+  /// Terminals are drawn as rounded boxes, and nonterminals as square ones.
+  /// Each alternative of a rule is drawn as a path with the class `branch`,
+  /// and each repetition as a path with the class `loop`.
+  pub fn to_railroad_svg(&self) -> String {
+    let mut elements = Vec::new();
+    let (mut width, mut y) = (0, 0);
+    for rule in self.rules() {
+      let diagram = rule_diagram(rule);
+      let size = diagram.size();
+
+      y += 2 * SPACING;
+      elements.push(format!(
+        r#"<text class="rule" x="{}" y="{}">{}</text>"#,
+        GAP,
+        y,
+        escape(&doc_string(rule.head()))
+      ));
+      // The main line starts and ends with a short stub.
+      y += SPACING + size.up;
+      elements.push(format!(
+        r#"<path d="M{} {} h{} M{} {} h{}"/>"#,
+        GAP,
+        y,
+        GAP,
+        2 * GAP + size.width,
+        y,
+        GAP
+      ));
+      diagram.render(2 * GAP, y, &mut elements);
+      y += size.down;
+      width = width.max(size.width + 4 * GAP);
+    }
+    y += 2 * SPACING;
+
+    let mut svg = format!(
+      r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+      width, y
+    );
+    svg.push_str(&format!("\n<style>{}</style>", STYLE));
+    for element in elements {
+      svg.push('\n');
+      svg.push_str(&element);
+    }
+    svg.push_str("\n</svg>\n");
+    svg
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::grammar::{examples, transform};
+
+  fn count(svg: &str, class: &str) -> usize {
+    svg.matches(&format!(r#"class="{}""#, class)).count()
+  }
+
+  #[test]
+  fn test_alternation_branches() {
+    let g = examples::from_rules(&[
+      ("s", &[&["A", "t"], &["B"], &[]]),
+      ("t", &[&["C"]]),
+    ]);
+    let svg = g.to_railroad_svg();
+    assert!(svg.starts_with("<svg "));
+    assert!(svg.trim_end().ends_with("</svg>"));
+    assert_eq!(count(&svg, "branch"), 3);
+    assert_eq!(count(&svg, "loop"), 0);
+    assert_eq!(count(&svg, "terminal"), 3);
+    assert_eq!(count(&svg, "nonterminal"), 1);
+    assert_eq!(count(&svg, "rule"), 2);
+  }
+
+  #[test]
+  fn test_list_loops() {
+    // `expr_list` is a possibly empty list of `expr`, so it is drawn as a
+    // choice between skipping and looping.
+    let svg = examples::make_paren().to_railroad_svg();
+    assert_eq!(count(&svg, "loop"), 1);
+    assert_eq!(count(&svg, "branch"), 2);
+
+    let g = examples::from_rules(&[("s", &[&["A", "B"], &["s", "A", "B"]])]);
+    let svg = g.to_railroad_svg();
+    assert_eq!(count(&svg, "loop"), 1);
+    assert_eq!(count(&svg, "branch"), 0);
+    assert_eq!(count(&svg, "terminal"), 2);
+  }
+
+  #[test]
+  fn test_rule_without_prods() {
+    // `s` derives no sentence, so minimizing leaves it without productions.
+    let g = examples::from_rules(&[("s", &[&["A", "s"]])]);
+    let minimized = transform::minimize(&g);
+    assert_eq!(minimized.rules().next().unwrap().prods().count(), 0);
+    let svg = minimized.to_railroad_svg();
+    assert_eq!(count(&svg, "rule"), 1);
+    assert_eq!(count(&svg, "branch"), 0);
+  }
+}
@@ -0,0 +1,459 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A quick summary of what the sentences of a grammar look like.
+
+use {
+  crate::{
+    grammar::{
+      passes::{firsts::Firsts, nullable::NullableSet, PassContext},
+      Elem, Grammar, Prod,
+    },
+    utils::{breadth_first_search, change_iter, change_loop, WasChanged},
+  },
+  std::collections::{BTreeMap, BTreeSet},
+};
+
+/// The symbols that can be adjacent to a terminal, from
+/// `Grammar::terminal_contexts`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TerminalContext<T, NT> {
+  pub preceded_by: BTreeSet<Elem<T, NT>>,
+  pub followed_by: BTreeSet<Elem<T, NT>>,
+}
+
+impl<T, NT> Default for TerminalContext<T, NT> {
+  fn default() -> Self {
+    TerminalContext {
+      preceded_by: BTreeSet::new(),
+      followed_by: BTreeSet::new(),
+    }
+  }
+}
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: Ord + Clone + 'static,
+  NT: Ord + Clone + 'static,
+  AK: Ord + Clone + 'static,
+{
+  /// Returns the terminals that can begin a sentence of the grammar.
+  pub fn start_firsts(&self) -> BTreeSet<T> {
+    let passes = PassContext::new(self);
+    passes
+      .get_pass::<Firsts<T, NT>>()
+      .expect("FIRST sets are defined for every grammar")
+      .terms(self.start_nt())
+      .cloned()
+      .collect()
+  }
+
+  /// Returns the terminals that can end a sentence of the grammar.
+  pub fn accepting_lasts(&self) -> BTreeSet<T> {
+    let passes = PassContext::new(self);
+    let nullables = passes
+      .get_pass::<NullableSet<NT>>()
+      .expect("nullable sets are defined for every grammar");
+
+    let mut lasts: BTreeMap<&NT, BTreeSet<&T>> = BTreeMap::new();
+    change_loop(|| {
+      change_iter(self.prods(), |prod| {
+        let mut prod_lasts = BTreeSet::new();
+        for elem in prod.prod_elements().iter().rev() {
+          match elem.elem() {
+            Elem::Term(t) => {
+              prod_lasts.insert(t);
+              break;
+            }
+            Elem::NonTerm(nt) => {
+              prod_lasts.extend(lasts.get(nt).into_iter().flatten().copied());
+              if !nullables.is_nullable(nt) {
+                break;
+              }
+            }
+          }
+        }
+
+        let head_lasts = lasts.entry(prod.head()).or_default();
+        change_iter(prod_lasts, |t| {
+          WasChanged::from_changed(head_lasts.insert(t))
+        })
+      })
+    });
+
+    lasts
+      .remove(self.start_nt())
+      .into_iter()
+      .flatten()
+      .cloned()
+      .collect()
+  }
+
+  /// Returns true if the grammar accepts the empty sentence.
+  pub fn accepts_empty(&self) -> bool {
+    let passes = PassContext::new(self);
+    passes
+      .get_pass::<NullableSet<NT>>()
+      .expect("nullable sets are defined for every grammar")
+      .is_nullable(self.start_nt())
+  }
+
+  /// Returns the nonterminals `A` that can derive a sentential form `x A`,
+  /// where `A` is the last symbol. Parsing these recursively uses stack
+  /// space proportional to the length of the input.
+  pub fn right_recursive_nonterms(&self) -> BTreeSet<NT> {
+    let passes = PassContext::new(self);
+    let nullables = passes
+      .get_pass::<NullableSet<NT>>()
+      .expect("nullable sets are defined for every grammar");
+
+    // An edge from the head of each production to every nonterminal that may
+    // end it.
+    let mut edges: BTreeMap<&NT, BTreeSet<&NT>> = BTreeMap::new();
+    for prod in self.prods() {
+      for elem in prod.prod_elements().iter().rev() {
+        match elem.elem() {
+          Elem::Term(_) => break,
+          Elem::NonTerm(nt) => {
+            edges.entry(prod.head()).or_default().insert(nt);
+            if !nullables.is_nullable(nt) {
+              break;
+            }
+          }
+        }
+      }
+    }
+
+    edges
+      .keys()
+      .copied()
+      .filter(|nt| {
+        breadth_first_search(std::iter::once(*nt), |curr| {
+          edges
+            .get(curr)
+            .into_iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>()
+        })
+        .contains(nt)
+      })
+      .cloned()
+      .collect()
+  }
+
+  /// Returns true if `b` can immediately follow `a` in some sentential form
+  /// derived from the start symbol.
+  ///
+  /// Unlike FOLLOW sets, this relates any two symbols, so a nonterminal can
+  /// be followed both by another nonterminal, and by the symbols that can
+  /// begin it.
+  pub fn can_follow(&self, a: &Elem<T, NT>, b: &Elem<T, NT>) -> bool {
+    self.adjacent_pairs().contains(&(a, b))
+  }
+
+  /// Returns the symbols that can immediately precede and follow each
+  /// terminal of the grammar, in the sense of `can_follow`.
+  ///
+  /// Terminals that only appear in unreachable rules have empty contexts.
+  pub fn terminal_contexts(&self) -> BTreeMap<T, TerminalContext<T, NT>> {
+    let mut contexts = self
+      .prods()
+      .flat_map(|prod| prod.elements())
+      .filter_map(|elem| elem.as_term())
+      .map(|t| (t.clone(), TerminalContext::default()))
+      .collect::<BTreeMap<_, _>>();
+    for (a, b) in self.adjacent_pairs() {
+      if let Some(context) = a.as_term().and_then(|t| contexts.get_mut(t)) {
+        context.followed_by.insert(b.clone());
+      }
+      if let Some(context) = b.as_term().and_then(|t| contexts.get_mut(t)) {
+        context.preceded_by.insert(a.clone());
+      }
+    }
+    contexts
+  }
+
+  /// Returns every pair of symbols `(a, b)` where `b` can immediately follow
+  /// `a` in some sentential form derived from the start symbol.
+  #[allow(clippy::type_complexity)]
+  fn adjacent_pairs(&self) -> BTreeSet<(&Elem<T, NT>, &Elem<T, NT>)> {
+    let passes = PassContext::new(self);
+    let nullables = passes
+      .get_pass::<NullableSet<NT>>()
+      .expect("nullable sets are defined for every grammar");
+
+    // The symbols that can begin and end a sentential form derived from each
+    // nonterminal in at least one step.
+    let mut firsts = BTreeMap::new();
+    let mut lasts = BTreeMap::new();
+    change_loop(|| {
+      change_iter(self.prods(), |prod| {
+        extend_derived(&mut firsts, &prod, false, &nullables)
+          .join(extend_derived(&mut lasts, &prod, true, &nullables))
+      })
+    });
+
+    let mut reachable = self.reachable_from(self.start_nt());
+    reachable.insert(self.start_nt());
+
+    let mut pairs = BTreeSet::new();
+    for prod in self.prods().filter(|prod| reachable.contains(prod.head())) {
+      let elems = prod.elements().collect::<Vec<_>>();
+      for (i, elem) in elems.iter().enumerate() {
+        let ends = with_derived(elem, &lasts);
+        // The following symbols, up to the first one that is not nullable.
+        for next in &elems[i + 1..] {
+          for begin in with_derived(next, &firsts) {
+            pairs.extend(ends.iter().map(|end| (*end, begin)));
+          }
+          match next {
+            Elem::NonTerm(nt) if nullables.is_nullable(nt) => {}
+            _ => break,
+          }
+        }
+      }
+    }
+    pairs
+  }
+
+  /// Returns true if the grammar accepts only finitely many sentences, so
+  /// its language can be fully enumerated.
+  ///
+  /// This checks that no productive nonterminal reachable from the start
+  /// symbol is recursive. Recursion that can't add any terminals, as in
+  /// `A => A`, is still counted, so some finite languages are reported as
+  /// infinite.
+  pub fn is_finite_language(&self) -> bool {
+    let empty = self.empty_language_nonterms();
+    let productive =
+      |nt: &NT| self.try_get_rule(nt).is_some() && !empty.contains(nt);
+
+    // An edge from the head of each production that derives a sentence to
+    // every nonterminal in it.
+    let mut edges: BTreeMap<&NT, BTreeSet<&NT>> = BTreeMap::new();
+    for prod in self.prods() {
+      let mut nonterms = prod.elements().filter_map(Elem::as_nonterm);
+      if nonterms.clone().all(productive) {
+        edges.entry(prod.head()).or_default().extend(&mut nonterms);
+      }
+    }
+
+    let successors = |curr: &&NT| {
+      edges
+        .get(curr)
+        .into_iter()
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>()
+    };
+    let mut reachable =
+      breadth_first_search(std::iter::once(self.start_nt()), successors);
+    reachable.insert(self.start_nt());
+    reachable.into_iter().all(|nt| {
+      !breadth_first_search(std::iter::once(nt), successors).contains(nt)
+    })
+  }
+}
+
+/// Returns `elem`, along with the symbols it derives in `derived`.
+fn with_derived<'a, T, NT: Ord>(
+  elem: &'a Elem<T, NT>,
+  derived: &BTreeMap<&'a NT, BTreeSet<&'a Elem<T, NT>>>,
+) -> Vec<&'a Elem<T, NT>> {
+  let mut elems = vec![elem];
+  if let Some(nt) = elem.as_nonterm() {
+    elems.extend(derived.get(nt).into_iter().flatten().copied());
+  }
+  elems
+}
+
+/// Adds the symbols that can begin the elements of `prod` to the derived
+/// symbols of its head, or the symbols that can end them if `from_end` is
+/// set. Returns whether any were new.
+fn extend_derived<'a, T, NT, AK, AV>(
+  derived: &mut BTreeMap<&'a NT, BTreeSet<&'a Elem<T, NT>>>,
+  prod: &Prod<'a, T, NT, AK, AV>,
+  from_end: bool,
+  nullables: &NullableSet<NT>,
+) -> WasChanged
+where
+  T: Ord,
+  NT: Ord,
+{
+  let mut elems = prod.elements().collect::<Vec<_>>();
+  if from_end {
+    elems.reverse();
+  }
+
+  let mut found = BTreeSet::new();
+  for elem in elems {
+    found.insert(elem);
+    match elem {
+      Elem::Term(_) => break,
+      Elem::NonTerm(nt) => {
+        found.extend(derived.get(nt).into_iter().flatten().copied());
+        if !nullables.is_nullable(nt) {
+          break;
+        }
+      }
+    }
+  }
+
+  let head_derived = derived.entry(prod.head()).or_default();
+  change_iter(found, |elem| {
+    WasChanged::from_changed(head_derived.insert(elem))
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use crate::grammar::{
+    examples,
+    passes::{follows::Follows, PassContext},
+    Elem, NonTerminal, Terminal,
+  };
+  use std::collections::BTreeSet;
+
+  #[test]
+  fn test_paren_summary() {
+    let g = examples::make_paren();
+    let single = |name| std::iter::once(Terminal::new(name)).collect();
+    assert_eq!(g.start_firsts(), single("LPAREN"));
+    assert_eq!(g.accepting_lasts(), single("RPAREN"));
+    assert!(!g.accepts_empty());
+
+    let g = examples::from_rules(&[
+      ("s", &[&["a", "b"]]),
+      ("a", &[&["X"], &[]]),
+      ("b", &[&["Y", "a"], &[]]),
+    ]);
+    let terms =
+      |names: &[&str]| names.iter().map(|name| Terminal::new(name)).collect();
+    assert_eq!(g.start_firsts(), terms(&["X", "Y"]));
+    assert_eq!(g.accepting_lasts(), terms(&["X", "Y"]));
+    assert!(g.accepts_empty());
+    assert!(examples::make_simple_nullable().accepts_empty());
+  }
+
+  #[test]
+  fn test_right_recursive_nonterms() {
+    let nts = |names: &[&str]| {
+      names.iter().map(|name| NonTerminal::new(name)).collect()
+    };
+    assert_eq!(
+      examples::make_paren().right_recursive_nonterms(),
+      nts(&["expr_list"])
+    );
+    assert!(examples::make_left_recursive()
+      .right_recursive_nonterms()
+      .is_empty());
+
+    // `x` is nested between terminals, so it is neither left nor right
+    // recursive.
+    let g = examples::from_rules(&[("x", &[&["A", "x", "A"], &[]])]);
+    assert!(g.right_recursive_nonterms().is_empty());
+
+    // Recursion through a nullable suffix, and through another nonterminal.
+    let g = examples::from_rules(&[
+      ("s", &[&["A", "s", "opt"], &["t"]]),
+      ("t", &[&["B", "s"], &["C"]]),
+      ("opt", &[&["D"], &[]]),
+    ]);
+    assert_eq!(g.right_recursive_nonterms(), nts(&["s", "t"]));
+  }
+
+  #[test]
+  fn test_is_finite_language() {
+    assert!(!examples::make_paren().is_finite_language());
+
+    let g = examples::from_rules(&[
+      ("s", &[&["a", "b"]]),
+      ("a", &[&["X"], &[]]),
+      ("b", &[&["Y", "a"], &[]]),
+    ]);
+    assert!(g.is_finite_language());
+
+    // Recursion that can never finish deriving a sentence doesn't make the
+    // language infinite.
+    let g = examples::from_rules(&[
+      ("s", &[&["A"], &["A", "loop"]]),
+      ("loop", &[&["B", "loop"]]),
+    ]);
+    assert!(g.is_finite_language());
+  }
+
+  #[test]
+  fn test_can_follow() {
+    let t = |name| Elem::Term(Terminal::new(name));
+    let nt = |name| Elem::NonTerm(NonTerminal::new(name));
+
+    let g = examples::make_paren();
+    assert!(g.can_follow(&nt("expr"), &t("RPAREN")));
+    assert!(g.can_follow(&nt("expr"), &nt("expr_list")));
+    assert!(g.can_follow(&t("LPAREN"), &t("RPAREN")));
+    // Siblings, as in `()()`.
+    assert!(g.can_follow(&t("RPAREN"), &t("LPAREN")));
+    assert!(!g.can_follow(&nt("expr_list"), &t("LPAREN")));
+    assert!(!g.can_follow(&nt("start"), &t("LPAREN")));
+
+    // Without siblings, a close paren is only followed by close parens.
+    let g =
+      examples::from_rules(&[("s", &[&["LPAREN", "s", "RPAREN"], &["X"]])]);
+    assert!(g.can_follow(&t("RPAREN"), &t("RPAREN")));
+    assert!(!g.can_follow(&t("RPAREN"), &t("LPAREN")));
+    assert!(g.can_follow(&t("LPAREN"), &t("X")));
+    assert!(!g.can_follow(&t("X"), &t("X")));
+  }
+
+  #[test]
+  fn test_terminal_contexts() {
+    let t = |name| Elem::Term(Terminal::new(name));
+    let nt = |name| Elem::NonTerm(NonTerminal::new(name));
+
+    let g = examples::make_paren();
+    let contexts = g.terminal_contexts();
+    assert_eq!(contexts.len(), 2);
+
+    let rparen = &contexts[&Terminal::new("RPAREN")];
+    assert_eq!(
+      rparen.preceded_by,
+      vec![nt("expr"), nt("expr_list"), t("LPAREN"), t("RPAREN")]
+        .into_iter()
+        .collect()
+    );
+    // RPAREN ends `expr`, so it can be followed by whatever follows `expr`:
+    // the terminals of FOLLOW(expr), and `expr_list`, which begins with
+    // `expr`.
+    assert_eq!(
+      rparen.followed_by,
+      vec![nt("expr"), nt("expr_list"), t("LPAREN"), t("RPAREN")]
+        .into_iter()
+        .collect()
+    );
+    let follow_terms = rparen
+      .followed_by
+      .iter()
+      .filter_map(|elem| elem.as_term().cloned())
+      .collect::<BTreeSet<_>>();
+    let passes = PassContext::new(&g);
+    let follows = passes.get_pass::<Follows<Terminal, NonTerminal>>().unwrap();
+    assert_eq!(
+      Some(follow_terms),
+      follows
+        .get(&NonTerminal::new("expr"))
+        .map(|set| set.to_set())
+    );
+  }
+}
@@ -0,0 +1,542 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parameterized rules.
+//!
+//! A template is a rule with parameters, such as
+//! `list<T> -> T | list<T> COMMA T`. Each use of a template with concrete
+//! arguments, such as `list<expr>`, becomes an ordinary nonterminal named
+//! after the use by `DefaultNameGen`, with a rule where each parameter is
+//! replaced by its argument. Expansion produces an ordinary grammar, so the rest of the
+//! library never sees templates.
+
+use {
+  crate::{
+    grammar::{
+      build,
+      transform::names::{fresh_nonterm, AuxKind, DefaultNameGen},
+      Elem, Grammar, GrammarErrors, NonTerminal, Terminal,
+    },
+    utils::Name,
+  },
+  std::collections::{BTreeMap, BTreeSet, VecDeque},
+};
+
+/// The deepest that template uses may be nested in the expanded grammar.
+/// This stops templates that use themselves with ever larger arguments, such
+/// as `nest<T> -> nest<list<T>>`, from expanding forever.
+pub const MAX_TEMPLATE_DEPTH: usize = 16;
+
+/// An element of a production that may refer to templates.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TemplateElem {
+  Term(Terminal),
+  NonTerm(NonTerminal),
+  /// A parameter of the enclosing template.
+  Param(String),
+  /// A use of the named template with the given arguments.
+  Apply(String, Vec<TemplateElem>),
+}
+
+impl TemplateElem {
+  pub fn term(name: &str) -> Self {
+    TemplateElem::Term(Terminal::new(name))
+  }
+
+  pub fn nonterm(name: &str) -> Self {
+    TemplateElem::NonTerm(NonTerminal::new(name))
+  }
+
+  pub fn param(name: &str) -> Self {
+    TemplateElem::Param(name.to_string())
+  }
+
+  pub fn apply(name: &str, args: Vec<TemplateElem>) -> Self {
+    TemplateElem::Apply(name.to_string(), args)
+  }
+}
+
+/// A production of a rule or template, with its action key.
+pub type TemplateProd = (Name, Vec<TemplateElem>);
+
+#[derive(thiserror::Error, Debug)]
+pub enum TemplateError {
+  #[error("unknown template {0}")]
+  UnknownTemplate(String),
+  #[error("template {name} takes {expected} arguments, but was given {found}")]
+  WrongArity {
+    name: String,
+    expected: usize,
+    found: usize,
+  },
+  #[error("parameter {0} is not bound")]
+  UnboundParam(String),
+  #[error("template use {0} is nested too deeply")]
+  TooDeep(NonTerminal),
+  #[error("the expanded grammar is invalid: {0:?}")]
+  InvalidGrammar(GrammarErrors<NonTerminal, Name>),
+}
+
+struct Template {
+  params: Vec<String>,
+  prods: Vec<TemplateProd>,
+}
+
+/// A grammar with templates, which can be expanded into an ordinary grammar.
+pub struct TemplateGrammar {
+  start: NonTerminal,
+  rules: Vec<(NonTerminal, Vec<TemplateProd>)>,
+  templates: BTreeMap<String, Template>,
+}
+
+impl TemplateGrammar {
+  pub fn new(start: NonTerminal) -> Self {
+    TemplateGrammar {
+      start,
+      rules: Vec::new(),
+      templates: BTreeMap::new(),
+    }
+  }
+
+  /// Adds an ordinary rule, whose productions may use templates but not
+  /// parameters.
+  pub fn add_rule(
+    &mut self,
+    head: NonTerminal,
+    prods: Vec<TemplateProd>,
+  ) -> &mut Self {
+    self.rules.push((head, prods));
+    self
+  }
+
+  /// Adds a template with the given parameters. A later template with the
+  /// same name replaces an earlier one.
+  pub fn add_template(
+    &mut self,
+    name: &str,
+    params: &[&str],
+    prods: Vec<TemplateProd>,
+  ) -> &mut Self {
+    let params = params.iter().map(|p| p.to_string()).collect();
+    self
+      .templates
+      .insert(name.to_string(), Template { params, prods });
+    self
+  }
+
+  /// Returns an ordinary grammar with a rule for each template use that is
+  /// reachable from the ordinary rules.
+  pub fn expand(
+    &self,
+  ) -> Result<Grammar<Terminal, NonTerminal, Name, ()>, TemplateError> {
+    let mut expander = Expander {
+      templates: &self.templates,
+      used: self.nonterms(),
+      instances: BTreeMap::new(),
+      depths: BTreeMap::new(),
+      queue: VecDeque::new(),
+    };
+    let no_params = BTreeMap::new();
+
+    let mut rules = Vec::new();
+    for (head, prods) in &self.rules {
+      rules.push((head.clone(), expander.prods(prods, &no_params)?));
+    }
+    while let Some((head, name, args)) = expander.queue.pop_front() {
+      let template = &self.templates[&name];
+      let params = template.params.iter().cloned().zip(args).collect();
+      rules.push((head, expander.prods(&template.prods, &params)?));
+    }
+
+    build(self.start.clone(), |gb| {
+      for (head, prods) in rules {
+        gb.add_rule(head, |rb| {
+          for (key, elems) in prods {
+            rb.add_prod(key, (), |pb| {
+              for elem in elems {
+                match elem {
+                  Elem::Term(t) => pb.add_term(t),
+                  Elem::NonTerm(nt) => pb.add_nonterm(nt),
+                };
+              }
+            });
+          }
+        });
+      }
+    })
+    .map_err(TemplateError::InvalidGrammar)
+  }
+
+  /// Returns the nonterminals named by the ordinary rules and templates, so
+  /// that template uses are not given the same names.
+  fn nonterms(&self) -> BTreeSet<NonTerminal> {
+    fn visit(elem: &TemplateElem, nonterms: &mut BTreeSet<NonTerminal>) {
+      match elem {
+        TemplateElem::NonTerm(nt) => {
+          nonterms.insert(nt.clone());
+        }
+        TemplateElem::Apply(_, args) => {
+          args.iter().for_each(|arg| visit(arg, nonterms));
+        }
+        TemplateElem::Term(_) | TemplateElem::Param(_) => {}
+      }
+    }
+
+    let mut nonterms = BTreeSet::new();
+    nonterms.insert(self.start.clone());
+    nonterms.extend(self.rules.iter().map(|(head, _)| head.clone()));
+    let prods = self
+      .rules
+      .iter()
+      .flat_map(|(_, prods)| prods)
+      .chain(self.templates.values().flat_map(|template| &template.prods));
+    for (_, elems) in prods {
+      elems.iter().for_each(|elem| visit(elem, &mut nonterms));
+    }
+    nonterms
+  }
+}
+
+/// A template use whose rule has not been expanded yet: its nonterminal, the
+/// name of the template and the arguments.
+type PendingInstance = (NonTerminal, String, Vec<Elem<Terminal, NonTerminal>>);
+
+struct Expander<'a> {
+  templates: &'a BTreeMap<String, Template>,
+  /// The nonterminals that template uses may not be named.
+  used: BTreeSet<NonTerminal>,
+  /// The nonterminal of each template use seen so far.
+  instances: BTreeMap<(String, Vec<Elem<Terminal, NonTerminal>>), NonTerminal>,
+  /// The nesting depth of each template use seen so far.
+  depths: BTreeMap<NonTerminal, usize>,
+  /// Template uses whose rules have not been expanded yet.
+  queue: VecDeque<PendingInstance>,
+}
+
+impl Expander<'_> {
+  #[allow(clippy::type_complexity)]
+  fn prods(
+    &mut self,
+    prods: &[TemplateProd],
+    params: &BTreeMap<String, Elem<Terminal, NonTerminal>>,
+  ) -> Result<Vec<(Name, Vec<Elem<Terminal, NonTerminal>>)>, TemplateError> {
+    prods
+      .iter()
+      .map(|(key, elems)| {
+        let elems = elems
+          .iter()
+          .map(|elem| self.elem(elem, params))
+          .collect::<Result<Vec<_>, _>>()?;
+        Ok((key.clone(), elems))
+      })
+      .collect()
+  }
+
+  fn elem(
+    &mut self,
+    elem: &TemplateElem,
+    params: &BTreeMap<String, Elem<Terminal, NonTerminal>>,
+  ) -> Result<Elem<Terminal, NonTerminal>, TemplateError> {
+    match elem {
+      TemplateElem::Term(t) => Ok(Elem::Term(t.clone())),
+      TemplateElem::NonTerm(nt) => Ok(Elem::NonTerm(nt.clone())),
+      TemplateElem::Param(param) => params
+        .get(param)
+        .cloned()
+        .ok_or_else(|| TemplateError::UnboundParam(param.clone())),
+      TemplateElem::Apply(name, args) => {
+        let template = self
+          .templates
+          .get(name)
+          .ok_or_else(|| TemplateError::UnknownTemplate(name.clone()))?;
+        if template.params.len() != args.len() {
+          return Err(TemplateError::WrongArity {
+            name: name.clone(),
+            expected: template.params.len(),
+            found: args.len(),
+          });
+        }
+
+        let args = args
+          .iter()
+          .map(|arg| self.elem(arg, params))
+          .collect::<Result<Vec<_>, _>>()?;
+        if let Some(instance) =
+          self.instances.get(&(name.clone(), args.clone()))
+        {
+          return Ok(Elem::NonTerm(instance.clone()));
+        }
+
+        let instance = fresh_nonterm(
+          &mut DefaultNameGen,
+          &mut self.used,
+          AuxKind::Instance(name, &args),
+        );
+        let depth = 1
+          + args
+            .iter()
+            .filter_map(|arg| match arg {
+              Elem::NonTerm(nt) => self.depths.get(nt).copied(),
+              Elem::Term(_) => None,
+            })
+            .max()
+            .unwrap_or(0);
+        if depth > MAX_TEMPLATE_DEPTH {
+          return Err(TemplateError::TooDeep(instance));
+        }
+        self
+          .instances
+          .insert((name.clone(), args.clone()), instance.clone());
+        self.depths.insert(instance.clone(), depth);
+        self.queue.push_back((instance.clone(), name.clone(), args));
+        Ok(Elem::NonTerm(instance))
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    parsers::{earley, tree::TreeOwner, Token},
+    start_grammar::wrap_grammar_with_start,
+  };
+
+  fn prod(key: &str, elems: Vec<TemplateElem>) -> TemplateProd {
+    (Name::new(key), elems)
+  }
+
+  fn add_list(tg: &mut TemplateGrammar) {
+    tg.add_template(
+      "list",
+      &["T"],
+      vec![
+        prod("one", vec![TemplateElem::param("T")]),
+        prod(
+          "more",
+          vec![
+            TemplateElem::apply("list", vec![TemplateElem::param("T")]),
+            TemplateElem::term("COMMA"),
+            TemplateElem::param("T"),
+          ],
+        ),
+      ],
+    );
+  }
+
+  #[test]
+  fn test_expand_list() {
+    let mut tg = TemplateGrammar::new(NonTerminal::new("start"));
+    add_list(&mut tg);
+    let list_of_exprs =
+      TemplateElem::apply("list", vec![TemplateElem::nonterm("expr")]);
+    tg.add_rule(
+      NonTerminal::new("start"),
+      vec![prod("start", vec![list_of_exprs.clone()])],
+    )
+    .add_rule(
+      NonTerminal::new("expr"),
+      vec![
+        prod("num", vec![TemplateElem::term("NUM")]),
+        prod(
+          "call",
+          vec![
+            TemplateElem::term("ID"),
+            TemplateElem::term("LPAREN"),
+            list_of_exprs,
+            TemplateElem::term("RPAREN"),
+          ],
+        ),
+      ],
+    );
+    let g = tg.expand().unwrap();
+
+    let list = NonTerminal::new("list<expr>");
+    assert_eq!(
+      g.get_rule(&list)
+        .prods()
+        .map(|prod| prod.symbols())
+        .collect::<Vec<_>>(),
+      vec![
+        vec![Elem::NonTerm(NonTerminal::new("expr"))],
+        vec![
+          Elem::NonTerm(list.clone()),
+          Elem::Term(Terminal::new("COMMA")),
+          Elem::NonTerm(NonTerminal::new("expr")),
+        ],
+      ]
+    );
+    // Both uses share one rule.
+    assert_eq!(g.rules().count(), 3);
+
+    let g = wrap_grammar_with_start(g).unwrap();
+    let tree: TreeOwner<_, _, ()> = TreeOwner::new();
+    let parse = |kinds: &[&str]| {
+      let tokens = kinds.iter().map(|kind| Token::new(Terminal::new(kind), ()));
+      earley::parse(&g, &tree.handle(), tokens.collect::<Vec<_>>()).is_some()
+    };
+    assert!(parse(&["NUM"]));
+    assert!(parse(&["NUM", "COMMA", "NUM", "COMMA", "NUM"]));
+    assert!(parse(&[
+      "ID", "LPAREN", "NUM", "COMMA", "NUM", "RPAREN", "COMMA", "NUM"
+    ]));
+    assert!(!parse(&["NUM", "COMMA"]));
+    assert!(!parse(&["COMMA", "NUM"]));
+  }
+
+  fn add_wrap(tg: &mut TemplateGrammar) {
+    tg.add_template(
+      "wrap",
+      &["T"],
+      vec![prod(
+        "wrap",
+        vec![
+          TemplateElem::term("LPAREN"),
+          TemplateElem::param("T"),
+          TemplateElem::term("RPAREN"),
+        ],
+      )],
+    );
+  }
+
+  fn wrapped(
+    g: &Grammar<Terminal, NonTerminal, Name, ()>,
+    nt: &str,
+  ) -> Elem<Terminal, NonTerminal> {
+    let rule = g.get_rule(&NonTerminal::new(nt));
+    let prod = rule.prods().next().unwrap();
+    prod.symbols()[1].clone()
+  }
+
+  #[test]
+  fn test_expand_term_and_nonterm_args() {
+    // The terminal `A` and the nonterminal `A` are different arguments, so
+    // they get different instances.
+    let mut tg = TemplateGrammar::new(NonTerminal::new("start"));
+    add_wrap(&mut tg);
+    tg.add_rule(
+      NonTerminal::new("start"),
+      vec![prod(
+        "start",
+        vec![
+          TemplateElem::apply("wrap", vec![TemplateElem::term("A")]),
+          TemplateElem::apply("wrap", vec![TemplateElem::nonterm("A")]),
+        ],
+      )],
+    )
+    .add_rule(
+      NonTerminal::new("A"),
+      vec![prod("b", vec![TemplateElem::term("B")])],
+    );
+    let g = tg.expand().unwrap();
+
+    assert_eq!(g.rules().count(), 4);
+    assert_eq!(wrapped(&g, "wrap<'A'>"), Elem::Term(Terminal::new("A")));
+    assert_eq!(wrapped(&g, "wrap<A>"), Elem::NonTerm(NonTerminal::new("A")));
+  }
+
+  #[test]
+  fn test_expand_name_clash() {
+    // A rule that already has the name of a template use keeps it, and the
+    // use is given another name.
+    let mut tg = TemplateGrammar::new(NonTerminal::new("start"));
+    add_wrap(&mut tg);
+    tg.add_rule(
+      NonTerminal::new("start"),
+      vec![prod(
+        "start",
+        vec![
+          TemplateElem::apply("wrap", vec![TemplateElem::nonterm("x")]),
+          TemplateElem::nonterm("wrap<x>"),
+        ],
+      )],
+    )
+    .add_rule(
+      NonTerminal::new("wrap<x>"),
+      vec![prod("c", vec![TemplateElem::term("C")])],
+    )
+    .add_rule(
+      NonTerminal::new("x"),
+      vec![prod("x", vec![TemplateElem::term("X")])],
+    );
+    let g = tg.expand().unwrap();
+
+    assert_eq!(g.rules().count(), 4);
+    assert_eq!(
+      g.get_rule(&NonTerminal::new("start"))
+        .prods()
+        .next()
+        .unwrap()
+        .symbols(),
+      vec![
+        Elem::NonTerm(NonTerminal::new("wrap<x>__2")),
+        Elem::NonTerm(NonTerminal::new("wrap<x>")),
+      ]
+    );
+    assert_eq!(
+      wrapped(&g, "wrap<x>__2"),
+      Elem::NonTerm(NonTerminal::new("x"))
+    );
+    assert_eq!(
+      g.get_rule(&NonTerminal::new("wrap<x>"))
+        .prods()
+        .map(|prod| prod.symbols())
+        .collect::<Vec<_>>(),
+      vec![vec![Elem::Term(Terminal::new("C"))]]
+    );
+  }
+
+  #[test]
+  fn test_expand_errors() {
+    let expand = |elem: TemplateElem| {
+      let mut tg = TemplateGrammar::new(NonTerminal::new("start"));
+      add_list(&mut tg);
+      tg.add_template(
+        "nest",
+        &["T"],
+        vec![prod(
+          "nest",
+          vec![TemplateElem::apply(
+            "nest",
+            vec![TemplateElem::apply("list", vec![TemplateElem::param("T")])],
+          )],
+        )],
+      );
+      tg.add_rule(NonTerminal::new("start"), vec![prod("start", vec![elem])]);
+      tg.expand()
+    };
+
+    assert!(matches!(
+      expand(TemplateElem::apply("map", vec![])),
+      Err(TemplateError::UnknownTemplate(name)) if name == "map"
+    ));
+    assert!(matches!(
+      expand(TemplateElem::apply("list", vec![])),
+      Err(TemplateError::WrongArity {
+        expected: 1,
+        found: 0,
+        ..
+      })
+    ));
+    assert!(matches!(
+      expand(TemplateElem::param("T")),
+      Err(TemplateError::UnboundParam(param)) if param == "T"
+    ));
+    assert!(matches!(
+      expand(TemplateElem::apply("nest", vec![TemplateElem::term("A")])),
+      Err(TemplateError::TooDeep(_))
+    ));
+  }
+}
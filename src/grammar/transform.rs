@@ -12,4 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod gnf;
+pub mod left_recursion;
+pub mod merge;
+pub mod minimize;
+pub mod names;
 pub mod nonnull;
+pub mod rename;
+
+pub use merge::merge_equivalent_nonterminals;
+pub use minimize::minimize;
+pub use rename::rename_terminals;
@@ -0,0 +1,666 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A Grammar transform that converts a grammar into Greibach Normal Form.
+//!
+//! In GNF, every production is a terminal followed by zero or more
+//! nonterminals. The conversion follows the classic approach:
+//!
+//! 1. Remove empty productions, unit productions, and useless nonterminals.
+//! 2. Order the nonterminals. For each nonterminal in turn, substitute any
+//!    leading earlier nonterminals, then eliminate the immediate left
+//!    recursion that results by introducing a new "tail" nonterminal.
+//! 3. Substitute leading nonterminals until every production begins with a
+//!    terminal.
+//! 4. Replace every non-leading terminal with a nonterminal that derives only
+//!    that terminal.
+//!
+//! The language of the grammar is preserved. If the language includes the
+//! empty sentence, the start nonterminal keeps an empty production (and a new
+//! start nonterminal is introduced if the original appears in a production
+//! body.)
+//!
+//! Actions are only preserved on a best-effort basis: each production records
+//! the original action keys that were combined to create it, but action
+//! values and element identifiers are discarded.
+
+use {
+  crate::{
+    grammar::{
+      build,
+      transform::names::{fresh_nonterm, AuxKind, NameGen},
+      Elem, Grammar, ProdElement,
+    },
+    utils::{
+      breadth_first_search, change_iter, change_loop, ToDoc, WasChanged,
+    },
+  },
+  std::collections::{BTreeMap, BTreeSet},
+};
+
+/// A nonterminal of a grammar in Greibach Normal Form.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum NonTerminal<T, NT> {
+  /// A new start nonterminal, only used when the language includes the empty
+  /// sentence and the original start nonterminal is used in a production.
+  Start,
+  /// A nonterminal of the original grammar.
+  Base(NT),
+  /// The tail introduced to remove left recursion from the given
+  /// nonterminal.
+  Tail(NT),
+  /// A nonterminal that derives only the given terminal.
+  Term(T),
+}
+
+impl<T, NT> ToDoc for NonTerminal<T, NT>
+where
+  T: ToDoc,
+  NT: ToDoc,
+{
+  fn to_doc<'a, DA: pretty::DocAllocator<'a>>(
+    &self,
+    da: &'a DA,
+  ) -> pretty::DocBuilder<'a, DA>
+  where
+    DA::Doc: Clone,
+  {
+    match self {
+      NonTerminal::Start => da.text("<START>"),
+      NonTerminal::Base(nt) => nt.to_doc(da),
+      NonTerminal::Tail(nt) => nt.to_doc(da).append(da.text("'")),
+      NonTerminal::Term(t) => da.text("<").append(t.to_doc(da)).append(">"),
+    }
+  }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct ActionKey<AK> {
+  /// The actions of the original productions that were combined to create
+  /// this production, outermost first.
+  origin: Vec<AK>,
+  /// Distinguishes productions with the same origin.
+  index: usize,
+}
+
+impl<AK> ActionKey<AK> {
+  pub fn origin(&self) -> &[AK] {
+    &self.origin
+  }
+}
+
+impl<AK> ToDoc for ActionKey<AK>
+where
+  AK: ToDoc,
+{
+  fn to_doc<'a, DA: pretty::DocAllocator<'a>>(
+    &self,
+    da: &'a DA,
+  ) -> pretty::DocBuilder<'a, DA>
+  where
+    DA::Doc: Clone,
+  {
+    da.intersperse(self.origin.iter().map(|ak| ak.to_doc(da)), da.text("/"))
+  }
+}
+
+pub type GnfGrammar<T, NT, AK> =
+  Grammar<T, NonTerminal<T, NT>, ActionKey<AK>, ()>;
+
+type Body<T, NT> = Vec<Elem<T, NonTerminal<T, NT>>>;
+type Bodies<T, NT, AK> = BTreeMap<Body<T, NT>, Vec<AK>>;
+type Rules<T, NT, AK> = BTreeMap<NonTerminal<T, NT>, Bodies<T, NT, AK>>;
+
+pub fn to_gnf<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> anyhow::Result<GnfGrammar<T, NT, AK>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  let mut rules: Rules<T, NT, AK> = BTreeMap::new();
+  for prod in g.prods() {
+    let body = prod
+      .elements()
+      .map(|elem| match elem {
+        Elem::Term(t) => Elem::Term(t.clone()),
+        Elem::NonTerm(nt) => Elem::NonTerm(NonTerminal::Base(nt.clone())),
+      })
+      .collect();
+    rules
+      .entry(NonTerminal::Base(prod.head().clone()))
+      .or_default()
+      .entry(body)
+      .or_insert_with(|| vec![prod.action_key().clone()]);
+  }
+
+  let start = NonTerminal::Base(g.start_nt().clone());
+  let nullables = nullable_set(&rules);
+  let mut rules = remove_units(remove_empty_bodies(rules, &nullables));
+  remove_useless(&mut rules, &start);
+
+  let order = rules
+    .keys()
+    .filter_map(|nt| match nt {
+      NonTerminal::Base(nt) => Some(nt.clone()),
+      _ => None,
+    })
+    .collect::<Vec<_>>();
+  for (i, nt) in order.iter().enumerate() {
+    let target = NonTerminal::Base(nt.clone());
+    for prev_nt in &order[..i] {
+      let prev = NonTerminal::Base(prev_nt.clone());
+      substitute_leading(&mut rules, &target, |lead| lead == &prev);
+    }
+    remove_left_recursion(&mut rules, nt);
+  }
+
+  // Every production now begins with a terminal or a later nonterminal, so
+  // repeatedly substituting the nonterminals that are already in GNF reaches
+  // every nonterminal.
+  loop {
+    let done = rules
+      .iter()
+      .filter(|(_, bodies)| {
+        bodies
+          .keys()
+          .all(|body| matches!(body.first(), Some(Elem::Term(_))))
+      })
+      .map(|(nt, _)| nt.clone())
+      .collect::<BTreeSet<_>>();
+    if done.len() == rules.len() {
+      break;
+    }
+
+    let remaining = rules
+      .keys()
+      .filter(|nt| !done.contains(nt))
+      .cloned()
+      .collect::<Vec<_>>();
+    let changed = change_iter(remaining, |nt| {
+      substitute_leading(&mut rules, &nt, |lead| done.contains(lead))
+    });
+    if changed == WasChanged::Unchanged {
+      anyhow::bail!("Failed to eliminate leading nonterminals");
+    }
+  }
+
+  let mut start = start;
+  if nullables.contains(&start) {
+    let start_in_body = rules
+      .values()
+      .flat_map(|bodies| bodies.keys().flatten())
+      .any(|elem| elem.as_nonterm() == Some(&start));
+    if start_in_body {
+      let bodies = rules.get(&start).cloned().unwrap_or_default();
+      start = NonTerminal::Start;
+      rules.insert(start.clone(), bodies);
+    }
+    rules
+      .entry(start.clone())
+      .or_default()
+      .insert(Vec::new(), Vec::new());
+  } else if !rules.contains_key(&start) {
+    anyhow::bail!("Grammar does not accept any sentences");
+  }
+
+  // Substitution can leave rules that are no longer used by any production.
+  remove_useless(&mut rules, &start);
+  let rules = replace_inner_terms(rules);
+
+  build(start, |g_builder| {
+    for (nt, bodies) in rules {
+      g_builder.add_rule(nt, |r_builder| {
+        for (index, (body, origin)) in bodies.into_iter().enumerate() {
+          r_builder.add_prod_with_elems(
+            ActionKey { origin, index },
+            (),
+            body
+              .into_iter()
+              .map(ProdElement::new_empty)
+              .collect::<Vec<_>>(),
+          );
+        }
+      });
+    }
+  })
+  .map_err(|_| anyhow::anyhow!("Grammar failed to build"))
+}
+
+/// Like `to_gnf`, but names each auxiliary nonterminal with the same type as
+/// the original nonterminals, as chosen by `names`.
+pub fn to_gnf_with_names<T, NT, AK, AV, G>(
+  g: &Grammar<T, NT, AK, AV>,
+  names: &mut G,
+) -> anyhow::Result<Grammar<T, NT, ActionKey<AK>, ()>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  G: NameGen<T, NT> + ?Sized,
+{
+  let gnf = to_gnf(g)?;
+
+  // Names of the original grammar are reserved, even if the nonterminal was
+  // removed by the transform.
+  let mut used = g
+    .rules()
+    .map(|rule| rule.head().clone())
+    .collect::<BTreeSet<_>>();
+  let mut renames = BTreeMap::new();
+  for rule in gnf.rules() {
+    let renamed = match rule.head() {
+      NonTerminal::Base(nt) => nt.clone(),
+      NonTerminal::Start => {
+        fresh_nonterm(names, &mut used, AuxKind::Start(g.start_nt()))
+      }
+      NonTerminal::Tail(nt) => {
+        fresh_nonterm(names, &mut used, AuxKind::Tail(nt))
+      }
+      NonTerminal::Term(t) => fresh_nonterm(names, &mut used, AuxKind::Term(t)),
+    };
+    renames.insert(rule.head(), renamed);
+  }
+
+  build(renames[gnf.start_nt()].clone(), |g_builder| {
+    for rule in gnf.rules() {
+      g_builder.add_rule(renames[rule.head()].clone(), |r_builder| {
+        for prod in rule.prods() {
+          let elems = prod
+            .elements()
+            .map(|elem| {
+              ProdElement::new_empty(match elem {
+                Elem::Term(t) => Elem::Term(t.clone()),
+                Elem::NonTerm(nt) => Elem::NonTerm(renames[nt].clone()),
+              })
+            })
+            .collect::<Vec<_>>();
+          r_builder.add_prod_with_elems(prod.action_key().clone(), (), elems);
+        }
+      });
+    }
+  })
+  .map_err(|_| anyhow::anyhow!("Grammar failed to build"))
+}
+
+fn nullable_set<T, NT, AK>(
+  rules: &Rules<T, NT, AK>,
+) -> BTreeSet<NonTerminal<T, NT>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+{
+  let mut nullables = BTreeSet::new();
+  change_loop(|| {
+    change_iter(rules, |(nt, bodies)| {
+      let nullable = bodies.keys().any(|body| {
+        body.iter().all(|elem| match elem {
+          Elem::Term(_) => false,
+          Elem::NonTerm(nt) => nullables.contains(nt),
+        })
+      });
+      WasChanged::from_changed(nullable && nullables.insert(nt.clone()))
+    })
+  });
+  nullables
+}
+
+/// Replaces each production with every variant that omits some subset of
+/// its nullable nonterminals, excluding the empty variant.
+fn remove_empty_bodies<T, NT, AK>(
+  rules: Rules<T, NT, AK>,
+  nullables: &BTreeSet<NonTerminal<T, NT>>,
+) -> Rules<T, NT, AK>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Clone,
+{
+  let mut new_rules: Rules<T, NT, AK> = BTreeMap::new();
+  for (nt, bodies) in rules {
+    let new_bodies = new_rules.entry(nt).or_default();
+    for (body, origin) in bodies {
+      let mut variants: Vec<Body<T, NT>> = vec![Vec::new()];
+      for elem in body {
+        let nullable =
+          matches!(&elem, Elem::NonTerm(nt) if nullables.contains(nt));
+        let mut omitted = if nullable {
+          variants.clone()
+        } else {
+          Vec::new()
+        };
+        for variant in &mut variants {
+          variant.push(elem.clone());
+        }
+        variants.append(&mut omitted);
+      }
+
+      for variant in variants {
+        if !variant.is_empty() {
+          new_bodies.entry(variant).or_insert_with(|| origin.clone());
+        }
+      }
+    }
+  }
+  new_rules
+}
+
+fn as_unit<T, NT>(body: &Body<T, NT>) -> Option<&NonTerminal<T, NT>> {
+  match body.as_slice() {
+    [Elem::NonTerm(nt)] => Some(nt),
+    _ => None,
+  }
+}
+
+/// Replaces each unit production `A -> B` with the non-unit productions of
+/// every nonterminal reachable from `B` through unit productions.
+fn remove_units<T, NT, AK>(rules: Rules<T, NT, AK>) -> Rules<T, NT, AK>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Clone,
+{
+  let mut new_rules: Rules<T, NT, AK> = BTreeMap::new();
+  for nt in rules.keys() {
+    let unit_closure =
+      breadth_first_search(std::iter::once(nt.clone()), |nt| {
+        rules
+          .get(nt)
+          .into_iter()
+          .flat_map(|bodies| bodies.keys().filter_map(as_unit))
+          .cloned()
+          .collect::<Vec<_>>()
+      });
+
+    let new_bodies = new_rules.entry(nt.clone()).or_default();
+    for reached in std::iter::once(nt).chain(&unit_closure) {
+      for (body, origin) in rules.get(reached).into_iter().flatten() {
+        if as_unit(body).is_none() {
+          new_bodies
+            .entry(body.clone())
+            .or_insert_with(|| origin.clone());
+        }
+      }
+    }
+  }
+  new_rules
+}
+
+/// Removes nonterminals that don't derive any sentence, or that are not
+/// reachable from the start nonterminal.
+fn remove_useless<T, NT, AK>(
+  rules: &mut Rules<T, NT, AK>,
+  start: &NonTerminal<T, NT>,
+) where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+{
+  let mut generating = BTreeSet::new();
+  change_loop(|| {
+    change_iter(rules.iter(), |(nt, bodies)| {
+      let is_generating = bodies.keys().any(|body| {
+        body.iter().all(|elem| match elem {
+          Elem::Term(_) => true,
+          Elem::NonTerm(nt) => generating.contains(nt),
+        })
+      });
+      WasChanged::from_changed(is_generating && generating.insert(nt.clone()))
+    })
+  });
+
+  rules.retain(|nt, _| generating.contains(nt));
+  for bodies in rules.values_mut() {
+    bodies.retain(|body, _| {
+      body.iter().all(|elem| match elem {
+        Elem::Term(_) => true,
+        Elem::NonTerm(nt) => generating.contains(nt),
+      })
+    });
+  }
+
+  if !rules.contains_key(start) {
+    rules.clear();
+    return;
+  }
+
+  let reachable = breadth_first_search(std::iter::once(start.clone()), |nt| {
+    rules
+      .get(nt)
+      .into_iter()
+      .flat_map(|bodies| bodies.keys().flatten())
+      .filter_map(|elem| elem.as_nonterm())
+      .cloned()
+      .collect::<Vec<_>>()
+  });
+  rules.retain(|nt, _| nt == start || reachable.contains(nt));
+}
+
+/// Replaces each production of `target` that begins with a nonterminal
+/// matching `pred` with the productions of that nonterminal, followed by the
+/// remainder of the production.
+fn substitute_leading<T, NT, AK>(
+  rules: &mut Rules<T, NT, AK>,
+  target: &NonTerminal<T, NT>,
+  pred: impl Fn(&NonTerminal<T, NT>) -> bool,
+) -> WasChanged
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Clone,
+{
+  let bodies = match rules.remove(target) {
+    Some(bodies) => bodies,
+    None => return WasChanged::Unchanged,
+  };
+
+  let mut changed = WasChanged::Unchanged;
+  let mut new_bodies = BTreeMap::new();
+  for (body, origin) in bodies {
+    let lead_bodies = match body.first() {
+      Some(Elem::NonTerm(lead)) if pred(lead) => rules.get(lead),
+      _ => None,
+    };
+
+    match lead_bodies {
+      Some(lead_bodies) => {
+        changed = WasChanged::Changed;
+        for (lead_body, lead_origin) in lead_bodies {
+          let mut new_body = lead_body.clone();
+          new_body.extend(body[1..].iter().cloned());
+          let mut new_origin = origin.clone();
+          new_origin.extend(lead_origin.iter().cloned());
+          new_bodies.entry(new_body).or_insert(new_origin);
+        }
+      }
+      None => {
+        new_bodies.entry(body).or_insert(origin);
+      }
+    }
+  }
+
+  rules.insert(target.clone(), new_bodies);
+  changed
+}
+
+/// Replaces immediate left recursion `A -> A x | y` with `A -> y | y A'` and
+/// `A' -> x | x A'`.
+fn remove_left_recursion<T, NT, AK>(rules: &mut Rules<T, NT, AK>, nt: &NT)
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Clone,
+{
+  let head = NonTerminal::Base(nt.clone());
+  let tail = NonTerminal::Tail(nt.clone());
+  let bodies = match rules.get_mut(&head) {
+    Some(bodies) => bodies,
+    None => return,
+  };
+
+  let (recursive, base): (Bodies<T, NT, AK>, Bodies<T, NT, AK>) =
+    std::mem::take(bodies).into_iter().partition(|(body, _)| {
+      body.first().and_then(Elem::as_nonterm) == Some(&head)
+    });
+  if recursive.is_empty() {
+    *bodies = base;
+    return;
+  }
+
+  let with_tail = |bodies: Bodies<T, NT, AK>| {
+    let mut new_bodies = BTreeMap::new();
+    for (body, origin) in bodies {
+      let mut tailed = body.clone();
+      tailed.push(Elem::NonTerm(tail.clone()));
+      new_bodies.insert(tailed, origin.clone());
+      new_bodies.insert(body, origin);
+    }
+    new_bodies
+  };
+
+  *bodies = with_tail(base);
+  let tail_bodies = with_tail(
+    recursive
+      .into_iter()
+      .map(|(body, origin)| (body[1..].to_vec(), origin))
+      .collect(),
+  );
+  rules.insert(tail.clone(), tail_bodies);
+}
+
+/// Replaces each terminal that doesn't begin a production with a nonterminal
+/// that derives only that terminal.
+fn replace_inner_terms<T, NT, AK>(rules: Rules<T, NT, AK>) -> Rules<T, NT, AK>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+{
+  let mut term_rules: Rules<T, NT, AK> = BTreeMap::new();
+  let mut new_rules: Rules<T, NT, AK> = rules
+    .into_iter()
+    .map(|(nt, bodies)| {
+      let bodies = bodies
+        .into_iter()
+        .map(|(mut body, origin)| {
+          for elem in body.iter_mut().skip(1) {
+            if let Elem::Term(t) = elem {
+              let term_nt = NonTerminal::Term(t.clone());
+              term_rules
+                .entry(term_nt.clone())
+                .or_default()
+                .insert(vec![Elem::Term(t.clone())], Vec::new());
+              *elem = Elem::NonTerm(term_nt);
+            }
+          }
+          (body, origin)
+        })
+        .collect();
+      (nt, bodies)
+    })
+    .collect();
+  new_rules.append(&mut term_rules);
+  new_rules
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{self, examples, transform::names::DefaultNameGen};
+
+  fn check_gnf<T, NT, AK, AV>(g: &Grammar<T, NT, AK, AV>)
+  where
+    T: Ord + Clone + std::fmt::Debug,
+    NT: Ord + Clone,
+    AK: Ord + Clone,
+  {
+    let gnf = to_gnf(g).unwrap();
+    assert!(gnf.is_gnf());
+    for prod in gnf.prods() {
+      assert!(
+        matches!(prod.first_elem(), Some(Elem::Term(_)))
+          || (prod.is_empty() && prod.head() == gnf.start_nt())
+      );
+    }
+    assert_eq!(g.bounded_equivalent(&gnf, 6), Ok(()));
+  }
+
+  #[test]
+  fn test_paren() {
+    check_gnf(&examples::make_paren());
+  }
+
+  #[test]
+  fn test_simple() {
+    check_gnf(&examples::make_simple());
+  }
+
+  #[test]
+  fn test_nullable() {
+    check_gnf(&examples::make_simple_nullable());
+    check_gnf(&examples::make_ambiguous_nullable());
+  }
+
+  #[test]
+  fn test_default_names() {
+    let g = examples::make_left_recursive();
+    let gnf = to_gnf_with_names(&g, &mut DefaultNameGen).unwrap();
+    assert!(gnf.is_gnf());
+    assert_eq!(g.bounded_equivalent(&gnf, 6), Ok(()));
+
+    let heads = gnf
+      .rules()
+      .map(|rule| rule.head().clone())
+      .collect::<Vec<_>>();
+    assert!(heads.contains(&grammar::NonTerminal::new("expr__tail__1")));
+    assert!(heads.contains(&grammar::NonTerminal::new("RPAREN__term__1")));
+  }
+
+  #[test]
+  fn test_custom_names() {
+    let g = examples::from_rules(&[
+      ("s", &[&["s", "A", "B"], &["t", "C", "D"]]),
+      ("t", &[&["t", "A", "C"], &["E", "B"]]),
+    ]);
+
+    // Every candidate clashes until the attempt number is high enough.
+    let mut names = |_: AuxKind<'_, _, _>, attempt: usize| {
+      grammar::NonTerminal::new(&format!("aux{}", attempt))
+    };
+    let gnf = to_gnf_with_names(&g, &mut names).unwrap();
+    assert!(gnf.is_gnf());
+    assert_eq!(g.bounded_equivalent(&gnf, 7), Ok(()));
+
+    // No auxiliary nonterminals were merged by reusing a name.
+    let aux_count = gnf
+      .rules()
+      .filter(|rule| rule.head().name().str().starts_with("aux"))
+      .count();
+    // `t` is substituted into `s`, so `s` is the only original nonterminal
+    // left.
+    let enum_gnf = to_gnf(&g).unwrap();
+    assert_eq!(aux_count + 1, enum_gnf.rules().count());
+    assert_eq!(aux_count, 5);
+  }
+
+  #[test]
+  fn test_left_recursive() {
+    let g = examples::make_left_recursive();
+    check_gnf(&g);
+
+    let gnf = to_gnf(&g).unwrap();
+    assert!(gnf
+      .rules()
+      .any(|rule| matches!(rule.head(), NonTerminal::Tail(_))));
+  }
+}
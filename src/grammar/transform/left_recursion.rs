@@ -0,0 +1,349 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A Grammar transform that eliminates immediate left recursion.
+//!
+//! Each rule of the form `A -> A x | y` is replaced with `A -> y A'` and
+//! `A' -> x A' | <empty>`. Rules that are not immediately left recursive are
+//! copied unchanged. Indirect left recursion is not removed.
+//!
+//! The rewritten grammar derives the same sentences, but its parse trees
+//! lean to the right, so evaluating the original actions directly would make
+//! left-associative operators right-associative.
+//! `eliminate_left_recursion_with_folds` also returns a `Fold` for every
+//! production, which describes how to combine the results of its elements
+//! to rebuild the original left-associative results.
+
+use {
+  crate::{
+    grammar::{build, Elem, Grammar, Prod, ProdElement, ProdKey},
+    utils::ToDoc,
+  },
+  std::collections::BTreeMap,
+};
+
+/// A nonterminal of a grammar without immediate left recursion.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum NonTerminal<NT> {
+  /// A nonterminal of the original grammar.
+  Base(NT),
+  /// The tail introduced to remove left recursion from the given
+  /// nonterminal.
+  Tail(NT),
+}
+
+impl<NT> ToDoc for NonTerminal<NT>
+where
+  NT: ToDoc,
+{
+  fn to_doc<'a, DA: pretty::DocAllocator<'a>>(
+    &self,
+    da: &'a DA,
+  ) -> pretty::DocBuilder<'a, DA>
+  where
+    DA::Doc: Clone,
+  {
+    match self {
+      NonTerminal::Base(nt) => nt.to_doc(da),
+      NonTerminal::Tail(nt) => nt.to_doc(da).append(da.text("'")),
+    }
+  }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ActionKey<AK> {
+  /// A production of the original rule, without its leading recursive
+  /// nonterminal if it had one.
+  Base(AK),
+  /// The empty production that ends a tail.
+  End,
+}
+
+impl<AK> ToDoc for ActionKey<AK>
+where
+  AK: ToDoc,
+{
+  fn to_doc<'a, DA: pretty::DocAllocator<'a>>(
+    &self,
+    da: &'a DA,
+  ) -> pretty::DocBuilder<'a, DA>
+  where
+    DA::Doc: Clone,
+  {
+    match self {
+      ActionKey::Base(ak) => ak.to_doc(da),
+      ActionKey::End => da.text("<END>"),
+    }
+  }
+}
+
+/// How the results of the elements of a rewritten production are combined
+/// into the result of the original, left-associative derivation.
+///
+/// The result of a tail nonterminal is a function from the result of
+/// everything to its left to the final result of the rewritten rule.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Fold<AK> {
+  /// The production is unchanged, so the results of its elements are passed
+  /// to the original action.
+  Apply(AK),
+  /// A non-recursive production of a rewritten rule, followed by its tail.
+  /// The results of every element but the tail are passed to the original
+  /// action, and the tail is applied to that.
+  Seed(AK),
+  /// A recursive production, followed by its tail. The result is a function
+  /// that passes its argument, followed by the results of every element but
+  /// the tail, to the original action, and applies the tail to that.
+  Step(AK),
+  /// The end of a tail. The result is the identity function.
+  Finish,
+}
+
+pub type LeftRecursionFreeGrammar<T, NT, AK, AV> =
+  Grammar<T, NonTerminal<NT>, ActionKey<AK>, AV>;
+
+/// The `Fold` of each production of a `LeftRecursionFreeGrammar`.
+pub type Folds<NT, AK> =
+  BTreeMap<ProdKey<NonTerminal<NT>, ActionKey<AK>>, Fold<AK>>;
+
+/// Eliminates the immediate left recursion of every rule of `g`.
+pub fn eliminate_left_recursion<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> anyhow::Result<LeftRecursionFreeGrammar<T, NT, AK, AV>>
+where
+  T: Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  AV: Clone + Default,
+{
+  eliminate_left_recursion_with_folds(g).map(|(g, _)| g)
+}
+
+/// Like `eliminate_left_recursion`, but also returns how to fold the
+/// results of each production back into the original left-associative
+/// structure.
+#[allow(clippy::type_complexity)]
+pub fn eliminate_left_recursion_with_folds<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> anyhow::Result<(LeftRecursionFreeGrammar<T, NT, AK, AV>, Folds<NT, AK>)>
+where
+  T: Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  AV: Clone + Default,
+{
+  // A production `A -> A` would become the tail production `A' -> A'`, which
+  // derives nothing.
+  let is_cyclic = |prod: Prod<T, NT, AK, AV>| {
+    prod.prod_elements().len() == 1
+      && prod.first_elem().and_then(Elem::as_nonterm) == Some(prod.head())
+  };
+  if g.prods().any(is_cyclic) {
+    anyhow::bail!("Grammar has a cyclic unit production");
+  }
+
+  let convert = |elem: &ProdElement<T, NT>| {
+    let e = match elem.elem() {
+      Elem::Term(t) => Elem::Term(t.clone()),
+      Elem::NonTerm(nt) => Elem::NonTerm(NonTerminal::Base(nt.clone())),
+    };
+    ProdElement::new(elem.id().cloned(), e)
+  };
+
+  let mut folds = BTreeMap::new();
+  let new_g = build(NonTerminal::Base(g.start_nt().clone()), |g_builder| {
+    for rule in g.rules() {
+      let head = rule.head();
+      let base = NonTerminal::Base(head.clone());
+      let is_recursive = |prod: &Prod<T, NT, AK, AV>| {
+        prod.first_elem().and_then(Elem::as_nonterm) == Some(head)
+      };
+
+      if !rule.prods().any(|prod| is_recursive(&prod)) {
+        g_builder.add_rule(base.clone(), |r_builder| {
+          for prod in rule.prods() {
+            let key = ActionKey::Base(prod.action_key().clone());
+            folds.insert(
+              ProdKey::new(base.clone(), key.clone()),
+              Fold::Apply(prod.action_key().clone()),
+            );
+            r_builder.add_prod_with_elems(
+              key,
+              prod.action_value().clone(),
+              prod.prod_elements().iter().map(convert).collect::<Vec<_>>(),
+            );
+          }
+        });
+        continue;
+      }
+
+      let tail = NonTerminal::Tail(head.clone());
+      let tail_elem = || ProdElement::new_empty(Elem::NonTerm(tail.clone()));
+      let (recursive, non_recursive): (Vec<_>, Vec<_>) =
+        rule.prods().partition(|prod| is_recursive(prod));
+
+      g_builder.add_rule(base.clone(), |r_builder| {
+        for prod in &non_recursive {
+          let key = ActionKey::Base(prod.action_key().clone());
+          folds.insert(
+            ProdKey::new(base.clone(), key.clone()),
+            Fold::Seed(prod.action_key().clone()),
+          );
+          let mut elems =
+            prod.prod_elements().iter().map(convert).collect::<Vec<_>>();
+          elems.push(tail_elem());
+          r_builder.add_prod_with_elems(
+            key,
+            prod.action_value().clone(),
+            elems,
+          );
+        }
+      });
+      g_builder.add_rule(tail.clone(), |r_builder| {
+        for prod in &recursive {
+          let key = ActionKey::Base(prod.action_key().clone());
+          folds.insert(
+            ProdKey::new(tail.clone(), key.clone()),
+            Fold::Step(prod.action_key().clone()),
+          );
+          let mut elems = prod.prod_elements()[1..]
+            .iter()
+            .map(convert)
+            .collect::<Vec<_>>();
+          elems.push(tail_elem());
+          r_builder.add_prod_with_elems(
+            key,
+            prod.action_value().clone(),
+            elems,
+          );
+        }
+        folds.insert(ProdKey::new(tail.clone(), ActionKey::End), Fold::Finish);
+        r_builder.add_prod_with_elems(ActionKey::End, AV::default(), vec![]);
+      });
+    }
+  })
+  .map_err(|_| anyhow::anyhow!("Grammar failed to build"))?;
+  Ok((new_g, folds))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::{examples, Terminal},
+    parsers::{
+      lalr::{build_lalr_table, parse_with_actions, ActionTable},
+      Token,
+    },
+  };
+  use std::rc::Rc;
+
+  enum Value {
+    Tree(String),
+    Tail(Rc<dyn Fn(String) -> String>),
+  }
+
+  impl Value {
+    fn tree(self) -> String {
+      match self {
+        Value::Tree(tree) => tree,
+        Value::Tail(_) => panic!("expected a tree"),
+      }
+    }
+
+    fn tail(self) -> Rc<dyn Fn(String) -> String> {
+      match self {
+        Value::Tail(tail) => tail,
+        Value::Tree(_) => panic!("expected a tail"),
+      }
+    }
+  }
+
+  #[test]
+  fn test_folds_are_left_associative() {
+    let g = examples::from_rules(&[(
+      "expr",
+      &[&["expr", "MINUS", "ATOM"], &["ATOM"]],
+    )]);
+    let (new_g, folds) = eliminate_left_recursion_with_folds(&g).unwrap();
+    assert_eq!(g.bounded_equivalent(&new_g, 6), Ok(()));
+    assert!(new_g
+      .prods()
+      .all(|prod| prod.first_elem().and_then(Elem::as_nonterm)
+        != Some(prod.head())));
+    assert_eq!(folds.len(), new_g.prods().count());
+
+    let original =
+      |key: &crate::utils::Name, children: Vec<String>| match key.str() {
+        "p0" => format!("({}{}{})", children[0], children[1], children[2]),
+        _ => children.concat(),
+      };
+
+    let mut actions = ActionTable::new(|t: &Terminal, _: ()| {
+      Value::Tree(if t.name().str() == "MINUS" { "-" } else { "a" }.into())
+    });
+    for (prod, fold) in &folds {
+      let fold = fold.clone();
+      actions.on(prod.clone(), move |mut children| match &fold {
+        Fold::Apply(key) => Value::Tree(original(
+          key,
+          children.into_iter().map(Value::tree).collect(),
+        )),
+        Fold::Seed(key) => {
+          let tail = children.pop().unwrap().tail();
+          let seed =
+            original(key, children.into_iter().map(Value::tree).collect());
+          Value::Tree(tail(seed))
+        }
+        Fold::Step(key) => {
+          let tail = children.pop().unwrap().tail();
+          let key = key.clone();
+          let rest = children.into_iter().map(Value::tree).collect::<Vec<_>>();
+          Value::Tail(Rc::new(move |acc| {
+            let mut args = vec![acc];
+            args.extend(rest.iter().cloned());
+            tail(original(&key, args))
+          }))
+        }
+        Fold::Finish => Value::Tail(Rc::new(|acc| acc)),
+      });
+    }
+
+    let table = build_lalr_table(&new_g).unwrap();
+    let parse = |input: &[&str]| {
+      let tokens = input.iter().map(|t| Token::new(Terminal::new(t), ()));
+      parse_with_actions(&table, tokens, &actions)
+        .ok()
+        .map(Value::tree)
+    };
+    assert_eq!(parse(&["ATOM"]), Some("a".to_string()));
+    assert_eq!(
+      parse(&["ATOM", "MINUS", "ATOM", "MINUS", "ATOM"]),
+      Some("((a-a)-a)".to_string())
+    );
+  }
+
+  #[test]
+  fn test_unchanged_rules() {
+    let g = examples::make_paren();
+    let (new_g, folds) = eliminate_left_recursion_with_folds(&g).unwrap();
+    assert_eq!(new_g.rules().count(), g.rules().count());
+    assert!(folds.values().all(|fold| matches!(fold, Fold::Apply(_))));
+    assert!(eliminate_left_recursion(&examples::from_rules(&[(
+      "s",
+      &[&["s"], &["A"]],
+    )]))
+    .is_err());
+  }
+}
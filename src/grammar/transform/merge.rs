@@ -0,0 +1,214 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A transform that merges structurally equivalent nonterminals.
+//!
+//! Two nonterminals are equivalent if their productions have the same
+//! bodies, where nonterminals in the bodies are compared by equivalence
+//! rather than by name. This is computed by partition refinement: every
+//! nonterminal starts in the same class, and classes are split by the bodies
+//! of their productions until no class changes. This also merges mutually
+//! recursive nonterminals, such as `a ::= X a | Y` and `b ::= X b | Y`.
+
+use {
+  crate::grammar::{build_lenient, Elem, Grammar, ProdElement},
+  std::collections::{BTreeMap, BTreeSet},
+};
+
+/// Returns a grammar where each class of equivalent nonterminals is replaced
+/// by a single survivor, and every reference to the class refers to the
+/// survivor.
+///
+/// The survivor of a class is the start nonterminal if it is in the class,
+/// and otherwise the smallest nonterminal. The survivor keeps its own
+/// productions, action keys and action values. A nonterminal without a rule
+/// is only equivalent to itself.
+pub fn merge_equivalent_nonterminals<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> Grammar<T, NT, AK, AV>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  AV: Clone,
+{
+  let survivors = equivalent_survivors(g);
+  let survivor = |nt: &NT| match survivors.get(nt) {
+    Some(survivor) => (*survivor).clone(),
+    None => nt.clone(),
+  };
+
+  let (merged, _) = build_lenient(g.start_nt().clone(), |gb| {
+    for rule in g.rules() {
+      if survivor(rule.head()) != *rule.head() {
+        continue;
+      }
+      gb.add_rule(rule.head().clone(), |rb| {
+        for prod in rule.prods() {
+          let elems = prod
+            .prod_elements()
+            .iter()
+            .map(|elem| {
+              let mapped = match elem.elem() {
+                Elem::Term(t) => Elem::Term(t.clone()),
+                Elem::NonTerm(nt) => Elem::NonTerm(survivor(nt)),
+              };
+              ProdElement::new(elem.id().cloned(), mapped)
+            })
+            .collect::<Vec<_>>();
+          rb.add_prod_with_elems(
+            prod.action_key().clone(),
+            prod.action_value().clone(),
+            elems,
+          );
+        }
+      });
+    }
+  });
+  merged
+}
+
+/// Returns the survivor of the equivalence class of each nonterminal.
+fn equivalent_survivors<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> BTreeMap<&NT, &NT>
+where
+  T: Ord,
+  NT: Ord,
+{
+  let mut classes: BTreeMap<&NT, usize> =
+    g.rules().map(|rule| (rule.head(), 0)).collect();
+  loop {
+    // Nonterminals without rules are kept apart from every class, and from
+    // each other, by their own name.
+    let class = |nt| classes.get(nt).copied().ok_or(nt);
+    let signatures = g
+      .rules()
+      .map(|rule| {
+        let bodies = rule
+          .prods()
+          .map(|prod| {
+            prod
+              .elements()
+              .map(|elem| match elem {
+                Elem::Term(t) => Elem::Term(t),
+                Elem::NonTerm(nt) => Elem::NonTerm(class(nt)),
+              })
+              .collect::<Vec<_>>()
+          })
+          .collect::<BTreeSet<_>>();
+        (rule.head(), (classes[rule.head()], bodies))
+      })
+      .collect::<BTreeMap<_, _>>();
+
+    let mut ids = BTreeMap::new();
+    let next_classes = signatures
+      .iter()
+      .map(|(nt, signature)| {
+        let next_id = ids.len();
+        (*nt, *ids.entry(signature).or_insert(next_id))
+      })
+      .collect::<BTreeMap<_, _>>();
+
+    // Classes are only ever split, so the partition is stable once the
+    // number of classes stops growing.
+    let stable = ids.len() == classes.values().collect::<BTreeSet<_>>().len();
+    classes = next_classes;
+    if stable {
+      break;
+    }
+  }
+
+  let mut class_survivors: BTreeMap<usize, &NT> = BTreeMap::new();
+  for (nt, class) in &classes {
+    class_survivors.entry(*class).or_insert(nt);
+  }
+  if let Some(class) = classes.get(g.start_nt()) {
+    class_survivors.insert(*class, g.start_nt());
+  }
+  classes
+    .iter()
+    .map(|(nt, class)| (*nt, class_survivors[class]))
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{examples, NonTerminal};
+
+  fn nonterms<AK, AV>(
+    g: &Grammar<crate::grammar::Terminal, NonTerminal, AK, AV>,
+  ) -> Vec<NonTerminal> {
+    g.rules().map(|rule| rule.head().clone()).collect()
+  }
+
+  #[test]
+  fn test_merge_identical_rules() {
+    let g = examples::from_rules(&[
+      ("s", &[&["a", "b", "c"]]),
+      ("a", &[&["X"]]),
+      ("b", &[&["X"]]),
+      ("c", &[&["Y"]]),
+    ]);
+    let merged = merge_equivalent_nonterminals(&g);
+    let expected = examples::from_rules(&[
+      ("s", &[&["a", "a", "c"]]),
+      ("a", &[&["X"]]),
+      ("c", &[&["Y"]]),
+    ]);
+    assert_eq!(merged.to_pretty(), expected.to_pretty());
+  }
+
+  #[test]
+  fn test_merge_recursive_rules() {
+    let g = examples::from_rules(&[
+      ("s", &[&["a", "Z", "b"]]),
+      ("a", &[&["X", "a"], &["Y"]]),
+      ("b", &[&["X", "b"], &["Y"]]),
+    ]);
+    let merged = merge_equivalent_nonterminals(&g);
+    assert_eq!(
+      nonterms(&merged),
+      vec![NonTerminal::new("a"), NonTerminal::new("s")]
+    );
+    assert!(g.bounded_equivalent(&merged, 6).is_ok());
+
+    let paren = examples::make_paren();
+    assert_eq!(
+      merge_equivalent_nonterminals(&paren).to_pretty(),
+      paren.to_pretty()
+    );
+  }
+
+  #[test]
+  fn test_merge_lenient() {
+    // `a` and `b` only differ in the nonterminals without rules that they
+    // use, so they are not merged, but `a`, `c` and `d` are.
+    let g = examples::from_rules_lenient(&[
+      ("s", &[&["a", "b", "c", "d"]]),
+      ("a", &[&["X", "missing1"]]),
+      ("b", &[&["X", "missing2"]]),
+      ("c", &[&["X", "missing1"]]),
+      ("d", &[&["X", "missing1"]]),
+    ]);
+    let merged = merge_equivalent_nonterminals(&g);
+    let expected = examples::from_rules_lenient(&[
+      ("s", &[&["a", "b", "a", "a"]]),
+      ("a", &[&["X", "missing1"]]),
+      ("b", &[&["X", "missing2"]]),
+    ]);
+    assert_eq!(merged.to_pretty(), expected.to_pretty());
+  }
+}
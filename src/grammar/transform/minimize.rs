@@ -0,0 +1,350 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transforms that remove redundant parts of a grammar, and a pipeline that
+//! combines them.
+//!
+//! Each transform preserves the language of the grammar. They do not
+//! preserve its parse trees: productions are removed or copied between
+//! rules, and the action keys and values of removed productions are lost.
+//!
+//! The transforms accept grammars built with `build_lenient`. Nonterminals
+//! without rules derive nothing, so productions that use them are removed
+//! as nonproductive.
+
+use {
+  super::merge_equivalent_nonterminals,
+  crate::grammar::{build_lenient, Elem, Grammar, ProdElement},
+  crate::utils::breadth_first_search,
+  std::collections::BTreeSet,
+};
+
+/// Returns a grammar with only the rules that are reachable from the start
+/// nonterminal.
+pub fn remove_unreachable<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> Grammar<T, NT, AK, AV>
+where
+  T: Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  AV: Clone,
+{
+  let mut reachable = g.reachable_from(g.start_nt());
+  reachable.insert(g.start_nt());
+  rebuild(g, |head, _| reachable.contains(head))
+}
+
+/// Returns a grammar without the productions that use a nonproductive
+/// nonterminal, which is one that derives no sentence.
+///
+/// Rules that are left without productions are removed, except for the
+/// start rule, which is kept without productions if the language of the
+/// grammar is empty.
+pub fn remove_nonproductive<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> Grammar<T, NT, AK, AV>
+where
+  T: Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  AV: Clone,
+{
+  let empty = g.empty_language_nonterms();
+  let productive =
+    |nt: &NT| g.try_get_rule(nt).is_some() && !empty.contains(nt);
+  rebuild(g, |head, elems| {
+    productive(head)
+      && elems.iter().all(|elem| match elem.elem() {
+        Elem::Term(_) => true,
+        Elem::NonTerm(nt) => productive(nt),
+      })
+  })
+}
+
+/// Returns a grammar without unit productions, which are productions whose
+/// body is a single nonterminal.
+///
+/// A unit production `A ::= B` is replaced by copies of the productions of
+/// `B`, following any unit productions of `B` in turn, with their own
+/// action keys and values. A unit production from a nonterminal to itself
+/// is removed.
+///
+/// If any of the copies would give `A` two productions with the same action
+/// key, the unit production is kept instead, so the result may still have
+/// unit productions when action keys are reused between rules.
+pub fn remove_unit_productions<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> Grammar<T, NT, AK, AV>
+where
+  T: Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  AV: Clone,
+{
+  let (new_g, _) = build_lenient(g.start_nt().clone(), |gb| {
+    for rule in g.rules() {
+      let head = rule.head();
+      let mut prods = Vec::new();
+      let mut units = Vec::new();
+      for prod in rule.prods() {
+        match unit_target(prod.prod_elements()) {
+          Some(target) if target == head => {}
+          Some(target) => units.push((prod, target)),
+          None => prods.push(prod),
+        }
+      }
+
+      let mut keys = prods
+        .iter()
+        .map(|prod| prod.action_key())
+        .collect::<BTreeSet<_>>();
+      let mut copied = BTreeSet::new();
+      for (unit, target) in units {
+        let mut sources = breadth_first_search(Some(target), |nt| {
+          g.try_get_rule(nt)
+            .into_iter()
+            .flat_map(|rule| rule.prods())
+            .filter_map(|prod| unit_target(prod.prod_elements()))
+            .collect::<Vec<_>>()
+        });
+        sources.insert(target);
+        sources.remove(head);
+
+        let copies = sources
+          .iter()
+          .filter(|source| !copied.contains(*source))
+          .filter_map(|source| g.try_get_rule(source))
+          .flat_map(|rule| rule.prods())
+          .filter(|prod| unit_target(prod.prod_elements()).is_none())
+          .collect::<Vec<_>>();
+        let copy_keys = copies
+          .iter()
+          .map(|prod| prod.action_key())
+          .collect::<BTreeSet<_>>();
+        if copy_keys.len() == copies.len() && keys.is_disjoint(&copy_keys) {
+          keys.extend(copy_keys);
+          copied.extend(sources);
+          prods.extend(copies);
+        } else {
+          keys.insert(unit.action_key());
+          prods.push(unit);
+        }
+      }
+
+      gb.add_rule(head.clone(), |rb| {
+        for prod in prods {
+          rb.add_prod_with_elems(
+            prod.action_key().clone(),
+            prod.action_value().clone(),
+            prod.prod_elements().clone(),
+          );
+        }
+      });
+    }
+  });
+  new_g
+}
+
+/// Returns a minimal grammar with the same language as `g`.
+///
+/// This runs `remove_unreachable`, `remove_nonproductive`,
+/// `remove_unit_productions` and `merge_equivalent_nonterminals`, in that
+/// order, until none of them changes the grammar. Each step can expose more
+/// work for the others: removing nonproductive productions can make rules
+/// unreachable, and removing unit productions can make rules equivalent.
+///
+/// If the language of `g` is empty, the result has a start rule without
+/// productions, and no other rules.
+pub fn minimize<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> Grammar<T, NT, AK, AV>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  AV: Clone,
+{
+  let mut g = remove_unreachable(g);
+  loop {
+    let before = shape(&g);
+    g = remove_unreachable(&remove_nonproductive(&g));
+    // An empty start rule leaves nothing to simplify, and is not a valid
+    // input to merging.
+    if g.get_rule(g.start_nt()).prods().next().is_none() {
+      return g;
+    }
+    g = merge_equivalent_nonterminals(&remove_unit_productions(&g));
+    g = remove_unreachable(&g);
+    if shape(&g) == before {
+      return g;
+    }
+  }
+}
+
+/// Returns the nonterminal of a unit production with the body `elems`, or
+/// `None` if `elems` is not a single nonterminal.
+fn unit_target<T, NT>(elems: &[ProdElement<T, NT>]) -> Option<&NT> {
+  match elems {
+    [elem] => elem.elem().as_nonterm(),
+    _ => None,
+  }
+}
+
+/// Returns the heads, action keys and elements of every production of `g`.
+#[allow(clippy::type_complexity)]
+fn shape<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> BTreeSet<(NT, AK, Vec<Elem<T, NT>>)>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  g.prods()
+    .map(|prod| {
+      (
+        prod.head().clone(),
+        prod.action_key().clone(),
+        prod.symbols(),
+      )
+    })
+    .collect()
+}
+
+/// Returns a copy of `g` with only the productions whose head and elements
+/// satisfy `keep`. Rules without any kept productions are left out, except
+/// for the start rule.
+fn rebuild<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+  keep: impl Fn(&NT, &[ProdElement<T, NT>]) -> bool,
+) -> Grammar<T, NT, AK, AV>
+where
+  T: Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  AV: Clone,
+{
+  let (new_g, _) = build_lenient(g.start_nt().clone(), |gb| {
+    for rule in g.rules() {
+      let prods = rule
+        .prods()
+        .filter(|prod| keep(rule.head(), prod.prod_elements()))
+        .collect::<Vec<_>>();
+      if prods.is_empty() && rule.head() != g.start_nt() {
+        continue;
+      }
+      gb.add_rule(rule.head().clone(), |rb| {
+        for prod in prods {
+          rb.add_prod_with_elems(
+            prod.action_key().clone(),
+            prod.action_value().clone(),
+            prod.prod_elements().clone(),
+          );
+        }
+      });
+    }
+  });
+  new_g
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{build, examples, NonTerminal, Terminal};
+  use crate::utils::Name;
+
+  type TestGrammar = Grammar<Terminal, NonTerminal, Name, ()>;
+
+  fn nonterms(g: &TestGrammar) -> Vec<NonTerminal> {
+    g.rules().map(|rule| rule.head().clone()).collect()
+  }
+
+  #[test]
+  fn test_minimize_bloated_grammar() {
+    let g = examples::from_rules_lenient(&[
+      ("s", &[&["e"], &["dead", "Y"]]),
+      (
+        "e",
+        &[
+          &["e", "PLUS", "t"],
+          &["e", "TIMES", "t2"],
+          &["NUM"],
+          &["LPAREN", "e", "RPAREN"],
+        ],
+      ),
+      ("t", &[&["NUM"], &["LPAREN", "e", "RPAREN"]]),
+      ("t2", &[&["NUM"], &["LPAREN", "e", "RPAREN"]]),
+      ("dead", &[&["dead", "X"]]),
+      ("orphan", &[&["NUM"]]),
+    ]);
+    assert_eq!(nonterms(&g).len(), 6);
+
+    let minimal = minimize(&g);
+    assert_eq!(
+      nonterms(&minimal),
+      vec![NonTerminal::new("s"), NonTerminal::new("t")]
+    );
+    assert_eq!(minimal.prods().count(), 6);
+    assert!(!minimal.has_unit_productions());
+    assert!(g.bounded_equivalent(&minimal, 7).is_ok());
+    assert_eq!(minimize(&minimal).to_pretty(), minimal.to_pretty());
+
+    let paren = examples::make_paren();
+    assert!(paren.bounded_equivalent(&minimize(&paren), 6).is_ok());
+  }
+
+  #[test]
+  fn test_remove_unit_productions() {
+    // `s ::= c` is replaced by the production of `d`, through `c ::= d`, but
+    // `s ::= a` is kept, since the productions of `a` would reuse the action
+    // key `p0` of the copy.
+    let g = examples::from_rules(&[
+      ("s", &[&["c"], &["a"], &["s"]]),
+      ("c", &[&["d"]]),
+      ("d", &[&["Z"]]),
+      ("a", &[&["X"], &["Y", "Y"]]),
+    ]);
+    let removed = remove_unit_productions(&g);
+    let prods = removed
+      .get_rule(&NonTerminal::new("s"))
+      .prods()
+      .map(|prod| (prod.action_key().str().to_string(), prod.symbols()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      prods,
+      vec![
+        ("p0".to_string(), vec![Elem::Term(Terminal::new("Z"))]),
+        ("p1".to_string(), vec![Elem::NonTerm(NonTerminal::new("a"))]),
+      ]
+    );
+    assert!(g.bounded_equivalent(&removed, 3).is_ok());
+  }
+
+  #[test]
+  fn test_empty_language() {
+    let g: TestGrammar = build(NonTerminal::new("s"), |gb| {
+      gb.add_rule(NonTerminal::new("s"), |rb| {
+        rb.add_prod("loop", (), |pb| {
+          pb.add_term(Terminal::new("A"))
+            .add_nonterm(NonTerminal::new("s"));
+        });
+      });
+    })
+    .unwrap();
+    let minimal = minimize(&g);
+    assert_eq!(nonterms(&minimal), vec![NonTerminal::new("s")]);
+    assert_eq!(minimal.prods().count(), 0);
+  }
+}
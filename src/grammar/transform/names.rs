@@ -0,0 +1,177 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Naming of the auxiliary nonterminals introduced by transforms.
+//!
+//! Transforms that introduce new nonterminals, the builder when it expands
+//! inline alternations, and template expansion ask a `NameGen` for candidate names. If a
+//! candidate clashes with a nonterminal that is already in use, they ask
+//! again with the next attempt number, so the generated nonterminals are
+//! always unique.
+
+use {
+  crate::grammar::{Elem, NonTerminal, Terminal},
+  std::collections::BTreeSet,
+};
+
+/// The reason a transform needs a new nonterminal.
+#[derive(Debug)]
+pub enum AuxKind<'a, T, NT> {
+  /// A new start nonterminal, replacing the given start nonterminal.
+  Start(&'a NT),
+  /// The tail introduced to remove left recursion from the given
+  /// nonterminal.
+  Tail(&'a NT),
+  /// A nonterminal that derives only the given terminal.
+  Term(&'a T),
+  /// An inline alternation in a production of the given nonterminal.
+  Alt(&'a NT),
+  /// A use of the named template with the given arguments.
+  Instance(&'a str, &'a [Elem<T, NT>]),
+}
+
+impl<T, NT> Clone for AuxKind<'_, T, NT> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<T, NT> Copy for AuxKind<'_, T, NT> {}
+
+/// A scheme for naming auxiliary nonterminals.
+pub trait NameGen<T, NT> {
+  /// Returns a candidate nonterminal for `kind`.
+  ///
+  /// `attempt` starts at 1 and increases each time the previous candidate
+  /// was already in use. Implementations must eventually return an unused
+  /// nonterminal as `attempt` increases.
+  fn name(&mut self, kind: AuxKind<'_, T, NT>, attempt: usize) -> NT;
+}
+
+impl<T, NT, F> NameGen<T, NT> for F
+where
+  F: FnMut(AuxKind<'_, T, NT>, usize) -> NT,
+{
+  fn name(&mut self, kind: AuxKind<'_, T, NT>, attempt: usize) -> NT {
+    self(kind, attempt)
+  }
+}
+
+/// The default naming scheme.
+///
+/// Nonterminals are named after the symbol they were derived from, the kind
+/// of nonterminal, and the attempt number, separated by double underscores:
+/// `expr__tail__1`, `LPAREN__term__1`, `start__start__1`, `expr__alt__1`.
+///
+/// Template uses are named after the template and its arguments, with
+/// terminal arguments quoted: `list<expr>`, `map<'KEY',value>`. Later
+/// attempts append the attempt number, as in `list<expr>__2`.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct DefaultNameGen;
+
+impl NameGen<Terminal, NonTerminal> for DefaultNameGen {
+  fn name(
+    &mut self,
+    kind: AuxKind<'_, Terminal, NonTerminal>,
+    attempt: usize,
+  ) -> NonTerminal {
+    let (base, suffix) = match kind {
+      AuxKind::Instance(name, args) => {
+        let name = instance_name(name, args);
+        return match attempt {
+          1 => NonTerminal::new(&name),
+          _ => NonTerminal::new(&format!("{}__{}", name, attempt)),
+        };
+      }
+      AuxKind::Start(nt) => (nt.name(), "start"),
+      AuxKind::Tail(nt) => (nt.name(), "tail"),
+      AuxKind::Term(t) => (t.name(), "term"),
+      AuxKind::Alt(nt) => (nt.name(), "alt"),
+    };
+    NonTerminal::new(&format!("{}__{}__{}", base, suffix, attempt))
+  }
+}
+
+/// Returns the name of a use of template `name` with `args`, such as
+/// `list<expr>` or `map<'KEY',value>`.
+fn instance_name(name: &str, args: &[Elem<Terminal, NonTerminal>]) -> String {
+  let args = args
+    .iter()
+    .map(|arg| match arg {
+      Elem::Term(t) => format!("'{}'", t.name()),
+      Elem::NonTerm(nt) => nt.name().to_string(),
+    })
+    .collect::<Vec<_>>();
+  format!("{}<{}>", name, args.join(","))
+}
+
+/// Returns a nonterminal from `names` that is not in `used`, and marks it as
+/// used.
+pub fn fresh_nonterm<T, NT, G>(
+  names: &mut G,
+  used: &mut BTreeSet<NT>,
+  kind: AuxKind<'_, T, NT>,
+) -> NT
+where
+  NT: Ord + Clone,
+  G: NameGen<T, NT> + ?Sized,
+{
+  let nt = (1..)
+    .map(|attempt| names.name(kind, attempt))
+    .find(|nt| !used.contains(nt))
+    .expect("name generators eventually produce an unused name");
+  used.insert(nt.clone());
+  nt
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_default_names() {
+    let mut used = BTreeSet::new();
+    let expr = NonTerminal::new("expr");
+    used.insert(NonTerminal::new("expr__tail__1"));
+
+    let tail =
+      fresh_nonterm(&mut DefaultNameGen, &mut used, AuxKind::Tail(&expr));
+    assert_eq!(tail, NonTerminal::new("expr__tail__2"));
+    let tail =
+      fresh_nonterm(&mut DefaultNameGen, &mut used, AuxKind::Tail(&expr));
+    assert_eq!(tail, NonTerminal::new("expr__tail__3"));
+
+    let term = fresh_nonterm(
+      &mut DefaultNameGen,
+      &mut used,
+      AuxKind::Term(&Terminal::new("LPAREN")),
+    );
+    assert_eq!(term, NonTerminal::new("LPAREN__term__1"));
+  }
+
+  #[test]
+  fn test_many_clashing_names() {
+    // A scheme that ignores the kind entirely, so every name is a clash
+    // until the attempt number passes all previous names.
+    let mut names = |_: AuxKind<'_, Terminal, NonTerminal>, attempt: usize| {
+      NonTerminal::new(&format!("aux{}", attempt % 1000))
+    };
+    let mut used = BTreeSet::new();
+    let nt = NonTerminal::new("x");
+    for _ in 0..500 {
+      fresh_nonterm(&mut names, &mut used, AuxKind::Tail(&nt));
+    }
+    assert_eq!(used.len(), 500);
+  }
+}
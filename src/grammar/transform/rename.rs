@@ -0,0 +1,95 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A transform that renames the terminals of a grammar.
+
+use {
+  crate::grammar::{build_lenient, Elem, Grammar, ProdElement},
+  std::collections::BTreeMap,
+};
+
+/// Returns a grammar where every terminal that is a key of `mapping` is
+/// replaced by its value, in every production. Other terminals, and the
+/// nonterminals, action keys and action values, are unchanged.
+///
+/// If `mapping` sends two terminals to the same terminal, they become
+/// indistinguishable, and the language may grow accordingly.
+pub fn rename_terminals<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+  mapping: &BTreeMap<T, T>,
+) -> Grammar<T, NT, AK, AV>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  AV: Clone,
+{
+  let rename = |t: &T| mapping.get(t).unwrap_or(t).clone();
+  let (renamed, _) = build_lenient(g.start_nt().clone(), |gb| {
+    for rule in g.rules() {
+      gb.add_rule(rule.head().clone(), |rb| {
+        for prod in rule.prods() {
+          let elems = prod
+            .prod_elements()
+            .iter()
+            .map(|elem| {
+              let mapped = match elem.elem() {
+                Elem::Term(t) => Elem::Term(rename(t)),
+                Elem::NonTerm(nt) => Elem::NonTerm(nt.clone()),
+              };
+              ProdElement::new(elem.id().cloned(), mapped)
+            })
+            .collect::<Vec<_>>();
+          rb.add_prod_with_elems(
+            prod.action_key().clone(),
+            prod.action_value().clone(),
+            elems,
+          );
+        }
+      });
+    }
+  });
+  renamed
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{examples, gen::bounded_sentences, Terminal};
+
+  #[test]
+  fn test_rename_paren() {
+    let g = examples::make_paren();
+    let mapping =
+      std::iter::once((Terminal::new("LPAREN"), Terminal::new("OPEN")))
+        .collect::<BTreeMap<_, _>>();
+    let renamed = rename_terminals(&g, &mapping);
+
+    assert!(!renamed.uses_terminal(&Terminal::new("LPAREN")));
+    assert!(renamed.uses_terminal(&Terminal::new("OPEN")));
+    assert!(renamed.uses_terminal(&Terminal::new("RPAREN")));
+    assert_eq!(renamed.to_pretty(), g.to_pretty().replace("LPAREN", "OPEN"));
+
+    let expected = bounded_sentences(&g, 6)
+      .into_iter()
+      .map(|sentence| {
+        sentence
+          .iter()
+          .map(|t| mapping.get(t).unwrap_or(t).clone())
+          .collect::<Vec<_>>()
+      })
+      .collect();
+    assert_eq!(bounded_sentences(&renamed, 6), expected);
+  }
+}
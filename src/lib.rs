@@ -19,7 +19,12 @@
 #[macro_use]
 extern crate derivative;
 
+#[cfg(test)]
+#[macro_use]
+mod test_util;
+
 pub mod grammar;
+pub mod multi_start;
 pub mod parsers;
 pub mod start_grammar;
 pub mod state;
@@ -30,9 +35,9 @@ mod tests {
   use crate::grammar::build;
   use crate::grammar::{
     passes::{nullable::Nullable, PassContext},
-    Grammar, NonTerminal, Terminal,
+    Grammar, NonTerminal, ProdKey, Terminal,
   };
-  use crate::utils::Name;
+  use crate::utils::{Name, TreeNode};
 
   fn base_grammar() -> Grammar<Terminal, NonTerminal, Name, ()> {
     let t_a = Terminal::new("A");
@@ -102,11 +107,42 @@ mod tests {
     let nullable = pass_map.get_pass::<Nullable<_, _>>().unwrap();
 
     assert!(nullable.is_nullable(&NonTerminal::new("x")));
+    assert!(Nullable::new(&g)
+      .unwrap()
+      .is_nullable(&NonTerminal::new("x")));
+  }
+
+  #[test]
+  fn test_empty_production() {
+    let g = base_grammar();
+    let rule = g.get_rule(&NonTerminal::new("x"));
+    let empty = rule.empty_production().unwrap();
+    assert!(empty.is_empty());
+    assert_eq!(empty.action_key(), &Name::new("Empty"));
+    assert_eq!(rule.prods().filter(|prod| prod.is_empty()).count(), 1);
+
+    let g = create_arithmetic_grammar();
+    assert!(g.get_rule(g.start_nt()).empty_production().is_none());
   }
 
   #[test]
   fn test_grammar_nullable() {
     let g = base_grammar();
     println!("{:#?}", g);
+
+    let pass_map = PassContext::new(&g);
+    let nullable = pass_map.get_pass::<Nullable<_, _>>().unwrap();
+    let x = NonTerminal::new("x");
+    assert!(nullable.get_nullable_set().contains(&x));
+    assert_eq!(
+      nullable.get_nullable_action(&x),
+      Some(&TreeNode::from_action(ProdKey::new(
+        x.clone(),
+        Name::new("Empty")
+      )))
+    );
+    assert!(nullable
+      .get_nullable_action(&NonTerminal::new("missing"))
+      .is_none());
   }
 }
@@ -0,0 +1,184 @@
+//! Grammars with several start nonterminals that share their rules.
+//!
+//! A grammar is given one start nonterminal per entry point by adding a new
+//! `Entry` start nonterminal, with a production `Entry -> <ENTRY s> s` for
+//! each declared start `s`. The `<ENTRY s>` terminals never appear in the
+//! input. Instead, a parser for the combined grammar begins by shifting the
+//! marker of the start it was asked for, so a single table can parse from
+//! any of the declared starts.
+
+use crate::{
+  grammar::{build, Elem, Grammar, GrammarErrors, ProdElement},
+  start_grammar::StartActionValue,
+  utils::{Name, ToDoc},
+};
+
+/// A terminal wrapper type that adds a marker terminal for each declared
+/// start nonterminal.
+#[derive(Clone, PartialOrd, Ord, PartialEq, Eq, Debug)]
+pub enum EntryTerminal<T, NT> {
+  Entry(NT),
+  Term(T),
+}
+
+impl<T, NT> ToDoc for EntryTerminal<T, NT>
+where
+  T: ToDoc,
+  NT: ToDoc,
+{
+  fn to_doc<'a, DA: pretty::DocAllocator<'a>>(
+    &self,
+    da: &'a DA,
+  ) -> pretty::DocBuilder<'a, DA>
+  where
+    DA::Doc: Clone,
+  {
+    match self {
+      EntryTerminal::Entry(nt) => da
+        .text("<ENTRY ")
+        .append(nt.to_doc(da))
+        .append(da.text(">")),
+      EntryTerminal::Term(t) => t.to_doc(da),
+    }
+  }
+}
+
+/// A non-terminal wrapper type that adds the combined `Entry` start
+/// nonterminal.
+#[derive(Clone, PartialOrd, Ord, PartialEq, Eq, Debug)]
+pub enum EntryNonTerminal<NT> {
+  Entry,
+  NTerm(NT),
+}
+
+impl<NT> ToDoc for EntryNonTerminal<NT>
+where
+  NT: ToDoc,
+{
+  fn to_doc<'a, DA: pretty::DocAllocator<'a>>(
+    &self,
+    da: &'a DA,
+  ) -> pretty::DocBuilder<'a, DA>
+  where
+    DA::Doc: Clone,
+  {
+    match self {
+      EntryNonTerminal::Entry => da.text("<ENTRY>"),
+      EntryNonTerminal::NTerm(nt) => nt.to_doc(da),
+    }
+  }
+}
+
+/// An action key wrapper type that adds a key for the entry production of
+/// each declared start nonterminal.
+#[derive(Clone, PartialOrd, Ord, PartialEq, Eq, Debug)]
+pub enum EntryActionKey<NT, AK> {
+  Entry(NT),
+  ActionKey(AK),
+}
+
+impl<NT, AK> EntryActionKey<NT, AK> {
+  pub fn as_base(&self) -> Option<&AK> {
+    match self {
+      EntryActionKey::Entry(_) => None,
+      EntryActionKey::ActionKey(ak) => Some(ak),
+    }
+  }
+}
+
+impl<NT, AK> ToDoc for EntryActionKey<NT, AK>
+where
+  NT: ToDoc,
+  AK: ToDoc,
+{
+  fn to_doc<'a, DA: pretty::DocAllocator<'a>>(
+    &self,
+    da: &'a DA,
+  ) -> pretty::DocBuilder<'a, DA>
+  where
+    DA::Doc: Clone,
+  {
+    match self {
+      EntryActionKey::Entry(nt) => da
+        .text("<ENTRY ")
+        .append(nt.to_doc(da))
+        .append(da.text(">")),
+      EntryActionKey::ActionKey(ak) => ak.to_doc(da),
+    }
+  }
+}
+
+pub type MultiStartGrammar<T, NT, AK, AV> = Grammar<
+  EntryTerminal<T, NT>,
+  EntryNonTerminal<NT>,
+  EntryActionKey<NT, AK>,
+  StartActionValue<AV>,
+>;
+
+impl<T, NT, AK, AV> Grammar<T, NT, AK, AV>
+where
+  T: Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  AV: Clone,
+{
+  /// Returns a grammar that may be parsed from any of `starts`, with the
+  /// rules of this grammar that are reachable from them. The start
+  /// nonterminal of this grammar is not included unless it is listed.
+  #[allow(clippy::type_complexity)]
+  pub fn with_start_symbols(
+    &self,
+    starts: &[NT],
+  ) -> Result<
+    MultiStartGrammar<T, NT, AK, AV>,
+    GrammarErrors<EntryNonTerminal<NT>, EntryActionKey<NT, AK>>,
+  > {
+    let mut reachable = self.reachable_through(starts, |_| true);
+    reachable.extend(starts);
+
+    build(EntryNonTerminal::Entry, |gb| {
+      gb.add_rule(EntryNonTerminal::Entry, |rb| {
+        for start in starts {
+          rb.add_prod_with_elems(
+            EntryActionKey::Entry(start.clone()),
+            StartActionValue::Start,
+            vec![
+              ProdElement::new_empty(Elem::Term(EntryTerminal::Entry(
+                start.clone(),
+              ))),
+              ProdElement::new_with_name(
+                Name::new("start"),
+                Elem::NonTerm(EntryNonTerminal::NTerm(start.clone())),
+              ),
+            ],
+          );
+        }
+      });
+
+      for rule in self.rules().filter(|rule| reachable.contains(&rule.head())) {
+        gb.add_rule(EntryNonTerminal::NTerm(rule.head().clone()), |rb| {
+          for prod in rule.prods() {
+            let elems = prod
+              .prod_elements()
+              .iter()
+              .map(|elem| {
+                let e = match elem.elem() {
+                  Elem::Term(t) => Elem::Term(EntryTerminal::Term(t.clone())),
+                  Elem::NonTerm(nt) => {
+                    Elem::NonTerm(EntryNonTerminal::NTerm(nt.clone()))
+                  }
+                };
+                ProdElement::new(elem.id().cloned(), e)
+              })
+              .collect::<Vec<_>>();
+            rb.add_prod_with_elems(
+              EntryActionKey::ActionKey(prod.action_key().clone()),
+              StartActionValue::ActionValue(prod.action_value().clone()),
+              elems,
+            );
+          }
+        });
+      }
+    })
+  }
+}
@@ -0,0 +1,117 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tracking which productions of a grammar are exercised by parsing a
+//! corpus of inputs.
+
+use {
+  crate::grammar::{Grammar, ProdKey},
+  std::collections::BTreeSet,
+};
+
+/// The set of productions that have been reduced by the parsers it was
+/// passed to.
+///
+/// A tracker may be shared between many parses, so that it records the
+/// coverage of a whole test corpus.
+#[derive(Clone, Debug)]
+pub struct CoverageTracker<NT, AK> {
+  covered: BTreeSet<ProdKey<NT, AK>>,
+}
+
+impl<NT, AK> Default for CoverageTracker<NT, AK> {
+  fn default() -> Self {
+    CoverageTracker {
+      covered: BTreeSet::new(),
+    }
+  }
+}
+
+impl<NT, AK> CoverageTracker<NT, AK>
+where
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Records that `prod` was reduced.
+  pub fn record(&mut self, prod: &ProdKey<NT, AK>) {
+    if !self.covered.contains(prod) {
+      self.covered.insert(prod.clone());
+    }
+  }
+
+  /// The productions that have been reduced at least once.
+  pub fn covered_productions(&self) -> &BTreeSet<ProdKey<NT, AK>> {
+    &self.covered
+  }
+
+  /// Returns the productions of `g` that have never been reduced, in grammar
+  /// order.
+  pub fn uncovered<T, AV>(
+    &self,
+    g: &Grammar<T, NT, AK, AV>,
+  ) -> Vec<ProdKey<NT, AK>> {
+    g.prods()
+      .map(|prod| prod.prod_key())
+      .filter(|key| !self.covered.contains(key))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::{examples, NonTerminal, Terminal},
+    parsers::lalr::build_lalr_table,
+    utils::Name,
+  };
+
+  fn terms(names: &[&str]) -> Vec<Terminal> {
+    names.iter().map(|name| Terminal::new(name)).collect()
+  }
+
+  fn key(head: &str, action: &str) -> ProdKey<NonTerminal, Name> {
+    ProdKey::new(NonTerminal::new(head), Name::new(action))
+  }
+
+  #[test]
+  fn test_paren_coverage() {
+    let g = examples::from_rules(&[
+      ("expr", &[&["LPAREN", "list", "RPAREN"], &["ATOM"]]),
+      ("list", &[&[], &["expr", "list"]]),
+    ]);
+    let table = build_lalr_table(&g).unwrap();
+
+    let mut coverage = CoverageTracker::new();
+    assert!(
+      table.accepts_with_coverage(terms(&["LPAREN", "RPAREN"]), &mut coverage)
+    );
+    assert_eq!(
+      coverage.uncovered(&g),
+      vec![key("expr", "p1"), key("list", "p1")]
+    );
+
+    // The recursive production is covered by a nested input, but no input
+    // uses an atom.
+    let nested = terms(&["LPAREN", "LPAREN", "RPAREN", "RPAREN"]);
+    assert!(table.accepts_with_coverage(nested, &mut coverage));
+    assert!(!table.accepts_with_coverage(terms(&["RPAREN"]), &mut coverage));
+    assert!(coverage.covered_productions().contains(&key("list", "p1")));
+    assert_eq!(coverage.uncovered(&g), vec![key("expr", "p1")]);
+  }
+}
@@ -48,12 +48,12 @@ pub fn close_state<'a, T, NT, AK, AV, V>(
       .states()
       .flat_map(|state| {
         std::iter::empty()
-          .chain(state.predict(grammar, prev_states).into_iter())
+          .chain(state.predict(grammar, prev_states))
           .chain(state.complete(tree_handle, prev_states, new_state))
       })
       .collect::<Vec<_>>();
 
-    change_iter(closure.into_iter(), |state| new_state.insert(&state))
+    change_iter(closure, |state| new_state.insert(&state))
   });
 }
 
@@ -77,6 +77,10 @@ where
 
   for token in tokens {
     let mut new_state = states.last().unwrap().shift(tree_handle, &token);
+    if new_state.is_empty() {
+      // No state could accept the token, so the input is rejected.
+      return None;
+    }
 
     close_state(grammar, tree_handle, &states, &mut new_state);
 
@@ -86,3 +90,220 @@ where
   let last_state = states.last().unwrap();
   last_state.get_final().cloned()
 }
+
+#[cfg(test)]
+mod test {
+  use {
+    super::*,
+    crate::{
+      grammar::{
+        build,
+        passes::{
+          firsts::Firsts, follows::Follows, nullable::Nullable, PassContext,
+        },
+        Elem, Grammar, NonTerminal, ProdElement, Terminal,
+      },
+      parsers::tree::TreeOwner,
+      start_grammar::{wrap_grammar_with_start, StreamTerminal},
+      utils::Name,
+    },
+    proptest::{collection::vec, prelude::*},
+    std::collections::{BTreeMap, BTreeSet},
+  };
+
+  type TestGrammar = Grammar<Terminal, NonTerminal, Name, ()>;
+
+  const NUM_TERMS: usize = 3;
+
+  fn terminal(i: usize) -> Terminal {
+    Terminal::new(&format!("t{}", i))
+  }
+
+  fn nonterminal(i: usize) -> NonTerminal {
+    NonTerminal::new(&format!("n{}", i))
+  }
+
+  /// Builds a grammar from rule descriptions. Each element is either a
+  /// terminal index (`Ok`), or a nonterminal index (`Err`).
+  fn grammar_from_spec(
+    spec: &[Vec<Vec<Result<usize, usize>>>],
+  ) -> Option<TestGrammar> {
+    build(nonterminal(0), |gb| {
+      for (i, prods) in spec.iter().enumerate() {
+        gb.add_rule(nonterminal(i), |rb| {
+          for (j, elems) in prods.iter().enumerate() {
+            rb.add_prod_with_elems(
+              Name::new(&format!("p{}", j)),
+              (),
+              elems
+                .iter()
+                .enumerate()
+                .map(|(k, e)| {
+                  let elem = match e {
+                    Ok(t) => Elem::Term(terminal(*t)),
+                    Err(nt) => Elem::NonTerm(nonterminal(*nt)),
+                  };
+                  ProdElement::new_with_name(
+                    Name::new(&format!("e{}", k)),
+                    elem,
+                  )
+                })
+                .collect::<Vec<_>>(),
+            );
+          }
+        });
+      }
+    })
+    .ok()
+  }
+
+  fn grammar_strategy() -> impl Strategy<Value = TestGrammar> {
+    (1..=3usize)
+      .prop_flat_map(|num_nts| {
+        let elem =
+          prop_oneof![(0..NUM_TERMS).prop_map(Ok), (0..num_nts).prop_map(Err),];
+        vec(vec(vec(elem, 0..=3), 1..=3), num_nts)
+      })
+      .prop_filter_map("grammar must be well-formed", |spec| {
+        grammar_from_spec(&spec)
+      })
+  }
+
+  /// Returns the set of terminals that may appear directly after each
+  /// terminal in a sentence, as derived from FIRST, FOLLOW and nullable.
+  fn terminal_follows<T, NT, AK, AV>(
+    passes: &PassContext<T, NT, AK, AV>,
+  ) -> BTreeMap<T, BTreeSet<T>>
+  where
+    T: Ord + Clone + 'static,
+    NT: Ord + Clone + 'static,
+    AK: Ord + Clone + 'static,
+  {
+    let nullable = passes.get_pass::<Nullable<NT, AK>>().unwrap();
+    let firsts = passes.get_pass::<Firsts<T, NT>>().unwrap();
+    let follows = passes.get_pass::<Follows<T, NT>>().unwrap();
+
+    let mut result: BTreeMap<T, BTreeSet<T>> = BTreeMap::new();
+    for prod in passes.grammar().prods() {
+      let elems = prod.elements().collect::<Vec<_>>();
+      for (i, elem) in elems.iter().enumerate() {
+        if let Elem::Term(t) = elem {
+          let entry = result.entry(t.clone()).or_default();
+          let mut suffix_nullable = true;
+          for next in &elems[i + 1..] {
+            match next {
+              Elem::Term(next_t) => {
+                entry.insert(next_t.clone());
+                suffix_nullable = false;
+              }
+              Elem::NonTerm(nt) => {
+                entry.extend(firsts.terms(nt).cloned());
+                suffix_nullable = nullable.is_nullable(nt);
+              }
+            }
+            if !suffix_nullable {
+              break;
+            }
+          }
+          if suffix_nullable {
+            entry.extend(follows.terms(prod.head()).cloned());
+          }
+        }
+      }
+    }
+    result
+  }
+
+  fn accepts(g: &TestGrammar, input: &[usize]) -> bool {
+    let g = wrap_grammar_with_start(g.clone()).unwrap();
+    let tree: TreeOwner<_, _, ()> = TreeOwner::new();
+    let tokens = input.iter().map(|t| Token::new(terminal(*t), ())).collect();
+    parse(&g, &tree.handle(), tokens).is_some()
+  }
+
+  /// Checks that every accepted input agrees with the predictions made by
+  /// the FIRST/FOLLOW/nullable analyses over `g`.
+  fn check_predictions(
+    g: &TestGrammar,
+    inputs: &[Vec<usize>],
+  ) -> Result<(), TestCaseError> {
+    let start_g = wrap_grammar_with_start(g.clone()).unwrap();
+    let passes = PassContext::new(&start_g);
+    prop_assume!(passes.get_pass::<Nullable<_, _>>().is_ok());
+
+    let firsts = passes.get_pass::<Firsts<_, _>>().unwrap();
+    let term_follows = terminal_follows(&passes);
+    let start_firsts = firsts.get(start_g.start_nt()).unwrap();
+
+    for input in inputs {
+      if !accepts(g, input) {
+        continue;
+      }
+
+      let sentence = input
+        .iter()
+        .map(|t| StreamTerminal::Term(terminal(*t)))
+        .chain(std::iter::once(StreamTerminal::EndOfStream))
+        .collect::<Vec<_>>();
+
+      prop_assert!(
+        start_firsts.contains(&sentence[0]),
+        "{:?} is not in FIRST(start) for accepted input {:?}",
+        sentence[0],
+        input
+      );
+
+      for pair in sentence.windows(2) {
+        prop_assert!(
+          term_follows
+            .get(&pair[0])
+            .is_some_and(|follows| follows.contains(&pair[1])),
+          "{:?} is not predicted to follow {:?} for accepted input {:?}",
+          pair[1],
+          pair[0],
+          input
+        );
+      }
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_follow_through_nullable_suffix() {
+    // Regression: FOLLOW(n1) must include t1 by looking past the nullable n2.
+    let g = grammar_from_spec(&[
+      vec![vec![Err(1), Err(2), Ok(1)]],
+      vec![vec![Ok(0)]],
+      vec![vec![]],
+    ])
+    .unwrap();
+    assert!(accepts(&g, &[0, 1]));
+    check_predictions(&g, &[vec![0, 1]]).unwrap();
+  }
+
+  #[test]
+  fn test_nullable_recursion() {
+    // Regression: Earley states at different positions of the same
+    // production must not be merged.
+    let g = grammar_from_spec(&[vec![vec![], vec![Err(0)]]]).unwrap();
+    assert!(accepts(&g, &[]));
+    assert!(!accepts(&g, &[0]));
+  }
+
+  proptest! {
+    #![proptest_config(ProptestConfig {
+      cases: 200,
+      max_global_rejects: 4096,
+      ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn test_analyses_agree_with_earley(
+      g in grammar_strategy(),
+      inputs in vec(vec(0..NUM_TERMS, 0..=5), 16),
+    ) {
+      check_predictions(&g, &inputs)?;
+    }
+  }
+}
@@ -24,10 +24,10 @@ use {
 #[derive(Derivative)]
 #[derivative(
   Clone(bound = ""),
-  Eq(bound = "NT: Ord"),
-  PartialEq(bound = "NT: Ord"),
-  Ord(bound = "NT: Ord"),
-  PartialOrd(bound = "NT: Ord"),
+  Eq(bound = "NT: Ord, AK: Ord"),
+  PartialEq(bound = "NT: Ord, AK: Ord"),
+  Ord(bound = "NT: Ord, AK: Ord"),
+  PartialOrd(bound = "NT: Ord, AK: Ord"),
   Debug(bound = "T: std::fmt::Debug, NT: std::fmt::Debug")
 )]
 struct EarleyStateKey<'a, T, NT, AK, AV> {
@@ -50,17 +50,17 @@ where
       .prod_state
       .next_elem()
       .and_then(|elem| elem.as_term())
-      .map_or(false, StreamTerminal::is_eos)
+      .is_some_and(StreamTerminal::is_eos)
   }
 }
 
 #[derive(Derivative)]
 #[derivative(
   Clone(bound = ""),
-  Eq(bound = "NT: Ord"),
-  PartialEq(bound = "NT: Ord"),
-  Ord(bound = "NT: Ord"),
-  PartialOrd(bound = "NT: Ord")
+  Eq(bound = "NT: Ord, AK: Ord"),
+  PartialEq(bound = "NT: Ord, AK: Ord"),
+  Ord(bound = "NT: Ord, AK: Ord"),
+  PartialOrd(bound = "NT: Ord, AK: Ord")
 )]
 pub struct EarleyState<'a, T, NT, AK, AV, V> {
   key: EarleyStateKey<'a, T, NT, AK, AV>,
@@ -184,7 +184,6 @@ where
 
       origin_state
         .states()
-        .into_iter()
         .filter_map(|state| {
           state
             .prod_state()
@@ -224,6 +223,7 @@ where
   }
 }
 
+#[allow(clippy::type_complexity)]
 pub struct EarleyStateSet<'a, T, NT, AK, AV, V> {
   states:
     BTreeMap<EarleyStateKey<'a, T, NT, AK, AV>, Vector<Node<'a, T, AK, V>>>,
@@ -314,7 +314,6 @@ where
     EarleyStateSet::from_states(
       self
         .states()
-        .into_iter()
         .filter_map(|state| state.scan(tree_handle, token)),
     )
   }
@@ -0,0 +1,445 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shared packed parse forests, which represent every parse of an ambiguous
+//! input at once.
+//!
+//! Each node of the forest is labeled with a symbol and the span of the input
+//! it derives, so subtrees that are shared between parses are only stored
+//! once. A nonterminal node has one packed alternative per distinct way it
+//! derives its span, so a node with more than one alternative is a point of
+//! ambiguity.
+//!
+//! A cyclic grammar (one where a nonterminal can derive itself) has inputs
+//! with infinitely many parses, which show up as cycles in the forest. The
+//! individual trees of a forest are only enumerated up to these cycles: no
+//! node of a tree appears again inside its own subtree, so an alternative is
+//! skipped wherever it would close a cycle on the path from the root.
+
+use {crate::grammar::ProdKey, std::collections::BTreeSet};
+
+/// A node in a `ParseForest`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ForestNode<T, NT, AK> {
+  /// The terminal at the given position of the input.
+  Leaf { terminal: T, position: usize },
+  /// A nonterminal that derives the input from `start` to `end`, in each of
+  /// the ways given by `alts`.
+  Symbol {
+    head: NT,
+    start: usize,
+    end: usize,
+    alts: Vec<Packed<NT, AK>>,
+  },
+}
+
+/// One derivation of a nonterminal node: a production, and the nodes its
+/// elements derive, in order.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Packed<NT, AK> {
+  pub prod: ProdKey<NT, AK>,
+  pub children: Vec<usize>,
+}
+
+/// A shared packed parse forest. Nodes are identified by their index.
+#[derive(Clone, Debug)]
+pub struct ParseForest<T, NT, AK> {
+  nodes: Vec<ForestNode<T, NT, AK>>,
+  root: usize,
+}
+
+impl<T, NT, AK> ParseForest<T, NT, AK> {
+  pub(crate) fn new(nodes: Vec<ForestNode<T, NT, AK>>, root: usize) -> Self {
+    ParseForest { nodes, root }
+  }
+
+  /// The node of the start nonterminal, spanning the whole input.
+  pub fn root(&self) -> usize {
+    self.root
+  }
+
+  pub fn node(&self, id: usize) -> &ForestNode<T, NT, AK> {
+    &self.nodes[id]
+  }
+
+  pub fn num_nodes(&self) -> usize {
+    self.nodes.len()
+  }
+
+  /// Returns the nonterminal nodes below the root that have more than one
+  /// alternative.
+  pub fn ambiguous_nodes(&self) -> BTreeSet<usize> {
+    self
+      .reachable()
+      .into_iter()
+      .filter(|&id| match &self.nodes[id] {
+        ForestNode::Leaf { .. } => false,
+        ForestNode::Symbol { alts, .. } => alts.len() > 1,
+      })
+      .collect()
+  }
+
+  /// Returns true if some node below the root can derive itself, so that the
+  /// input has infinitely many parses.
+  ///
+  /// The parser may leave nodes in the forest that the root does not use,
+  /// and cycles among those nodes do not count.
+  pub fn is_cyclic(&self) -> bool {
+    let components = self.components();
+    self
+      .reachable()
+      .into_iter()
+      .any(|id| match &self.nodes[id] {
+        ForestNode::Leaf { .. } => false,
+        ForestNode::Symbol { alts, .. } => alts
+          .iter()
+          .flat_map(|alt| alt.children.iter())
+          .any(|child| components[*child] == components[id]),
+      })
+  }
+
+  /// Returns the number of trees yielded by `iter_trees`, saturating at
+  /// `u64::MAX`.
+  pub fn count_trees(&self) -> u64 {
+    TreeCounter::new(self).count(self.root)
+  }
+
+  /// Returns an iterator over each parse tree in the forest. Each tree is
+  /// only built when the iterator reaches it.
+  pub fn iter_trees(&self) -> impl Iterator<Item = ParseTree<'_, T, NT, AK>> {
+    let mut counter = TreeCounter::new(self);
+    (0..counter.count(self.root))
+      .map(move |index| counter.tree_at(self.root, index))
+  }
+
+  /// Returns the nodes that are the root or below it.
+  fn reachable(&self) -> BTreeSet<usize> {
+    let mut seen = BTreeSet::new();
+    let mut stack = vec![self.root];
+    while let Some(id) = stack.pop() {
+      if !seen.insert(id) {
+        continue;
+      }
+      if let ForestNode::Symbol { alts, .. } = &self.nodes[id] {
+        stack.extend(alts.iter().flat_map(|alt| alt.children.iter()));
+      }
+    }
+    seen
+  }
+
+  /// Returns the strongly connected component of each node, using Tarjan's
+  /// algorithm.
+  fn components(&self) -> Vec<usize> {
+    struct Tarjan<'f, T, NT, AK> {
+      forest: &'f ParseForest<T, NT, AK>,
+      indexes: Vec<Option<usize>>,
+      lowlinks: Vec<usize>,
+      stack: Vec<usize>,
+      on_stack: Vec<bool>,
+      components: Vec<usize>,
+      next_index: usize,
+      next_component: usize,
+    }
+
+    impl<T, NT, AK> Tarjan<'_, T, NT, AK> {
+      fn visit(&mut self, id: usize) {
+        let index = self.next_index;
+        self.next_index += 1;
+        self.indexes[id] = Some(index);
+        self.lowlinks[id] = index;
+        self.stack.push(id);
+        self.on_stack[id] = true;
+
+        if let ForestNode::Symbol { alts, .. } = &self.forest.nodes[id] {
+          for child in alts.iter().flat_map(|alt| alt.children.iter()) {
+            match self.indexes[*child] {
+              None => {
+                self.visit(*child);
+                self.lowlinks[id] =
+                  self.lowlinks[id].min(self.lowlinks[*child]);
+              }
+              Some(child_index) if self.on_stack[*child] => {
+                self.lowlinks[id] = self.lowlinks[id].min(child_index);
+              }
+              Some(_) => {}
+            }
+          }
+        }
+
+        if self.lowlinks[id] == index {
+          while let Some(member) = self.stack.pop() {
+            self.on_stack[member] = false;
+            self.components[member] = self.next_component;
+            if member == id {
+              break;
+            }
+          }
+          self.next_component += 1;
+        }
+      }
+    }
+
+    let len = self.nodes.len();
+    let mut tarjan = Tarjan {
+      forest: self,
+      indexes: vec![None; len],
+      lowlinks: vec![0; len],
+      stack: Vec::new(),
+      on_stack: vec![false; len],
+      components: vec![0; len],
+      next_index: 0,
+      next_component: 0,
+    };
+    for id in 0..len {
+      if tarjan.indexes[id].is_none() {
+        tarjan.visit(id);
+      }
+    }
+    tarjan.components
+  }
+}
+
+/// Counts and builds the trees of a forest, skipping the alternatives that
+/// would put a node inside its own subtree.
+///
+/// The number of trees of a node depends on which nodes are on the path to
+/// it, but only through the nodes of its own strongly connected component.
+/// Counts are only cached for nodes reached with none of those on the path,
+/// so the work is only repeated inside cycles.
+struct TreeCounter<'f, T, NT, AK> {
+  forest: &'f ParseForest<T, NT, AK>,
+  components: Vec<usize>,
+  on_path: Vec<bool>,
+  /// The number of nodes of each component that are on the path.
+  path_in_component: Vec<usize>,
+  counts: Vec<Option<u64>>,
+}
+
+impl<'f, T, NT, AK> TreeCounter<'f, T, NT, AK> {
+  fn new(forest: &'f ParseForest<T, NT, AK>) -> Self {
+    let len = forest.nodes.len();
+    TreeCounter {
+      forest,
+      components: forest.components(),
+      on_path: vec![false; len],
+      path_in_component: vec![0; len],
+      counts: vec![None; len],
+    }
+  }
+
+  fn set_on_path(&mut self, id: usize, on_path: bool) {
+    self.on_path[id] = on_path;
+    let in_component = &mut self.path_in_component[self.components[id]];
+    if on_path {
+      *in_component += 1;
+    } else {
+      *in_component -= 1;
+    }
+  }
+
+  /// Returns the number of trees of `id` below the current path.
+  fn count(&mut self, id: usize) -> u64 {
+    if self.on_path[id] {
+      return 0;
+    }
+    let alts = match &self.forest.nodes[id] {
+      ForestNode::Leaf { .. } => return 1,
+      ForestNode::Symbol { alts, .. } => alts,
+    };
+    let cacheable = self.path_in_component[self.components[id]] == 0;
+    if let (true, Some(count)) = (cacheable, self.counts[id]) {
+      return count;
+    }
+
+    self.set_on_path(id, true);
+    let mut count = 0u64;
+    for alt in alts {
+      count = count.saturating_add(self.alt_count(alt));
+    }
+    self.set_on_path(id, false);
+
+    if cacheable {
+      self.counts[id] = Some(count);
+    }
+    count
+  }
+
+  fn alt_count(&mut self, alt: &Packed<NT, AK>) -> u64 {
+    let mut count = 1u64;
+    for child in &alt.children {
+      count = count.saturating_mul(self.count(*child));
+    }
+    count
+  }
+
+  /// Returns the `index`th tree of `id` below the current path, in the order
+  /// where the earlier alternatives come first, and the first child varies
+  /// fastest.
+  fn tree_at(&mut self, id: usize, mut index: u64) -> ParseTree<'f, T, NT, AK> {
+    let alts = match &self.forest.nodes[id] {
+      ForestNode::Leaf { terminal, position } => {
+        return ParseTree::Leaf {
+          terminal,
+          position: *position,
+        }
+      }
+      ForestNode::Symbol { alts, .. } => alts,
+    };
+
+    self.set_on_path(id, true);
+    for alt in alts {
+      let alt_count = self.alt_count(alt);
+      if index >= alt_count {
+        index -= alt_count;
+        continue;
+      }
+      let children = alt
+        .children
+        .iter()
+        .map(|child| {
+          let child_count = self.count(*child);
+          let child_index = index % child_count;
+          index /= child_count;
+          self.tree_at(*child, child_index)
+        })
+        .collect();
+      self.set_on_path(id, false);
+      return ParseTree::Node {
+        prod: &alt.prod,
+        children,
+      };
+    }
+    unreachable!("tree indexes are less than the count of their node")
+  }
+}
+
+/// A single parse tree, borrowed from a `ParseForest`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ParseTree<'f, T, NT, AK> {
+  Leaf {
+    terminal: &'f T,
+    position: usize,
+  },
+  Node {
+    prod: &'f ProdKey<NT, AK>,
+    children: Vec<ParseTree<'f, T, NT, AK>>,
+  },
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::{examples, Terminal},
+    parsers::{glr::parse, lalr::Lr0Automaton},
+  };
+
+  fn terms(names: &[&str]) -> Vec<Terminal> {
+    names.iter().map(|name| Terminal::new(name)).collect()
+  }
+
+  /// Returns the number of children of each node of the tree, in preorder.
+  fn shape<T, NT, AK>(tree: &ParseTree<T, NT, AK>) -> Vec<usize> {
+    match tree {
+      ParseTree::Leaf { .. } => vec![0],
+      ParseTree::Node { children, .. } => {
+        let mut result = vec![children.len()];
+        result.extend(children.iter().flat_map(shape));
+        result
+      }
+    }
+  }
+
+  #[test]
+  fn test_ambiguous_trees() {
+    let g =
+      examples::from_rules(&[("expr", &[&["expr", "PLUS", "expr"], &["NUM"]])]);
+    let automaton = Lr0Automaton::new(&g);
+
+    let forest =
+      parse(&automaton, terms(&["NUM", "PLUS", "NUM", "PLUS", "NUM"])).unwrap();
+    assert_eq!(forest.count_trees(), 2);
+    assert!(!forest.is_cyclic());
+    assert_eq!(
+      forest.ambiguous_nodes(),
+      std::iter::once(forest.root()).collect()
+    );
+
+    let shapes = forest.iter_trees().map(|t| shape(&t)).collect::<Vec<_>>();
+    let left = vec![3, 3, 1, 0, 0, 1, 0, 0, 1, 0];
+    let right = vec![3, 1, 0, 0, 3, 1, 0, 0, 1, 0];
+    assert_eq!(shapes.len(), 2);
+    assert!(shapes.contains(&left));
+    assert!(shapes.contains(&right));
+
+    // The number of ways to parenthesize a sum of five terms.
+    let forest = parse(
+      &automaton,
+      terms(&[
+        "NUM", "PLUS", "NUM", "PLUS", "NUM", "PLUS", "NUM", "PLUS", "NUM",
+      ]),
+    )
+    .unwrap();
+    assert_eq!(forest.count_trees(), 14);
+    let trees = forest.iter_trees().collect::<Vec<_>>();
+    assert_eq!(trees.len(), 14);
+    for (i, tree) in trees.iter().enumerate() {
+      assert!(!trees[..i].contains(tree));
+    }
+  }
+
+  #[test]
+  fn test_cyclic_forest() {
+    let g = examples::from_rules(&[("s", &[&["s"], &["X"]])]);
+    let automaton = Lr0Automaton::new(&g);
+    let forest = parse(&automaton, terms(&["X"])).unwrap();
+    assert!(forest.is_cyclic());
+    assert_eq!(forest.count_trees(), 1);
+    assert_eq!(
+      forest.iter_trees().map(|t| shape(&t)).collect::<Vec<_>>(),
+      vec![vec![1, 0]]
+    );
+
+    // A tree may pass through a cycle as long as it does not close it.
+    let g = examples::from_rules(&[
+      ("a", &[&["b"], &["X"]]),
+      ("b", &[&["a"], &["X"]]),
+    ]);
+    let automaton = Lr0Automaton::new(&g);
+    let forest = parse(&automaton, terms(&["X"])).unwrap();
+    assert!(forest.is_cyclic());
+    assert_eq!(forest.count_trees(), 2);
+    let shapes = forest.iter_trees().map(|t| shape(&t)).collect::<Vec<_>>();
+    assert_eq!(shapes.len(), 2);
+    assert!(shapes.contains(&vec![1, 0]));
+    assert!(shapes.contains(&vec![1, 1, 0]));
+  }
+
+  #[test]
+  fn test_unused_cycle() {
+    // The parser builds the cyclic `c` and `d` nodes for `X` before `Y`
+    // rules them out, but the root does not use them.
+    let g = examples::from_rules(&[
+      ("s", &[&["A", "e", "Y"], &["A", "c", "Z"]]),
+      ("c", &[&["d"], &["X"]]),
+      ("d", &[&["c"], &["X"]]),
+      ("e", &[&["X"]]),
+    ]);
+    let automaton = Lr0Automaton::new(&g);
+    let forest = parse(&automaton, terms(&["A", "X", "Y"])).unwrap();
+    assert!(!forest.is_cyclic());
+    assert_eq!(forest.count_trees(), 1);
+    assert!(forest.ambiguous_nodes().is_empty());
+  }
+}
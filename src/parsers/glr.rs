@@ -0,0 +1,438 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A generalized LR parser, which parses with any context-free grammar.
+//!
+//! The parser runs the LR(0) automaton of the grammar, and whenever more
+//! than one action is possible, it takes all of them. The resulting stacks
+//! are kept in a graph-structured stack (GSS), where stacks that reach the
+//! same state at the same position are merged, so the number of stacks stays
+//! bounded by the number of states. The parses are collected in a
+//! `ParseForest`.
+//!
+//! Productions can be gated on semantic predicates with
+//! `parse_with_predicates`, to handle languages that are mildly context
+//! sensitive, such as C's typedef names. Only this parser takes predicates,
+//! since it is the one that keeps conflicting actions until the input rules
+//! them out.
+
+use {
+  crate::{
+    grammar::{Elem, ProdKey},
+    parsers::{
+      forest::{ForestNode, Packed, ParseForest},
+      lalr::Lr0Automaton,
+    },
+    start_grammar::StreamTerminal,
+  },
+  std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+  },
+};
+
+#[derive(thiserror::Error, Clone, PartialEq, Eq, Debug)]
+pub enum GlrError<T> {
+  #[error("Unexpected token {token:?} at position {position}")]
+  UnexpectedToken {
+    position: usize,
+    token: StreamTerminal<T>,
+  },
+}
+
+struct GssNode {
+  state: usize,
+  position: usize,
+  /// The nodes this node was pushed on, each with the forest node of the
+  /// symbol between them.
+  edges: Vec<(usize, usize)>,
+}
+
+struct ForestBuilder<'a, T, NT, AK> {
+  nodes: Vec<ForestNode<T, NT, AK>>,
+  symbols: BTreeMap<(&'a NT, usize, usize), usize>,
+  packed: BTreeSet<(usize, usize, Vec<usize>)>,
+}
+
+impl<'a, T, NT, AK> ForestBuilder<'a, T, NT, AK>
+where
+  NT: Ord + Clone,
+{
+  fn leaf(&mut self, terminal: T, position: usize) -> usize {
+    self.nodes.push(ForestNode::Leaf { terminal, position });
+    self.nodes.len() - 1
+  }
+
+  /// Returns the node of `head` spanning `start..end`, and adds the given
+  /// derivation to it. Also returns true if the derivation is new.
+  fn derive(
+    &mut self,
+    (head, start, end): (&'a NT, usize, usize),
+    (prod_index, prod): (usize, Packed<NT, AK>),
+  ) -> (usize, bool) {
+    let nodes = &mut self.nodes;
+    let id = *self.symbols.entry((head, start, end)).or_insert_with(|| {
+      nodes.push(ForestNode::Symbol {
+        head: head.clone(),
+        start,
+        end,
+        alts: Vec::new(),
+      });
+      nodes.len() - 1
+    });
+
+    if !self.packed.insert((id, prod_index, prod.children.clone())) {
+      return (id, false);
+    }
+    if let ForestNode::Symbol { alts, .. } = &mut self.nodes[id] {
+      alts.push(prod);
+    }
+    (id, true)
+  }
+}
+
+/// A semantic predicate on a production. It is called with the whole input,
+/// and the range of it that a reduction by the production would span.
+pub type Predicate<'p, T> = Box<dyn Fn(&[T], Range<usize>) -> bool + 'p>;
+
+/// The semantic predicates of some productions, by production.
+pub type Predicates<'p, T, NT, AK> =
+  BTreeMap<ProdKey<NT, AK>, Predicate<'p, T>>;
+
+/// Parses `input` with the automaton, and returns the forest of every parse
+/// of it.
+///
+/// The error reports the first token at which every stack failed.
+pub fn parse<'a, T, NT, AK, AV>(
+  automaton: &Lr0Automaton<'a, T, NT, AK, AV>,
+  input: impl IntoIterator<Item = T>,
+) -> Result<ParseForest<T, NT, AK>, GlrError<T>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Clone,
+{
+  parse_filtered(automaton, input, |_, _, _| true)
+}
+
+/// Like `parse`, but only reduces by a production with a predicate in
+/// `predicates` where the predicate returns true. Other reductions are
+/// pruned, along with the stacks that depend on them.
+///
+/// Productions without a predicate are always reduced.
+pub fn parse_with_predicates<'a, T, NT, AK, AV>(
+  automaton: &Lr0Automaton<'a, T, NT, AK, AV>,
+  input: impl IntoIterator<Item = T>,
+  predicates: &Predicates<'_, T, NT, AK>,
+) -> Result<ParseForest<T, NT, AK>, GlrError<T>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  parse_filtered(automaton, input, |prod_key, input, range| {
+    predicates
+      .get(prod_key)
+      .is_none_or(|predicate| predicate(input, range))
+  })
+}
+
+/// Parses `input`, only reducing where `allow` returns true for the
+/// production, the input, and the span of the reduction.
+fn parse_filtered<'a, T, NT, AK, AV>(
+  automaton: &Lr0Automaton<'a, T, NT, AK, AV>,
+  input: impl IntoIterator<Item = T>,
+  allow: impl Fn(&ProdKey<NT, AK>, &[T], Range<usize>) -> bool,
+) -> Result<ParseForest<T, NT, AK>, GlrError<T>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Clone,
+{
+  let input = input.into_iter().collect::<Vec<_>>();
+  let reductions = (0..automaton.num_states())
+    .map(|state| {
+      automaton
+        .closure(automaton.kernel(state))
+        .into_iter()
+        .filter(|item| automaton.next_elem(*item).is_none())
+        .collect::<Vec<_>>()
+    })
+    .collect::<Vec<_>>();
+
+  let mut gss = vec![GssNode {
+    state: 0,
+    position: 0,
+    edges: Vec::new(),
+  }];
+  let mut forest = ForestBuilder {
+    nodes: Vec::new(),
+    symbols: BTreeMap::new(),
+    packed: BTreeSet::new(),
+  };
+  let mut level: BTreeMap<usize, usize> = std::iter::once((0, 0)).collect();
+
+  for position in 0..=input.len() {
+    // Perform every reduction until no new nodes, edges or derivations are
+    // found. Reductions may enable more reductions, including through edges
+    // that are added to nodes that were already reduced.
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for top in level.values().copied().collect::<Vec<_>>() {
+        for &(prod_index, len) in &reductions[gss[top].state] {
+          let prod = automaton.prod(prod_index);
+          let prod_key = prod.prod_key();
+          for (base, mut children) in paths(&gss, top, len) {
+            let start = gss[base].position;
+            if !allow(&prod_key, &input, start..position) {
+              continue;
+            }
+            children.reverse();
+            let (symbol, new_derivation) = forest.derive(
+              (prod.head(), start, position),
+              (
+                prod_index,
+                Packed {
+                  prod: prod_key.clone(),
+                  children,
+                },
+              ),
+            );
+            changed |= new_derivation;
+
+            let next = match automaton
+              .transition(gss[base].state, &Elem::NonTerm(prod.head()))
+            {
+              Some(next) => next,
+              None => continue,
+            };
+            let node = *level.entry(next).or_insert_with(|| {
+              gss.push(GssNode {
+                state: next,
+                position,
+                edges: Vec::new(),
+              });
+              changed = true;
+              gss.len() - 1
+            });
+            if !gss[node].edges.iter().any(|(target, _)| *target == base) {
+              gss[node].edges.push((base, symbol));
+              changed = true;
+            }
+          }
+        }
+      }
+    }
+
+    let token = match input.get(position) {
+      Some(token) => token,
+      None => break,
+    };
+    let leaf = forest.leaf(token.clone(), position);
+    let mut next_level = BTreeMap::new();
+    for (state, top) in &level {
+      let next =
+        automaton
+          .transitions(*state)
+          .find_map(|(elem, next)| match elem {
+            Elem::Term(t) if *t == token => Some(next),
+            _ => None,
+          });
+      if let Some(next) = next {
+        let node = *next_level.entry(next).or_insert_with(|| {
+          gss.push(GssNode {
+            state: next,
+            position: position + 1,
+            edges: Vec::new(),
+          });
+          gss.len() - 1
+        });
+        gss[node].edges.push((*top, leaf));
+      }
+    }
+
+    if next_level.is_empty() {
+      return Err(GlrError::UnexpectedToken {
+        position,
+        token: StreamTerminal::Term(token.clone()),
+      });
+    }
+    level = next_level;
+  }
+
+  let root = level
+    .get(&automaton.accept_state())
+    .and_then(|node| {
+      gss[*node]
+        .edges
+        .iter()
+        .find(|(target, _)| *target == 0)
+        .map(|(_, symbol)| *symbol)
+    })
+    .ok_or(GlrError::UnexpectedToken {
+      position: input.len(),
+      token: StreamTerminal::EndOfStream,
+    })?;
+  Ok(ParseForest::new(forest.nodes, root))
+}
+
+/// Returns every path of `len` edges down from `top`, as the node at the end
+/// of the path and the forest nodes of the edges, from the top down.
+fn paths(gss: &[GssNode], top: usize, len: usize) -> Vec<(usize, Vec<usize>)> {
+  if len == 0 {
+    return vec![(top, Vec::new())];
+  }
+  let mut result = Vec::new();
+  for (next, symbol) in &gss[top].edges {
+    for (base, rest) in paths(gss, *next, len - 1) {
+      let mut symbols = vec![*symbol];
+      symbols.extend(rest);
+      result.push((base, symbols));
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::{examples, NonTerminal, Terminal},
+    utils::Name,
+  };
+
+  fn terms(names: &[&str]) -> Vec<Terminal> {
+    names.iter().map(|name| Terminal::new(name)).collect()
+  }
+
+  #[test]
+  fn test_ambiguous_sum() {
+    let g =
+      examples::from_rules(&[("expr", &[&["expr", "PLUS", "expr"], &["NUM"]])]);
+    let automaton = Lr0Automaton::new(&g);
+
+    let forest =
+      parse(&automaton, terms(&["NUM", "PLUS", "NUM", "PLUS", "NUM"])).unwrap();
+    match forest.node(forest.root()) {
+      ForestNode::Symbol {
+        head,
+        start,
+        end,
+        alts,
+      } => {
+        assert_eq!(head, &NonTerminal::new("expr"));
+        assert_eq!((*start, *end), (0, 5));
+        // `(1 + 1) + 1` and `1 + (1 + 1)`.
+        assert_eq!(alts.len(), 2);
+      }
+      node => panic!("unexpected root {:?}", node),
+    }
+
+    let forest = parse(&automaton, terms(&["NUM"])).unwrap();
+    assert!(matches!(
+      forest.node(forest.root()),
+      ForestNode::Symbol { alts, .. } if alts.len() == 1
+    ));
+
+    assert_eq!(
+      parse(&automaton, terms(&["NUM", "NUM"])).unwrap_err(),
+      GlrError::UnexpectedToken {
+        position: 1,
+        token: StreamTerminal::Term(Terminal::new("NUM")),
+      }
+    );
+    assert_eq!(
+      parse(&automaton, terms(&["NUM", "PLUS"])).unwrap_err(),
+      GlrError::UnexpectedToken {
+        position: 2,
+        token: StreamTerminal::EndOfStream,
+      }
+    );
+  }
+
+  #[test]
+  fn test_nullable_and_paren() {
+    let g = examples::make_paren();
+    let automaton = Lr0Automaton::new(&g);
+    assert!(parse(&automaton, terms(&["LPAREN", "RPAREN"])).is_ok());
+    assert!(parse(
+      &automaton,
+      terms(&["LPAREN", "LPAREN", "RPAREN", "LPAREN", "RPAREN", "RPAREN"])
+    )
+    .is_ok());
+    assert!(parse(&automaton, terms(&["LPAREN"])).is_err());
+
+    // Hidden left recursion through a nullable prefix.
+    let g = examples::from_rules(&[
+      ("s", &[&["opt", "s", "X"], &["Y"]]),
+      ("opt", &[&[]]),
+    ]);
+    let automaton = Lr0Automaton::new(&g);
+    assert!(parse(&automaton, terms(&["Y", "X", "X"])).is_ok());
+    assert!(parse(&automaton, terms(&["X"])).is_err());
+  }
+
+  #[test]
+  fn test_predicates() {
+    // `ID TIMES ID SEMI` is either a declaration, with the type `t ::= ID
+    // TIMES`, or a product, with the variable `v ::= ID`, so it is ambiguous
+    // without predicates.
+    let g = examples::from_rules(&[
+      ("s", &[&["t", "ID", "SEMI"], &["v", "TIMES", "ID", "SEMI"]]),
+      ("t", &[&["ID", "TIMES"]]),
+      ("v", &[&["ID"]]),
+    ]);
+    let automaton = Lr0Automaton::new(&g);
+    let input = terms(&["ID", "TIMES", "ID", "SEMI"]);
+    let root_alts =
+      |forest: &ParseForest<Terminal, NonTerminal, Name>| match forest
+        .node(forest.root())
+      {
+        ForestNode::Symbol { alts, .. } => alts
+          .iter()
+          .map(|alt| alt.prod.action_key().clone())
+          .collect::<Vec<_>>(),
+        node => panic!("unexpected root {:?}", node),
+      };
+    let forest = parse(&automaton, input.clone()).unwrap();
+    assert_eq!(root_alts(&forest).len(), 2);
+
+    // `t` is a type name, and `v` a variable, depending on whether the
+    // lexeme of the first identifier was declared as a type.
+    let parse_lexemes = |lexemes: &'static [&'static str]| {
+      let is_type = move |range: Range<usize>| lexemes[range.start] == "Foo";
+      let mut predicates: Predicates<Terminal, NonTerminal, Name> =
+        BTreeMap::new();
+      predicates.insert(
+        ProdKey::new(NonTerminal::new("t"), Name::new("p0")),
+        Box::new(move |_, range| is_type(range)),
+      );
+      predicates.insert(
+        ProdKey::new(NonTerminal::new("v"), Name::new("p0")),
+        Box::new(move |_, range| !is_type(range)),
+      );
+      parse_with_predicates(&automaton, input.clone(), &predicates)
+        .map(|forest| root_alts(&forest))
+    };
+    assert_eq!(
+      parse_lexemes(&["Foo", "*", "x", ";"]),
+      Ok(vec![Name::new("p0")])
+    );
+    assert_eq!(
+      parse_lexemes(&["a", "*", "x", ";"]),
+      Ok(vec![Name::new("p1")])
+    );
+  }
+}
@@ -1,3 +1,11 @@
+#[cfg(feature = "binary-tables")]
+mod binary;
+mod compress;
+mod driver;
+mod incremental;
+mod lr0;
+mod table;
+
 use std::collections::{btree_map, BTreeMap, BTreeSet};
 
 use crate::{
@@ -9,6 +17,22 @@ use crate::{
   utils::{change_iter, change_loop, CollectMap},
 };
 
+#[cfg(feature = "binary-tables")]
+pub use binary::DecodeError;
+pub use compress::CompressedTable;
+pub use driver::{
+  parse_from_start, parse_to_tree, parse_with_actions, parse_with_matcher,
+  parse_with_skip, ActionTable, LrParseError,
+};
+pub use incremental::{
+  parse_syntax_tree, reparse, Reparse, SyntaxNode, TokenEdit,
+};
+pub use lr0::Lr0Automaton;
+pub use table::{
+  build_lalr_table, build_lalr_table_with_resolver, Action, Conflict,
+  ConflictItem, Conflicts, LalrTable, ResolvedConflict,
+};
+
 fn shuffle_iter<K, V>(
   iter: impl Iterator<Item = (K, V)>,
 ) -> impl Iterator<Item = (K, BTreeSet<V>)>
@@ -40,6 +64,7 @@ impl<'a, T, NT, AK, AV> Ord for ParseState<'a, T, NT, AK, AV>
 where
   T: Ord,
   NT: Ord,
+  AK: Ord,
 {
   fn cmp(&self, other: &Self) -> std::cmp::Ordering {
     self.prods.cmp(&other.prods)
@@ -50,6 +75,7 @@ impl<'a, T, NT, AK, AV> PartialEq for ParseState<'a, T, NT, AK, AV>
 where
   T: Ord,
   NT: Ord,
+  AK: Ord,
 {
   fn eq(&self, other: &Self) -> bool {
     self.prods == other.prods
@@ -60,6 +86,7 @@ impl<'a, T, NT, AK, AV> Eq for ParseState<'a, T, NT, AK, AV>
 where
   T: Ord,
   NT: Ord,
+  AK: Ord,
 {
 }
 
@@ -67,6 +94,7 @@ impl<'a, T, NT, AK, AV> PartialOrd for ParseState<'a, T, NT, AK, AV>
 where
   T: Ord,
   NT: Ord,
+  AK: Ord,
 {
   fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
     Some(self.cmp(other))
@@ -91,7 +119,7 @@ where
       for (prod, la) in prods.iter() {
         if let Some(Elem::NonTerm(nt)) = prod.next_elem() {
           let new_la = match prod.offset_elem(1) {
-            Some(Elem::NonTerm(nt)) => firsts.get(nt).unwrap().clone(),
+            Some(Elem::NonTerm(nt)) => firsts.get(nt).unwrap().to_set(),
             Some(Elem::Term(t)) => std::iter::once(t.clone()).collect(),
             None => la.clone(),
           };
@@ -0,0 +1,356 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A compact binary encoding of `LalrTable`s.
+//!
+//! Symbols and productions are encoded by their index in the grammar's
+//! `SymbolTable` and production order, so the encoding is only meaningful
+//! together with the grammar the table was built from. A fingerprint of the
+//! grammar's productions is included, so decoding with a different grammar
+//! is detected. All other integers are encoded as LEB128 varints.
+
+use {
+  super::{Action, LalrTable},
+  crate::{
+    grammar::{
+      passes::{symbols::SymbolTable, PassContext},
+      Elem, Grammar,
+    },
+    start_grammar::StreamTerminal,
+    utils::FnvHasher,
+  },
+  std::{collections::BTreeMap, hash::Hasher},
+};
+
+const MAGIC: &[u8; 4] = b"BLT1";
+
+const TAG_SHIFT: u8 = 0;
+const TAG_REDUCE: u8 = 1;
+const TAG_ACCEPT: u8 = 2;
+
+#[derive(thiserror::Error, Clone, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+  #[error("The input is not an encoded LALR table")]
+  BadMagic,
+  #[error("The input ended unexpectedly")]
+  Truncated,
+  #[error("The table was encoded for a different grammar")]
+  GrammarMismatch,
+  #[error("Invalid {kind} index {index}")]
+  InvalidIndex { kind: &'static str, index: usize },
+  #[error("Invalid action tag {0}")]
+  InvalidTag(u8),
+  #[error("The input has {0} unused trailing bytes")]
+  TrailingBytes(usize),
+  #[error("An encoded integer does not fit in a usize")]
+  Overlong,
+}
+
+impl<T, NT, AK> LalrTable<T, NT, AK>
+where
+  T: Ord + Clone + 'static,
+  NT: Ord + Clone + 'static,
+  AK: Ord + Clone + 'static,
+{
+  /// Encodes this table, which must have been built from `g`.
+  ///
+  /// Resolved conflicts are not encoded, so the decoded table has no
+  /// `resolved_conflicts`.
+  pub fn serialize<AV>(&self, g: &Grammar<T, NT, AK, AV>) -> Vec<u8> {
+    let passes = PassContext::new(g);
+    let symbols = passes.get_infallible_pass::<SymbolTable<T, NT>>();
+    let prod_indexes = g
+      .prods()
+      .enumerate()
+      .map(|(i, prod)| (prod.prod_key(), i))
+      .collect::<BTreeMap<_, _>>();
+
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&fingerprint(g, &symbols).to_le_bytes());
+    write_varint(&mut out, self.num_states());
+
+    for (actions, gotos) in self.actions.iter().zip(&self.gotos) {
+      write_varint(&mut out, actions.len());
+      for (lookahead, action) in actions {
+        let lookahead = match lookahead {
+          StreamTerminal::EndOfStream => 0,
+          StreamTerminal::Term(t) => {
+            symbols.term_index(t).expect("table terminals are in g") + 1
+          }
+        };
+        write_varint(&mut out, lookahead);
+        match action {
+          Action::Shift(next) => {
+            out.push(TAG_SHIFT);
+            write_varint(&mut out, *next);
+          }
+          Action::Reduce { prod, .. } => {
+            out.push(TAG_REDUCE);
+            write_varint(&mut out, prod_indexes[prod]);
+          }
+          Action::Accept => out.push(TAG_ACCEPT),
+        }
+      }
+
+      write_varint(&mut out, gotos.len());
+      for (nt, next) in gotos {
+        let nt = symbols.nonterm_index(nt).expect("table nonterms are in g");
+        write_varint(&mut out, nt);
+        write_varint(&mut out, *next);
+      }
+    }
+
+    out
+  }
+
+  /// Decodes a table encoded by `serialize` with the same grammar.
+  pub fn deserialize<AV>(
+    bytes: &[u8],
+    g: &Grammar<T, NT, AK, AV>,
+  ) -> Result<Self, DecodeError> {
+    let passes = PassContext::new(g);
+    let symbols = passes.get_infallible_pass::<SymbolTable<T, NT>>();
+    let prods = g.prods().collect::<Vec<_>>();
+
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len())? != MAGIC {
+      return Err(DecodeError::BadMagic);
+    }
+    if reader.take(8)? != fingerprint(g, &symbols).to_le_bytes() {
+      return Err(DecodeError::GrammarMismatch);
+    }
+
+    let num_states = reader.varint()?;
+    let state_index = |index: usize| {
+      if index < num_states {
+        Ok(index)
+      } else {
+        Err(DecodeError::InvalidIndex {
+          kind: "state",
+          index,
+        })
+      }
+    };
+
+    let mut table = LalrTable {
+      actions: Vec::new(),
+      gotos: Vec::new(),
+      resolved: Vec::new(),
+    };
+    for _ in 0..num_states {
+      let mut actions = BTreeMap::new();
+      for _ in 0..reader.varint()? {
+        let lookahead = match reader.varint()? {
+          0 => StreamTerminal::EndOfStream,
+          index if index <= symbols.num_terms() => {
+            StreamTerminal::Term(symbols.term(index - 1).clone())
+          }
+          index => {
+            return Err(DecodeError::InvalidIndex {
+              kind: "terminal",
+              index,
+            })
+          }
+        };
+        let action = match reader.byte()? {
+          TAG_SHIFT => Action::Shift(state_index(reader.varint()?)?),
+          TAG_REDUCE => {
+            let index = reader.varint()?;
+            let prod = prods.get(index).ok_or(DecodeError::InvalidIndex {
+              kind: "production",
+              index,
+            })?;
+            Action::Reduce {
+              prod: prod.prod_key(),
+              len: prod.num_elements(),
+            }
+          }
+          TAG_ACCEPT => Action::Accept,
+          tag => return Err(DecodeError::InvalidTag(tag)),
+        };
+        actions.insert(lookahead, action);
+      }
+
+      let mut gotos = BTreeMap::new();
+      for _ in 0..reader.varint()? {
+        let index = reader.varint()?;
+        if index >= symbols.num_nonterms() {
+          return Err(DecodeError::InvalidIndex {
+            kind: "nonterminal",
+            index,
+          });
+        }
+        let next = state_index(reader.varint()?)?;
+        gotos.insert(symbols.nonterm(index).clone(), next);
+      }
+
+      table.actions.push(actions);
+      table.gotos.push(gotos);
+    }
+
+    if !reader.bytes.is_empty() {
+      return Err(DecodeError::TrailingBytes(reader.bytes.len()));
+    }
+    Ok(table)
+  }
+}
+
+/// Returns an FNV-1a hash of the symbol indexes of every production of `g`.
+fn fingerprint<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+  symbols: &SymbolTable<T, NT>,
+) -> u64
+where
+  T: Ord,
+  NT: Ord,
+{
+  let mut encoded = Vec::new();
+  write_varint(&mut encoded, symbols.num_terms());
+  write_varint(&mut encoded, symbols.num_nonterms());
+  for prod in g.prods() {
+    let head = symbols
+      .nonterm_index(prod.head())
+      .expect("heads are symbols");
+    let elems = symbols.index_elems(&prod);
+    write_varint(&mut encoded, head);
+    write_varint(&mut encoded, elems.len());
+    for elem in elems {
+      match elem {
+        Elem::Term(t) => write_varint(&mut encoded, 2 * t),
+        Elem::NonTerm(nt) => write_varint(&mut encoded, 2 * nt + 1),
+      }
+    }
+  }
+
+  let mut hasher = FnvHasher::default();
+  hasher.write(&encoded);
+  hasher.finish()
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+  while value >= 0x80 {
+    out.push((value as u8) | 0x80);
+    value >>= 7;
+  }
+  out.push(value as u8);
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+    if self.bytes.len() < len {
+      return Err(DecodeError::Truncated);
+    }
+    let (head, rest) = self.bytes.split_at(len);
+    self.bytes = rest;
+    Ok(head)
+  }
+
+  fn byte(&mut self) -> Result<u8, DecodeError> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn varint(&mut self) -> Result<usize, DecodeError> {
+    let mut value = 0usize;
+    let mut shift = 0;
+    loop {
+      let byte = self.byte()?;
+      let bits = usize::from(byte & 0x7f);
+      if shift >= usize::BITS || (bits << shift) >> shift != bits {
+        return Err(DecodeError::Overlong);
+      }
+      value |= bits << shift;
+      if byte & 0x80 == 0 {
+        return Ok(value);
+      }
+      shift += 7;
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::{examples, gen::bounded_sentences, Terminal},
+    parsers::lalr::build_lalr_table,
+  };
+
+  #[test]
+  fn test_round_trip() {
+    let g = examples::make_paren();
+    let table = build_lalr_table(&g).unwrap();
+    let bytes = table.serialize(&g);
+    let decoded = LalrTable::deserialize(&bytes, &g).unwrap();
+
+    assert_eq!(decoded.num_states(), table.num_states());
+    assert_eq!(decoded.actions, table.actions);
+    assert_eq!(decoded.gotos, table.gotos);
+    for sentence in bounded_sentences(&g, 8) {
+      assert!(decoded.accepts(sentence));
+    }
+    let lparen = Terminal::new("LPAREN");
+    let rparen = Terminal::new("RPAREN");
+    for input in [vec![], vec![lparen.clone()], vec![rparen, lparen]] {
+      assert_eq!(decoded.accepts(input.clone()), table.accepts(input));
+    }
+  }
+
+  #[test]
+  fn test_invalid_input() {
+    let g = examples::make_paren();
+    let bytes = build_lalr_table(&g).unwrap().serialize(&g);
+
+    assert_eq!(
+      LalrTable::deserialize(b"nope", &g).unwrap_err(),
+      DecodeError::BadMagic
+    );
+    assert_eq!(
+      LalrTable::deserialize(&bytes[..bytes.len() - 1], &g).unwrap_err(),
+      DecodeError::Truncated
+    );
+    assert_eq!(
+      LalrTable::deserialize(&bytes, &examples::make_simple()).unwrap_err(),
+      DecodeError::GrammarMismatch
+    );
+  }
+
+  #[test]
+  fn test_varint() {
+    for value in [0, 1, 0x7f, 0x80, 0x3fff, usize::MAX] {
+      let mut bytes = Vec::new();
+      write_varint(&mut bytes, value);
+      let mut reader = Reader { bytes: &bytes };
+      assert_eq!(reader.varint(), Ok(value));
+      assert!(reader.bytes.is_empty());
+    }
+
+    // Continuation bytes past the width of a usize.
+    let mut bytes = vec![0xff; 10];
+    bytes.push(0x01);
+    let mut reader = Reader { bytes: &bytes };
+    assert_eq!(reader.varint(), Err(DecodeError::Overlong));
+    // A last byte whose high bits don't fit.
+    let mut bytes = vec![0xff; 9];
+    bytes.push(0x7f);
+    let mut reader = Reader { bytes: &bytes };
+    assert_eq!(reader.varint(), Err(DecodeError::Overlong));
+
+    let mut reader = Reader { bytes: &[0x80] };
+    assert_eq!(reader.varint(), Err(DecodeError::Truncated));
+  }
+}
@@ -0,0 +1,340 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A compressed form of `LalrTable`.
+//!
+//! Two standard compressions are applied to the table:
+//!
+//! - Default reductions: the most common reduction of each state becomes
+//!   the state's default action, taken on any lookahead without another
+//!   action. A syntax error may then be found after some extra reductions,
+//!   but always before the offending token is shifted, so the same error is
+//!   reported.
+//! - Row merging: states whose actions are identical share one row. Gotos
+//!   are stored by nonterminal, with the most common target state as the
+//!   default.
+
+use {
+  super::{
+    driver::{drive, DriveTable},
+    Action, ActionTable, LalrTable, LrParseError,
+  },
+  crate::{parsers::Token, start_grammar::StreamTerminal},
+  std::collections::BTreeMap,
+};
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct ActionRow<T, NT, AK> {
+  /// The actions of the row other than the default, in lookahead order.
+  actions: Vec<(StreamTerminal<T>, Action<NT, AK>)>,
+  default: Option<Action<NT, AK>>,
+}
+
+#[derive(Clone, Debug)]
+struct GotoColumn {
+  default: usize,
+  /// The states whose goto is not the default.
+  exceptions: BTreeMap<usize, usize>,
+}
+
+/// An LALR(1) table with default reductions and merged rows, from
+/// `LalrTable::compress`.
+///
+/// It parses exactly the same inputs as the table it was built from.
+#[derive(Clone, Debug)]
+pub struct CompressedTable<T, NT, AK> {
+  /// The index in `rows` of each state's row.
+  state_rows: Vec<usize>,
+  rows: Vec<ActionRow<T, NT, AK>>,
+  gotos: BTreeMap<NT, GotoColumn>,
+}
+
+impl<T, NT, AK> LalrTable<T, NT, AK>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  /// Returns a compressed copy of this table.
+  pub fn compress(&self) -> CompressedTable<T, NT, AK> {
+    let mut row_indices = BTreeMap::new();
+    let mut rows = Vec::new();
+    let state_rows = self
+      .actions
+      .iter()
+      .map(|actions| {
+        let row = compress_row(actions);
+        *row_indices.entry(row.clone()).or_insert_with(|| {
+          rows.push(row);
+          rows.len() - 1
+        })
+      })
+      .collect();
+
+    let mut columns: BTreeMap<&NT, BTreeMap<usize, usize>> = BTreeMap::new();
+    for (state, gotos) in self.gotos.iter().enumerate() {
+      for (nt, target) in gotos {
+        columns.entry(nt).or_default().insert(state, *target);
+      }
+    }
+    let gotos = columns
+      .into_iter()
+      .map(|(nt, mut targets)| {
+        let default = most_common(targets.values())
+          .copied()
+          .expect("columns have at least one goto");
+        targets.retain(|_, target| *target != default);
+        let column = GotoColumn {
+          default,
+          exceptions: targets,
+        };
+        (nt.clone(), column)
+      })
+      .collect();
+
+    CompressedTable {
+      state_rows,
+      rows,
+      gotos,
+    }
+  }
+
+  /// The number of actions and gotos in the table.
+  pub fn num_entries(&self) -> usize {
+    let actions = self.actions.iter().map(BTreeMap::len).sum::<usize>();
+    actions + self.gotos.iter().map(BTreeMap::len).sum::<usize>()
+  }
+}
+
+/// Replaces the most common reduction in `actions`, if there is one, by a
+/// default action.
+fn compress_row<T, NT, AK>(
+  actions: &BTreeMap<StreamTerminal<T>, Action<NT, AK>>,
+) -> ActionRow<T, NT, AK>
+where
+  T: Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  let default = most_common(
+    actions
+      .values()
+      .filter(|action| matches!(action, Action::Reduce { .. })),
+  )
+  .cloned();
+  let actions = actions
+    .iter()
+    .filter(|(_, action)| Some(*action) != default.as_ref())
+    .map(|(la, action)| (la.clone(), action.clone()))
+    .collect();
+  ActionRow { actions, default }
+}
+
+/// Returns the most common value, preferring the smallest on ties.
+fn most_common<V: Ord>(values: impl Iterator<Item = V>) -> Option<V> {
+  let mut counts = BTreeMap::new();
+  for value in values {
+    *counts.entry(value).or_insert(0) += 1;
+  }
+  let max = *counts.values().max()?;
+  counts
+    .into_iter()
+    .find(|(_, count)| *count == max)
+    .map(|(v, _)| v)
+}
+
+impl<T, NT, AK> CompressedTable<T, NT, AK>
+where
+  T: Ord,
+  NT: Ord,
+{
+  /// The number of states in the table. The initial state is always 0.
+  pub fn num_states(&self) -> usize {
+    self.state_rows.len()
+  }
+
+  /// The number of distinct rows of actions, after merging.
+  pub fn num_rows(&self) -> usize {
+    self.rows.len()
+  }
+
+  /// The number of actions and gotos stored in the table, counting each
+  /// default once.
+  pub fn num_entries(&self) -> usize {
+    let actions = self
+      .rows
+      .iter()
+      .map(|row| row.actions.len() + usize::from(row.default.is_some()))
+      .sum::<usize>();
+    let gotos = self
+      .gotos
+      .values()
+      .map(|column| 1 + column.exceptions.len());
+    actions + gotos.sum::<usize>()
+  }
+
+  /// Returns the action of `state` on `lookahead`, falling back to the
+  /// state's default reduction.
+  pub fn action(
+    &self,
+    state: usize,
+    lookahead: &StreamTerminal<T>,
+  ) -> Option<&Action<NT, AK>> {
+    let row = &self.rows[*self.state_rows.get(state)?];
+    match row.actions.binary_search_by(|(la, _)| la.cmp(lookahead)) {
+      Ok(i) => Some(&row.actions[i].1),
+      Err(_) => row.default.as_ref(),
+    }
+  }
+
+  /// Returns the goto of `state` on `nt`. Unlike `LalrTable::goto`, this
+  /// may return a state even where the original table has no goto, since
+  /// the parser never looks those up.
+  pub fn goto(&self, state: usize, nt: &NT) -> Option<usize> {
+    let column = self.gotos.get(nt)?;
+    Some(
+      column
+        .exceptions
+        .get(&state)
+        .copied()
+        .unwrap_or(column.default),
+    )
+  }
+
+  /// Runs the table on the given terminals, and returns true if they form a
+  /// sentence of the grammar.
+  pub fn accepts(&self, input: impl IntoIterator<Item = T>) -> bool
+  where
+    T: Clone,
+    NT: Clone,
+    AK: Ord + Clone,
+  {
+    let tokens = input.into_iter().map(|t| Token::new(t, ()));
+    let reduce = |_: &_, _| Ok::<_, LrParseError<T, NT, AK>>(());
+    drive(self, tokens, |_, _| (), reduce, vec![0], Vec::new()).is_ok()
+  }
+
+  /// Like `parse_with_actions`, but with a compressed table.
+  pub fn parse_with_actions<V, R>(
+    &self,
+    input: impl IntoIterator<Item = Token<T, V>>,
+    actions: &ActionTable<T, NT, AK, V, R>,
+  ) -> Result<R, LrParseError<T, NT, AK>>
+  where
+    T: Clone,
+    NT: Clone,
+    AK: Ord + Clone,
+  {
+    drive(
+      self,
+      input,
+      |kind, value| actions.token(kind, value),
+      |prod, children| actions.reduce(prod, children),
+      vec![0],
+      Vec::new(),
+    )
+  }
+}
+
+impl<T, NT, AK> DriveTable<T, NT, AK> for CompressedTable<T, NT, AK>
+where
+  T: Ord + Clone,
+  NT: Ord,
+{
+  fn action(
+    &self,
+    state: usize,
+    lookahead: Option<&T>,
+  ) -> Option<&Action<NT, AK>> {
+    let lookahead = match lookahead {
+      Some(kind) => StreamTerminal::Term(kind.clone()),
+      None => StreamTerminal::EndOfStream,
+    };
+    CompressedTable::action(self, state, &lookahead)
+  }
+
+  fn goto(&self, state: usize, nt: &NT) -> Option<usize> {
+    CompressedTable::goto(self, state, nt)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::{examples, gen::bounded_sentences, Terminal},
+    parsers::lalr::{build_lalr_table, parse_with_actions},
+  };
+
+  #[test]
+  fn test_compressed_paren_table() {
+    let g = examples::make_paren();
+    let table = build_lalr_table(&g).unwrap();
+    let compressed = table.compress();
+    assert_eq!(compressed.num_states(), table.num_states());
+    assert!(compressed.num_rows() < table.num_states());
+    assert!(compressed.num_entries() < table.num_entries());
+
+    // Render each parse as the bracketing of its reductions.
+    let mut actions =
+      ActionTable::new(|kind: &Terminal, ()| kind.name().str().to_string());
+    for prod in g.prods() {
+      let key = prod.prod_key();
+      let name = format!("{:?}", key.action_key());
+      actions.on(key, move |children| {
+        format!("{}[{}]", name, children.join(" "))
+      });
+    }
+    let tokens = |terms: &[Terminal]| {
+      terms
+        .iter()
+        .map(|t| Token::new(t.clone(), ()))
+        .collect::<Vec<_>>()
+    };
+
+    let mut inputs = bounded_sentences(&g, 8).into_iter().collect::<Vec<_>>();
+    assert!(inputs.len() > 3);
+    for invalid in [
+      &[][..],
+      &["LPAREN"],
+      &["RPAREN"],
+      &["LPAREN", "RPAREN", "RPAREN"],
+      &["LPAREN", "LPAREN", "RPAREN"],
+    ] {
+      inputs.push(invalid.iter().map(|t| Terminal::new(t)).collect());
+    }
+    for input in &inputs {
+      assert_eq!(
+        compressed
+          .parse_with_actions(tokens(input), &actions)
+          .map_err(|e| e.to_string()),
+        parse_with_actions(&table, tokens(input), &actions)
+          .map_err(|e| e.to_string())
+      );
+      assert_eq!(
+        compressed.accepts(input.iter().cloned()),
+        table.accepts(input.iter().cloned())
+      );
+    }
+
+    let input = ["LPAREN", "LPAREN", "RPAREN", "RPAREN"].map(Terminal::new);
+    assert_eq!(
+      compressed
+        .parse_with_actions(tokens(&input), &actions)
+        .unwrap(),
+      "start[paren_expr[LPAREN elem[paren_expr[LPAREN empty[] RPAREN] \
+       empty[]] RPAREN]]"
+    );
+  }
+}
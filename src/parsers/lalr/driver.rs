@@ -0,0 +1,732 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An LR parser driver that evaluates semantic actions on each reduction,
+//! in the style of yacc.
+
+use {
+  super::{Action, LalrTable},
+  crate::{
+    grammar::ProdKey,
+    multi_start::{EntryActionKey, EntryNonTerminal, EntryTerminal},
+    parsers::{
+      coverage::CoverageTracker,
+      tree::{Node, TreeHandle},
+      MatchTerminal, Token,
+    },
+    start_grammar::StreamTerminal,
+  },
+  std::collections::{BTreeMap, BTreeSet},
+};
+
+/// The semantic actions used by `parse_with_actions`.
+///
+/// Each shifted token is converted to a result with the token function, and
+/// each reduction calls the action for its production with the results of
+/// the production's elements, in order.
+#[allow(clippy::type_complexity)]
+pub struct ActionTable<'f, T, NT, AK, V, R> {
+  token: Box<dyn Fn(&T, V) -> R + 'f>,
+  actions: BTreeMap<ProdKey<NT, AK>, Box<dyn Fn(Vec<R>) -> R + 'f>>,
+}
+
+impl<'f, T, NT, AK, V, R> ActionTable<'f, T, NT, AK, V, R>
+where
+  NT: Ord,
+  AK: Ord,
+{
+  /// Creates an action table with no production actions, where tokens are
+  /// converted to results with `token`.
+  pub fn new(token: impl Fn(&T, V) -> R + 'f) -> Self {
+    ActionTable {
+      token: Box::new(token),
+      actions: BTreeMap::new(),
+    }
+  }
+
+  /// Sets the action that is called when `prod` is reduced.
+  pub fn on(
+    &mut self,
+    prod: ProdKey<NT, AK>,
+    action: impl Fn(Vec<R>) -> R + 'f,
+  ) -> &mut Self {
+    self.actions.insert(prod, Box::new(action));
+    self
+  }
+}
+
+#[derive(thiserror::Error, Clone, Debug)]
+pub enum LrParseError<T, NT, AK> {
+  #[error("Unexpected token {token:?} at position {position}")]
+  UnexpectedToken {
+    position: usize,
+    token: StreamTerminal<T>,
+  },
+  #[error("No semantic action for production {0:?}")]
+  MissingAction(ProdKey<NT, AK>),
+  #[error("{0:?} is not a start nonterminal of the table")]
+  UnknownStart(NT),
+}
+
+impl<'f, T, NT, AK, V, R> ActionTable<'f, T, NT, AK, V, R>
+where
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  pub(super) fn token(&self, kind: &T, value: V) -> R {
+    (self.token)(kind, value)
+  }
+
+  pub(super) fn reduce<K>(
+    &self,
+    prod: &ProdKey<NT, AK>,
+    children: Vec<R>,
+  ) -> Result<R, LrParseError<K, NT, AK>> {
+    let action = self
+      .actions
+      .get(prod)
+      .ok_or_else(|| LrParseError::MissingAction(prod.clone()))?;
+    Ok(action(children))
+  }
+}
+
+/// Parses `input` with `table`, and returns the result of the action of the
+/// final reduction to the start nonterminal.
+pub fn parse_with_actions<T, NT, AK, V, R>(
+  table: &LalrTable<T, NT, AK>,
+  input: impl IntoIterator<Item = Token<T, V>>,
+  actions: &ActionTable<T, NT, AK, V, R>,
+) -> Result<R, LrParseError<T, NT, AK>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  drive(
+    table,
+    input,
+    &actions.token,
+    |prod, children| actions.reduce(prod, children),
+    vec![0],
+    Vec::new(),
+  )
+}
+
+/// Like `parse_with_actions`, but tokens whose kind is in `skip_terminals`,
+/// such as whitespace and comments, are consumed without being shifted, so
+/// the grammar does not need to mention them.
+///
+/// Error positions still count the skipped tokens.
+pub fn parse_with_skip<T, NT, AK, V, R>(
+  table: &LalrTable<T, NT, AK>,
+  input: impl IntoIterator<Item = Token<T, V>>,
+  skip_terminals: &BTreeSet<T>,
+  actions: &ActionTable<T, NT, AK, V, R>,
+) -> Result<R, LrParseError<T, NT, AK>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  let table = Skipping {
+    inner: table,
+    skip: skip_terminals,
+  };
+  drive(
+    &table,
+    input,
+    &actions.token,
+    |prod, children| actions.reduce(prod, children),
+    vec![0],
+    Vec::new(),
+  )
+}
+
+/// Like `parse_with_actions`, but also records each reduced production in
+/// `coverage`.
+pub fn parse_with_coverage<T, NT, AK, V, R>(
+  table: &LalrTable<T, NT, AK>,
+  input: impl IntoIterator<Item = Token<T, V>>,
+  actions: &ActionTable<T, NT, AK, V, R>,
+  coverage: &mut CoverageTracker<NT, AK>,
+) -> Result<R, LrParseError<T, NT, AK>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  drive(
+    table,
+    input,
+    &actions.token,
+    |prod, children| {
+      let result = actions.reduce(prod, children)?;
+      coverage.record(prod);
+      Ok(result)
+    },
+    vec![0],
+    Vec::new(),
+  )
+}
+
+/// Like `parse_with_actions`, but with tokens of kind `K`, which are matched
+/// against the table's terminals by `matcher`.
+///
+/// If a token matches more than one terminal that has an action in the
+/// current state, the smallest such terminal is used.
+pub fn parse_with_matcher<T, K, NT, AK, V, R>(
+  table: &LalrTable<T, NT, AK>,
+  input: impl IntoIterator<Item = Token<K, V>>,
+  matcher: impl MatchTerminal<T, K>,
+  actions: &ActionTable<K, NT, AK, V, R>,
+) -> Result<R, LrParseError<K, NT, AK>>
+where
+  T: Ord,
+  K: Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  drive(
+    &Matching::new(table, matcher),
+    input,
+    &actions.token,
+    |prod, children| actions.reduce(prod, children),
+    vec![0],
+    Vec::new(),
+  )
+}
+
+/// Parses `input` with `table`, and returns its parse tree.
+///
+/// Each token becomes a leaf with the token's kind and value, and each
+/// reduction becomes a branch with the action key of its production.
+pub fn parse_to_tree<'a, T, NT, AK, V>(
+  table: &LalrTable<T, NT, AK>,
+  tree: &TreeHandle<'a, T, AK, V>,
+  input: impl IntoIterator<Item = Token<T, V>>,
+) -> Result<Node<'a, T, AK, V>, LrParseError<T, NT, AK>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+  V: Ord,
+{
+  drive(
+    table,
+    input,
+    |kind: &T, value| tree.make_leaf_node(kind.clone(), value),
+    |prod, children| {
+      Ok(tree.make_branch_node(prod.action_key().clone(), children))
+    },
+    vec![0],
+    Vec::new(),
+  )
+}
+
+/// Parses `input` from `start` with a table built from a grammar returned by
+/// `Grammar::with_start_symbols`, and returns the result of the action of
+/// the final reduction to `start`.
+///
+/// The actions are keyed by the productions of the original grammar, so the
+/// same action table may be used for every start.
+pub fn parse_from_start<T, NT, AK, V, R>(
+  table: &LalrTable<
+    EntryTerminal<T, NT>,
+    EntryNonTerminal<NT>,
+    EntryActionKey<NT, AK>,
+  >,
+  start: &NT,
+  input: impl IntoIterator<Item = Token<T, V>>,
+  actions: &ActionTable<T, NT, AK, V, R>,
+) -> Result<R, LrParseError<T, NT, AK>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  // Shift the entry marker of `start` before reading any input.
+  let marker = StreamTerminal::Term(EntryTerminal::Entry(start.clone()));
+  let entry_state = match table.action(0, &marker) {
+    Some(Action::Shift(next)) => *next,
+    _ => return Err(LrParseError::UnknownStart(start.clone())),
+  };
+
+  let matcher = |t: &EntryTerminal<T, NT>, kind: &T| matches!(t, EntryTerminal::Term(t) if t == kind);
+  let result = drive(
+    &Matching::new(table, matcher),
+    input,
+    |kind: &T, value| Some((actions.token)(kind, value)),
+    |prod, mut children| match (prod.head(), prod.action_key()) {
+      (_, EntryActionKey::Entry(_)) => Ok(children.pop().flatten()),
+      (EntryNonTerminal::NTerm(head), EntryActionKey::ActionKey(key)) => {
+        let children = children
+          .into_iter()
+          .collect::<Option<Vec<_>>>()
+          .expect("only entry markers have no result");
+        actions
+          .reduce(&ProdKey::new(head.clone(), key.clone()), children)
+          .map(Some)
+      }
+      (EntryNonTerminal::Entry, _) => {
+        unreachable!("entry productions have entry keys")
+      }
+    },
+    vec![0, entry_state],
+    vec![None],
+  )?;
+  Ok(result.expect("the start nonterminal was reduced"))
+}
+
+/// The lookups that `drive` makes in a parse table, for tokens of kind `K`.
+pub(super) trait DriveTable<K, NT, AK> {
+  /// Returns the action of `state` on a token of kind `lookahead`, or on the
+  /// end of the stream if it is `None`.
+  fn action(
+    &self,
+    state: usize,
+    lookahead: Option<&K>,
+  ) -> Option<&Action<NT, AK>>;
+
+  fn goto(&self, state: usize, nt: &NT) -> Option<usize>;
+
+  /// Returns true if tokens of kind `kind` are skipped without being
+  /// shifted.
+  fn is_skipped(&self, _kind: &K) -> bool {
+    false
+  }
+}
+
+/// A table that also skips the tokens whose kinds are in `skip`.
+struct Skipping<'s, D, K> {
+  inner: &'s D,
+  skip: &'s BTreeSet<K>,
+}
+
+impl<D, K, NT, AK> DriveTable<K, NT, AK> for Skipping<'_, D, K>
+where
+  D: DriveTable<K, NT, AK>,
+  K: Ord,
+{
+  fn action(
+    &self,
+    state: usize,
+    lookahead: Option<&K>,
+  ) -> Option<&Action<NT, AK>> {
+    self.inner.action(state, lookahead)
+  }
+
+  fn goto(&self, state: usize, nt: &NT) -> Option<usize> {
+    self.inner.goto(state, nt)
+  }
+
+  fn is_skipped(&self, kind: &K) -> bool {
+    self.skip.contains(kind) || self.inner.is_skipped(kind)
+  }
+}
+
+impl<T, NT, AK> DriveTable<T, NT, AK> for LalrTable<T, NT, AK>
+where
+  T: Ord + Clone,
+  NT: Ord,
+{
+  fn action(
+    &self,
+    state: usize,
+    lookahead: Option<&T>,
+  ) -> Option<&Action<NT, AK>> {
+    let lookahead = match lookahead {
+      Some(kind) => StreamTerminal::Term(kind.clone()),
+      None => StreamTerminal::EndOfStream,
+    };
+    LalrTable::action(self, state, &lookahead)
+  }
+
+  fn goto(&self, state: usize, nt: &NT) -> Option<usize> {
+    LalrTable::goto(self, state, nt)
+  }
+}
+
+/// An `LalrTable` whose terminals are matched against tokens by a
+/// `MatchTerminal`.
+///
+/// Each lookup scans the actions of the state, so tables whose terminals
+/// are the token kinds themselves are driven directly instead.
+struct Matching<'t, T, NT, AK, M> {
+  table: &'t LalrTable<T, NT, AK>,
+  matcher: M,
+}
+
+impl<'t, T, NT, AK, M> Matching<'t, T, NT, AK, M> {
+  fn new(table: &'t LalrTable<T, NT, AK>, matcher: M) -> Self {
+    Matching { table, matcher }
+  }
+}
+
+impl<T, K, NT, AK, M> DriveTable<K, NT, AK> for Matching<'_, T, NT, AK, M>
+where
+  T: Ord,
+  NT: Ord,
+  M: MatchTerminal<T, K>,
+{
+  fn action(
+    &self,
+    state: usize,
+    lookahead: Option<&K>,
+  ) -> Option<&Action<NT, AK>> {
+    match lookahead {
+      Some(kind) => {
+        self
+          .table
+          .state_actions(state)
+          .find_map(|(t, action)| match t {
+            StreamTerminal::Term(t) if self.matcher.matches(t, kind) => {
+              Some(action)
+            }
+            _ => None,
+          })
+      }
+      None => self.table.action(state, &StreamTerminal::EndOfStream),
+    }
+  }
+
+  fn goto(&self, state: usize, nt: &NT) -> Option<usize> {
+    self.table.goto(state, nt)
+  }
+}
+
+pub(super) fn drive<K, NT, AK, V, R, NTe, AKe>(
+  table: &impl DriveTable<K, NT, AK>,
+  input: impl IntoIterator<Item = Token<K, V>>,
+  token: impl Fn(&K, V) -> R,
+  mut reduce: impl FnMut(
+    &ProdKey<NT, AK>,
+    Vec<R>,
+  ) -> Result<R, LrParseError<K, NTe, AKe>>,
+  mut states: Vec<usize>,
+  mut results: Vec<R>,
+) -> Result<R, LrParseError<K, NTe, AKe>>
+where
+  K: Clone,
+{
+  let mut input = input.into_iter().map(Token::into_parts).fuse();
+  // Reads the next token that is not skipped. `position` counts the skipped
+  // tokens too, so errors report positions in the original input.
+  let mut next_token = |position: &mut usize| loop {
+    match input.next() {
+      Some((kind, _)) if table.is_skipped(&kind) => *position += 1,
+      token => return token,
+    }
+  };
+  let mut position = 0;
+  let mut lookahead = next_token(&mut position);
+
+  loop {
+    let state = *states.last().expect("the stack is never empty");
+    let kind = lookahead.as_ref().map(|(kind, _)| kind);
+    let la = match kind {
+      Some(kind) => StreamTerminal::Term(kind.clone()),
+      None => StreamTerminal::EndOfStream,
+    };
+    let action = table.action(state, kind);
+
+    match action {
+      None => {
+        return Err(LrParseError::UnexpectedToken {
+          position,
+          token: la,
+        })
+      }
+      Some(Action::Accept) => {
+        return Ok(results.pop().expect("the start symbol was reduced"));
+      }
+      Some(Action::Shift(next)) => {
+        let (kind, value) = lookahead.take().expect("only tokens are shifted");
+        results.push(token(&kind, value));
+        states.push(*next);
+        position += 1;
+        lookahead = next_token(&mut position);
+      }
+      Some(Action::Reduce { prod, len }) => {
+        let children = results.split_off(results.len() - len);
+        results.push(reduce(prod, children)?);
+        states.truncate(states.len() - len);
+        let top = *states.last().expect("the stack is never empty");
+        match table.goto(top, prod.head()) {
+          Some(next) => states.push(next),
+          None => {
+            return Err(LrParseError::UnexpectedToken {
+              position,
+              token: la,
+            })
+          }
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::{examples, NonTerminal, Terminal},
+    parsers::lalr::build_lalr_table,
+    parsers::tree::{Branch, NodeContent, TreeOwner},
+    utils::Name,
+  };
+
+  fn key(head: &str, action: &str) -> ProdKey<NonTerminal, Name> {
+    ProdKey::new(NonTerminal::new(head), Name::new(action))
+  }
+
+  fn tokens(input: &str) -> Vec<Token<Terminal, i64>> {
+    input
+      .split_whitespace()
+      .map(|word| match word {
+        "+" => Token::new(Terminal::new("PLUS"), 0),
+        "*" => Token::new(Terminal::new("TIMES"), 0),
+        num => Token::new(Terminal::new("NUM"), num.parse().unwrap()),
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_arithmetic() {
+    let g = examples::from_rules(&[
+      ("expr", &[&["expr", "PLUS", "term"], &["term"]]),
+      ("term", &[&["term", "TIMES", "NUM"], &["NUM"]]),
+    ]);
+    let table = build_lalr_table(&g).unwrap();
+
+    let mut actions = ActionTable::new(|_: &Terminal, value: i64| value);
+    actions
+      .on(key("expr", "p0"), |c| c[0] + c[2])
+      .on(key("expr", "p1"), |c| c[0])
+      .on(key("term", "p0"), |c| c[0] * c[2])
+      .on(key("term", "p1"), |c| c[0]);
+
+    let eval = |input| parse_with_actions(&table, tokens(input), &actions);
+    assert_eq!(eval("2 + 3 * 4").unwrap(), 14);
+    assert_eq!(eval("2 * 3 + 4 * 5 * 6").unwrap(), 126);
+    assert_eq!(eval("7").unwrap(), 7);
+
+    match eval("2 + * 3") {
+      Err(LrParseError::UnexpectedToken { position, token }) => {
+        assert_eq!(position, 2);
+        assert_eq!(token, StreamTerminal::Term(Terminal::new("TIMES")));
+      }
+      _ => panic!("expected a parse error"),
+    }
+    assert!(matches!(
+      eval("2 +"),
+      Err(LrParseError::UnexpectedToken {
+        position: 2,
+        token: StreamTerminal::EndOfStream,
+      })
+    ));
+
+    let mut coverage = CoverageTracker::new();
+    let sum =
+      parse_with_coverage(&table, tokens("1 + 2"), &actions, &mut coverage);
+    assert_eq!(sum.unwrap(), 3);
+    assert_eq!(coverage.uncovered(&g), vec![key("term", "p0")]);
+
+    let mut partial = ActionTable::new(|_: &Terminal, value: i64| value);
+    partial.on(key("term", "p1"), |c| c[0]);
+    assert!(matches!(
+      parse_with_actions(&table, tokens("1"), &partial),
+      Err(LrParseError::MissingAction(prod)) if prod == key("expr", "p1")
+    ));
+  }
+
+  #[test]
+  fn test_predicate_terminals() {
+    let g = examples::from_rules(&[
+      ("call", &[&["IDENT", "LPAREN", "args", "RPAREN"]]),
+      ("args", &[&[], &["IDENT", "args"]]),
+    ]);
+    let table = build_lalr_table(&g).unwrap();
+
+    // Every alphabetic word is an `IDENT`, and punctuation matches exactly.
+    let matcher = |t: &Terminal, word: &&str| match t.name().str() {
+      "IDENT" => word.chars().all(char::is_alphabetic),
+      "LPAREN" => *word == "(",
+      "RPAREN" => *word == ")",
+      _ => false,
+    };
+    let mut actions =
+      ActionTable::new(|word: &&str, _: ()| vec![word.to_string()]);
+    actions
+      .on(key("call", "p0"), |c| {
+        vec![format!("{}({})", c[0][0], c[2].join(", "))]
+      })
+      .on(key("args", "p0"), |_| vec![])
+      .on(key("args", "p1"), |mut c| {
+        let mut args = c.remove(0);
+        args.extend(c.remove(0));
+        args
+      });
+
+    let parse = |input: &'static str| {
+      let tokens = input.split_whitespace().map(|word| Token::new(word, ()));
+      parse_with_matcher(&table, tokens, matcher, &actions)
+    };
+    assert_eq!(parse("max ( a b c )").unwrap(), vec!["max(a, b, c)"]);
+    assert_eq!(parse("f ( )").unwrap(), vec!["f()"]);
+    assert!(matches!(
+      parse("f ( 1 )"),
+      Err(LrParseError::UnexpectedToken {
+        position: 2,
+        token: StreamTerminal::Term("1"),
+      })
+    ));
+  }
+
+  #[test]
+  fn test_parse_to_tree() {
+    let g =
+      examples::from_rules(&[("expr", &[&["expr", "PLUS", "NUM"], &["NUM"]])]);
+    let table = build_lalr_table(&g).unwrap();
+    let owner = TreeOwner::new();
+    let tree = owner.handle();
+
+    let root = parse_to_tree(&table, &tree, tokens("3 + 4")).unwrap();
+    type TestNode<'a> = Node<'a, Terminal, Name, i64>;
+    fn only(node: TestNode<'_>) -> NodeContent<'_, Terminal, Name, i64> {
+      let alts = node.alts().collect::<Vec<_>>();
+      assert_eq!(alts.len(), 1);
+      alts[0].content()
+    }
+    fn branch(node: TestNode<'_>) -> Branch<'_, Terminal, Name, i64> {
+      match only(node) {
+        NodeContent::Branch(branch) => branch,
+        NodeContent::Leaf(_) => panic!("expected a branch"),
+      }
+    }
+    fn leaf(node: TestNode<'_>) -> (Terminal, i64) {
+      match only(node) {
+        NodeContent::Leaf(leaf) => (leaf.kind(), *leaf.value()),
+        NodeContent::Branch(_) => panic!("expected a leaf"),
+      }
+    }
+
+    let sum = branch(root);
+    assert_eq!(sum.action(), Name::new("p0"));
+    let children = sum.nodes().collect::<Vec<_>>();
+    assert_eq!(children.len(), 3);
+    let first = branch(children[0].clone());
+    assert_eq!(first.action(), Name::new("p1"));
+    let first_leaves = first.nodes().map(leaf).collect::<Vec<_>>();
+    assert_eq!(first_leaves, vec![(Terminal::new("NUM"), 3)]);
+    assert_eq!(leaf(children[2].clone()), (Terminal::new("NUM"), 4));
+
+    assert!(parse_to_tree(&table, &tree, tokens("3 +")).is_err());
+  }
+
+  #[test]
+  fn test_skip_terminals() {
+    let table = build_lalr_table(&examples::make_paren()).unwrap();
+    let ws = Terminal::new("WS");
+    let skip = std::iter::once(ws.clone()).collect::<BTreeSet<_>>();
+    let lex = |source: &str| {
+      source
+        .chars()
+        .map(|c| {
+          let kind = match c {
+            '(' => Terminal::new("LPAREN"),
+            ')' => Terminal::new("RPAREN"),
+            _ => ws.clone(),
+          };
+          Token::new(kind, ())
+        })
+        .collect::<Vec<_>>()
+    };
+
+    // Render each parse as the bracketing of its reductions.
+    let mut actions = ActionTable::new(|_: &Terminal, ()| String::new());
+    actions
+      .on(key("start", "start"), |c| c.concat())
+      .on(key("expr", "paren_expr"), |c| format!("({})", c[1]))
+      .on(key("expr_list", "empty"), |_| String::new())
+      .on(key("expr_list", "elem"), |c| c.concat());
+
+    let parse = |source| parse_with_skip(&table, lex(source), &skip, &actions);
+    assert_eq!(parse("( )").unwrap(), parse("()").unwrap());
+    assert_eq!(parse("  ( ( )\t() ) ").unwrap(), "(()())");
+    assert!(parse_with_actions(&table, lex("( )"), &actions).is_err());
+
+    // Positions count the skipped tokens.
+    match parse(" ( ) )") {
+      Err(LrParseError::UnexpectedToken { position, .. }) => {
+        assert_eq!(position, 5)
+      }
+      result => panic!("unexpected result {:?}", result),
+    }
+  }
+
+  #[test]
+  fn test_parse_from_start() {
+    // `unused` is not reachable from `stmts`, which only a lenient grammar
+    // allows.
+    let g = examples::from_rules_lenient(&[
+      ("stmts", &[&[], &["expr", "SEMI", "stmts"]]),
+      ("expr", &[&["expr", "PLUS", "NUM"], &["NUM"]]),
+      ("unused", &[&["TIMES"]]),
+    ]);
+    let (stmts, expr) = (NonTerminal::new("stmts"), NonTerminal::new("expr"));
+    let multi = g
+      .with_start_symbols(&[stmts.clone(), expr.clone()])
+      .unwrap();
+    assert!(multi
+      .try_get_rule(&EntryNonTerminal::NTerm(NonTerminal::new("unused")))
+      .is_none());
+    let table = build_lalr_table(&multi).unwrap();
+
+    let mut actions = ActionTable::new(|_: &Terminal, value: i64| vec![value]);
+    actions
+      .on(key("stmts", "p0"), |_| vec![])
+      .on(key("stmts", "p1"), |mut c| {
+        let mut sums = c.remove(0);
+        sums.extend(c.remove(1));
+        sums
+      })
+      .on(key("expr", "p0"), |c| vec![c[0][0] + c[2][0]])
+      .on(key("expr", "p1"), |c| c[0].clone());
+
+    let tokens = |input: &str| {
+      input
+        .split_whitespace()
+        .map(|word| match word {
+          "+" => Token::new(Terminal::new("PLUS"), 0),
+          ";" => Token::new(Terminal::new("SEMI"), 0),
+          num => Token::new(Terminal::new("NUM"), num.parse().unwrap()),
+        })
+        .collect::<Vec<_>>()
+    };
+    let parse = |start: &NonTerminal, input| {
+      parse_from_start(&table, start, tokens(input), &actions)
+    };
+    assert_eq!(parse(&expr, "1 + 2 + 3").unwrap(), vec![6]);
+    assert_eq!(parse(&stmts, "1 + 2 ; 3 ;").unwrap(), vec![3, 3]);
+    assert!(parse(&stmts, "").unwrap().is_empty());
+    assert!(matches!(
+      parse(&expr, "1 ;"),
+      Err(LrParseError::UnexpectedToken { position: 1, .. })
+    ));
+    assert!(matches!(
+      parse(&NonTerminal::new("unused"), "1"),
+      Err(LrParseError::UnknownStart(nt)) if nt == NonTerminal::new("unused")
+    ));
+  }
+}
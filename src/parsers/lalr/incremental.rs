@@ -0,0 +1,337 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Incremental reparsing with an LALR table.
+//!
+//! After an edit, the parser runs over the new tokens as usual, but whenever
+//! it is about to shift the first token of a subtree of the previous parse
+//! that the edit did not affect, it shifts the whole subtree instead. A
+//! subtree can be reused if it was started in the same parser state, and
+//! neither its tokens nor the token after it changed. Since the parser is
+//! deterministic, it would rebuild exactly the same subtree from there.
+
+use {
+  super::{Action, LalrTable, LrParseError},
+  crate::{grammar::ProdKey, parsers::Token, start_grammar::StreamTerminal},
+  std::{collections::BTreeMap, rc::Rc},
+};
+
+/// The subtrees of an old tree that may be reused, keyed by the index of
+/// their first token in the new input.
+type Reusable<T, NT, AK, V> =
+  BTreeMap<usize, Vec<Rc<SyntaxNode<T, NT, AK, V>>>>;
+
+/// A node of a concrete syntax tree that can be reused by `reparse`.
+#[derive(Debug)]
+pub enum SyntaxNode<T, NT, AK, V> {
+  Leaf {
+    kind: T,
+    value: V,
+  },
+  Branch {
+    prod: ProdKey<NT, AK>,
+    children: Vec<Rc<SyntaxNode<T, NT, AK, V>>>,
+    /// The number of tokens the branch spans.
+    num_tokens: usize,
+    /// The parser state the branch was started in.
+    start_state: usize,
+  },
+}
+
+impl<T, NT, AK, V> SyntaxNode<T, NT, AK, V> {
+  /// Returns the number of tokens this node spans.
+  pub fn num_tokens(&self) -> usize {
+    match self {
+      SyntaxNode::Leaf { .. } => 1,
+      SyntaxNode::Branch { num_tokens, .. } => *num_tokens,
+    }
+  }
+
+  /// Returns the children of a branch, or nothing for a leaf.
+  pub fn children(&self) -> &[Rc<SyntaxNode<T, NT, AK, V>>] {
+    match self {
+      SyntaxNode::Leaf { .. } => &[],
+      SyntaxNode::Branch { children, .. } => children,
+    }
+  }
+}
+
+/// A replacement of the tokens `start..old_end` of the previous input with
+/// the tokens `start..new_end` of the new input.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TokenEdit {
+  pub start: usize,
+  pub old_end: usize,
+  pub new_end: usize,
+}
+
+/// The result of `reparse`.
+#[derive(Debug)]
+pub struct Reparse<T, NT, AK, V> {
+  pub root: Rc<SyntaxNode<T, NT, AK, V>>,
+  /// The number of subtrees of the previous tree that were shifted whole.
+  pub reused: usize,
+}
+
+/// Parses `input` with `table` into a tree that can later be passed to
+/// `reparse`.
+#[allow(clippy::type_complexity)]
+pub fn parse_syntax_tree<T, NT, AK, V>(
+  table: &LalrTable<T, NT, AK>,
+  input: impl IntoIterator<Item = Token<T, V>>,
+) -> Result<Rc<SyntaxNode<T, NT, AK, V>>, LrParseError<T, NT, AK>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  run(table, input, &BTreeMap::new()).map(|(root, _)| root)
+}
+
+/// Parses `input`, which is the input of `old` after `edit`, reusing the
+/// subtrees of `old` that the edit did not affect.
+///
+/// Reused subtrees keep the token values of the previous input, so values
+/// should not hold anything that an edit elsewhere changes, such as
+/// absolute offsets.
+#[allow(clippy::type_complexity)]
+pub fn reparse<T, NT, AK, V>(
+  table: &LalrTable<T, NT, AK>,
+  old: &Rc<SyntaxNode<T, NT, AK, V>>,
+  edit: TokenEdit,
+  input: impl IntoIterator<Item = Token<T, V>>,
+) -> Result<Reparse<T, NT, AK, V>, LrParseError<T, NT, AK>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  let mut reusable = BTreeMap::new();
+  collect_reusable(old, 0, edit, &mut reusable);
+  run(table, input, &reusable).map(|(root, reused)| Reparse { root, reused })
+}
+
+/// Adds the nonempty branches under `node`, which starts at token `start` of
+/// the previous input, that end before the edit, or start after it, to
+/// `reusable` by their starting position in the new input. The branches at
+/// each position are ordered from the outermost.
+fn collect_reusable<T, NT, AK, V>(
+  node: &Rc<SyntaxNode<T, NT, AK, V>>,
+  start: usize,
+  edit: TokenEdit,
+  reusable: &mut Reusable<T, NT, AK, V>,
+) {
+  if let SyntaxNode::Branch {
+    children,
+    num_tokens,
+    ..
+  } = &**node
+  {
+    // The token after a branch decides when it is reduced, so it must not
+    // have been edited either.
+    let new_start = if start + num_tokens < edit.start {
+      Some(start)
+    } else if start >= edit.old_end {
+      Some(start - edit.old_end + edit.new_end)
+    } else {
+      None
+    };
+    if let (Some(new_start), true) = (new_start, *num_tokens > 0) {
+      reusable.entry(new_start).or_default().push(node.clone());
+    }
+
+    let mut child_start = start;
+    for child in children {
+      collect_reusable(child, child_start, edit, reusable);
+      child_start += child.num_tokens();
+    }
+  }
+}
+
+/// Runs the parser on `input`, shifting a subtree from `reusable` whenever
+/// one starts at the current position in the current state. Also returns
+/// the number of subtrees that were reused.
+#[allow(clippy::type_complexity)]
+fn run<T, NT, AK, V>(
+  table: &LalrTable<T, NT, AK>,
+  input: impl IntoIterator<Item = Token<T, V>>,
+  reusable: &Reusable<T, NT, AK, V>,
+) -> Result<(Rc<SyntaxNode<T, NT, AK, V>>, usize), LrParseError<T, NT, AK>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  let mut input = input
+    .into_iter()
+    .map(|token| Some(token.into_parts()))
+    .collect::<Vec<_>>();
+  let mut states = vec![0];
+  let mut results: Vec<Rc<SyntaxNode<T, NT, AK, V>>> = Vec::new();
+  let mut position = 0;
+  let mut reused = 0;
+
+  loop {
+    let state = *states.last().expect("the stack is never empty");
+    let lookahead = match input.get(position) {
+      Some(token) => StreamTerminal::Term(
+        token
+          .as_ref()
+          .expect("tokens are only taken once")
+          .0
+          .clone(),
+      ),
+      None => StreamTerminal::EndOfStream,
+    };
+
+    match table.action(state, &lookahead) {
+      None => {
+        return Err(LrParseError::UnexpectedToken {
+          position,
+          token: lookahead,
+        })
+      }
+      Some(Action::Accept) => {
+        let root = results.pop().expect("the start symbol was reduced");
+        return Ok((root, reused));
+      }
+      Some(Action::Shift(next)) => {
+        let subtree = reusable.get(&position).and_then(|nodes| {
+          nodes.iter().find_map(|node| match &**node {
+            SyntaxNode::Branch {
+              prod, start_state, ..
+            } if *start_state == state => table
+              .goto(state, prod.head())
+              .map(|next| (node.clone(), next)),
+            _ => None,
+          })
+        });
+        match subtree {
+          Some((node, next)) => {
+            position += node.num_tokens();
+            reused += 1;
+            results.push(node);
+            states.push(next);
+          }
+          None => {
+            let (kind, value) =
+              input[position].take().expect("tokens are only taken once");
+            results.push(Rc::new(SyntaxNode::Leaf { kind, value }));
+            states.push(*next);
+            position += 1;
+          }
+        }
+      }
+      Some(Action::Reduce { prod, len }) => {
+        let children = results.split_off(results.len() - len);
+        states.truncate(states.len() - len);
+        let top = *states.last().expect("the stack is never empty");
+        results.push(Rc::new(SyntaxNode::Branch {
+          prod: prod.clone(),
+          num_tokens: children.iter().map(|child| child.num_tokens()).sum(),
+          children,
+          start_state: top,
+        }));
+        match table.goto(top, prod.head()) {
+          Some(next) => states.push(next),
+          None => {
+            return Err(LrParseError::UnexpectedToken {
+              position,
+              token: lookahead,
+            })
+          }
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::{examples, Terminal},
+    parsers::lalr::build_lalr_table,
+  };
+
+  fn tokens(input: &str) -> Vec<Token<Terminal, char>> {
+    input
+      .chars()
+      .map(|c| {
+        let kind = match c {
+          '(' => "LPAREN",
+          ')' => "RPAREN",
+          c if c.is_ascii_digit() => "NUM",
+          _ => "ATOM",
+        };
+        Token::new(Terminal::new(kind), c)
+      })
+      .collect()
+  }
+
+  /// Renders a tree back into its input.
+  fn source<T, NT, AK>(node: &SyntaxNode<T, NT, AK, char>) -> String {
+    match node {
+      SyntaxNode::Leaf { value, .. } => value.to_string(),
+      SyntaxNode::Branch { children, .. } => {
+        children.iter().map(|child| source(child)).collect()
+      }
+    }
+  }
+
+  #[test]
+  fn test_reparse_reuses_unedited_groups() {
+    let g = examples::from_rules(&[
+      (
+        "expr",
+        &[&["LPAREN", "list", "RPAREN"], &["ATOM"], &["NUM"]],
+      ),
+      ("list", &[&[], &["expr", "list"]]),
+    ]);
+    let table = build_lalr_table(&g).unwrap();
+
+    let old = parse_syntax_tree(&table, tokens("((a)(b)(c))")).unwrap();
+    assert_eq!(old.num_tokens(), 11);
+    // expr ::= ( list ), where list ::= expr list holds each group.
+    let old_list = &old.children()[1];
+    let old_first = old_list.children()[0].clone();
+    let old_third = old_list.children()[1].children()[1].children()[0].clone();
+    assert_eq!(source(&old_first), "(a)");
+    assert_eq!(source(&old_third), "(c)");
+
+    // Replace the `b` in the middle group with `1`.
+    let edit = TokenEdit {
+      start: 5,
+      old_end: 6,
+      new_end: 6,
+    };
+    let new = reparse(&table, &old, edit, tokens("((a)(1)(c))")).unwrap();
+    assert_eq!(source(&new.root), "((a)(1)(c))");
+    assert!(!Rc::ptr_eq(&new.root, &old));
+
+    let new_list = &new.root.children()[1];
+    assert!(Rc::ptr_eq(&new_list.children()[0], &old_first));
+    let new_third = &new_list.children()[1].children()[1].children()[0];
+    assert!(Rc::ptr_eq(new_third, &old_third));
+    assert!(new.reused >= 2);
+
+    // An edit that makes the input invalid still fails.
+    let edit = TokenEdit {
+      start: 4,
+      old_end: 7,
+      new_end: 4,
+    };
+    assert!(reparse(&table, &new.root, edit, tokens("((a)(c)")).is_err());
+  }
+}
@@ -0,0 +1,846 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The LR(0) automaton of a grammar, which recognizes its viable prefixes.
+
+use {
+  crate::{
+    grammar::{
+      passes::{symbols::SymbolTable, IterationLimitExceeded, PassContext},
+      Elem, Grammar, Prod, ProdElement,
+    },
+    state::ProdState,
+    utils::{doc_string, ToDoc},
+  },
+  std::collections::{BTreeMap, BTreeSet},
+};
+
+/// An LR item: the index of a production, and the position of the dot within
+/// it.
+pub(crate) type Item = (usize, usize);
+
+/// The LR(0) automaton of a grammar.
+///
+/// Each state is identified by its kernel: the items that were advanced to
+/// enter the state. Only the kernels are stored, and the closures of states
+/// are recomputed when needed.
+///
+/// The initial state is always 0, and its kernel contains every production
+/// of the start nonterminal. The start rule is implicitly augmented with an
+/// end of stream terminal, which is accepted in `accept_state`.
+#[derive(Clone)]
+pub struct Lr0Automaton<'a, T, NT, AK, AV> {
+  grammar: &'a Grammar<T, NT, AK, AV>,
+  prods: Vec<Prod<'a, T, NT, AK, AV>>,
+  prods_by_head: BTreeMap<&'a NT, Vec<usize>>,
+  kernels: Vec<BTreeSet<Item>>,
+  transitions: Vec<BTreeMap<Elem<&'a T, &'a NT>, usize>>,
+  accept_state: usize,
+}
+
+impl<'a, T, NT, AK, AV> Lr0Automaton<'a, T, NT, AK, AV>
+where
+  T: Ord,
+  NT: Ord,
+{
+  pub fn new(grammar: &'a Grammar<T, NT, AK, AV>) -> Self {
+    Lr0Automaton::build(grammar, None).expect("there is no iteration limit")
+  }
+
+  /// Like `new`, but fails if the automaton has more than `max_states`
+  /// states, not counting the accepting state.
+  pub fn new_with_limit(
+    grammar: &'a Grammar<T, NT, AK, AV>,
+    max_states: usize,
+  ) -> Result<Self, IterationLimitExceeded> {
+    Lr0Automaton::build(grammar, Some(max_states))
+  }
+
+  fn build(
+    grammar: &'a Grammar<T, NT, AK, AV>,
+    max_states: Option<usize>,
+  ) -> Result<Self, IterationLimitExceeded> {
+    let prods = grammar.prods().collect::<Vec<_>>();
+    let mut prods_by_head: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for (i, prod) in prods.iter().enumerate() {
+      prods_by_head.entry(prod.head()).or_default().push(i);
+    }
+
+    let mut automaton = Lr0Automaton {
+      grammar,
+      prods,
+      prods_by_head,
+      kernels: Vec::new(),
+      transitions: Vec::new(),
+      accept_state: 0,
+    };
+    automaton.build_states(max_states)?;
+    Ok(automaton)
+  }
+
+  fn build_states(
+    &mut self,
+    max_states: Option<usize>,
+  ) -> Result<(), IterationLimitExceeded> {
+    let start_kernel = self
+      .prods_of(self.grammar.start_nt())
+      .iter()
+      .map(|prod| (*prod, 0))
+      .collect::<BTreeSet<_>>();
+
+    let mut kernels = vec![start_kernel.clone()];
+    let mut indices = BTreeMap::new();
+    indices.insert(start_kernel, 0);
+    let mut transitions = Vec::new();
+
+    let mut curr = 0;
+    while curr < kernels.len() {
+      IterationLimitExceeded::check(
+        "LR(0) automaton construction",
+        max_states,
+        kernels.len(),
+      )?;
+      let mut nexts: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+      for item in self.closure(&kernels[curr]) {
+        if let Some(elem) = self.next_elem(item) {
+          nexts
+            .entry(elem.elem().as_ref())
+            .or_default()
+            .insert((item.0, item.1 + 1));
+        }
+      }
+
+      let mut state_transitions = BTreeMap::new();
+      for (elem, next_kernel) in nexts {
+        let next = *indices.entry(next_kernel.clone()).or_insert_with(|| {
+          kernels.push(next_kernel);
+          kernels.len() - 1
+        });
+        state_transitions.insert(elem, next);
+      }
+      transitions.push(state_transitions);
+      curr += 1;
+    }
+
+    // The start nonterminal only has a transition from the initial state if
+    // it is left recursive. Otherwise, add an empty state to accept in.
+    self.accept_state = *transitions[0]
+      .entry(Elem::NonTerm(self.grammar.start_nt()))
+      .or_insert_with(|| {
+        kernels.push(BTreeSet::new());
+        kernels.len() - 1
+      });
+    transitions.resize_with(kernels.len(), BTreeMap::new);
+
+    self.kernels = kernels;
+    self.transitions = transitions;
+    Ok(())
+  }
+
+  pub fn grammar(&self) -> &'a Grammar<T, NT, AK, AV> {
+    self.grammar
+  }
+
+  pub fn num_states(&self) -> usize {
+    self.kernels.len()
+  }
+
+  /// The state in which the end of stream is accepted.
+  pub fn accept_state(&self) -> usize {
+    self.accept_state
+  }
+
+  /// Returns the state entered from `state` on `elem`, if any.
+  pub fn transition(
+    &self,
+    state: usize,
+    elem: &Elem<&'a T, &'a NT>,
+  ) -> Option<usize> {
+    self.transitions.get(state)?.get(elem).copied()
+  }
+
+  /// Returns the kernel items of `state`.
+  pub fn state_kernel(
+    &self,
+    state: usize,
+  ) -> Vec<ProdState<'a, T, NT, AK, AV>> {
+    self.to_prod_states(&self.kernels[state])
+  }
+
+  /// Returns every item of `state`, including those added by its closure.
+  pub fn state_items(&self, state: usize) -> Vec<ProdState<'a, T, NT, AK, AV>> {
+    self.to_prod_states(&self.closure(&self.kernels[state]))
+  }
+
+  fn to_prod_states(
+    &self,
+    items: &BTreeSet<Item>,
+  ) -> Vec<ProdState<'a, T, NT, AK, AV>> {
+    items
+      .iter()
+      .map(|(prod, dot)| ProdState::at_index(self.prods[*prod], *dot))
+      .collect()
+  }
+
+  /// Returns the transitions out of `state`.
+  pub fn transitions(
+    &self,
+    state: usize,
+  ) -> impl Iterator<Item = (&Elem<&'a T, &'a NT>, usize)> + '_ {
+    self.transitions[state]
+      .iter()
+      .map(|(elem, next)| (elem, *next))
+  }
+
+  /// Follows the transitions on `elems` from the initial state, and returns
+  /// the final state. Returns `None` if `elems` is not a viable prefix.
+  pub fn walk(
+    &self,
+    elems: impl IntoIterator<Item = Elem<&'a T, &'a NT>>,
+  ) -> Option<usize> {
+    elems
+      .into_iter()
+      .try_fold(0, |state, elem| self.transition(state, &elem))
+  }
+
+  /// Returns the minimal automaton that recognizes the same viable prefixes,
+  /// and permits the same reductions after each of them.
+  ///
+  /// This uses Hopcroft's algorithm, where states are initially partitioned
+  /// by the reductions they permit. A reduction is identified by the head and
+  /// length of its production, which is all an LR parser needs to perform
+  /// it. The kernel of each merged state is the union of the kernels of the
+  /// states it replaces.
+  pub fn minimize(&self) -> Self {
+    let num_states = self.num_states();
+    // An implicit state that every missing transition leads to.
+    let dead = num_states;
+    let symbols = self
+      .transitions
+      .iter()
+      .flat_map(|transitions| transitions.keys())
+      .collect::<BTreeSet<_>>();
+    let target = |state: usize, elem: &Elem<&'a T, &'a NT>| {
+      self.transition(state, elem).unwrap_or(dead)
+    };
+
+    let mut blocks_by_label: BTreeMap<_, BTreeSet<usize>> = BTreeMap::new();
+    for state in 0..num_states {
+      blocks_by_label
+        .entry(Some(self.reduce_label(state)))
+        .or_default()
+        .insert(state);
+    }
+    blocks_by_label.entry(None).or_default().insert(dead);
+    let mut partition = blocks_by_label.into_values().collect::<Vec<_>>();
+
+    let mut inverse = BTreeMap::new();
+    for elem in &symbols {
+      let mut sources = vec![Vec::new(); num_states + 1];
+      for state in 0..=num_states {
+        sources[target(state, elem)].push(state);
+      }
+      inverse.insert(*elem, sources);
+    }
+
+    let mut work = partition.clone();
+    while let Some(splitter) = work.pop() {
+      for elem in &symbols {
+        let preimage = splitter
+          .iter()
+          .flat_map(|state| inverse[elem][*state].iter().copied())
+          .collect::<BTreeSet<_>>();
+        if preimage.is_empty() {
+          continue;
+        }
+
+        let mut next_partition = Vec::new();
+        for block in partition {
+          let (inside, outside): (BTreeSet<_>, BTreeSet<_>) =
+            block.iter().partition(|state| preimage.contains(state));
+          if inside.is_empty() || outside.is_empty() {
+            next_partition.push(block);
+            continue;
+          }
+
+          if let Some(pos) = work.iter().position(|w| *w == block) {
+            work.swap_remove(pos);
+            work.push(inside.clone());
+            work.push(outside.clone());
+          } else if inside.len() <= outside.len() {
+            work.push(inside.clone());
+          } else {
+            work.push(outside.clone());
+          }
+          next_partition.push(inside);
+          next_partition.push(outside);
+        }
+        partition = next_partition;
+      }
+    }
+
+    // The dead state is alone in its block, since it is the only state
+    // without a label.
+    partition.retain(|block| !block.contains(&dead));
+    partition.sort_by_key(|block| *block.iter().next().expect("non-empty"));
+    let mut block_of = vec![0; num_states];
+    for (i, block) in partition.iter().enumerate() {
+      for state in block {
+        block_of[*state] = i;
+      }
+    }
+
+    let kernels = partition
+      .iter()
+      .map(|block| {
+        block
+          .iter()
+          .flat_map(|state| self.kernels[*state].iter().copied())
+          .collect()
+      })
+      .collect();
+    let transitions = partition
+      .iter()
+      .map(|block| {
+        let repr = *block.iter().next().expect("non-empty");
+        self.transitions[repr]
+          .iter()
+          .map(|(elem, next)| (elem.clone(), block_of[*next]))
+          .collect()
+      })
+      .collect();
+
+    Lr0Automaton {
+      grammar: self.grammar,
+      prods: self.prods.clone(),
+      prods_by_head: self.prods_by_head.clone(),
+      kernels,
+      transitions,
+      accept_state: block_of[self.accept_state],
+    }
+  }
+
+  /// Whether `state` accepts, and the head and length of every production
+  /// that may be reduced in it.
+  fn reduce_label(&self, state: usize) -> (bool, BTreeSet<(&'a NT, usize)>) {
+    let reductions = self
+      .closure(&self.kernels[state])
+      .into_iter()
+      .filter(|item| self.next_elem(*item).is_none())
+      .map(|(prod, dot)| (self.prods[prod].head(), dot))
+      .collect();
+    (state == self.accept_state, reductions)
+  }
+
+  pub(crate) fn prod(&self, prod: usize) -> Prod<'a, T, NT, AK, AV> {
+    self.prods[prod]
+  }
+
+  /// Returns the indices of the productions of `nt`.
+  pub(crate) fn prods_of(&self, nt: &NT) -> &[usize] {
+    self.prods_by_head.get(nt).map_or(&[], Vec::as_slice)
+  }
+
+  pub(crate) fn next_elem(
+    &self,
+    (prod, dot): Item,
+  ) -> Option<&'a ProdElement<T, NT>> {
+    self.prods[prod].prod_elements().get(dot)
+  }
+
+  pub(crate) fn kernel(&self, state: usize) -> &BTreeSet<Item> {
+    &self.kernels[state]
+  }
+
+  /// Returns the closure of a set of kernel items.
+  pub(crate) fn closure(&self, kernel: &BTreeSet<Item>) -> BTreeSet<Item> {
+    let mut items = kernel.clone();
+    let mut stack = items.iter().copied().collect::<Vec<_>>();
+    while let Some(item) = stack.pop() {
+      if let Some(Elem::NonTerm(nt)) = self.next_elem(item).map(|e| e.elem()) {
+        for prod in self.prods_of(nt) {
+          if items.insert((*prod, 0)) {
+            stack.push((*prod, 0));
+          }
+        }
+      }
+    }
+    items
+  }
+}
+
+impl<'a, T, NT, AK, AV> Lr0Automaton<'a, T, NT, AK, AV>
+where
+  T: Ord + Clone + ToDoc + 'static,
+  NT: Ord + Clone + ToDoc + 'static,
+  AK: ToDoc,
+{
+  /// Returns the automaton as a CSV table, with one row per state.
+  ///
+  /// The columns are the terminals, the end of stream terminal `$` and the
+  /// nonterminals, in `SymbolTable` order. Terminal cells hold `s<n>` for a
+  /// shift to state `n`, or `acc` under `$` in the accept state, and
+  /// nonterminal cells hold the goto target. The final `reduce` column lists
+  /// the productions that are complete in the state, as `head:key`.
+  pub fn to_csv(&self) -> String {
+    let passes = PassContext::new(self.grammar);
+    let symbols = passes.get_infallible_pass::<SymbolTable<T, NT>>();
+    let terms = (0..symbols.num_terms()).map(|i| symbols.term(i));
+    let nonterms = (0..symbols.num_nonterms()).map(|i| symbols.nonterm(i));
+
+    let mut header = vec!["state".to_string()];
+    header.extend(terms.clone().map(doc_string));
+    header.push("$".to_string());
+    header.extend(nonterms.clone().map(doc_string));
+    header.push("reduce".to_string());
+    let mut rows = vec![header];
+
+    for state in 0..self.num_states() {
+      let transitions = &self.transitions[state];
+      let mut row = vec![state.to_string()];
+      row.extend(terms.clone().map(|t| {
+        transitions
+          .get(&Elem::Term(t))
+          .map_or_else(String::new, |next| format!("s{}", next))
+      }));
+      row.push(if state == self.accept_state {
+        "acc".to_string()
+      } else {
+        String::new()
+      });
+      row.extend(nonterms.clone().map(|nt| {
+        transitions
+          .get(&Elem::NonTerm(nt))
+          .map_or_else(String::new, usize::to_string)
+      }));
+      let reductions = self
+        .closure(&self.kernels[state])
+        .into_iter()
+        .filter(|item| self.next_elem(*item).is_none())
+        .map(|(prod, _)| {
+          let prod = self.prods[prod];
+          format!(
+            "{}:{}",
+            doc_string(prod.head()),
+            doc_string(prod.action_key())
+          )
+        });
+      row.push(reductions.collect::<Vec<_>>().join(" "));
+      rows.push(row);
+    }
+
+    rows
+      .iter()
+      .map(|row| {
+        let cells = row.iter().map(|cell| csv_escape(cell));
+        cells.collect::<Vec<_>>().join(",")
+      })
+      .collect::<Vec<_>>()
+      .join("\n")
+  }
+}
+
+impl<'a, T, NT, AK, AV> Lr0Automaton<'a, T, NT, AK, AV>
+where
+  T: Ord + ToDoc,
+  NT: Ord + ToDoc,
+{
+  /// Returns a regular expression for the viable prefixes of the grammar:
+  /// the sequences of symbols that may be on the stack of an LR parser.
+  ///
+  /// Every state of the automaton accepts, so this is computed by state
+  /// elimination, from an initial state into state 0, and from every state
+  /// into a final state. Symbols are written as they are displayed,
+  /// sequences are separated by spaces, and `|`, `*` and parentheses have
+  /// their usual meanings. `ε` is the empty sequence.
+  pub fn viable_prefix_regex(&self) -> String {
+    self.viable_prefix_ast().to_string()
+  }
+
+  fn viable_prefix_ast(&self) -> Regex {
+    let num_states = self.num_states();
+    let (start, end) = (num_states, num_states + 1);
+    let mut edges: BTreeMap<(usize, usize), Regex> = BTreeMap::new();
+    let add_edge = |edges: &mut BTreeMap<_, _>, from, to, regex| {
+      let regex = match edges.remove(&(from, to)) {
+        Some(prev) => Regex::alt(prev, regex),
+        None => regex,
+      };
+      edges.insert((from, to), regex);
+    };
+
+    add_edge(&mut edges, start, 0, Regex::Epsilon);
+    for state in 0..num_states {
+      add_edge(&mut edges, state, end, Regex::Epsilon);
+      for (elem, next) in self.transitions(state) {
+        let symbol = match elem {
+          Elem::Term(t) => doc_string(*t),
+          Elem::NonTerm(nt) => format!("<{}>", doc_string(*nt)),
+        };
+        add_edge(&mut edges, state, next, Regex::Symbol(symbol));
+      }
+    }
+
+    for state in 0..num_states {
+      let looped = edges.remove(&(state, state)).map(Regex::star);
+      let (incoming, outgoing): (Vec<_>, Vec<_>) = edges
+        .keys()
+        .copied()
+        .filter(|(from, to)| *from == state || *to == state)
+        .partition(|(_, to)| *to == state);
+      let incoming = incoming
+        .into_iter()
+        .map(|key| (key.0, edges.remove(&key).unwrap()))
+        .collect::<Vec<_>>();
+      let outgoing = outgoing
+        .into_iter()
+        .map(|key| (key.1, edges.remove(&key).unwrap()))
+        .collect::<Vec<_>>();
+
+      for (from, before) in &incoming {
+        for (to, after) in &outgoing {
+          let mut regex = before.clone();
+          if let Some(looped) = &looped {
+            regex = Regex::concat(regex, looped.clone());
+          }
+          add_edge(&mut edges, *from, *to, Regex::concat(regex, after.clone()));
+        }
+      }
+    }
+
+    edges.remove(&(start, end)).unwrap_or(Regex::Epsilon)
+  }
+}
+
+/// A regular expression over symbols, as built by `viable_prefix_regex`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Regex {
+  Epsilon,
+  Symbol(String),
+  Concat(Vec<Regex>),
+  Alt(BTreeSet<Regex>),
+  Star(Box<Regex>),
+}
+
+impl Regex {
+  fn concat(a: Regex, b: Regex) -> Regex {
+    let parts = |regex| match regex {
+      Regex::Epsilon => Vec::new(),
+      Regex::Concat(parts) => parts,
+      regex => vec![regex],
+    };
+    let mut all = parts(a);
+    all.extend(parts(b));
+    match all.len() {
+      0 => Regex::Epsilon,
+      1 => all.pop().unwrap(),
+      _ => Regex::Concat(all),
+    }
+  }
+
+  fn alt(a: Regex, b: Regex) -> Regex {
+    let alts = |regex| match regex {
+      Regex::Alt(alts) => alts,
+      regex => std::iter::once(regex).collect(),
+    };
+    let mut all = alts(a);
+    all.extend(alts(b));
+    if all.len() == 1 {
+      all.into_iter().next().unwrap()
+    } else {
+      Regex::Alt(all)
+    }
+  }
+
+  fn star(regex: Regex) -> Regex {
+    match regex {
+      Regex::Epsilon => Regex::Epsilon,
+      Regex::Star(inner) => Regex::Star(inner),
+      // `(ε | x)*` is the same as `x*`.
+      Regex::Alt(alts) if alts.contains(&Regex::Epsilon) => Regex::star(
+        alts
+          .into_iter()
+          .filter(|alt| alt != &Regex::Epsilon)
+          .fold(Regex::Alt(BTreeSet::new()), Regex::alt),
+      ),
+      regex => Regex::Star(Box::new(regex)),
+    }
+  }
+
+  /// Writes this expression, with parentheses around alternations inside
+  /// sequences and stars, and around sequences inside stars.
+  fn fmt_prec(
+    &self,
+    f: &mut std::fmt::Formatter,
+    prec: u8,
+  ) -> std::fmt::Result {
+    let (own_prec, parenthesize) = match self {
+      Regex::Alt(_) => (0, prec > 0),
+      Regex::Concat(_) => (1, prec > 1),
+      _ => (2, false),
+    };
+    if parenthesize {
+      write!(f, "(")?;
+    }
+    let mut fmt_items = |items: &mut dyn Iterator<Item = &Regex>, sep| {
+      for (i, item) in items.enumerate() {
+        if i > 0 {
+          write!(f, "{}", sep)?;
+        }
+        item.fmt_prec(f, own_prec + 1)?;
+      }
+      Ok(())
+    };
+    match self {
+      Regex::Epsilon => write!(f, "ε")?,
+      Regex::Symbol(symbol) => write!(f, "{}", symbol)?,
+      Regex::Concat(parts) => fmt_items(&mut parts.iter(), " ")?,
+      Regex::Alt(alts) => fmt_items(&mut alts.iter(), " | ")?,
+      Regex::Star(inner) => {
+        inner.fmt_prec(f, 2)?;
+        write!(f, "*")?;
+      }
+    }
+    if parenthesize {
+      write!(f, ")")?;
+    }
+    Ok(())
+  }
+}
+
+impl std::fmt::Display for Regex {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    self.fmt_prec(f, 0)
+  }
+}
+
+/// Quotes a CSV cell if it contains a separator, quote or line break.
+fn csv_escape(cell: &str) -> String {
+  if cell.contains(&[',', '"', '\n', '\r'][..]) {
+    format!("\"{}\"", cell.replace('"', "\"\""))
+  } else {
+    cell.to_string()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::{examples, NonTerminal, Terminal},
+    start_grammar::{wrap_grammar_with_start, StartNonTerminal},
+  };
+
+  #[test]
+  fn test_kernels_and_closures() {
+    let g = wrap_grammar_with_start(examples::make_paren()).unwrap();
+    let automaton = Lr0Automaton::new(&g);
+
+    let kernel = automaton.state_kernel(0);
+    assert_eq!(kernel.len(), 1);
+    assert_eq!(kernel[0].prod(), g.start_prod());
+    assert_eq!(kernel[0].index(), 0);
+
+    let items = automaton.state_items(0);
+    assert!(items.len() > kernel.len());
+    assert!(items.contains(&kernel[0]));
+    assert!(items.iter().any(|item| item.prod().head()
+      == &StartNonTerminal::NTerm(NonTerminal::new("expr"))));
+  }
+
+  #[test]
+  fn test_minimize_merges_equivalent_states() {
+    // The states after `A` and `B` behave identically, as do the states
+    // after `A x` and `B x`, and after `A x D` and `B x D`.
+    let g = examples::from_rules(&[
+      ("s", &[&["A", "x", "D"], &["B", "x", "D"]]),
+      ("x", &[&["C"]]),
+    ]);
+    let automaton = Lr0Automaton::new(&g);
+    let minimized = automaton.minimize();
+    assert_eq!(minimized.num_states(), automaton.num_states() - 3);
+
+    let terms = ["A", "B", "C", "D"].map(Terminal::new);
+    let nonterms = ["s", "x"].map(NonTerminal::new);
+    let symbols = terms
+      .iter()
+      .map(Elem::Term)
+      .chain(nonterms.iter().map(Elem::NonTerm))
+      .collect::<Vec<_>>();
+
+    let mut prefixes = vec![vec![]];
+    let mut frontier = vec![vec![]];
+    for _ in 0..4 {
+      frontier = frontier
+        .iter()
+        .flat_map(|prefix: &Vec<Elem<&Terminal, &NonTerminal>>| {
+          symbols.iter().map(move |elem| {
+            let mut prefix = prefix.clone();
+            prefix.push(elem.clone());
+            prefix
+          })
+        })
+        .collect();
+      prefixes.extend(frontier.iter().cloned());
+    }
+
+    for prefix in prefixes {
+      let state = automaton.walk(prefix.iter().cloned());
+      let min_state = minimized.walk(prefix.iter().cloned());
+      assert_eq!(state.is_some(), min_state.is_some());
+      if let (Some(state), Some(min_state)) = (state, min_state) {
+        assert_eq!(
+          automaton.reduce_label(state),
+          minimized.reduce_label(min_state)
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn test_minimal_automaton_is_unchanged() {
+    let g = examples::make_paren();
+    let automaton = Lr0Automaton::new(&g);
+    assert_eq!(automaton.minimize().num_states(), automaton.num_states());
+  }
+
+  #[test]
+  fn test_to_csv() {
+    let g = examples::make_paren();
+    let automaton = Lr0Automaton::new(&g);
+    let csv = automaton.to_csv();
+    let rows = csv.lines().collect::<Vec<_>>();
+
+    assert_eq!(rows[0], "state,LPAREN,RPAREN,$,expr,expr_list,start,reduce");
+    assert_eq!(rows.len(), automaton.num_states() + 1);
+    for (state, row) in rows[1..].iter().enumerate() {
+      let cells = row.split(',').collect::<Vec<_>>();
+      assert_eq!(cells.len(), 8);
+      assert_eq!(cells[0], state.to_string());
+      assert_eq!(cells[3] == "acc", state == automaton.accept_state());
+    }
+    assert!(rows[1].starts_with("0,s"));
+    assert!(rows.iter().any(|row| row.ends_with(",expr:paren_expr")));
+  }
+
+  /// Returns the end positions of the matches of `regex` in `input` that
+  /// start at `start`.
+  fn match_ends(
+    regex: &Regex,
+    input: &[String],
+    start: usize,
+  ) -> BTreeSet<usize> {
+    match regex {
+      Regex::Epsilon => std::iter::once(start).collect(),
+      Regex::Symbol(symbol) => {
+        if input.get(start) == Some(symbol) {
+          std::iter::once(start + 1).collect()
+        } else {
+          BTreeSet::new()
+        }
+      }
+      Regex::Concat(parts) => {
+        parts
+          .iter()
+          .fold(std::iter::once(start).collect(), |ends, part| {
+            ends
+              .into_iter()
+              .flat_map(|end| match_ends(part, input, end))
+              .collect()
+          })
+      }
+      Regex::Alt(alts) => alts
+        .iter()
+        .flat_map(|alt| match_ends(alt, input, start))
+        .collect(),
+      Regex::Star(inner) => {
+        let mut ends: BTreeSet<_> = std::iter::once(start).collect();
+        let mut stack = vec![start];
+        while let Some(end) = stack.pop() {
+          for next in match_ends(inner, input, end) {
+            if ends.insert(next) {
+              stack.push(next);
+            }
+          }
+        }
+        ends
+      }
+    }
+  }
+
+  #[test]
+  fn test_state_limit() {
+    let g = examples::make_paren();
+    let num_states = Lr0Automaton::new(&g).num_states();
+    assert_eq!(
+      Lr0Automaton::new_with_limit(&g, 1).err(),
+      Some(IterationLimitExceeded {
+        analysis: "LR(0) automaton construction",
+        limit: 1,
+      })
+    );
+    // The accepting state is not counted.
+    let automaton = Lr0Automaton::new_with_limit(&g, num_states - 1).unwrap();
+    assert_eq!(automaton.num_states(), num_states);
+  }
+
+  #[test]
+  fn test_viable_prefix_regex() {
+    let g = examples::from_rules(&[("s", &[&["A", "s"], &["B"]])]);
+    let automaton = Lr0Automaton::new(&g);
+    let regex = automaton.viable_prefix_ast();
+    assert_eq!(
+      automaton.viable_prefix_regex(),
+      "ε | <s> | B | A A* | A A* <s> | A A* B"
+    );
+
+    let (a, b, s) = (
+      Terminal::new("A"),
+      Terminal::new("B"),
+      NonTerminal::new("s"),
+    );
+    let symbols = [Elem::Term(&a), Elem::Term(&b), Elem::NonTerm(&s)];
+    let mut sequences = vec![Vec::new()];
+    for len in 0..4 {
+      let longer = sequences
+        .iter()
+        .filter(|seq| seq.len() == len)
+        .flat_map(|seq: &Vec<_>| {
+          symbols.iter().map(move |symbol| {
+            let mut seq = seq.clone();
+            seq.push(symbol.clone());
+            seq
+          })
+        })
+        .collect::<Vec<_>>();
+      sequences.extend(longer);
+    }
+
+    for seq in sequences {
+      let input = seq
+        .iter()
+        .map(|elem| match elem {
+          Elem::Term(t) => doc_string(*t),
+          Elem::NonTerm(nt) => format!("<{}>", doc_string(*nt)),
+        })
+        .collect::<Vec<_>>();
+      assert_eq!(
+        match_ends(&regex, &input, 0).contains(&input.len()),
+        automaton.walk(seq.iter().cloned()).is_some(),
+        "{:?}",
+        input
+      );
+    }
+  }
+}
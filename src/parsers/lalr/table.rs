@@ -0,0 +1,782 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! LALR(1) parse table construction.
+//!
+//! The table is built by first constructing the `Lr0Automaton` of the
+//! grammar, then propagating lookaheads between the kernel items of its
+//! states until a fixpoint is reached.
+
+use {
+  super::lr0::{Item, Lr0Automaton},
+  crate::{
+    grammar::{
+      ir::{DiagnosticIr, IrName, SeverityIr},
+      passes::{firsts::Firsts, nullable::NullableSet, PassContext},
+      Elem, Grammar, ProdElement, ProdKey,
+    },
+    parsers::coverage::CoverageTracker,
+    start_grammar::StreamTerminal,
+  },
+  std::collections::{BTreeMap, BTreeSet},
+};
+
+/// An action of an LR parser, taken on a lookahead terminal.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Action<NT, AK> {
+  /// Consume the lookahead, and push the given state.
+  Shift(usize),
+  /// Pop `len` states, then push the goto state of the production's head.
+  Reduce { prod: ProdKey<NT, AK>, len: usize },
+  /// The input has been parsed successfully.
+  Accept,
+}
+
+/// Multiple actions that are possible in the same state on the same
+/// lookahead.
+///
+/// Conflicts are ordered by state, then by lookahead.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Conflict<T, NT, AK> {
+  state: usize,
+  lookahead: StreamTerminal<T>,
+  actions: Vec<Action<NT, AK>>,
+  items: Vec<ConflictItem<T, NT, AK>>,
+  kernel: Vec<(ProdKey<NT, AK>, usize)>,
+  predecessors: Vec<usize>,
+}
+
+/// An LR(1) item of a conflicting state that leads to one of the conflicting
+/// actions: either a complete item whose lookaheads include the conflicting
+/// terminal, or an item with that terminal after the dot.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct ConflictItem<T, NT, AK> {
+  pub prod: ProdKey<NT, AK>,
+  /// The number of elements of the production before the dot.
+  pub dot: usize,
+  /// All of the lookaheads of the item in the state, after LALR(1) merging.
+  pub lookaheads: BTreeSet<StreamTerminal<T>>,
+}
+
+impl<T, NT, AK> Conflict<T, NT, AK> {
+  pub fn state(&self) -> usize {
+    self.state
+  }
+
+  pub fn lookahead(&self) -> &StreamTerminal<T> {
+    &self.lookahead
+  }
+
+  /// The conflicting actions, in sorted order.
+  pub fn actions(&self) -> &[Action<NT, AK>] {
+    &self.actions
+  }
+
+  /// The items that lead to the conflicting actions, in sorted order.
+  ///
+  /// For a reduce/reduce conflict, these are the complete items of the
+  /// competing productions.
+  pub fn items(&self) -> &[ConflictItem<T, NT, AK>] {
+    &self.items
+  }
+
+  /// The kernel items of the conflicting state, as productions with the
+  /// number of elements before the dot.
+  pub fn kernel(&self) -> &[(ProdKey<NT, AK>, usize)] {
+    &self.kernel
+  }
+
+  /// The states with a transition to the conflicting state.
+  ///
+  /// In an LALR(1) table, the lookaheads of each of these contexts are
+  /// merged into the one state, so a reduce/reduce conflict that is not
+  /// LR(1) comes from lookaheads arriving from different predecessors.
+  pub fn predecessors(&self) -> &[usize] {
+    &self.predecessors
+  }
+
+  /// Returns true if one of the conflicting actions is a shift.
+  pub fn is_shift_reduce(&self) -> bool {
+    self
+      .actions
+      .iter()
+      .any(|action| matches!(action, Action::Shift(_)))
+  }
+}
+
+/// A conflict that was resolved by a callback, along with the action it
+/// chose.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ResolvedConflict<T, NT, AK> {
+  pub conflict: Conflict<T, NT, AK>,
+  pub chosen: Action<NT, AK>,
+}
+
+#[derive(thiserror::Error, Clone, Debug)]
+#[error("The grammar has {} unresolved LALR(1) conflicts", .conflicts.len())]
+pub struct Conflicts<T, NT, AK> {
+  conflicts: Vec<Conflict<T, NT, AK>>,
+}
+
+impl<T, NT, AK> Conflicts<T, NT, AK> {
+  /// The unresolved conflicts, sorted by state and then by lookahead.
+  pub fn conflicts(&self) -> &[Conflict<T, NT, AK>] {
+    &self.conflicts
+  }
+
+  pub fn iter(&self) -> std::slice::Iter<'_, Conflict<T, NT, AK>> {
+    self.conflicts.iter()
+  }
+}
+
+impl<T, NT, AK> Conflicts<T, NT, AK>
+where
+  T: IrName,
+  NT: Ord + IrName,
+{
+  /// Returns one error diagnostic per conflict, listing the lookahead
+  /// terminal and the heads of the conflicting reductions.
+  pub fn to_diagnostics(&self) -> Vec<DiagnosticIr> {
+    self
+      .iter()
+      .map(|conflict| {
+        let (code, kind) = if conflict.is_shift_reduce() {
+          ("shift_reduce_conflict", "shift/reduce")
+        } else {
+          ("reduce_reduce_conflict", "reduce/reduce")
+        };
+        let lookahead = match conflict.lookahead() {
+          StreamTerminal::Term(t) => Some(t.ir_name()),
+          StreamTerminal::EndOfStream => None,
+        };
+        let heads = conflict
+          .actions()
+          .iter()
+          .filter_map(|action| match action {
+            Action::Reduce { prod, .. } => Some(prod.head()),
+            _ => None,
+          })
+          .collect::<BTreeSet<_>>();
+        DiagnosticIr {
+          severity: SeverityIr::Error,
+          code: code.to_string(),
+          message: format!(
+            "state {} has a {} conflict on {}",
+            conflict.state(),
+            kind,
+            lookahead.as_deref().unwrap_or("the end of input")
+          ),
+          symbols: lookahead
+            .into_iter()
+            .chain(heads.into_iter().map(IrName::ir_name))
+            .collect(),
+        }
+      })
+      .collect()
+  }
+}
+
+impl<T, NT, AK> IntoIterator for Conflicts<T, NT, AK> {
+  type Item = Conflict<T, NT, AK>;
+  type IntoIter = std::vec::IntoIter<Conflict<T, NT, AK>>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.conflicts.into_iter()
+  }
+}
+
+impl<'a, T, NT, AK> IntoIterator for &'a Conflicts<T, NT, AK> {
+  type Item = &'a Conflict<T, NT, AK>;
+  type IntoIter = std::slice::Iter<'a, Conflict<T, NT, AK>>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.conflicts.iter()
+  }
+}
+
+/// An LALR(1) action and goto table.
+#[derive(Clone, Debug)]
+pub struct LalrTable<T, NT, AK> {
+  pub(super) actions: Vec<BTreeMap<StreamTerminal<T>, Action<NT, AK>>>,
+  pub(super) gotos: Vec<BTreeMap<NT, usize>>,
+  pub(super) resolved: Vec<ResolvedConflict<T, NT, AK>>,
+}
+
+impl<T, NT, AK> LalrTable<T, NT, AK>
+where
+  T: Ord,
+  NT: Ord,
+{
+  /// The number of states in the table. The initial state is always 0.
+  pub fn num_states(&self) -> usize {
+    self.actions.len()
+  }
+
+  pub fn action(
+    &self,
+    state: usize,
+    lookahead: &StreamTerminal<T>,
+  ) -> Option<&Action<NT, AK>> {
+    self.actions.get(state)?.get(lookahead)
+  }
+
+  /// Returns the actions of `state`, in lookahead order.
+  pub fn state_actions(
+    &self,
+    state: usize,
+  ) -> impl Iterator<Item = (&StreamTerminal<T>, &Action<NT, AK>)> {
+    self.actions.get(state).into_iter().flatten()
+  }
+
+  pub fn goto(&self, state: usize, nt: &NT) -> Option<usize> {
+    self.gotos.get(state)?.get(nt).copied()
+  }
+
+  /// The conflicts that were resolved while building the table.
+  pub fn resolved_conflicts(&self) -> &[ResolvedConflict<T, NT, AK>] {
+    &self.resolved
+  }
+
+  /// Runs the table on the given terminals, and returns true if they form a
+  /// sentence of the grammar.
+  pub fn accepts(&self, input: impl IntoIterator<Item = T>) -> bool {
+    self.run(input, |_| {})
+  }
+
+  /// Like `accepts`, but records each reduced production in `coverage`.
+  ///
+  /// Reductions made before a syntax error is found are recorded too.
+  pub fn accepts_with_coverage(
+    &self,
+    input: impl IntoIterator<Item = T>,
+    coverage: &mut CoverageTracker<NT, AK>,
+  ) -> bool
+  where
+    NT: Clone,
+    AK: Ord + Clone,
+  {
+    self.run(input, |prod| coverage.record(prod))
+  }
+
+  fn run(
+    &self,
+    input: impl IntoIterator<Item = T>,
+    mut on_reduce: impl FnMut(&ProdKey<NT, AK>),
+  ) -> bool {
+    let mut input = input
+      .into_iter()
+      .map(StreamTerminal::Term)
+      .chain(std::iter::once(StreamTerminal::EndOfStream));
+    let mut lookahead = input.next();
+    let mut stack = vec![0];
+
+    while let Some(la) = &lookahead {
+      let state = *stack.last().expect("the stack is never empty");
+      match self.action(state, la) {
+        None => return false,
+        Some(Action::Accept) => return true,
+        Some(Action::Shift(next)) => {
+          stack.push(*next);
+          lookahead = input.next();
+        }
+        Some(Action::Reduce { prod, len }) => {
+          on_reduce(prod);
+          stack.truncate(stack.len() - len);
+          let top = *stack.last().expect("the stack is never empty");
+          match self.goto(top, prod.head()) {
+            Some(next) => stack.push(next),
+            None => return false,
+          }
+        }
+      }
+    }
+
+    false
+  }
+}
+
+/// Builds the LALR(1) table of a grammar, failing if there are any
+/// conflicts.
+pub fn build_lalr_table<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+) -> Result<LalrTable<T, NT, AK>, Conflicts<T, NT, AK>>
+where
+  T: Ord + Clone + 'static,
+  NT: Ord + Clone + 'static,
+  AK: Ord + Clone + 'static,
+{
+  build_lalr_table_with_resolver(g, |_| None)
+}
+
+/// Builds the LALR(1) table of a grammar, calling `resolve` on each conflict.
+///
+/// `resolve` may return one of the conflicting actions, which is then used in
+/// the table, or `None` to report the conflict as an error. Returning an
+/// action that is not part of the conflict leaves the conflict unresolved.
+/// Resolved conflicts are recorded in `LalrTable::resolved_conflicts`.
+pub fn build_lalr_table_with_resolver<T, NT, AK, AV>(
+  g: &Grammar<T, NT, AK, AV>,
+  resolve: impl Fn(&Conflict<T, NT, AK>) -> Option<Action<NT, AK>>,
+) -> Result<LalrTable<T, NT, AK>, Conflicts<T, NT, AK>>
+where
+  T: Ord + Clone + 'static,
+  NT: Ord + Clone + 'static,
+  AK: Ord + Clone + 'static,
+{
+  let passes = PassContext::new(g);
+  let nullables = passes
+    .get_pass::<NullableSet<NT>>()
+    .expect("nullable sets are defined for every grammar");
+  let firsts = passes
+    .get_pass::<Firsts<T, NT>>()
+    .expect("FIRST sets are defined for every grammar");
+  let automaton = Lr0Automaton::new(g);
+  let builder = TableBuilder {
+    automaton: &automaton,
+    firsts: &firsts,
+    nullables: &nullables,
+  };
+  let lookaheads = builder.lookaheads();
+
+  let mut table = LalrTable {
+    actions: Vec::new(),
+    gotos: Vec::new(),
+    resolved: Vec::new(),
+  };
+  let mut conflicts = Vec::new();
+
+  let mut predecessors = vec![BTreeSet::new(); automaton.num_states()];
+  for state in 0..automaton.num_states() {
+    for (_, next) in automaton.transitions(state) {
+      predecessors[next].insert(state);
+    }
+  }
+
+  for (state, items) in lookaheads.iter().enumerate() {
+    let mut candidates: BTreeMap<StreamTerminal<T>, BTreeSet<Action<NT, AK>>> =
+      BTreeMap::new();
+    let mut gotos = BTreeMap::new();
+
+    for (elem, next) in automaton.transitions(state) {
+      match elem {
+        Elem::Term(t) => {
+          candidates
+            .entry(StreamTerminal::Term((*t).clone()))
+            .or_default()
+            .insert(Action::Shift(next));
+        }
+        Elem::NonTerm(nt) => {
+          gotos.insert((*nt).clone(), next);
+        }
+      }
+    }
+
+    for ((prod, dot), la) in items {
+      let prod = automaton.prod(*prod);
+      if *dot != prod.num_elements() {
+        continue;
+      }
+      for t in la {
+        candidates
+          .entry(t.clone())
+          .or_default()
+          .insert(Action::Reduce {
+            prod: prod.prod_key(),
+            len: *dot,
+          });
+      }
+    }
+
+    if state == automaton.accept_state() {
+      candidates
+        .entry(StreamTerminal::EndOfStream)
+        .or_default()
+        .insert(Action::Accept);
+    }
+
+    let mut actions = BTreeMap::new();
+    for (lookahead, options) in candidates {
+      let mut options = options.into_iter().collect::<Vec<_>>();
+      if options.len() == 1 {
+        actions.insert(lookahead, options.pop().expect("checked length"));
+        continue;
+      }
+
+      let conflict = Conflict {
+        items: conflict_items(&automaton, items, &lookahead),
+        kernel: automaton
+          .kernel(state)
+          .iter()
+          .map(|(prod, dot)| (automaton.prod(*prod).prod_key(), *dot))
+          .collect(),
+        predecessors: predecessors[state].iter().copied().collect(),
+        state,
+        lookahead,
+        actions: options,
+      };
+      match resolve(&conflict) {
+        Some(chosen) if conflict.actions.contains(&chosen) => {
+          actions.insert(conflict.lookahead.clone(), chosen.clone());
+          table.resolved.push(ResolvedConflict { conflict, chosen });
+        }
+        _ => conflicts.push(conflict),
+      }
+    }
+
+    table.actions.push(actions);
+    table.gotos.push(gotos);
+  }
+
+  if conflicts.is_empty() {
+    Ok(table)
+  } else {
+    // States and lookaheads are visited in order, so this is already sorted,
+    // but sort anyway so that reports stay stable if that ever changes.
+    conflicts.sort();
+    Err(Conflicts { conflicts })
+  }
+}
+
+type ItemLookaheads<T> = BTreeMap<Item, BTreeSet<StreamTerminal<T>>>;
+
+/// Returns the items of a state that shift or reduce on `lookahead`.
+fn conflict_items<T, NT, AK, AV>(
+  automaton: &Lr0Automaton<T, NT, AK, AV>,
+  items: &ItemLookaheads<T>,
+  lookahead: &StreamTerminal<T>,
+) -> Vec<ConflictItem<T, NT, AK>>
+where
+  T: Ord + Clone,
+  NT: Ord + Clone,
+  AK: Ord + Clone,
+{
+  let mut found = items
+    .iter()
+    .filter(|(item, la)| match automaton.next_elem(**item) {
+      Some(elem) => match (elem.elem(), lookahead) {
+        (Elem::Term(t), StreamTerminal::Term(la)) => t == la,
+        _ => false,
+      },
+      None => la.contains(lookahead),
+    })
+    .map(|((prod, dot), la)| ConflictItem {
+      prod: automaton.prod(*prod).prod_key(),
+      dot: *dot,
+      lookaheads: la.clone(),
+    })
+    .collect::<Vec<_>>();
+  found.sort();
+  found
+}
+
+struct TableBuilder<'a, 'p, T, NT, AK, AV> {
+  automaton: &'p Lr0Automaton<'a, T, NT, AK, AV>,
+  firsts: &'p Firsts<T, NT>,
+  nullables: &'p NullableSet<NT>,
+}
+
+impl<'a, 'p, T, NT, AK, AV> TableBuilder<'a, 'p, T, NT, AK, AV>
+where
+  T: Ord + Clone + 'static,
+  NT: Ord + Clone + 'static,
+  AK: Ord + Clone + 'static,
+{
+  /// Returns the terminals that may begin `elems` followed by a terminal of
+  /// `tail`.
+  fn first_of_seq(
+    &self,
+    elems: &[ProdElement<T, NT>],
+    tail: &BTreeSet<StreamTerminal<T>>,
+  ) -> BTreeSet<StreamTerminal<T>> {
+    let mut result = BTreeSet::new();
+    for elem in elems {
+      match elem.elem() {
+        Elem::Term(t) => {
+          result.insert(StreamTerminal::Term(t.clone()));
+          return result;
+        }
+        Elem::NonTerm(nt) => {
+          result
+            .extend(self.firsts.terms(nt).cloned().map(StreamTerminal::Term));
+          if !self.nullables.is_nullable(nt) {
+            return result;
+          }
+        }
+      }
+    }
+    result.extend(tail.iter().cloned());
+    result
+  }
+
+  /// Returns the closure of a set of kernel items, along with their
+  /// lookaheads.
+  fn closure(&self, kernel: &ItemLookaheads<T>) -> ItemLookaheads<T> {
+    let mut items = kernel.clone();
+    let mut stack = items.keys().copied().collect::<Vec<_>>();
+    while let Some(item) = stack.pop() {
+      let nt = match self.automaton.next_elem(item).map(ProdElement::elem) {
+        Some(Elem::NonTerm(nt)) => nt,
+        _ => continue,
+      };
+      let rest = &self.automaton.prod(item.0).prod_elements()[item.1 + 1..];
+      let la = self.first_of_seq(rest, &items[&item]);
+      for prod in self.automaton.prods_of(nt) {
+        let new_item = (*prod, 0);
+        let entry = items.entry(new_item);
+        let is_new =
+          matches!(entry, std::collections::btree_map::Entry::Vacant(_));
+        let set = entry.or_default();
+        let old_len = set.len();
+        set.extend(la.iter().cloned());
+        if is_new || set.len() != old_len {
+          stack.push(new_item);
+        }
+      }
+    }
+    items
+  }
+
+  /// Returns the closure of every state of the automaton, with LALR(1)
+  /// lookaheads.
+  fn lookaheads(&self) -> Vec<ItemLookaheads<T>> {
+    let automaton = self.automaton;
+    let mut kernels = (0..automaton.num_states())
+      .map(|state| {
+        automaton
+          .kernel(state)
+          .iter()
+          .map(|item| (*item, BTreeSet::new()))
+          .collect::<ItemLookaheads<T>>()
+      })
+      .collect::<Vec<_>>();
+    for la in kernels[0].values_mut() {
+      la.insert(StreamTerminal::EndOfStream);
+    }
+
+    loop {
+      let mut changed = false;
+      for state in 0..kernels.len() {
+        for (item, la) in self.closure(&kernels[state]) {
+          let next = match automaton.next_elem(item) {
+            Some(elem) => automaton
+              .transition(state, &elem.elem().as_ref())
+              .expect("every next element has a transition"),
+            None => continue,
+          };
+          let next_la = kernels[next]
+            .get_mut(&(item.0, item.1 + 1))
+            .expect("the advanced item is in the kernel of the next state");
+          let old_len = next_la.len();
+          next_la.extend(la);
+          changed |= next_la.len() != old_len;
+        }
+      }
+      if !changed {
+        break;
+      }
+    }
+
+    kernels.iter().map(|kernel| self.closure(kernel)).collect()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::grammar::{
+    examples, gen::bounded_sentences, NonTerminal, Terminal,
+  };
+  use crate::utils::Name;
+
+  fn terms(names: &[&str]) -> Vec<Terminal> {
+    names.iter().map(|name| Terminal::new(name)).collect()
+  }
+
+  #[test]
+  fn test_conflict_free() {
+    let table = build_lalr_table(&examples::make_paren()).unwrap();
+    assert!(table.resolved_conflicts().is_empty());
+    assert!(table.accepts(terms(&["LPAREN", "RPAREN"])));
+    assert!(!table.accepts(terms(&[])));
+    assert!(table.accepts(terms(&["LPAREN", "LPAREN", "RPAREN", "RPAREN"])));
+    assert!(!table.accepts(terms(&["LPAREN"])));
+    assert!(!table.accepts(terms(&["RPAREN", "LPAREN"])));
+
+    // The table accepts exactly the short sentences of a grammar with
+    // nullable nonterminals.
+    let g = examples::from_rules(&[
+      ("s", &[&["opt", "a", "opt"]]),
+      ("opt", &[&[], &["X"]]),
+      ("a", &[&["A", "a"], &["B"]]),
+    ]);
+    let table = build_lalr_table(&g).unwrap();
+    let sentences = bounded_sentences(&g, 5);
+    let mut inputs = vec![vec![]];
+    let mut frontier = vec![vec![]];
+    for _ in 0..5 {
+      frontier = frontier
+        .iter()
+        .flat_map(|input: &Vec<Terminal>| {
+          terms(&["X", "A", "B"]).into_iter().map(move |t| {
+            let mut input = input.clone();
+            input.push(t);
+            input
+          })
+        })
+        .collect();
+      inputs.extend(frontier.iter().cloned());
+    }
+    for input in inputs {
+      assert_eq!(table.accepts(input.clone()), sentences.contains(&input));
+    }
+
+    let table = build_lalr_table(&examples::make_left_recursive()).unwrap();
+    assert!(table.accepts(terms(&["NUM", "PLUS", "NUM", "PLUS", "NUM"])));
+    assert!(table.accepts(terms(&["LPAREN", "NUM", "RPAREN", "PLUS", "NUM"])));
+    assert!(!table.accepts(terms(&["NUM", "PLUS"])));
+  }
+
+  #[test]
+  fn test_resolve_shift_reduce() {
+    let g =
+      examples::from_rules(&[("expr", &[&["expr", "PLUS", "expr"], &["NUM"]])]);
+
+    let conflicts = build_lalr_table(&g).unwrap_err();
+    assert_eq!(conflicts.conflicts().len(), 1);
+    assert!(conflicts.conflicts()[0].is_shift_reduce());
+
+    let table = build_lalr_table_with_resolver(&g, |conflict| {
+      conflict
+        .actions()
+        .iter()
+        .find(|action| matches!(action, Action::Shift(_)))
+        .cloned()
+    })
+    .unwrap();
+    let resolved = table.resolved_conflicts();
+    assert_eq!(resolved.len(), 1);
+    assert_eq!(resolved[0].conflict, conflicts.conflicts()[0]);
+    assert!(matches!(resolved[0].chosen, Action::Shift(_)));
+
+    assert!(table.accepts(terms(&["NUM"])));
+    assert!(table.accepts(terms(&["NUM", "PLUS", "NUM", "PLUS", "NUM"])));
+    assert!(!table.accepts(terms(&["NUM", "PLUS"])));
+    assert!(!table.accepts(terms(&["PLUS", "NUM"])));
+  }
+
+  #[test]
+  fn test_reduce_reduce_context() {
+    // After `A E` or `F E`, both `x` and `y` may be reduced. `x` is followed
+    // by `C` in the first production and `y` by `D`, but the third and
+    // fourth swap them, and both prefixes arrive at the same LR(0) state
+    // after `E`.
+    let g = examples::from_rules(&[
+      (
+        "s",
+        &[
+          &["A", "x", "C"],
+          &["A", "y", "D"],
+          &["F", "x", "D"],
+          &["F", "y", "C"],
+        ],
+      ),
+      ("x", &[&["E"]]),
+      ("y", &[&["E"]]),
+    ]);
+
+    let conflicts = build_lalr_table(&g).unwrap_err();
+    assert_eq!(conflicts.conflicts().len(), 2);
+    let conflict = &conflicts.conflicts()[0];
+    assert!(!conflict.is_shift_reduce());
+    assert_eq!(
+      conflict.lookahead(),
+      &StreamTerminal::Term(Terminal::new("C"))
+    );
+
+    let lookaheads = terms(&["C", "D"])
+      .into_iter()
+      .map(StreamTerminal::Term)
+      .collect::<BTreeSet<_>>();
+    let key =
+      |head: &str| ProdKey::new(NonTerminal::new(head), Name::new("p0"));
+    assert_eq!(
+      conflict.items(),
+      &[
+        ConflictItem {
+          prod: key("x"),
+          dot: 1,
+          lookaheads: lookaheads.clone(),
+        },
+        ConflictItem {
+          prod: key("y"),
+          dot: 1,
+          lookaheads,
+        },
+      ]
+    );
+    assert_eq!(conflict.kernel(), &[(key("x"), 1), (key("y"), 1)]);
+
+    // The state of `E` is reached both after `A` and after `F`.
+    let automaton = Lr0Automaton::new(&g);
+    let (a, e, f) =
+      (Terminal::new("A"), Terminal::new("E"), Terminal::new("F"));
+    let after = |first| {
+      let state = automaton.walk(vec![Elem::Term(first)]).unwrap();
+      assert_eq!(
+        automaton.transition(state, &Elem::Term(&e)),
+        Some(conflict.state())
+      );
+      state
+    };
+    let mut expected = [after(&a), after(&f)];
+    expected.sort_unstable();
+    assert_eq!(conflict.predecessors(), &expected[..]);
+  }
+
+  #[test]
+  fn test_conflict_order() {
+    let g = examples::from_rules(&[(
+      "expr",
+      &[
+        &["expr", "PLUS", "expr"],
+        &["expr", "TIMES", "expr"],
+        &["NUM"],
+      ],
+    )]);
+
+    let conflicts = build_lalr_table(&g).unwrap_err();
+    assert_eq!(conflicts.conflicts().len(), 4);
+    let keys = conflicts
+      .iter()
+      .map(|conflict| (conflict.state(), conflict.lookahead().clone()))
+      .collect::<Vec<_>>();
+    let mut sorted = keys.clone();
+    sorted.sort();
+    assert_eq!(keys, sorted);
+
+    let again = build_lalr_table(&g).unwrap_err();
+    assert_eq!(
+      (&conflicts).into_iter().collect::<Vec<_>>(),
+      again.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(again.into_iter().collect::<Vec<_>>(), conflicts.conflicts());
+
+    let diagnostics = conflicts.to_diagnostics();
+    assert_eq!(diagnostics.len(), 4);
+    assert!(diagnostics.iter().all(|diagnostic| {
+      diagnostic.code == "shift_reduce_conflict"
+        && diagnostic.symbols.last().map(String::as_str) == Some("expr")
+    }));
+  }
+}
@@ -0,0 +1,307 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Adapters from lexers to the input of the parsers.
+//!
+//! Most lexers, including the popular lexer crates, produce an iterator of
+//! token kinds paired with the span of the source they cover. `FromTokenIter`
+//! turns such an iterator into the `Token`s the parsers take, keeping the
+//! span as the value of each token.
+//!
+//! For grammars whose terminals are all fixed strings, `LiteralLexer` is a
+//! small table-driven lexer that produces such an iterator directly.
+
+use {
+  crate::{parsers::Token, start_grammar::StreamTerminal},
+  std::{collections::BTreeMap, ops::Range},
+};
+
+/// A range of byte offsets in the source.
+///
+/// Unlike `Range<usize>`, this is ordered, which the parsers require of
+/// token values.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+}
+
+impl From<Range<usize>> for Span {
+  fn from(range: Range<usize>) -> Self {
+    Span {
+      start: range.start,
+      end: range.end,
+    }
+  }
+}
+
+/// Wraps an iterator of `(kind, span)` pairs as an iterator of tokens. The
+/// spans may be any type that converts into a `Span`, such as the
+/// `Range<usize>`s most lexers produce.
+pub struct FromTokenIter<I> {
+  inner: I,
+  /// The end of the span of the last token yielded so far.
+  end: usize,
+}
+
+impl<I> FromTokenIter<I> {
+  pub fn new(iter: impl IntoIterator<IntoIter = I>) -> Self {
+    FromTokenIter {
+      inner: iter.into_iter(),
+      end: 0,
+    }
+  }
+
+  /// The empty span just after the last token yielded so far, which is
+  /// where the end of stream is reported.
+  pub fn end_span(&self) -> Span {
+    Span {
+      start: self.end,
+      end: self.end,
+    }
+  }
+
+  /// Returns an iterator over the remaining tokens as terminals, followed by
+  /// a synthesized `EndOfStream` at the end of the last token.
+  pub fn with_end_of_stream<T, S>(
+    mut self,
+  ) -> impl Iterator<Item = (StreamTerminal<T>, Span)>
+  where
+    I: Iterator<Item = (T, S)>,
+    S: Into<Span>,
+  {
+    let mut done = false;
+    std::iter::from_fn(move || {
+      if done {
+        return None;
+      }
+      match self.next() {
+        Some(token) => {
+          let (kind, span) = token.into_parts();
+          Some((StreamTerminal::Term(kind), span))
+        }
+        None => {
+          done = true;
+          Some((StreamTerminal::EndOfStream, self.end_span()))
+        }
+      }
+    })
+  }
+}
+
+impl<T, S, I> Iterator for FromTokenIter<I>
+where
+  I: Iterator<Item = (T, S)>,
+  S: Into<Span>,
+{
+  type Item = Token<T, Span>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let (kind, span) = self.inner.next()?;
+    let span = span.into();
+    self.end = self.end.max(span.end);
+    Some(Token::new(kind, span))
+  }
+}
+
+#[derive(thiserror::Error, Clone, PartialEq, Eq, Debug)]
+pub enum LexError {
+  #[error("the literal {0:?} is given to more than one terminal")]
+  DuplicateLiteral(String),
+  #[error("the empty string cannot be a literal")]
+  EmptyLiteral,
+  #[error("no literal matches the source at byte {0}")]
+  NoMatch(usize),
+}
+
+/// A lexer for terminals that each match one fixed string, which skips
+/// whitespace between tokens.
+///
+/// At each position the longest matching literal wins, so with the literals
+/// `=` and `==`, the source `==` is a single `==` token. Pairs of literals
+/// where this choice is made can be listed with `prefix_overlaps`.
+#[derive(Clone, Debug)]
+pub struct LiteralLexer<T> {
+  literals: BTreeMap<String, T>,
+}
+
+impl<T: Clone> LiteralLexer<T> {
+  /// Creates a lexer from `(literal, terminal)` pairs. Fails if a literal is
+  /// empty or appears more than once, since either would make some sources
+  /// ambiguous no matter how they are split.
+  pub fn new<'a>(
+    literals: impl IntoIterator<Item = (&'a str, T)>,
+  ) -> Result<Self, LexError> {
+    let mut map = BTreeMap::new();
+    for (literal, term) in literals {
+      if literal.is_empty() {
+        return Err(LexError::EmptyLiteral);
+      }
+      if map.insert(literal.to_string(), term).is_some() {
+        return Err(LexError::DuplicateLiteral(literal.to_string()));
+      }
+    }
+    Ok(LiteralLexer { literals: map })
+  }
+
+  /// Returns each pair of literals where the first is a proper prefix of the
+  /// second. Sources containing the longer literal could also be split into
+  /// the shorter one followed by something else, and are always lexed as
+  /// the longer one.
+  pub fn prefix_overlaps(&self) -> Vec<(&str, &str)> {
+    let mut overlaps = Vec::new();
+    for short in self.literals.keys() {
+      // Literals starting with `short` sort right after it.
+      let longer = self
+        .literals
+        .range::<String, _>(short..)
+        .skip(1)
+        .take_while(|(long, _)| long.starts_with(short.as_str()));
+      overlaps.extend(longer.map(|(long, _)| (short.as_str(), long.as_str())));
+    }
+    overlaps
+  }
+
+  /// Splits `source` into tokens, each paired with the range of bytes it
+  /// covers.
+  pub fn tokenize(
+    &self,
+    source: &str,
+  ) -> Result<Vec<(T, Range<usize>)>, LexError> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < source.len() {
+      let rest = &source[position..];
+      let trimmed = rest.trim_start();
+      if trimmed.is_empty() {
+        break;
+      }
+      position += rest.len() - trimmed.len();
+
+      let (literal, term) = self
+        .literals
+        .iter()
+        .filter(|(literal, _)| trimmed.starts_with(literal.as_str()))
+        .max_by_key(|(literal, _)| literal.len())
+        .ok_or(LexError::NoMatch(position))?;
+      tokens.push((term.clone(), position..position + literal.len()));
+      position += literal.len();
+    }
+    Ok(tokens)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::{examples, Terminal},
+    parsers::{earley, tree::TreeOwner},
+    start_grammar::wrap_grammar_with_start,
+  };
+
+  /// A hand-rolled lexer for parentheses, which skips whitespace.
+  fn lex(source: &str) -> impl Iterator<Item = (Terminal, Range<usize>)> + '_ {
+    source.char_indices().filter_map(|(i, c)| {
+      let kind = match c {
+        '(' => "LPAREN",
+        ')' => "RPAREN",
+        _ => return None,
+      };
+      Some((Terminal::new(kind), i..i + 1))
+    })
+  }
+
+  #[test]
+  fn test_earley_from_lexer() {
+    let g = wrap_grammar_with_start(examples::make_paren()).unwrap();
+    let tree: TreeOwner<_, _, Span> = TreeOwner::new();
+
+    let tokens = FromTokenIter::new(lex("( () ( ) )")).collect::<Vec<_>>();
+    assert_eq!(tokens.len(), 6);
+    assert_eq!(tokens[5].value(), &Span::from(9..10));
+    assert!(earley::parse(&g, &tree.handle(), tokens).is_some());
+
+    let tokens = FromTokenIter::new(lex("( ()")).collect();
+    assert!(earley::parse(&g, &tree.handle(), tokens).is_none());
+  }
+
+  #[test]
+  fn test_end_of_stream() {
+    let stream = FromTokenIter::new(lex(" ( ) ")).with_end_of_stream();
+    assert_eq!(
+      stream.collect::<Vec<_>>(),
+      vec![
+        (
+          StreamTerminal::Term(Terminal::new("LPAREN")),
+          Span::from(1..2)
+        ),
+        (
+          StreamTerminal::Term(Terminal::new("RPAREN")),
+          Span::from(3..4)
+        ),
+        (StreamTerminal::EndOfStream, Span::from(4..4)),
+      ]
+    );
+
+    let empty = FromTokenIter::new(lex("")).with_end_of_stream();
+    assert_eq!(
+      empty.collect::<Vec<_>>(),
+      vec![(StreamTerminal::EndOfStream, Span::from(0..0))]
+    );
+  }
+
+  #[test]
+  fn test_literal_lexer_maximal_munch() {
+    let lexer = LiteralLexer::new(vec![
+      ("=", Terminal::new("ASSIGN")),
+      ("==", Terminal::new("EQ")),
+      ("!=", Terminal::new("NE")),
+      ("x", Terminal::new("X")),
+    ])
+    .unwrap();
+    let assign = Terminal::new("ASSIGN");
+    let eq = Terminal::new("EQ");
+
+    assert_eq!(lexer.tokenize("==").unwrap(), vec![(eq.clone(), 0..2)]);
+    assert_eq!(
+      lexer.tokenize("===").unwrap(),
+      vec![(eq, 0..2), (assign.clone(), 2..3)]
+    );
+    assert_eq!(
+      lexer.tokenize(" = =").unwrap(),
+      vec![(assign.clone(), 1..2), (assign, 3..4)]
+    );
+    assert_eq!(lexer.tokenize("x ! x"), Err(LexError::NoMatch(2)));
+    assert_eq!(lexer.tokenize("  ").unwrap(), vec![]);
+    assert_eq!(lexer.prefix_overlaps(), vec![("=", "==")]);
+
+    let tokens = FromTokenIter::new(lexer.tokenize("x != x").unwrap())
+      .map(|token| token.into_parts())
+      .collect::<Vec<_>>();
+    assert_eq!(tokens[1], (Terminal::new("NE"), Span::from(2..4)));
+  }
+
+  #[test]
+  fn test_literal_lexer_errors() {
+    assert_eq!(
+      LiteralLexer::new(vec![("=", 1), ("=", 2)]).unwrap_err(),
+      LexError::DuplicateLiteral("=".to_string())
+    );
+    assert_eq!(
+      LiteralLexer::new(vec![("", 1)]).unwrap_err(),
+      LexError::EmptyLiteral
+    );
+  }
+}
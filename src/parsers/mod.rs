@@ -14,8 +14,12 @@
 
 use std::sync::Arc;
 
+pub mod coverage;
 pub mod earley;
+pub mod forest;
+pub mod glr;
 pub mod lalr;
+pub mod lex;
 pub mod tree;
 
 #[derive(Clone, Copy)]
@@ -28,6 +32,39 @@ impl<K, T> Token<K, T> {
   pub fn new(kind: K, value: T) -> Self {
     Token { kind, value }
   }
+
+  pub fn kind(&self) -> &K {
+    &self.kind
+  }
+
+  pub fn value(&self) -> &T {
+    &self.value
+  }
+
+  /// Consumes the token, returning its kind and value.
+  pub fn into_parts(self) -> (K, T) {
+    (self.kind, self.value)
+  }
+}
+
+/// Decides which grammar terminals a concrete token kind matches.
+///
+/// By default, parser drivers match a token only against the terminal equal
+/// to its kind. A matcher decouples the two, so a single terminal (for
+/// example, "any identifier") can match a whole class of token kinds. Grammar
+/// analyses are unaffected, and still treat each terminal as an opaque
+/// symbol.
+pub trait MatchTerminal<T, K> {
+  fn matches(&self, terminal: &T, kind: &K) -> bool;
+}
+
+impl<T, K, F> MatchTerminal<T, K> for F
+where
+  F: Fn(&T, &K) -> bool,
+{
+  fn matches(&self, terminal: &T, kind: &K) -> bool {
+    self(terminal, kind)
+  }
 }
 
 pub trait TokenStream<K, T>: Clone {
@@ -77,5 +114,5 @@ pub trait Parser<T, AK, V> {
   fn parse<S: TokenStream<T, V>>(
     &self,
     stream: S,
-  ) -> anyhow::Result<tree::Node<T, AK, V>>;
+  ) -> anyhow::Result<tree::Node<'_, T, AK, V>>;
 }
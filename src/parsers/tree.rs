@@ -14,7 +14,7 @@
 
 use std::sync::{Arc, RwLock};
 
-use crate::utils::{change_iter, WasChanged};
+use crate::utils::{change_iter, doc_string, ToDoc, WasChanged};
 use im::Vector;
 use std::collections::{BTreeMap, BTreeSet};
 
@@ -220,7 +220,7 @@ where
   /// Returns a TreeHandle for this owner.
   ///
   /// This can be used to create new nodes and alternatives.
-  pub fn handle(&self) -> TreeHandle<T, AK, V> {
+  pub fn handle(&self) -> TreeHandle<'_, T, AK, V> {
     TreeHandle(&self.inner)
   }
 }
@@ -326,7 +326,7 @@ where
     &self,
     action: AK,
     nodes: impl IntoIterator<Item = Node<'a, T, AK, V>>,
-  ) -> Alternative<T, AK, V> {
+  ) -> Alternative<'_, T, AK, V> {
     let node_indexes = self.with(|inner| {
       nodes
         .into_iter()
@@ -424,6 +424,145 @@ where
   }
 }
 
+impl<'a, T, AK, V> Node<'a, T, AK, V>
+where
+  T: Ord + Clone,
+  AK: Ord + Clone,
+  V: Ord,
+{
+  /// Returns true if this node and `other`, which may belong to different
+  /// trees, have the same alternatives in the same order, with leaves of the
+  /// same kinds and values, and branches with the same actions and children.
+  ///
+  /// The derived `PartialEq` only checks whether two nodes are the same node
+  /// of the same tree.
+  pub fn structural_eq(&self, other: &Node<'_, T, AK, V>) -> bool {
+    self.structural_eq_by(other, |a, b| a == b)
+  }
+
+  /// Like `structural_eq`, but compares leaf values with `value_eq`. This
+  /// can be used to ignore metadata stored in the values, such as spans.
+  pub fn structural_eq_by(
+    &self,
+    other: &Node<'_, T, AK, V>,
+    value_eq: impl Fn(&V, &V) -> bool,
+  ) -> bool {
+    /// Compares two nodes, assuming that the pairs in `visiting` are equal,
+    /// so that cycles in ambiguous trees terminate.
+    fn nodes_eq<T, AK, V>(
+      a: &Node<T, AK, V>,
+      b: &Node<T, AK, V>,
+      value_eq: &dyn Fn(&V, &V) -> bool,
+      visiting: &mut BTreeSet<(usize, usize)>,
+    ) -> bool
+    where
+      T: Ord + Clone,
+      AK: Ord + Clone,
+      V: Ord,
+    {
+      if !visiting.insert((a.index, b.index)) {
+        return true;
+      }
+      let (a_alts, b_alts) =
+        (a.alts().collect::<Vec<_>>(), b.alts().collect::<Vec<_>>());
+      let equal = a_alts.len() == b_alts.len()
+        && a_alts.iter().zip(&b_alts).all(|(a_alt, b_alt)| {
+          match (a_alt.content(), b_alt.content()) {
+            (NodeContent::Leaf(a), NodeContent::Leaf(b)) => {
+              a.kind() == b.kind() && value_eq(&a.value(), &b.value())
+            }
+            (NodeContent::Branch(a), NodeContent::Branch(b)) => {
+              let (a_nodes, b_nodes) =
+                (a.nodes().collect::<Vec<_>>(), b.nodes().collect::<Vec<_>>());
+              a.action() == b.action()
+                && a_nodes.len() == b_nodes.len()
+                && a_nodes
+                  .iter()
+                  .zip(&b_nodes)
+                  .all(|(a, b)| nodes_eq(a, b, value_eq, visiting))
+            }
+            _ => false,
+          }
+        });
+      visiting.remove(&(a.index, b.index));
+      equal
+    }
+
+    nodes_eq(self, other, &value_eq, &mut BTreeSet::new())
+  }
+}
+
+/// A leaf value that may hold the source text of its token.
+pub trait Lexeme {
+  /// Returns the source text of the token, if it is known.
+  fn lexeme(&self) -> Option<&str>;
+}
+
+impl Lexeme for String {
+  fn lexeme(&self) -> Option<&str> {
+    Some(self)
+  }
+}
+
+impl Lexeme for &'_ str {
+  fn lexeme(&self) -> Option<&str> {
+    Some(self)
+  }
+}
+
+impl Lexeme for () {
+  fn lexeme(&self) -> Option<&str> {
+    None
+  }
+}
+
+impl<L: Lexeme> Lexeme for Option<L> {
+  fn lexeme(&self) -> Option<&str> {
+    self.as_ref().and_then(Lexeme::lexeme)
+  }
+}
+
+impl<'a, T, AK, V> Node<'a, T, AK, V>
+where
+  T: Ord + Clone + ToDoc,
+  AK: Ord + Clone,
+  V: Ord + Lexeme,
+{
+  /// Reconstructs the source text of this node, by joining the lexemes of
+  /// its leaves in order with `separator`. Leaves without a lexeme are
+  /// written as their terminal.
+  ///
+  /// Where a node is ambiguous, only its first alternative is used.
+  pub fn to_source(&self, separator: &str) -> String {
+    fn collect_lexemes<T, AK, V>(node: &Node<T, AK, V>, out: &mut Vec<String>)
+    where
+      T: Ord + Clone + ToDoc,
+      AK: Ord + Clone,
+      V: Ord + Lexeme,
+    {
+      let alt = match node.alts().next() {
+        Some(alt) => alt,
+        None => return,
+      };
+      match alt.content() {
+        NodeContent::Leaf(leaf) => out.push(match leaf.value().lexeme() {
+          Some(lexeme) => lexeme.to_string(),
+          None => doc_string(&leaf.kind()),
+        }),
+        NodeContent::Branch(branch) => {
+          for child in branch.nodes() {
+            collect_lexemes(&child, out);
+          }
+        }
+      }
+    }
+
+    let mut lexemes = Vec::new();
+    collect_lexemes(self, &mut lexemes);
+    lexemes.join(separator)
+  }
+}
+
 #[derive(Derivative)]
 #[derivative(
   Clone(bound = ""),
@@ -760,3 +899,77 @@ where
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    grammar::{examples, Terminal},
+    parsers::{
+      earley,
+      lalr::{build_lalr_table, parse_to_tree},
+      Token,
+    },
+    start_grammar::wrap_grammar_with_start,
+    utils::Name,
+  };
+
+  #[test]
+  fn test_to_source() {
+    let g = wrap_grammar_with_start(examples::make_paren()).unwrap();
+    let source = "(()(()))";
+    let tokens = |lexeme: fn(&str) -> Option<String>| {
+      source
+        .chars()
+        .map(|c| {
+          let kind = if c == '(' { "LPAREN" } else { "RPAREN" };
+          Token::new(Terminal::new(kind), lexeme(&c.to_string()))
+        })
+        .collect::<Vec<_>>()
+    };
+
+    let tree = TreeOwner::new();
+    let node =
+      earley::parse(&g, &tree.handle(), tokens(|c| Some(c.to_string())))
+        .unwrap();
+    assert_eq!(node.to_source(""), source);
+    assert_eq!(node.to_source(" "), "( ( ) ( ( ) ) )");
+
+    let tree = TreeOwner::new();
+    let node = earley::parse(&g, &tree.handle(), tokens(|_| None)).unwrap();
+    assert_eq!(
+      node.to_source(" "),
+      "LPAREN LPAREN RPAREN LPAREN LPAREN RPAREN RPAREN RPAREN"
+    );
+  }
+
+  #[test]
+  fn test_structural_eq() {
+    let g = examples::from_rules(&[("s", &[&["LPAREN", "A", "RPAREN"]])]);
+    let table = build_lalr_table(&g).unwrap();
+    // Each token's value is its span in the input `(a)`.
+    let tokens = vec![
+      Token::new(Terminal::new("LPAREN"), (0, 1)),
+      Token::new(Terminal::new("A"), (1, 2)),
+      Token::new(Terminal::new("RPAREN"), (2, 3)),
+    ];
+    let parsed_owner = TreeOwner::new();
+    let parsed = parse_to_tree(&table, &parsed_owner.handle(), tokens).unwrap();
+
+    let owner = TreeOwner::new();
+    let tree = owner.handle();
+    let expected = |action: &str| {
+      let leaves = ["LPAREN", "A", "RPAREN"]
+        .iter()
+        .map(|kind| tree.make_leaf_node(Terminal::new(kind), (0, 0)))
+        .collect::<Vec<_>>();
+      tree.make_branch_node(Name::new(action), leaves)
+    };
+
+    let ignore_spans = |_: &(usize, usize), _: &(usize, usize)| true;
+    assert!(parsed.structural_eq_by(&expected("p0"), ignore_spans));
+    assert!(!parsed.structural_eq(&expected("p0")));
+    assert!(!parsed.structural_eq_by(&expected("p1"), ignore_spans));
+    assert!(parsed.structural_eq(&parsed));
+  }
+}
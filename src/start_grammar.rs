@@ -150,6 +150,7 @@ where
   pub fn start_rule(
     &self,
   ) -> Rule<
+    '_,
     StreamTerminal<T>,
     StartNonTerminal<NT>,
     StartActionKey<AK>,
@@ -161,6 +162,7 @@ where
   pub fn start_prod(
     &self,
   ) -> Prod<
+    '_,
     StreamTerminal<T>,
     StartNonTerminal<NT>,
     StartActionKey<AK>,
@@ -180,9 +182,13 @@ fn base_elem_to_start_elem<T, NT>(
   }
 }
 
+#[allow(clippy::type_complexity)]
 pub fn wrap_grammar_with_start<T, NT, AK, AV>(
   g: Grammar<T, NT, AK, AV>,
-) -> Result<StartGrammar<T, NT, AK, AV>, GrammarErrors<StartNonTerminal<NT>>>
+) -> Result<
+  StartGrammar<T, NT, AK, AV>,
+  GrammarErrors<StartNonTerminal<NT>, StartActionKey<AK>>,
+>
 where
   T: Clone,
   NT: Ord + Clone,
@@ -35,7 +35,6 @@ use std::{
   Clone(bound = ""),
   Debug(bound = "T: std::fmt::Debug, NT: std::fmt::Debug")
 )]
-
 pub struct ProdState<'a, T, NT, AK, AV> {
   /// The production this state is part of.
   prod: Prod<'a, T, NT, AK, AV>,
@@ -48,15 +47,20 @@ pub struct ProdState<'a, T, NT, AK, AV> {
 impl<'a, T, NT, AK, AV> Ord for ProdState<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-    self.prod.cmp(&other.prod)
+    self
+      .prod
+      .cmp(&other.prod)
+      .then_with(|| self.index.cmp(&other.index))
   }
 }
 
 impl<'a, T, NT, AK, AV> PartialOrd for ProdState<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
     Some(self.cmp(other))
@@ -66,13 +70,19 @@ where
 impl<'a, T, NT, AK, AV> PartialEq for ProdState<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn eq(&self, other: &Self) -> bool {
-    self.prod == other.prod
+    self.prod == other.prod && self.index == other.index
   }
 }
 
-impl<'a, T, NT, AK, AV> Eq for ProdState<'a, T, NT, AK, AV> where NT: Ord {}
+impl<'a, T, NT, AK, AV> Eq for ProdState<'a, T, NT, AK, AV>
+where
+  NT: Ord,
+  AK: Ord,
+{
+}
 
 impl<'a, T, NT, AK, AV> ProdState<'a, T, NT, AK, AV> {
   /// Create a ProdState from a given NonTerminal and Prod.
@@ -82,10 +92,23 @@ impl<'a, T, NT, AK, AV> ProdState<'a, T, NT, AK, AV> {
     ProdState { prod, index: 0 }
   }
 
+  /// Create a ProdState at the given index of a production.
+  ///
+  /// Panics if the index is past the end of the production.
+  pub fn at_index(prod: Prod<'a, T, NT, AK, AV>, index: usize) -> Self {
+    assert!(index <= prod.num_elements());
+    ProdState { prod, index }
+  }
+
   pub fn prod(&self) -> Prod<'a, T, NT, AK, AV> {
     self.prod
   }
 
+  /// Returns the index of this state within its production.
+  pub fn index(&self) -> usize {
+    self.index
+  }
+
   pub fn offset_prod_elem(&self, i: usize) -> Option<&'a ProdElement<T, NT>> {
     self.prod.prod_elements().get(self.index + i)
   }
@@ -104,6 +127,7 @@ impl<'a, T, NT, AK, AV> ProdState<'a, T, NT, AK, AV> {
 
   /// Returns the next element after the current index. If it is at the
   /// end, then it reuturns `None`.
+  #[allow(clippy::type_complexity)]
   pub fn next_elem_state(
     &self,
   ) -> Option<(&'a ProdElement<T, NT>, ProdState<'a, T, NT, AK, AV>)> {
@@ -155,6 +179,7 @@ pub struct ProdStateSet<'a, T, NT, AK, AV> {
 impl<'a, T, NT, AK, AV> Ord for ProdStateSet<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn cmp(&self, other: &Self) -> std::cmp::Ordering {
     self.states.cmp(&other.states)
@@ -164,27 +189,35 @@ where
 impl<'a, T, NT, AK, AV> PartialOrd for ProdStateSet<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-    self.states.partial_cmp(&other.states)
+    Some(self.cmp(other))
   }
 }
 
 impl<'a, T, NT, AK, AV> PartialEq for ProdStateSet<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn eq(&self, other: &Self) -> bool {
     self.states.eq(&other.states)
   }
 }
 
-impl<'a, T, NT, AK, AV> Eq for ProdStateSet<'a, T, NT, AK, AV> where NT: Ord {}
+impl<'a, T, NT, AK, AV> Eq for ProdStateSet<'a, T, NT, AK, AV>
+where
+  NT: Ord,
+  AK: Ord,
+{
+}
 
 impl<'a, T, NT, AK, AV> FromIterator<ProdState<'a, T, NT, AK, AV>>
   for ProdStateSet<'a, T, NT, AK, AV>
 where
   NT: Ord,
+  AK: Ord,
 {
   fn from_iter<I: IntoIterator<Item = ProdState<'a, T, NT, AK, AV>>>(
     iter: I,
@@ -218,6 +251,7 @@ impl<'a, T, NT, AK, AV> ProdStateSet<'a, T, NT, AK, AV>
 where
   T: Ord,
   NT: Ord,
+  AK: Ord,
 {
   pub fn add(&mut self, state: ProdState<'a, T, NT, AK, AV>) {
     self.states.insert(state);
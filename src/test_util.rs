@@ -0,0 +1,114 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers for tests of grammar transforms.
+
+use crate::{grammar::Grammar, utils::ToDoc};
+
+/// Asserts that two grammars are equal. On failure, the panic message is a
+/// line diff of the displayed grammars.
+macro_rules! assert_grammar_eq {
+  ($left:expr, $right:expr $(,)?) => {
+    match (&$left, &$right) {
+      (left, right) => {
+        if left != right {
+          panic!(
+            "grammars are not equal (- left, + right):\n{}",
+            $crate::test_util::grammar_diff(left, right)
+          );
+        }
+      }
+    }
+  };
+}
+
+/// Returns a line diff between the displayed forms of `left` and `right`,
+/// with removed lines prefixed by `-`, added lines by `+`, and unchanged
+/// lines by a space.
+pub fn grammar_diff<T, NT, AK, AV>(
+  left: &Grammar<T, NT, AK, AV>,
+  right: &Grammar<T, NT, AK, AV>,
+) -> String
+where
+  T: ToDoc,
+  NT: ToDoc,
+  AK: ToDoc,
+{
+  let (left, right) = (left.to_pretty(), right.to_pretty());
+  if left == right {
+    return "the grammars only differ in their action values".to_string();
+  }
+  let (left, right) = (
+    left.lines().collect::<Vec<_>>(),
+    right.lines().collect::<Vec<_>>(),
+  );
+
+  // lcs[i][j] is the length of the longest common subsequence of
+  // left[i..] and right[j..].
+  let mut lcs = vec![vec![0; right.len() + 1]; left.len() + 1];
+  for i in (0..left.len()).rev() {
+    for j in (0..right.len()).rev() {
+      lcs[i][j] = if left[i] == right[j] {
+        lcs[i + 1][j + 1] + 1
+      } else {
+        lcs[i + 1][j].max(lcs[i][j + 1])
+      };
+    }
+  }
+
+  let mut lines = Vec::new();
+  let (mut i, mut j) = (0, 0);
+  while i < left.len() || j < right.len() {
+    if i < left.len() && j < right.len() && left[i] == right[j] {
+      lines.push(format!("  {}", left[i]));
+      i += 1;
+      j += 1;
+    } else if j == right.len()
+      || (i < left.len() && lcs[i + 1][j] >= lcs[i][j + 1])
+    {
+      lines.push(format!("- {}", left[i]));
+      i += 1;
+    } else {
+      lines.push(format!("+ {}", right[j]));
+      j += 1;
+    }
+  }
+  lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+  use crate::grammar::examples;
+
+  #[test]
+  fn test_assert_grammar_eq() {
+    assert_grammar_eq!(examples::make_paren(), examples::make_paren());
+
+    let result = std::panic::catch_unwind(|| {
+      assert_grammar_eq!(
+        examples::from_rules(&[("s", &[&["A"], &["B"]])]),
+        examples::from_rules(&[("s", &[&["A"], &["C"]])]),
+      );
+    });
+    let message = result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.starts_with("grammars are not equal"), "{}", message);
+    let changed = message
+      .lines()
+      .filter(|line| line.starts_with(['-', '+']))
+      .collect::<Vec<_>>();
+    assert_eq!(changed.len(), 2, "{}", message);
+    assert!(changed[0].starts_with('-') && changed[0].contains('B'));
+    assert!(changed[1].starts_with('+') && changed[1].contains('C'));
+  }
+}
@@ -14,6 +14,7 @@
 
 use std::collections::{btree_map, BTreeMap, BTreeSet};
 
+pub mod bitset;
 pub mod buffer;
 pub mod fmt;
 pub mod graph_closure;
@@ -51,6 +52,22 @@ pub trait ToDoc {
     DA::Doc: Clone;
 }
 
+/// Writes the layout of `value` at a width of 80 columns. This is used to
+/// implement `Display` for types that implement `ToDoc`.
+pub fn fmt_doc(
+  value: &impl ToDoc,
+  fmt: &mut std::fmt::Formatter,
+) -> std::fmt::Result {
+  let arena = pretty::Arena::new();
+  write!(fmt, "{}", value.to_doc(&arena).into_doc().pretty(80))
+}
+
+/// Returns the layout of `value` on a single line.
+pub fn doc_string(value: &impl ToDoc) -> String {
+  let arena = pretty::Arena::new();
+  format!("{}", value.to_doc(&arena).into_doc().pretty(usize::MAX))
+}
+
 impl ToDoc for () {
   fn to_doc<'a, DA: pretty::DocAllocator<'a>>(
     &self,
@@ -71,23 +88,32 @@ pub fn take_only<I: Iterator>(mut iter: I) -> Option<I::Item> {
 /// A refcounted name type, used to avoid duplicating common string values
 /// throughout an AST.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct Name(std::rc::Rc<String>);
+pub struct Name(std::sync::Arc<String>);
 
 impl Name {
   /// Creates a new Name containing the given string.
   pub fn new(s: &(impl AsRef<str> + ?Sized)) -> Self {
-    Name(std::rc::Rc::new(s.as_ref().to_string()))
+    Name(std::sync::Arc::new(s.as_ref().to_string()))
   }
 
   /// Returns a reference to the internal ref.
   pub fn str(&self) -> &str {
-    &**self.0
+    &self.0
+  }
+
+  /// Returns true if both names share the same string allocation, as names
+  /// handed out by the same `SymbolPool` do.
+  ///
+  /// Names that share an allocation are compared without looking at their
+  /// strings, so this is only needed to check that sharing happened.
+  pub fn ptr_eq(&self, other: &Name) -> bool {
+    std::sync::Arc::ptr_eq(&self.0, &other.0)
   }
 
   /// Returns a mutable reference to a string to modify this name. Will not
   /// alter any other names.
   pub fn make_mut(&mut self) -> &mut String {
-    std::rc::Rc::make_mut(&mut self.0)
+    std::sync::Arc::make_mut(&mut self.0)
   }
 }
 
@@ -97,6 +123,14 @@ impl AsRef<str> for Name {
   }
 }
 
+/// Names compare and hash the same as their strings, so maps keyed by `Name`
+/// can be queried with a `&str`.
+impl std::borrow::Borrow<str> for Name {
+  fn borrow(&self) -> &str {
+    self.str()
+  }
+}
+
 impl std::fmt::Debug for Name {
   fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
     fmt.write_str(&self.0)
@@ -145,9 +179,44 @@ impl<L: Ord, V> TreeNode<L, V> {
   }
 }
 
+impl<L, V> TreeNode<L, V> {
+  pub fn action(&self) -> &L {
+    &self.action_name
+  }
+
+  pub fn params(&self) -> &BTreeMap<Name, TreeValue<L, V>> {
+    &self.params
+  }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub enum Void {}
 
+/// A 64-bit FNV-1a hasher.
+///
+/// Unlike `std`'s default hasher, its output is fixed, so hashes may be
+/// persisted and compared across builds.
+#[derive(Clone, Copy, Debug)]
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+  fn default() -> Self {
+    FnvHasher(0xcbf29ce484222325)
+  }
+}
+
+impl std::hash::Hasher for FnvHasher {
+  fn finish(&self) -> u64 {
+    self.0
+  }
+
+  fn write(&mut self, bytes: &[u8]) {
+    for byte in bytes {
+      self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x100000001b3);
+    }
+  }
+}
+
 pub fn breadth_first_search<T, InitI, StepI, F>(
   initial: InitI,
   mut f: F,
@@ -305,9 +374,7 @@ where
     match self.0.entry(key) {
       btree_map::Entry::Occupied(mut occ) => {
         let set = occ.get_mut();
-        change_iter(values.into_iter(), |val| {
-          WasChanged::from_changed(set.insert(val))
-        })
+        change_iter(values, |val| WasChanged::from_changed(set.insert(val)))
       }
       btree_map::Entry::Vacant(vac) => {
         let mut val_iter = values.into_iter();
@@ -397,3 +464,22 @@ pub fn apply_fixed_point<K, V, P: FixedPointProcessor<K, V>>(
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_name_borrow_str() {
+    let mut map = BTreeMap::new();
+    map.insert(Name::new("b"), 2);
+    map.insert(Name::new("a"), 1);
+    assert_eq!(map.get("a"), Some(&1));
+    assert_eq!(map.get("b"), Some(&2));
+    assert_eq!(map.get("c"), None);
+
+    let set =
+      std::iter::once(Name::new("x")).collect::<std::collections::HashSet<_>>();
+    assert!(set.contains("x"));
+  }
+}
@@ -0,0 +1,123 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A fixed-width set of small integers, stored as a bitmap.
+
+use super::WasChanged;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct BitSet {
+  words: Vec<u64>,
+}
+
+impl BitSet {
+  /// Creates an empty set that can hold the values `0..width`.
+  pub fn new(width: usize) -> Self {
+    BitSet {
+      words: vec![0; width.div_ceil(WORD_BITS)],
+    }
+  }
+
+  pub fn contains(&self, value: usize) -> bool {
+    self.words[value / WORD_BITS] & (1 << (value % WORD_BITS)) != 0
+  }
+
+  pub fn insert(&mut self, value: usize) -> WasChanged {
+    let word = &mut self.words[value / WORD_BITS];
+    let prev = *word;
+    *word |= 1 << (value % WORD_BITS);
+    WasChanged::from_changed(*word != prev)
+  }
+
+  /// Adds all values of `other` to this set. Both sets must have the same
+  /// width.
+  pub fn union_with(&mut self, other: &BitSet) -> WasChanged {
+    assert_eq!(self.words.len(), other.words.len());
+    let mut changed = false;
+    for (word, other_word) in self.words.iter_mut().zip(&other.words) {
+      let prev = *word;
+      *word |= other_word;
+      changed |= *word != prev;
+    }
+    WasChanged::from_changed(changed)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.words.iter().all(|word| *word == 0)
+  }
+
+  /// Iterates over the values in the set, in increasing order.
+  pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+    self.words.iter().enumerate().flat_map(|(i, &word)| {
+      (0..WORD_BITS)
+        .filter(move |bit| word & (1 << bit) != 0)
+        .map(move |bit| i * WORD_BITS + bit)
+    })
+  }
+}
+
+/// Adds all values of `sets[src]` to `sets[dest]`.
+///
+/// This allows unions between elements of the same slice without cloning.
+pub fn union_within(
+  sets: &mut [BitSet],
+  dest: usize,
+  src: usize,
+) -> WasChanged {
+  use std::cmp::Ordering;
+  match dest.cmp(&src) {
+    Ordering::Equal => WasChanged::Unchanged,
+    Ordering::Less => {
+      let (head, tail) = sets.split_at_mut(src);
+      head[dest].union_with(&tail[0])
+    }
+    Ordering::Greater => {
+      let (head, tail) = sets.split_at_mut(dest);
+      tail[0].union_with(&head[src])
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_insert_and_iter() {
+    let mut set = BitSet::new(130);
+    assert!(set.is_empty());
+    assert_eq!(set.insert(3), WasChanged::Changed);
+    assert_eq!(set.insert(129), WasChanged::Changed);
+    assert_eq!(set.insert(64), WasChanged::Changed);
+    assert_eq!(set.insert(3), WasChanged::Unchanged);
+    assert!(set.contains(64));
+    assert!(!set.contains(63));
+    assert_eq!(set.iter().collect::<Vec<_>>(), vec![3, 64, 129]);
+  }
+
+  #[test]
+  fn test_union_within() {
+    let mut sets = vec![BitSet::new(10), BitSet::new(10), BitSet::new(10)];
+    sets[0].insert(1);
+    sets[2].insert(7);
+    assert_eq!(union_within(&mut sets, 2, 0), WasChanged::Changed);
+    assert_eq!(union_within(&mut sets, 0, 2), WasChanged::Changed);
+    assert_eq!(union_within(&mut sets, 0, 2), WasChanged::Unchanged);
+    assert_eq!(union_within(&mut sets, 1, 1), WasChanged::Unchanged);
+    assert_eq!(sets[0].iter().collect::<Vec<_>>(), vec![1, 7]);
+    assert!(sets[1].is_empty());
+  }
+}
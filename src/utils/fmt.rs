@@ -5,13 +5,13 @@ use std::fmt::{Debug, Formatter, Result};
 #[derive(Clone)]
 struct DebugListFormatted<C>(C);
 
-impl<'a, C: IntoIterator + Clone> Debug for DebugListFormatted<C>
+impl<C: IntoIterator + Clone> Debug for DebugListFormatted<C>
 where
   C::Item: Debug,
 {
   fn fmt(&self, fmt: &mut Formatter) -> Result {
     let mut list = fmt.debug_list();
-    list.entries(self.0.clone().into_iter());
+    list.entries(self.0.clone());
     list.finish()
   }
 }
@@ -19,13 +19,13 @@ where
 #[derive(Clone)]
 struct DebugSetFormatted<C>(C);
 
-impl<'a, C: IntoIterator + Clone> Debug for DebugSetFormatted<C>
+impl<C: IntoIterator + Clone> Debug for DebugSetFormatted<C>
 where
   C::Item: Debug,
 {
   fn fmt(&self, fmt: &mut Formatter) -> Result {
     let mut dbg_set = fmt.debug_set();
-    dbg_set.entries(self.0.clone().into_iter());
+    dbg_set.entries(self.0.clone());
     dbg_set.finish()
   }
 }
@@ -54,10 +54,7 @@ where
 
 impl<'a, K, V> Clone for ValueRef<'a, K, V> {
   fn clone(&self) -> Self {
-    ValueRef {
-      key: self.key,
-      value: self.value,
-    }
+    *self
   }
 }
 
@@ -183,7 +180,7 @@ where
     }
   }
 
-  pub fn get_entry(&self, key: &K) -> Option<ValueRef<K, V>> {
+  pub fn get_entry(&self, key: &K) -> Option<ValueRef<'_, K, V>> {
     self
       .map
       .get_key_value(key)
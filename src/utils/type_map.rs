@@ -51,7 +51,7 @@ impl Ord for dyn TypeKeyObjectTrait {
 
 impl PartialOrd for dyn TypeKeyObjectTrait {
   fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-    Some(self.cmp_concrete(other))
+    Some(self.cmp(other))
   }
 }
 
@@ -73,7 +73,7 @@ impl Ord for TypeKeyObject {
 
 impl PartialOrd for TypeKeyObject {
   fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-    (*self.0).partial_cmp(&*other.0)
+    Some(self.cmp(other))
   }
 }
 
@@ -96,6 +96,12 @@ impl Borrow<dyn TypeKeyObjectTrait> for TypeKeyObject {
 /// map, meaning both the types and the keys can be of different concrete types. The `TypeKey` trait is used to provide a type-safe interface on the map.
 pub struct TypeMap(BTreeMap<TypeKeyObject, Box<dyn Any + 'static>>);
 
+impl Default for TypeMap {
+  fn default() -> Self {
+    TypeMap::new()
+  }
+}
+
 impl TypeMap {
   /// Creates a new empty `TypeMap`.
   pub fn new() -> Self {